@@ -1,5 +1,6 @@
 use super::{
-    BackendCapabilities, BackendKind, EnvironmentBackend, EnvironmentTarget, OutputMode, RunRequest,
+    BackendCapabilities, BackendKind, ChannelPriorityMode, EnvironmentBackend, EnvironmentTarget,
+    OutputMode, RunRequest, TransactionApproval,
 };
 use crate::error::{EnvError, Result};
 use crate::micromamba::{CondaEnvironment, MicromambaManager, ValidationResult};
@@ -9,14 +10,40 @@ use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub struct CliBackend {
     package_manager: Option<PackageManager>,
+    transaction_approval: TransactionApproval,
+    channels: Vec<String>,
+    channel_priority: Option<ChannelPriorityMode>,
 }
 
 impl CliBackend {
     pub fn new(package_manager: Option<PackageManager>) -> Self {
-        Self { package_manager }
+        Self {
+            package_manager,
+            transaction_approval: TransactionApproval::default(),
+            channels: Vec::new(),
+            channel_priority: None,
+        }
+    }
+
+    /// Hands `--yes` and `--dry-run` of installs and updates to the package manager.
+    pub fn with_transaction_approval(mut self, approval: TransactionApproval) -> Self {
+        self.transaction_approval = approval;
+        self
+    }
+
+    /// Hands `--channel` of installs and updates to the package manager.
+    pub fn with_channels(mut self, channels: Vec<String>) -> Self {
+        self.channels = channels;
+        self
+    }
+
+    /// Hands `--channel-priority` of installs and updates to the package manager.
+    pub fn with_channel_priority(mut self, channel_priority: Option<ChannelPriorityMode>) -> Self {
+        self.channel_priority = channel_priority;
+        self
     }
 
     async fn global_manager(&self) -> Result<Arc<Mutex<MicromambaManager>>> {
@@ -28,17 +55,20 @@ impl CliBackend {
     }
 
     async fn runtime_manager(&self) -> Result<MicromambaManager> {
-        match self.package_manager {
+        let manager = match self.package_manager {
             Some(package_manager) => {
-                MicromambaManager::new_runtime_with_package_manager(package_manager).await
+                MicromambaManager::new_runtime_with_package_manager(package_manager).await?
             }
             None => {
                 let manager = self.global_manager().await?;
                 let guard = manager.lock().await;
-                let cloned = guard.clone();
-                Ok(cloned)
+                guard.clone()
             }
-        }
+        };
+        Ok(manager
+            .with_transaction_approval(self.transaction_approval)
+            .with_channels(self.channels.clone())
+            .with_channel_priority(self.channel_priority))
     }
 }
 
@@ -65,6 +95,12 @@ impl EnvironmentBackend for CliBackend {
         force: bool,
         output_mode: OutputMode,
     ) -> Result<()> {
+        if !self.channels.is_empty() || self.channel_priority.is_some() {
+            return Err(EnvError::Execution(
+                "create --channel and --channel-priority require the default rattler backend; compatibility mode creates from the YAML's channels"
+                    .to_string(),
+            ));
+        }
         let manager = self.runtime_manager().await?;
         manager
             .create_environment(env_name, yaml_file, dry_run, force, output_mode)
@@ -114,6 +150,48 @@ impl EnvironmentBackend for CliBackend {
         }
     }
 
+    async fn uninstall_packages_for_target(
+        &self,
+        target: &EnvironmentTarget,
+        packages: &[String],
+        output_mode: OutputMode,
+    ) -> Result<()> {
+        let manager = self.runtime_manager().await?;
+        match target {
+            EnvironmentTarget::Name(env_name) => {
+                manager
+                    .uninstall_packages(env_name, packages, output_mode)
+                    .await
+            }
+            EnvironmentTarget::Prefix(prefix) => {
+                manager
+                    .uninstall_packages_by_prefix(prefix, packages, output_mode)
+                    .await
+            }
+        }
+    }
+
+    async fn update_packages_for_target(
+        &self,
+        target: &EnvironmentTarget,
+        packages: &[String],
+        output_mode: OutputMode,
+    ) -> Result<()> {
+        let manager = self.runtime_manager().await?;
+        match target {
+            EnvironmentTarget::Name(env_name) => {
+                manager
+                    .update_packages(env_name, packages, output_mode)
+                    .await
+            }
+            EnvironmentTarget::Prefix(prefix) => {
+                manager
+                    .update_packages_by_prefix(prefix, packages, output_mode)
+                    .await
+            }
+        }
+    }
+
     async fn adopt_environment(
         &self,
         _target: &EnvironmentTarget,
@@ -1,6 +1,6 @@
 use super::cli::CliBackend;
 use super::rattler::RattlerBackend;
-use super::{BackendSelector, EnvironmentBackend};
+use super::{BackendOptions, BackendSelector, EnvironmentBackend};
 use crate::error::Result;
 use std::sync::Arc;
 
@@ -14,3 +14,31 @@ pub async fn build_backend(selector: BackendSelector) -> Result<Arc<dyn Environm
 pub async fn build_default_backend() -> Result<Arc<dyn EnvironmentBackend>> {
     build_backend(BackendSelector::from_env()).await
 }
+
+/// Builds the default backend with per-invocation `options`; the compatibility backend
+/// passes the transaction approval, channels and channel priority to its package manager.
+pub async fn build_default_backend_with_options(
+    options: BackendOptions,
+) -> Result<Arc<dyn EnvironmentBackend>> {
+    let selector = BackendSelector::from_env();
+    match selector.kind {
+        super::BackendKind::Cli => Ok(Arc::new(
+            CliBackend::new(selector.package_manager)
+                .with_transaction_approval(options.transaction_approval)
+                .with_channel_priority(options.channel_priority)
+                .with_channels(options.channels),
+        )),
+        super::BackendKind::Rattler => {
+            let backend = RattlerBackend::new()
+                .with_transaction_approval(options.transaction_approval)
+                .with_channel_priority(options.channel_priority)
+                .with_channels(options.channels);
+            // `ENVA_OFFLINE` is already read by `RattlerBackend::new`.
+            Ok(Arc::new(if options.offline {
+                backend.with_offline(true)
+            } else {
+                backend
+            }))
+        }
+    }
+}
@@ -5,12 +5,16 @@ pub mod types;
 
 use crate::error::{EnvError, Result};
 use crate::micromamba::{CondaEnvironment, ValidationResult};
+use crate::revisions::EnvironmentRevision;
+use crate::verify::PrefixVerification;
 use async_trait::async_trait;
+use rattler_conda_types::RepoDataRecord;
 use std::path::{Path, PathBuf};
 use tokio::process::Command as AsyncCommand;
 pub use types::{
-    BackendCapabilities, BackendCapability, BackendKind, BackendSelector, CapabilitySupport,
-    EnvironmentName, EnvironmentResolution, EnvironmentTarget, OutputMode, RunCommand, RunRequest,
+    BackendCapabilities, BackendCapability, BackendKind, BackendOptions, BackendSelector,
+    CapabilitySupport, ChannelPriorityMode, EnvironmentName, EnvironmentResolution,
+    EnvironmentTarget, ExportFormat, OutputMode, RunCommand, RunRequest, TransactionApproval,
 };
 
 pub(crate) const ENVIRONMENT_SHELL: &str = "bash";
@@ -85,6 +89,80 @@ pub trait EnvironmentBackend: Send + Sync {
         output_mode: OutputMode,
     ) -> Result<()>;
 
+    /// Like `create_environment`, but solves against `channels` instead of the channels
+    /// listed in the YAML when any are given.
+    async fn create_environment_with_channels(
+        &self,
+        env_name: &str,
+        yaml_file: &Path,
+        channels: &[String],
+        dry_run: bool,
+        force: bool,
+        output_mode: OutputMode,
+    ) -> Result<()> {
+        if channels.is_empty() {
+            return self
+                .create_environment(env_name, yaml_file, dry_run, force, output_mode)
+                .await;
+        }
+        Err(EnvError::Execution(format!(
+            "The selected backend cannot override the channels of environment {}",
+            env_name
+        )))
+    }
+
+    /// Solves `yaml_file`, stores every package archive in the package cache and writes
+    /// them to `output` as a local channel. Returns the number of packages bundled.
+    async fn bundle_environment(
+        &self,
+        yaml_file: &Path,
+        output: &Path,
+        _output_mode: OutputMode,
+    ) -> Result<usize> {
+        Err(EnvError::Execution(format!(
+            "The selected backend cannot bundle {} into {}",
+            yaml_file.display(),
+            output.display()
+        )))
+    }
+
+    /// Solves `yaml_file` and stores its repodata and every package archive in the
+    /// caches so a later offline create succeeds. Returns the number of packages fetched.
+    async fn fetch_environment(&self, yaml_file: &Path, _output_mode: OutputMode) -> Result<usize> {
+        Err(EnvError::Execution(format!(
+            "The selected backend cannot fetch the packages of {}",
+            yaml_file.display()
+        )))
+    }
+
+    async fn lock_environment(
+        &self,
+        yaml_file: &Path,
+        lock_file: &Path,
+        _output_mode: OutputMode,
+    ) -> Result<()> {
+        Err(EnvError::Execution(format!(
+            "The selected backend cannot write lockfiles: {} -> {}",
+            yaml_file.display(),
+            lock_file.display()
+        )))
+    }
+
+    async fn create_environment_from_lock(
+        &self,
+        env_name: &str,
+        lock_file: &Path,
+        _dry_run: bool,
+        _force: bool,
+        _output_mode: OutputMode,
+    ) -> Result<()> {
+        Err(EnvError::Execution(format!(
+            "The selected backend cannot create environment {} from lockfile {}",
+            env_name,
+            lock_file.display()
+        )))
+    }
+
     async fn validate_yaml(&self, yaml_file: &Path) -> Result<ValidationResult>;
 
     async fn validate_yaml_with_packages(
@@ -127,6 +205,203 @@ pub trait EnvironmentBackend: Send + Sync {
         }
     }
 
+    async fn uninstall_packages_for_target(
+        &self,
+        target: &EnvironmentTarget,
+        _packages: &[String],
+        _output_mode: OutputMode,
+    ) -> Result<()> {
+        Err(EnvError::Execution(format!(
+            "The selected backend cannot uninstall packages from environment {}",
+            target
+        )))
+    }
+
+    /// Updates `packages` (every installed package when empty) to the newest versions
+    /// the environment's channels allow.
+    async fn update_packages_for_target(
+        &self,
+        target: &EnvironmentTarget,
+        _packages: &[String],
+        _output_mode: OutputMode,
+    ) -> Result<()> {
+        Err(EnvError::Execution(format!(
+            "The selected backend cannot update packages in environment {}",
+            target
+        )))
+    }
+
+    async fn update_all_environments(
+        &self,
+        _packages: &[String],
+        _output_mode: OutputMode,
+    ) -> Result<()> {
+        Err(EnvError::Execution(
+            "The selected backend cannot update every managed environment".to_string(),
+        ))
+    }
+
+    /// Applies only the delta between the installed packages and a fresh solve of
+    /// `yaml_file`, and records the YAML as the environment's source.
+    async fn sync_environment(
+        &self,
+        target: &EnvironmentTarget,
+        yaml_file: &Path,
+        _output_mode: OutputMode,
+    ) -> Result<()> {
+        Err(EnvError::Execution(format!(
+            "The selected backend cannot sync environment {} with {}",
+            target,
+            yaml_file.display()
+        )))
+    }
+
+    async fn export_environment(
+        &self,
+        target: &EnvironmentTarget,
+        _format: ExportFormat,
+    ) -> Result<String> {
+        Err(EnvError::Execution(format!(
+            "The selected backend cannot export environment {}",
+            target
+        )))
+    }
+
+    async fn environment_history(
+        &self,
+        target: &EnvironmentTarget,
+    ) -> Result<Vec<EnvironmentRevision>> {
+        Err(EnvError::Execution(format!(
+            "The selected backend does not record revisions for environment {}",
+            target
+        )))
+    }
+
+    /// Reinstalls the exact record set of `revision` through a staged prefix.
+    async fn rollback_environment(
+        &self,
+        target: &EnvironmentTarget,
+        revision: u32,
+        _output_mode: OutputMode,
+    ) -> Result<()> {
+        Err(EnvError::Execution(format!(
+            "The selected backend cannot roll back environment {} to revision {}",
+            target, revision
+        )))
+    }
+
+    async fn installed_package_records(
+        &self,
+        target: &EnvironmentTarget,
+    ) -> Result<Vec<RepoDataRecord>> {
+        Err(EnvError::Execution(format!(
+            "The selected backend cannot read the package records of environment {}",
+            target
+        )))
+    }
+
+    /// Solves `yaml_file` for the current platform without creating a prefix.
+    async fn solve_yaml_records(&self, yaml_file: &Path) -> Result<Vec<RepoDataRecord>> {
+        Err(EnvError::Execution(format!(
+            "The selected backend cannot solve {} without creating an environment",
+            yaml_file.display()
+        )))
+    }
+
+    /// Checks installed files against the sha256 and size recorded in `conda-meta`.
+    async fn verify_environment(&self, target: &EnvironmentTarget) -> Result<PrefixVerification> {
+        Err(EnvError::Execution(format!(
+            "The selected backend cannot verify environment {}",
+            target
+        )))
+    }
+
+    /// Reinstalls the packages whose files fail verification through a staged prefix and
+    /// returns the verification that found them.
+    async fn repair_environment(
+        &self,
+        target: &EnvironmentTarget,
+        _output_mode: OutputMode,
+    ) -> Result<PrefixVerification> {
+        Err(EnvError::Execution(format!(
+            "The selected backend cannot repair environment {}",
+            target
+        )))
+    }
+
+    /// Copies `source` to a new environment named `env_name`, rewriting embedded prefixes
+    /// instead of solving and downloading again.
+    async fn clone_environment(
+        &self,
+        source: &EnvironmentTarget,
+        env_name: &str,
+        _force: bool,
+        _output_mode: OutputMode,
+    ) -> Result<()> {
+        Err(EnvError::Execution(format!(
+            "The selected backend cannot clone environment {} to {}",
+            source, env_name
+        )))
+    }
+
+    /// Moves `target` to `<its envs directory>/<new_name>` and returns the new prefix.
+    async fn rename_environment(
+        &self,
+        target: &EnvironmentTarget,
+        new_name: &str,
+        _output_mode: OutputMode,
+    ) -> Result<PathBuf> {
+        Err(EnvError::Execution(format!(
+            "The selected backend cannot rename environment {} to {}",
+            target, new_name
+        )))
+    }
+
+    /// Moves `target` to `<root_prefix>/envs/<name>` and returns the new prefix.
+    async fn move_environment(
+        &self,
+        target: &EnvironmentTarget,
+        root_prefix: &Path,
+        _output_mode: OutputMode,
+    ) -> Result<PathBuf> {
+        Err(EnvError::Execution(format!(
+            "The selected backend cannot move environment {} to {}",
+            target,
+            root_prefix.display()
+        )))
+    }
+
+    /// Writes `target` to a relocatable archive at `output` and returns the number of
+    /// files packed.
+    async fn pack_environment(
+        &self,
+        target: &EnvironmentTarget,
+        output: &Path,
+        _output_mode: OutputMode,
+    ) -> Result<usize> {
+        Err(EnvError::Execution(format!(
+            "The selected backend cannot pack environment {} to {}",
+            target,
+            output.display()
+        )))
+    }
+
+    /// Publishes the environment stored in `archive` at `destination`, relocating it
+    /// from the prefix it was packed from, and returns the new prefix.
+    async fn unpack_environment(
+        &self,
+        archive: &Path,
+        destination: &EnvironmentTarget,
+        _force: bool,
+        _output_mode: OutputMode,
+    ) -> Result<PathBuf> {
+        Err(EnvError::Execution(format!(
+            "The selected backend cannot unpack {} to {}",
+            archive.display(),
+            destination
+        )))
+    }
+
     async fn adopt_environment(
         &self,
         target: &EnvironmentTarget,
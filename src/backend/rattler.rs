@@ -1,42 +1,90 @@
 use super::{
-    build_environment_run_command, BackendCapabilities, BackendKind, EnvironmentBackend,
-    EnvironmentName, EnvironmentTarget, OutputMode, RunRequest,
+    build_environment_run_command, BackendCapabilities, BackendKind, ChannelPriorityMode,
+    EnvironmentBackend, EnvironmentName, EnvironmentTarget, ExportFormat, OutputMode, RunRequest,
+    TransactionApproval,
+};
+use crate::environment_metadata::{
+    read_environment_metadata, write_environment_metadata, EnvironmentMetadata, YamlSource,
+};
+use crate::environment_registry::{
+    default_environment_registry, read_registered_prefixes, replace_registered_prefix,
+};
+use crate::environment_variables::{
+    activation_script_changes, read_environment_variables, validate_variable_name,
+    write_environment_variables, EnvironmentVariables,
 };
 use crate::error::{EnvError, Result};
+use crate::export::{exported_requested_specs, render_environment_export};
+use crate::global_config::{default_global_config_path, read_global_config};
+use crate::local_channel::{
+    download_client, fetch_package_archive, write_channel_repodata, ChannelPackage,
+};
+use crate::lockfile::{read_locked_environment, write_lock_file};
 use crate::micromamba::{CondaEnvironment, MicromambaManager, ValidationDetails, ValidationResult};
 use crate::operation_lock::{LockOperation, OperationLock};
 use crate::ownership::{
     ownership_record_path, read_ownership_record, write_rattler_ownership_record,
 };
+use crate::pack::{extract_pack_archive, write_pack_archive, PackManifest};
+use crate::package_changes::{diff_package_records, summarize_package_changes, TransactionPlan};
 use crate::package_manager::{PackageManager, PackageManagerDetector};
 use crate::prefix_registry::{
     discover_cli_environments, merge_discovered_environments, DiscoveredEnvironment,
     EnvironmentOwner, EnvironmentSource,
 };
+use crate::prefix_relocation::{relocate_prefix_records, replace_all_bytes, PrefixRelocation};
+use crate::revisions::{
+    has_revisions, list_revisions, read_revision, record_revision, EnvironmentRevision,
+    RevisionOperation,
+};
+use crate::solve_conflict::SolveConflict;
 use crate::staged_prefix::StagedPrefix;
+use crate::verify::{verify_prefix, PrefixVerification};
 use async_trait::async_trait;
 use indicatif::{ProgressBar, ProgressStyle};
 use rattler::install::Installer;
 use rattler::package_cache::PackageCache;
+use rattler::validation::ValidationMode;
+use rattler_conda_types::package::{PackageFile, PathsJson};
 use rattler_conda_types::{
-    Channel, ChannelConfig, EnvironmentYaml, MatchSpec, Platform, PrefixRecord, RepoDataRecord,
+    Channel, ChannelConfig, EnvironmentYaml, MatchSpec, NamedChannelOrUrl, PackageName,
+    ParseStrictness, Platform, PrefixRecord, RepoDataRecord,
+};
+use rattler_repodata_gateway::fetch::CacheAction;
+use rattler_repodata_gateway::{Gateway, RepoData, SourceConfig};
+use rattler_solve::{
+    resolvo::Solver as RattlerSolver, ChannelPriority, SolveError, SolverImpl, SolverTask,
 };
-use rattler_repodata_gateway::{Gateway, RepoData};
-use rattler_solve::{resolvo::Solver as RattlerSolver, ChannelPriority, SolverImpl, SolverTask};
 use rattler_virtual_packages::{VirtualPackage, VirtualPackageOverrides};
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeSet, HashMap, HashSet};
 use std::ffi::OsString;
 use std::fs;
-use std::io;
+use std::io::{self, IsTerminal, Write};
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::time::{Duration, Instant};
 use tokio::fs as async_fs;
+use url::Url;
 
 #[derive(Debug, Clone)]
 pub struct RattlerBackend {
     root_prefixes: Vec<PathBuf>,
+    environment_registry: Option<PathBuf>,
+    offline: bool,
+    transaction_approval: TransactionApproval,
+    global_config: Option<PathBuf>,
+    channel_priority: Option<ChannelPriorityMode>,
+    channels: Vec<String>,
+}
+
+/// Solver settings conda reads from an environment YAML next to its channels.
+#[derive(Debug, Default, Deserialize)]
+struct YamlSolverSettings {
+    #[serde(default)]
+    channel_priority: Option<ChannelPriorityMode>,
+    #[serde(default)]
+    channel_alias: Option<Url>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -296,22 +344,6 @@ fn clone_prefix_without_reflinks(source: &Path, destination: &Path) -> Result<Pr
     clone_prefix_with_copy_mode(source, destination, false)
 }
 
-fn replace_all_bytes(input: &[u8], from: &[u8], to: &[u8]) -> Vec<u8> {
-    let mut output = Vec::with_capacity(input.len());
-    let mut cursor = 0;
-    while let Some(relative) = input[cursor..]
-        .windows(from.len())
-        .position(|window| window == from)
-    {
-        let match_start = cursor + relative;
-        output.extend_from_slice(&input[cursor..match_start]);
-        output.extend_from_slice(to);
-        cursor = match_start + from.len();
-    }
-    output.extend_from_slice(&input[cursor..]);
-    output
-}
-
 fn validate_publication_entries(
     root: &Path,
     staging_prefix: &[u8],
@@ -409,15 +441,73 @@ impl RattlerBackend {
     pub fn new() -> Self {
         Self {
             root_prefixes: Self::detect_root_prefixes(),
+            environment_registry: default_environment_registry(),
+            offline: Self::offline_from_env(),
+            transaction_approval: TransactionApproval::default(),
+            global_config: default_global_config_path(),
+            channel_priority: None,
+            channels: Vec::new(),
         }
     }
 
     pub fn with_root_prefixes(root_prefixes: Vec<PathBuf>) -> Self {
         Self {
             root_prefixes: Self::dedupe_paths(root_prefixes),
+            environment_registry: None,
+            offline: false,
+            transaction_approval: TransactionApproval::default(),
+            global_config: None,
+            channel_priority: None,
+            channels: Vec::new(),
         }
     }
 
+    /// Uses `registry` as the enva-owned `environments.txt` that records moved environments.
+    pub fn with_environment_registry(mut self, registry: PathBuf) -> Self {
+        self.environment_registry = Some(registry);
+        self
+    }
+
+    /// Solves only from cached repodata and installs only from the package cache.
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Sets how install, update and create transactions are confirmed before they apply.
+    pub fn with_transaction_approval(mut self, approval: TransactionApproval) -> Self {
+        self.transaction_approval = approval;
+        self
+    }
+
+    /// Reads user-wide defaults such as the channel priority from `config`.
+    pub fn with_global_config(mut self, config: PathBuf) -> Self {
+        self.global_config = Some(config);
+        self
+    }
+
+    /// Forces the channel priority of every solve, e.g. from `--channel-priority`.
+    pub fn with_channel_priority(mut self, channel_priority: Option<ChannelPriorityMode>) -> Self {
+        self.channel_priority = channel_priority;
+        self
+    }
+
+    /// Solves installs, updates and syncs against `channels` instead of the channels the
+    /// environment recorded, e.g. from `--channel`.
+    pub fn with_channels(mut self, channels: Vec<String>) -> Self {
+        self.channels = channels;
+        self
+    }
+
+    fn offline_from_env() -> bool {
+        std::env::var("ENVA_OFFLINE").is_ok_and(|value| {
+            matches!(
+                value.trim().to_ascii_lowercase().as_str(),
+                "1" | "true" | "yes" | "on"
+            )
+        })
+    }
+
     fn detect_root_prefixes() -> Vec<PathBuf> {
         let mut candidates = Vec::new();
 
@@ -621,6 +711,12 @@ impl RattlerBackend {
             );
         }
 
+        for name in environment_yaml.variables.keys() {
+            if let Err(error) = validate_variable_name(name) {
+                issues.push(error.to_string());
+            }
+        }
+
         if let Some(pip_specs) = environment_yaml
             .pip_specs()
             .filter(|specs| !specs.is_empty())
@@ -634,6 +730,15 @@ impl RattlerBackend {
         issues
     }
 
+    /// The YAML's `variables:` section, written to `conda-meta/state` like conda does.
+    fn yaml_variables(environment_yaml: &EnvironmentYaml) -> EnvironmentVariables {
+        environment_yaml
+            .variables
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect()
+    }
+
     fn conda_specs(environment_yaml: &EnvironmentYaml) -> Vec<MatchSpec> {
         environment_yaml.match_specs().cloned().collect()
     }
@@ -642,8 +747,68 @@ impl RattlerBackend {
         vec!["conda-forge".to_string(), "bioconda".to_string()]
     }
 
-    fn default_channel_priority() -> ChannelPriority {
-        ChannelPriority::Disabled
+    fn default_channel_priority() -> ChannelPriorityMode {
+        ChannelPriorityMode::Disabled
+    }
+
+    /// Reads the conda-style `channel_priority` and `channel_alias` keys that
+    /// `EnvironmentYaml` ignores.
+    fn yaml_solver_settings(yaml_file: &Path) -> Result<YamlSolverSettings> {
+        let content = fs::read_to_string(yaml_file).map_err(|error| {
+            EnvError::FileOperation(format!("Failed to read YAML file: {}", error))
+        })?;
+        serde_yaml::from_str::<YamlSolverSettings>(&content).map_err(|error| {
+            EnvError::Validation(format!(
+                "Invalid solver settings in {}: {}",
+                yaml_file.display(),
+                error
+            ))
+        })
+    }
+
+    /// Picks the channel priority of a solve: the command line wins over the environment
+    /// YAML, which wins over the value the environment recorded and the global config.
+    fn resolve_channel_priority(
+        &self,
+        yaml_file: Option<&Path>,
+        prefix: Option<&Path>,
+    ) -> Result<ChannelPriorityMode> {
+        if let Some(priority) = self.channel_priority {
+            return Ok(priority);
+        }
+        if let Some(priority) = yaml_file
+            .map(Self::yaml_solver_settings)
+            .transpose()?
+            .and_then(|settings| settings.channel_priority)
+        {
+            return Ok(priority);
+        }
+        if let Some(priority) = prefix
+            .filter(|prefix| Self::is_environment_prefix(prefix))
+            .map(read_environment_metadata)
+            .transpose()?
+            .and_then(|metadata| metadata.channel_priority)
+        {
+            return Ok(priority);
+        }
+        if let Some(priority) = self
+            .global_config
+            .as_deref()
+            .map(read_global_config)
+            .transpose()?
+            .and_then(|config| config.channel_priority)
+        {
+            return Ok(priority);
+        }
+        Ok(Self::default_channel_priority())
+    }
+
+    /// Metadata of `prefix` with the channel priority its next solve uses, so the value
+    /// is recorded when the solve is published.
+    fn metadata_with_channel_priority(&self, prefix: &Path) -> Result<EnvironmentMetadata> {
+        let mut metadata = read_environment_metadata(prefix)?;
+        metadata.channel_priority = Some(self.resolve_channel_priority(None, Some(prefix))?);
+        Ok(metadata)
     }
 
     fn summary_spinner(message: impl Into<String>) -> Result<ProgressBar> {
@@ -660,13 +825,24 @@ impl RattlerBackend {
         Ok(pb)
     }
 
-    fn resolve_channel_config(yaml_file: &Path) -> ChannelConfig {
+    fn resolve_channel_config(yaml_file: &Path) -> Result<ChannelConfig> {
         let root_dir = yaml_file
             .parent()
             .map(Path::to_path_buf)
             .or_else(|| std::env::current_dir().ok())
             .unwrap_or_else(|| PathBuf::from("."));
-        ChannelConfig::default_with_root_dir(root_dir)
+        Ok(Self::channel_config_with_alias(
+            root_dir,
+            Self::yaml_solver_settings(yaml_file)?.channel_alias,
+        ))
+    }
+
+    fn channel_config_with_alias(root_dir: PathBuf, channel_alias: Option<Url>) -> ChannelConfig {
+        let mut channel_config = ChannelConfig::default_with_root_dir(root_dir);
+        if let Some(channel_alias) = channel_alias {
+            channel_config.channel_alias = channel_alias;
+        }
+        channel_config
     }
 
     fn resolve_channel_names(
@@ -691,18 +867,99 @@ impl RattlerBackend {
         yaml_file: &Path,
         environment_yaml: &EnvironmentYaml,
     ) -> Result<Vec<Channel>> {
-        let channel_config = Self::resolve_channel_config(yaml_file);
+        let channel_config = Self::resolve_channel_config(yaml_file)?;
         Self::resolve_channel_names(&channel_config, Self::extract_string_list(environment_yaml))
     }
 
+    /// Parses `--channel` values; relative paths are taken from the current directory
+    /// rather than from the directory of the YAML they replace the channels of.
+    fn channel_overrides(channels: &[String]) -> Result<Vec<NamedChannelOrUrl>> {
+        let current_dir = std::env::current_dir().map_err(|error| {
+            EnvError::FileOperation(format!(
+                "Failed to resolve the current directory: {}",
+                error
+            ))
+        })?;
+        channels
+            .iter()
+            .map(|channel| {
+                let parse = |value: &str| {
+                    NamedChannelOrUrl::from_str(value).map_err(|error| {
+                        EnvError::Validation(format!(
+                            "Failed to parse channel '{}': {}",
+                            channel, error
+                        ))
+                    })
+                };
+                match parse(channel)? {
+                    NamedChannelOrUrl::Path(path) if !Path::new(path.as_str()).is_absolute() => {
+                        parse(&current_dir.join(path.as_str()).to_string_lossy())
+                    }
+                    parsed => Ok(parsed),
+                }
+            })
+            .collect()
+    }
+
     fn resolve_channels_for_prefix(
         prefix: &Path,
         channel_names: Vec<String>,
     ) -> Result<Vec<Channel>> {
-        let channel_config = ChannelConfig::default_with_root_dir(prefix.to_path_buf());
+        let channel_alias = if Self::is_environment_prefix(prefix) {
+            read_environment_metadata(prefix)?.channel_alias
+        } else {
+            None
+        };
+        let channel_config = Self::channel_config_with_alias(prefix.to_path_buf(), channel_alias);
         Self::resolve_channel_names(&channel_config, channel_names)
     }
 
+    /// Channels recorded for a new environment: the resolved URLs in solve order, so
+    /// relative paths and the channel alias no longer depend on where the YAML was.
+    fn channel_urls(channels: &[Channel]) -> Vec<String> {
+        channels
+            .iter()
+            .map(|channel| channel.base_url.to_string())
+            .collect()
+    }
+
+    /// The `--channel` overrides, or else the channels recorded for `prefix`.
+    fn override_or_recorded_channels(&self, prefix: &Path) -> Result<Vec<String>> {
+        if !self.channels.is_empty() {
+            return Ok(Self::channel_overrides(&self.channels)?
+                .iter()
+                .map(ToString::to_string)
+                .collect());
+        }
+        Ok(read_environment_metadata(prefix)?.channels)
+    }
+
+    /// Channels a later solve of `prefix` uses: `--channel` overrides win, then the
+    /// channels recorded when the environment was created. Environments without recorded
+    /// channels fall back to hints from their packages. Channels named by a requested
+    /// `channel::package` spec are always added.
+    fn prefix_channels(
+        &self,
+        prefix: &Path,
+        installed: &[PrefixRecord],
+        requested_specs: &[String],
+    ) -> Result<Vec<String>> {
+        let base = self.override_or_recorded_channels(prefix)?;
+        if base.is_empty() {
+            return Ok(Self::install_channel_hints(installed, requested_specs));
+        }
+
+        let mut channels = Vec::new();
+        let mut seen = HashSet::new();
+        for channel in base
+            .into_iter()
+            .chain(Self::channel_hints_from_spec_strings(requested_specs))
+        {
+            Self::push_unique_string(&mut channels, &mut seen, channel);
+        }
+        Ok(channels)
+    }
+
     fn exact_match_spec_for_record(record: &PrefixRecord) -> String {
         format!(
             "{} =={} {}",
@@ -764,6 +1021,39 @@ impl RattlerBackend {
         merged
     }
 
+    fn spec_package_name(spec: &str) -> Option<String> {
+        MatchSpec::from_str(spec, ParseStrictness::Lenient)
+            .ok()
+            .and_then(|spec| spec.name.as_exact().cloned())
+            .map(|name| name.as_normalized().to_string())
+    }
+
+    fn package_names_from_specs(packages: &[String]) -> Result<Vec<String>> {
+        let mut names = Vec::new();
+        let mut seen = HashSet::new();
+        for package in packages {
+            let name = Self::spec_package_name(package).ok_or_else(|| {
+                EnvError::Validation(format!("Expected a package name but got '{}'", package))
+            })?;
+            Self::push_unique_string(&mut names, &mut seen, name);
+        }
+        Ok(names)
+    }
+
+    fn remove_requested_specs(
+        existing_specs: Vec<String>,
+        removed_names: &[String],
+    ) -> Vec<String> {
+        existing_specs
+            .into_iter()
+            .filter(|spec| {
+                Self::spec_package_name(spec)
+                    .map(|name| !removed_names.contains(&name))
+                    .unwrap_or(true)
+            })
+            .collect()
+    }
+
     fn channel_hints_from_spec_strings(specs: &[String]) -> Vec<String> {
         let mut channels = Vec::new();
         let mut seen = HashSet::new();
@@ -899,27 +1189,43 @@ impl RattlerBackend {
             })
     }
 
-    async fn solve_environment(
+    async fn fetch_repodata(
         &self,
-        yaml_file: &Path,
-        environment_yaml: &EnvironmentYaml,
-    ) -> Result<(Vec<MatchSpec>, Vec<RepoDataRecord>)> {
-        let specs = Self::conda_specs(environment_yaml);
-        let channels = Self::resolve_channels(yaml_file, environment_yaml)?;
-        let virtual_packages = Self::detect_virtual_packages()?;
+        channels: Vec<Channel>,
+        specs: &[MatchSpec],
+    ) -> Result<Vec<RepoData>> {
         let platforms = [Platform::current(), Platform::NoArch];
-
         let cache_root = Self::cache_root_dir()?;
-        let repo_data_sets: Vec<RepoData> = Gateway::builder()
+        let mut gateway = Gateway::builder()
+            .with_client(download_client())
             .with_cache_dir(cache_root.clone())
-            .with_package_cache(PackageCache::new(Self::package_cache_dir(&cache_root)))
+            .with_package_cache(PackageCache::new(Self::package_cache_dir(&cache_root)));
+        if self.offline {
+            gateway = gateway.with_channel_config(rattler_repodata_gateway::ChannelConfig {
+                default: SourceConfig {
+                    cache_action: CacheAction::ForceCacheOnly,
+                    ..SourceConfig::default()
+                },
+                ..rattler_repodata_gateway::ChannelConfig::default()
+            });
+        }
+        let repo_data_sets: Vec<RepoData> = gateway
             .finish()
-            .query(channels, platforms, specs.clone())
+            .query(channels, platforms, specs.to_vec())
             .recursive(true)
             .execute()
             .await
             .map_err(|error| {
-                EnvError::Execution(format!("Failed to fetch repodata for solve: {}", error))
+                if self.offline {
+                    EnvError::Execution(format!(
+                        "Failed to read cached repodata in offline mode: {}. Run `enva fetch` \
+                         with network access first to populate {}",
+                        error,
+                        cache_root.display()
+                    ))
+                } else {
+                    EnvError::Execution(format!("Failed to fetch repodata for solve: {}", error))
+                }
             })?;
 
         if repo_data_sets.iter().all(RepoData::is_empty) {
@@ -928,19 +1234,257 @@ impl RattlerBackend {
             ));
         }
 
-        let mut solver = RattlerSolver;
-        let solved = solver
-            .solve(SolverTask {
+        Ok(repo_data_sets)
+    }
+
+    fn solve_with_repodata(
+        specs: Vec<MatchSpec>,
+        repo_data_sets: &[RepoData],
+        locked_packages: &[RepoDataRecord],
+        channel_priority: ChannelPriorityMode,
+    ) -> Result<Vec<RepoDataRecord>> {
+        let virtual_packages = Self::detect_virtual_packages()?;
+        let solve = |channel_priority| {
+            RattlerSolver.solve(SolverTask {
                 specs: specs.clone(),
-                virtual_packages,
-                channel_priority: Self::default_channel_priority(),
+                virtual_packages: virtual_packages.clone(),
+                channel_priority,
+                locked_packages: locked_packages.iter().collect(),
                 ..SolverTask::from_iter(repo_data_sets.iter())
             })
-            .map_err(|error| {
-                EnvError::Execution(format!("Failed to solve environment: {}", error))
-            })?;
+        };
+        // rattler has no flexible mode; like conda, it only falls back to lower-priority
+        // channels when the strict solve is unsatisfiable.
+        let solved = match channel_priority {
+            ChannelPriorityMode::Strict => solve(ChannelPriority::Strict),
+            ChannelPriorityMode::Disabled => solve(ChannelPriority::Disabled),
+            ChannelPriorityMode::Flexible => match solve(ChannelPriority::Strict) {
+                Err(SolveError::Unsolvable(_)) => solve(ChannelPriority::Disabled),
+                solved => solved,
+            },
+        }
+        .map_err(|error| match error {
+            SolveError::Unsolvable(messages) => EnvError::SolveConflict(Box::new(
+                SolveConflict::from_solver_messages(&messages, &specs),
+            )),
+            error => EnvError::Execution(format!("Failed to solve environment: {}", error)),
+        })?;
+
+        Ok(solved.records)
+    }
+
+    fn channels_with_repodata(repo_data_sets: &[RepoData]) -> Vec<String> {
+        let mut channels = Vec::new();
+        let mut seen = HashSet::new();
+        for record in repo_data_sets.iter().flat_map(RepoData::iter) {
+            if let Some(channel) = record.channel.clone() {
+                Self::push_unique_string(&mut channels, &mut seen, channel);
+            }
+        }
+        channels
+    }
+
+    /// Sums `paths.json` sizes of the solved packages that are already extracted in
+    /// the package cache; returns `None` when any package still has to be downloaded.
+    fn cached_install_size_bytes(records: &[RepoDataRecord]) -> Result<Option<u64>> {
+        let package_cache = Self::package_cache_dir(&Self::cache_root_dir()?);
+        let mut total = 0;
+        for record in records {
+            let package_dir = Self::cached_package_dir(&package_cache, record);
+            match PathsJson::from_package_directory(&package_dir) {
+                Ok(paths) => {
+                    total += paths
+                        .paths
+                        .iter()
+                        .filter_map(|entry| entry.size_in_bytes)
+                        .sum::<u64>();
+                }
+                Err(_) => return Ok(None),
+            }
+        }
+        Ok(Some(total))
+    }
+
+    fn cached_package_dir(package_cache: &Path, record: &RepoDataRecord) -> PathBuf {
+        let package = &record.package_record;
+        package_cache.join(format!(
+            "{}-{}-{}",
+            package.name.as_normalized(),
+            package.version,
+            package.build
+        ))
+    }
+
+    /// In offline mode, fails with every package of `records` that would still have to
+    /// be downloaded; packages already extracted in the cache or on a local path pass.
+    fn ensure_records_available_offline(
+        &self,
+        records: &[RepoDataRecord],
+        installed: &[PrefixRecord],
+    ) -> Result<()> {
+        if !self.offline {
+            return Ok(());
+        }
+
+        let package_cache = Self::package_cache_dir(&Self::cache_root_dir()?);
+        let installed = installed
+            .iter()
+            .map(|record| &record.repodata_record.identifier)
+            .collect::<HashSet<_>>();
+        let missing = records
+            .iter()
+            .filter(|record| !installed.contains(&record.identifier))
+            .filter(|record| {
+                !Self::cached_package_dir(&package_cache, record)
+                    .join("info")
+                    .join("index.json")
+                    .is_file()
+            })
+            .filter(|record| {
+                record.url.scheme() != "file"
+                    || !record.url.to_file_path().is_ok_and(|path| path.is_file())
+            })
+            .map(|record| record.identifier.to_string())
+            .collect::<Vec<String>>();
+        if missing.is_empty() {
+            return Ok(());
+        }
+
+        Err(EnvError::Execution(format!(
+            "{} package(s) are not in the package cache and cannot be downloaded in offline \
+             mode: {}. Run `enva fetch` with network access first",
+            missing.len(),
+            missing.join(", ")
+        )))
+    }
+
+    /// Downloads the archive of every record into the package cache and extracts it there.
+    /// Returns the cached archive paths in the order of `records`.
+    async fn fetch_records_into_cache(
+        cache_root: &Path,
+        records: &[RepoDataRecord],
+        progress: Option<&ProgressBar>,
+        output_mode: OutputMode,
+    ) -> Result<Vec<PathBuf>> {
+        let package_cache_dir = Self::package_cache_dir(cache_root);
+        fs::create_dir_all(&package_cache_dir).map_err(|error| {
+            io_error("Failed to create package cache", &package_cache_dir, error)
+        })?;
+        let package_cache = PackageCache::new(&package_cache_dir);
+        let client = download_client();
+        let mut archives = Vec::with_capacity(records.len());
+        for (index, record) in records.iter().enumerate() {
+            let file_name = record.identifier.to_string();
+            if let Some(pb) = progress {
+                pb.set_message(format!(
+                    "Fetching {} ({}/{})...",
+                    file_name,
+                    index + 1,
+                    records.len()
+                ));
+            }
+            if matches!(output_mode, OutputMode::Stream) {
+                println!("Fetching {} from {}", file_name, record.url);
+            }
+
+            let cached_archive = package_cache_dir.join(&file_name);
+            fetch_package_archive(client.client(), record, &cached_archive).await?;
+            package_cache
+                .get_or_fetch_from_path(&cached_archive, Some(&record.package_record), None)
+                .await
+                .map_err(|error| {
+                    EnvError::Execution(format!(
+                        "Failed to add {} to the package cache: {}",
+                        file_name, error
+                    ))
+                })?;
+            archives.push(cached_archive);
+        }
+        Ok(archives)
+    }
+
+    fn transaction_plan<'a>(
+        prefix: &Path,
+        installed: impl IntoIterator<Item = &'a RepoDataRecord>,
+        solved_records: &'a [RepoDataRecord],
+    ) -> Result<TransactionPlan> {
+        let package_cache = Self::package_cache_dir(&Self::cache_root_dir()?);
+        Ok(TransactionPlan::new(
+            prefix.to_path_buf(),
+            installed,
+            solved_records,
+            |record| {
+                Self::cached_package_dir(&package_cache, record)
+                    .join("info")
+                    .join("index.json")
+                    .is_file()
+            },
+        ))
+    }
+
+    /// Shows `plan` and applies the transaction approval. Returns `false` when the plan
+    /// must not be applied (a dry run); a declined prompt fails the command.
+    fn approve_transaction(
+        &self,
+        plan: &TransactionPlan,
+        progress: Option<&ProgressBar>,
+        output_mode: OutputMode,
+    ) -> Result<bool> {
+        let dry_run = self.transaction_approval == TransactionApproval::DryRun;
+        let show = || -> Result<bool> {
+            if plan.is_empty() {
+                if dry_run {
+                    println!("Nothing to change in {}", plan.prefix.display());
+                }
+                return Ok(!dry_run);
+            }
+            if dry_run || !matches!(output_mode, OutputMode::Quiet) {
+                println!("{}", plan);
+            }
+            if dry_run {
+                println!("Dry run: no changes were made to {}", plan.prefix.display());
+                return Ok(false);
+            }
+            let prompt = self.transaction_approval == TransactionApproval::Prompt
+                && !matches!(output_mode, OutputMode::Quiet)
+                && io::stdin().is_terminal()
+                && io::stderr().is_terminal();
+            if !prompt {
+                return Ok(true);
+            }
+
+            eprint!("Proceed? [y/N] ");
+            io::stderr().flush()?;
+            let mut answer = String::new();
+            io::stdin().read_line(&mut answer)?;
+            if matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes") {
+                Ok(true)
+            } else {
+                Err(EnvError::Execution(format!(
+                    "Transaction declined; no changes were made to {}",
+                    plan.prefix.display()
+                )))
+            }
+        };
+        match progress {
+            Some(pb) => pb.suspend(show),
+            None => show(),
+        }
+    }
+
+    async fn solve_environment(
+        &self,
+        yaml_file: &Path,
+        environment_yaml: &EnvironmentYaml,
+    ) -> Result<(Vec<MatchSpec>, Vec<RepoDataRecord>)> {
+        let specs = Self::conda_specs(environment_yaml);
+        let channels = Self::resolve_channels(yaml_file, environment_yaml)?;
+        let repo_data_sets = self.fetch_repodata(channels, &specs).await?;
+        let channel_priority = self.resolve_channel_priority(Some(yaml_file), None)?;
+        let records =
+            Self::solve_with_repodata(specs.clone(), &repo_data_sets, &[], channel_priority)?;
 
-        Ok((specs, solved.records))
+        Ok((specs, records))
     }
 
     fn extract_string_list(environment_yaml: &EnvironmentYaml) -> Vec<String> {
@@ -1186,6 +1730,20 @@ impl RattlerBackend {
             }
         }
 
+        if let Some(registry) = &self.environment_registry {
+            prefixes.extend(
+                read_registered_prefixes(registry)?
+                    .into_iter()
+                    .filter(|prefix| {
+                        Self::is_environment_prefix(prefix)
+                            && read_ownership_record(prefix)
+                                .ok()
+                                .flatten()
+                                .is_some_and(|record| record.is_rattler_owned())
+                    }),
+            );
+        }
+
         Ok(Self::dedupe_paths(prefixes))
     }
 
@@ -1212,27 +1770,63 @@ impl RattlerBackend {
         environment.rattler_managed() && Self::helper_package_manager(environment).is_none()
     }
 
+    /// A package manager for `environment` that installs with the `--channel` overrides or
+    /// the channels recorded for it, and with the `--channel-priority` or recorded priority.
     async fn helper_manager_for_environment(
         &self,
         environment: &DiscoveredEnvironment,
     ) -> Result<MicromambaManager> {
-        if let Some(package_manager) = Self::helper_package_manager(environment) {
-            return MicromambaManager::new_runtime_with_package_manager(package_manager).await;
-        }
+        let package_manager = match Self::helper_package_manager(environment) {
+            Some(package_manager) => package_manager,
+            None => {
+                let detector = PackageManagerDetector::new();
+                detector
+                    .available_managers_with_env_override()
+                    .into_iter()
+                    .next()
+                    .ok_or_else(|| {
+                        EnvError::Execution(
+                            "No helper package manager is available for this rattler-managed operation"
+                                .to_string(),
+                        )
+                    })?
+            }
+        };
 
-        let detector = PackageManagerDetector::new();
-        let package_manager = detector
-            .available_managers_with_env_override()
-            .into_iter()
-            .next()
-            .ok_or_else(|| {
-                EnvError::Execution(
-                    "No helper package manager is available for this rattler-managed operation"
-                        .to_string(),
-                )
-            })?;
+        let channel_priority = match self.channel_priority {
+            Some(priority) => Some(priority),
+            None => read_environment_metadata(&environment.prefix)?.channel_priority,
+        };
+        Ok(
+            MicromambaManager::new_runtime_with_package_manager(package_manager)
+                .await?
+                .with_transaction_approval(self.transaction_approval)
+                .with_channels(self.override_or_recorded_channels(&environment.prefix)?)
+                .with_channel_priority(channel_priority),
+        )
+    }
 
-        MicromambaManager::new_runtime_with_package_manager(package_manager).await
+    /// Adopts `environment` before a transaction changes it. A dry run writes no ownership
+    /// marker; the unadopted environment still delegates to the same helper package manager,
+    /// which only prints its plan.
+    async fn adopt_for_transaction(
+        &self,
+        environment: DiscoveredEnvironment,
+        output_mode: OutputMode,
+    ) -> Result<DiscoveredEnvironment> {
+        if environment.rattler_managed() {
+            return Ok(environment);
+        }
+        if self.transaction_approval == TransactionApproval::DryRun {
+            println!(
+                "Dry run: environment '{}' at {} would be adopted into rattler ownership",
+                environment.name,
+                environment.prefix.display()
+            );
+            return Ok(environment);
+        }
+        self.adopt_discovered_environment(&environment, output_mode)
+            .await
     }
 
     async fn adopt_discovered_environment(
@@ -1547,44 +2141,13 @@ impl RattlerBackend {
         prefix: &Path,
         channel_names: Vec<String>,
         specs: Vec<MatchSpec>,
+        locked_packages: &[RepoDataRecord],
     ) -> Result<Vec<RepoDataRecord>> {
         let channels = Self::resolve_channels_for_prefix(prefix, channel_names)?;
-        let virtual_packages = Self::detect_virtual_packages()?;
-        let platforms = [Platform::current(), Platform::NoArch];
-
-        let cache_root = Self::cache_root_dir()?;
-        let repo_data_sets: Vec<RepoData> = Gateway::builder()
-            .with_cache_dir(cache_root.clone())
-            .with_package_cache(PackageCache::new(Self::package_cache_dir(&cache_root)))
-            .finish()
-            .query(channels, platforms, specs.clone())
-            .recursive(true)
-            .execute()
-            .await
-            .map_err(|error| {
-                EnvError::Execution(format!("Failed to fetch repodata for solve: {}", error))
-            })?;
-
-        if repo_data_sets.iter().all(RepoData::is_empty) {
-            return Err(EnvError::Execution(
-                "No package metadata was returned for the requested channels and specs".to_string(),
-            ));
-        }
-
-        let mut solver = RattlerSolver;
-        let solved = solver
-            .solve(SolverTask {
-                specs,
-                virtual_packages,
-                channel_priority: Self::default_channel_priority(),
-                ..SolverTask::from_iter(repo_data_sets.iter())
-            })
-            .map_err(|error| {
-                EnvError::Execution(format!("Failed to solve environment: {}", error))
-            })?;
-
-        Ok(solved.records)
-    }
+        let repo_data_sets = self.fetch_repodata(channels, &specs).await?;
+        let channel_priority = self.resolve_channel_priority(None, Some(prefix))?;
+        Self::solve_with_repodata(specs, &repo_data_sets, locked_packages, channel_priority)
+    }
 
     async fn install_packages_by_prefix_natively(
         &self,
@@ -1635,11 +2198,24 @@ impl RattlerBackend {
         let solved_records = self
             .solve_package_specs(
                 prefix,
-                Self::install_channel_hints(&installed, &requested_spec_strings),
+                self.prefix_channels(prefix, &installed, &requested_spec_strings)?,
                 requested_specs.clone(),
+                &[],
             )
             .await?;
 
+        let plan = Self::transaction_plan(
+            prefix,
+            installed.iter().map(|record| &record.repodata_record),
+            &solved_records,
+        )?;
+        if !self.approve_transaction(&plan, progress.as_ref(), output_mode)? {
+            if let Some(pb) = progress {
+                pb.finish_and_clear();
+            }
+            return Ok(());
+        }
+
         if let Some(pb) = &progress {
             pb.set_message(format!(
                 "Installing {} packages into {}...",
@@ -1655,48 +2231,18 @@ impl RattlerBackend {
             );
         }
 
-        let ownership_record = read_ownership_record(prefix)?;
-        let cache_root = Self::cache_root_dir()?;
-        let staged_prefix = StagedPrefix::prepare(prefix)?;
-        let staging_path = staged_prefix.path().to_path_buf();
-        let clone_result = clone_prefix_for_staging(prefix, &staging_path)?;
-        Self::remove_ownership_marker_before_installation(&staging_path)?;
-        if matches!(output_mode, OutputMode::Stream) {
-            println!(
-                "Cloned {} files ({} bytes, {} hard links) into staging in {} ms",
-                clone_result.files_copied,
-                clone_result.bytes_copied,
-                clone_result.hard_links_preserved,
-                clone_result.elapsed_millis
-            );
-        }
-        let install_result = Installer::new()
-            .with_package_cache(PackageCache::new(Self::package_cache_dir(&cache_root)))
-            .with_installed_packages(installed)
-            .with_requested_specs(requested_specs)
-            .with_alternative_target_prefix(prefix)
-            .install(&staging_path, solved_records)
-            .await
-            .map(|_| ())
-            .map_err(|error| {
-                EnvError::Execution(format!(
-                    "Failed to install solved packages into staging prefix {}: {}",
-                    staging_path.display(),
-                    error
-                ))
-            })
-            .and_then(|()| {
-                let adopted_from: Option<&str> = ownership_record
-                    .as_ref()
-                    .and_then(|record| record.adopted_from.as_deref());
-                write_rattler_ownership_record(&staging_path, adopted_from).map(|_| ())
-            })
-            .and_then(|()| {
-                validate_staged_prefix_for_publication(&staging_path, prefix).map(|_| ())
-            })
-            .and_then(|()| staged_prefix.commit());
-
-        let result = install_result;
+        let metadata = self.metadata_with_channel_priority(prefix)?;
+        let result = self
+            .apply_solved_records_to_prefix(
+                prefix,
+                installed,
+                requested_specs,
+                solved_records,
+                RevisionOperation::Install,
+                Some(&metadata),
+                output_mode,
+            )
+            .await;
 
         if let Some(pb) = progress {
             match &result {
@@ -1716,30 +2262,16 @@ impl RattlerBackend {
         result
     }
 
-    fn build_prefixed_path(&self, prefix: &Path) -> Result<OsString> {
-        let mut path_entries = Vec::new();
-        path_entries.push(prefix.join("bin"));
-
-        #[cfg(target_os = "windows")]
-        {
-            path_entries.push(prefix.join("Scripts"));
-            path_entries.push(prefix.join("Library").join("bin"));
+    async fn uninstall_packages_by_prefix_natively(
+        &self,
+        prefix: &Path,
+        packages: &[String],
+        output_mode: OutputMode,
+    ) -> Result<()> {
+        if packages.is_empty() {
+            return Ok(());
         }
 
-        path_entries.extend(std::env::split_paths(
-            &std::env::var_os("PATH").unwrap_or_default(),
-        ));
-
-        std::env::join_paths(path_entries).map_err(|error| {
-            EnvError::Environment(format!(
-                "Failed to construct PATH for environment {}: {}",
-                prefix.display(),
-                error
-            ))
-        })
-    }
-
-    async fn run_command_in_prefix(&self, prefix: &Path, request: &RunRequest) -> Result<()> {
         if !Self::is_environment_prefix(prefix) {
             return Err(EnvError::Execution(format!(
                 "Environment prefix is not a valid conda-style environment: {}",
@@ -1747,875 +2279,4269 @@ impl RattlerBackend {
             )));
         }
 
-        let env_name = self.environment_name_for_prefix(prefix);
-        let mut cmd = build_environment_run_command(&request.command)?;
-        cmd.current_dir(&request.cwd);
-        cmd.env("PATH", self.build_prefixed_path(prefix)?);
-        cmd.env("CONDA_PREFIX", prefix);
-        cmd.env("CONDA_DEFAULT_ENV", &env_name);
-        cmd.env("CONDA_SHLVL", "1");
-        cmd.env("RATTLER_ENV_PREFIX", prefix);
-
-        for env_pair in &request.env_vars {
-            let (key, value) = env_pair.split_once('=').ok_or_else(|| {
-                EnvError::Validation(format!("Invalid environment variable format: {}", env_pair))
-            })?;
-            cmd.env(key, value);
+        let removed_names = Self::package_names_from_specs(packages)?;
+        let installed = Self::collect_installed_prefix_records(prefix)?;
+        let missing = removed_names
+            .iter()
+            .filter(|name| {
+                !installed.iter().any(|record| {
+                    record.repodata_record.package_record.name.as_normalized() == name.as_str()
+                })
+            })
+            .cloned()
+            .collect::<Vec<String>>();
+        if !missing.is_empty() {
+            return Err(EnvError::Validation(format!(
+                "Packages are not installed in {}: {}",
+                prefix.display(),
+                missing.join(", ")
+            )));
         }
 
-        if request.capture_output {
-            cmd.stdout(std::process::Stdio::piped())
-                .stderr(std::process::Stdio::piped());
+        let _cache_lock = self.acquire_cache_lock(LockOperation::CacheUse).await?;
+        let progress = if matches!(output_mode, OutputMode::Summary) {
+            Some(Self::summary_spinner(format!(
+                "Resolving package removal for {}...",
+                prefix.display()
+            ))?)
         } else {
-            cmd.stdout(std::process::Stdio::inherit())
-                .stderr(std::process::Stdio::inherit());
+            None
+        };
+        if matches!(output_mode, OutputMode::Stream) {
+            println!(
+                "Resolving package removal for {} with rattler...",
+                prefix.display()
+            );
         }
 
-        let output = if request.capture_output {
-            let output = cmd.output().await.map_err(|error| {
-                EnvError::Execution(format!("Failed to execute command: {}", error))
-            })?;
+        let result = async {
+            let remaining_spec_strings = Self::remove_requested_specs(
+                Self::requested_spec_strings_from_prefix_records(&installed),
+                &removed_names,
+            );
+            let requested_specs = Self::parse_match_specs(&remaining_spec_strings)?;
+            let solved_records = if requested_specs.is_empty() {
+                Vec::new()
+            } else {
+                self.solve_package_specs(
+                    prefix,
+                    self.prefix_channels(prefix, &installed, &remaining_spec_strings)?,
+                    requested_specs.clone(),
+                    &[],
+                )
+                .await?
+            };
 
-            if !output.stdout.is_empty() {
-                print!("{}", String::from_utf8_lossy(&output.stdout));
+            let still_required = removed_names
+                .iter()
+                .filter(|name| {
+                    solved_records.iter().any(|record| {
+                        record.package_record.name.as_normalized() == name.as_str()
+                    })
+                })
+                .cloned()
+                .collect::<Vec<String>>();
+            if !still_required.is_empty() {
+                return Err(EnvError::Validation(format!(
+                    "Cannot uninstall {} from {}: still required by the remaining requested packages",
+                    still_required.join(", "),
+                    prefix.display()
+                )));
             }
-            if !output.stderr.is_empty() {
-                eprint!("{}", String::from_utf8_lossy(&output.stderr));
+
+            if let Some(pb) = &progress {
+                pb.set_message(format!(
+                    "Removing {} from {}...",
+                    removed_names.join(", "),
+                    prefix.display()
+                ));
             }
+            self.apply_solved_records_to_prefix(
+                prefix,
+                installed,
+                requested_specs,
+                solved_records,
+                RevisionOperation::Uninstall,
+                None,
+                output_mode,
+            )
+            .await
+        }
+        .await;
 
-            output
-        } else {
-            let status = cmd.status().await.map_err(|error| {
-                EnvError::Execution(format!("Failed to execute command: {}", error))
-            })?;
-            std::process::Output {
-                status,
-                stdout: Vec::new(),
-                stderr: Vec::new(),
+        if let Some(pb) = progress {
+            match &result {
+                Ok(()) => pb.finish_and_clear(),
+                Err(error) => pb.abandon_with_message(format!(
+                    "✗ Failed package removal for {}: {}",
+                    prefix.display(),
+                    error
+                )),
             }
-        };
+        }
 
-        if !output.status.success() {
-            return Err(EnvError::Execution(format!(
-                "Command failed with exit code {:?}",
-                output.status.code()
-            )));
+        if result.is_ok() && matches!(output_mode, OutputMode::Summary) {
+            println!(
+                "✓ Removed {} from {}",
+                removed_names.join(", "),
+                prefix.display()
+            );
         }
 
-        Ok(())
+        result
     }
-}
 
-#[async_trait]
-impl EnvironmentBackend for RattlerBackend {
-    fn kind(&self) -> BackendKind {
-        BackendKind::Rattler
-    }
+    /// Selects the installed package names an update should unlock. With `strict`, every
+    /// requested package must be installed; otherwise missing names are skipped.
+    fn update_selection(
+        installed: &[PrefixRecord],
+        packages: &[String],
+        strict: bool,
+        prefix: &Path,
+    ) -> Result<Vec<String>> {
+        let installed_names = installed
+            .iter()
+            .map(|record| {
+                record
+                    .repodata_record
+                    .package_record
+                    .name
+                    .as_normalized()
+                    .to_string()
+            })
+            .collect::<Vec<String>>();
+        if packages.is_empty() {
+            return Ok(installed_names);
+        }
 
-    fn capabilities(&self) -> BackendCapabilities {
-        BackendCapabilities::rattler()
+        let (selected, missing): (Vec<String>, Vec<String>) =
+            Self::package_names_from_specs(packages)?
+                .into_iter()
+                .partition(|name| installed_names.contains(name));
+        if strict && !missing.is_empty() {
+            return Err(EnvError::Validation(format!(
+                "Packages are not installed in {}: {}",
+                prefix.display(),
+                missing.join(", ")
+            )));
+        }
+        Ok(selected)
     }
 
-    async fn clean_package_cache(&self, dry_run: bool, output_mode: OutputMode) -> Result<()> {
-        let cache_root = Self::cache_root_dir()?;
-        if !dry_run {
-            let _cache_lock = self.acquire_cache_lock(LockOperation::CacheClean).await?;
+    async fn update_packages_by_prefix_natively(
+        &self,
+        prefix: &Path,
+        packages: &[String],
+        strict: bool,
+        output_mode: OutputMode,
+    ) -> Result<()> {
+        if !Self::is_environment_prefix(prefix) {
+            return Err(EnvError::Execution(format!(
+                "Environment prefix is not a valid conda-style environment: {}",
+                prefix.display()
+            )));
         }
-        let cache_directories = Self::cache_directory_entries(&cache_root);
 
-        if dry_run {
+        let installed = Self::collect_installed_prefix_records(prefix)?;
+        let selected = Self::update_selection(&installed, packages, strict, prefix)?;
+        if selected.is_empty() {
             if matches!(output_mode, OutputMode::Summary | OutputMode::Stream) {
-                println!(
-                    "[DRY-RUN] Would clean rattler caches under {}",
-                    cache_root.display()
-                );
-                for (label, path) in &cache_directories {
-                    println!("  - {}: {}", label, path.display());
-                }
+                println!("Nothing to update in {}", prefix.display());
             }
             return Ok(());
         }
 
-        if matches!(output_mode, OutputMode::Summary | OutputMode::Stream) {
-            println!("Cleaning rattler caches under {}", cache_root.display());
-        }
-
-        let mut removed = Vec::new();
-        let mut missing = Vec::new();
-        for (label, path) in cache_directories {
-            if path.exists() {
-                Self::clear_cache_directory(&path).await?;
-                removed.push((label, path));
-            } else {
-                missing.push((label, path));
-            }
+        let _cache_lock = self.acquire_cache_lock(LockOperation::CacheUse).await?;
+        let progress = if matches!(output_mode, OutputMode::Summary) {
+            Some(Self::summary_spinner(format!(
+                "Resolving package update for {}...",
+                prefix.display()
+            ))?)
+        } else {
+            None
+        };
+        if matches!(output_mode, OutputMode::Stream) {
+            println!(
+                "Resolving package update for {} with rattler...",
+                prefix.display()
+            );
         }
 
-        if matches!(output_mode, OutputMode::Summary | OutputMode::Stream) {
-            for (label, path) in &removed {
-                println!("  ✓ Removed {} cache: {}", label, path.display());
-            }
-            if matches!(output_mode, OutputMode::Stream) {
-                for (label, path) in &missing {
-                    println!("  - No {} cache present: {}", label, path.display());
-                }
-            }
-            if matches!(output_mode, OutputMode::Summary) {
-                println!("✓ Rattler cache cleanup complete");
-            }
+        // The requested specs stay as recorded so their pins survive the update; only the
+        // selected records are unlocked for the solver.
+        let requested_spec_strings = Self::requested_spec_strings_from_prefix_records(&installed);
+        let locked_packages = installed
+            .iter()
+            .filter(|record| {
+                !selected.iter().any(|name| {
+                    record.repodata_record.package_record.name.as_normalized() == name.as_str()
+                })
+            })
+            .map(|record| record.repodata_record.clone())
+            .collect::<Vec<RepoDataRecord>>();
+        let solved: Result<(Vec<MatchSpec>, Vec<RepoDataRecord>)> = async {
+            let requested_specs = Self::parse_match_specs(&requested_spec_strings)?;
+            let solved_records = self
+                .solve_package_specs(
+                    prefix,
+                    self.prefix_channels(prefix, &installed, &requested_spec_strings)?,
+                    requested_specs.clone(),
+                    &locked_packages,
+                )
+                .await?;
+            Ok((requested_specs, solved_records))
+        }
+        .await;
+        if let Some(pb) = &progress {
+            pb.finish_and_clear();
         }
+        let (requested_specs, solved_records) = solved?;
 
-        Ok(())
-    }
+        let plan = Self::transaction_plan(
+            prefix,
+            installed.iter().map(|record| &record.repodata_record),
+            &solved_records,
+        )?;
+        if plan.is_empty() {
+            if matches!(output_mode, OutputMode::Summary | OutputMode::Stream) {
+                println!("✓ {} is already up to date", prefix.display());
+            }
+            return Ok(());
+        }
+        if !self.approve_transaction(&plan, None, output_mode)? {
+            return Ok(());
+        }
+        let changes = plan.changes;
+        let summary = summarize_package_changes(&changes);
 
-    async fn create_environment(
-        &self,
-        env_name: &str,
-        yaml_file: &Path,
-        dry_run: bool,
-        force: bool,
-        output_mode: OutputMode,
-    ) -> Result<()> {
         let progress = if matches!(output_mode, OutputMode::Summary) {
             Some(Self::summary_spinner(format!(
-                "Preparing environment {}...",
-                env_name
+                "Applying {} package changes to {}...",
+                changes.len(),
+                prefix.display()
             ))?)
         } else {
             None
         };
+        let metadata = self.metadata_with_channel_priority(prefix)?;
+        let result = self
+            .apply_solved_records_to_prefix(
+                prefix,
+                installed,
+                requested_specs,
+                solved_records,
+                RevisionOperation::Update,
+                Some(&metadata),
+                output_mode,
+            )
+            .await;
 
-        if dry_run {
-            if let Some(pb) = &progress {
-                pb.set_message(format!("Validating YAML for {}...", env_name));
-            }
-            let validation = self.validate_yaml(yaml_file).await?;
-            if let Some(pb) = progress {
-                pb.finish_and_clear();
+        if let Some(pb) = progress {
+            match &result {
+                Ok(()) => pb.finish_and_clear(),
+                Err(error) => pb.abandon_with_message(format!(
+                    "✗ Failed package update for {}: {}",
+                    prefix.display(),
+                    error
+                )),
             }
-            println!("{}", serde_json::to_string_pretty(&validation)?);
-            return Ok(());
         }
 
-        if let Some(pb) = &progress {
-            pb.set_message(format!("Validating YAML for {}...", env_name));
-        }
-        let environment_yaml = Self::parse_environment_yaml(yaml_file)?;
-        let issues = Self::environment_issues(&environment_yaml);
-        if !issues.is_empty() {
-            return Err(EnvError::Validation(issues.join("; ")));
+        if result.is_ok() && matches!(output_mode, OutputMode::Summary) {
+            println!("✓ Updated {} ({})", prefix.display(), summary);
         }
 
-        if let Some(pb) = &progress {
-            pb.set_message(format!("Resolving target prefix for {}...", env_name));
+        result
+    }
+
+    async fn update_discovered_environment(
+        &self,
+        environment: &DiscoveredEnvironment,
+        packages: &[String],
+        strict: bool,
+        output_mode: OutputMode,
+    ) -> Result<()> {
+        if Self::helper_package_manager(environment).is_none() {
+            return self
+                .update_packages_by_prefix_natively(
+                    &environment.prefix,
+                    packages,
+                    strict,
+                    output_mode,
+                )
+                .await;
         }
-        let target_prefix = self.target_prefix_for_env_name(env_name)?;
-        let _prefix_lock = Self::acquire_prefix_lock(&target_prefix, LockOperation::Create).await?;
-        StagedPrefix::recover(&target_prefix)?;
-        let conflicting_environments =
-            Self::prioritize_named_records(env_name, self.accessible_environment_records().await?)
-                .into_iter()
-                .filter(|environment| environment.prefix != target_prefix)
-                .collect::<Vec<DiscoveredEnvironment>>();
 
-        let native_owned_conflicts = conflicting_environments
-            .iter()
-            .filter(|environment| Self::has_native_rattler_conflict(environment))
-            .collect::<Vec<&DiscoveredEnvironment>>();
-        if !native_owned_conflicts.is_empty() {
+        let installed = Self::collect_installed_prefix_records(&environment.prefix)?;
+        let selected = if packages.is_empty() {
+            Vec::new()
+        } else {
+            let selected =
+                Self::update_selection(&installed, packages, strict, &environment.prefix)?;
+            if selected.is_empty() {
+                return Ok(());
+            }
+            selected
+        };
+        let manager = self.helper_manager_for_environment(environment).await?;
+        manager
+            .update_packages_by_prefix(&environment.prefix, &selected, output_mode)
+            .await
+    }
+
+    async fn rollback_prefix_natively(
+        &self,
+        prefix: &Path,
+        revision: u32,
+        output_mode: OutputMode,
+    ) -> Result<()> {
+        if !Self::is_environment_prefix(prefix) {
             return Err(EnvError::Execution(format!(
-                "Environment '{}' already exists in other native rattler-owned prefixes: {}. Use --prefix to disambiguate.",
-                env_name,
-                native_owned_conflicts
-                    .iter()
-                    .map(|environment| environment.prefix.display().to_string())
-                    .collect::<Vec<String>>()
-                    .join(", ")
+                "Environment prefix is not a valid conda-style environment: {}",
+                prefix.display()
             )));
         }
 
-        if !conflicting_environments.is_empty() {
-            if !force {
-                return Err(EnvError::Execution(format!(
-                    "Environment '{}' already exists in other tool-managed or adopted prefixes: {}. Re-run with --force to remove them via their original package manager before recreating with rattler.",
-                    env_name,
-                    conflicting_environments
-                        .iter()
-                        .map(|environment| environment.prefix.display().to_string())
-                        .collect::<Vec<String>>()
-                        .join(", ")
-                )));
+        let target_revision = read_revision(prefix, revision)?;
+        let installed = Self::collect_installed_prefix_records(prefix)?;
+        let changes = diff_package_records(
+            installed.iter().map(|record| &record.repodata_record),
+            &target_revision.records,
+        );
+        if changes.is_empty() {
+            if matches!(output_mode, OutputMode::Summary | OutputMode::Stream) {
+                println!(
+                    "✓ {} already matches revision {}",
+                    prefix.display(),
+                    revision
+                );
             }
+            return Ok(());
+        }
 
-            for environment in &conflicting_environments {
-                if let Some(pb) = &progress {
-                    pb.set_message(format!(
-                        "Removing conflicting {} environment '{}'...",
-                        Self::removable_conflict_manager_label(environment)
-                            .unwrap_or_else(|| "rattler".to_string()),
-                        env_name
-                    ));
-                }
-                if matches!(output_mode, OutputMode::Stream) {
-                    println!(
-                        "Removing conflicting {} environment '{}' at {} before rattler create...",
-                        Self::removable_conflict_manager_label(environment)
-                            .unwrap_or_else(|| "rattler".to_string()),
-                        env_name,
-                        environment.prefix.display()
-                    );
-                }
-                self.remove_conflicting_environment(environment, output_mode)
-                    .await?;
+        let summary = summarize_package_changes(&changes);
+        if matches!(output_mode, OutputMode::Summary | OutputMode::Stream) {
+            println!(
+                "Rollback plan for {} to revision {} ({}):",
+                prefix.display(),
+                revision,
+                summary
+            );
+            for change in &changes {
+                println!("  {}", change);
             }
         }
 
-        if target_prefix.exists() {
-            let metadata = fs::symlink_metadata(&target_prefix).map_err(|error| {
-                io_error(
-                    "Failed to inspect existing environment",
-                    &target_prefix,
-                    error,
-                )
-            })?;
-            if metadata.file_type().is_symlink() || !metadata.is_dir() {
-                return Err(EnvError::PermissionDenied(format!(
-                    "Refusing to replace non-directory or symlink environment target: {}",
-                    target_prefix.display()
-                )));
-            }
-            if !Self::is_environment_prefix(&target_prefix) {
-                return Err(EnvError::Execution(format!(
-                    "Failed to create environment: Non-conda folder exists at prefix {}",
-                    target_prefix.display()
-                )));
-            }
-            if !force {
-                return Err(EnvError::Execution(format!(
-                    "Environment {} already exists. Re-run with --force to replace it.",
-                    env_name
-                )));
+        let requested_specs = Self::parse_match_specs(&target_revision.requested_specs)?;
+        let _cache_lock = self.acquire_cache_lock(LockOperation::CacheUse).await?;
+        let progress = if matches!(output_mode, OutputMode::Summary) {
+            Some(Self::summary_spinner(format!(
+                "Restoring revision {} of {}...",
+                revision,
+                prefix.display()
+            ))?)
+        } else {
+            None
+        };
+        let result = self
+            .apply_solved_records_to_prefix(
+                prefix,
+                installed,
+                requested_specs,
+                target_revision.records,
+                RevisionOperation::Rollback,
+                None,
+                output_mode,
+            )
+            .await;
+
+        if let Some(pb) = progress {
+            match &result {
+                Ok(()) => pb.finish_and_clear(),
+                Err(error) => pb.abandon_with_message(format!(
+                    "✗ Failed rollback of {}: {}",
+                    prefix.display(),
+                    error
+                )),
             }
         }
 
-        if let Some(pb) = &progress {
-            pb.set_message(format!("Solving environment {} with rattler...", env_name));
-        }
-        if matches!(output_mode, OutputMode::Stream) {
-            println!("Solving environment {} with rattler...", env_name);
+        if result.is_ok() && matches!(output_mode, OutputMode::Summary) {
+            println!(
+                "✓ Rolled back {} to revision {} ({})",
+                prefix.display(),
+                revision,
+                summary
+            );
         }
-        let _cache_lock = self.acquire_cache_lock(LockOperation::CacheUse).await?;
-        let (requested_specs, solved_records) =
-            self.solve_environment(yaml_file, &environment_yaml).await?;
 
-        if let Some(pb) = &progress {
-            pb.set_message(format!(
-                "Installing {} solved packages into {}...",
-                solved_records.len(),
-                target_prefix.display()
-            ));
+        result
+    }
+
+    /// Solves `yaml_file` preferring the installed builds, so that only packages the
+    /// edited YAML actually affects are changed, removed or added.
+    async fn sync_prefix_natively(
+        &self,
+        prefix: &Path,
+        yaml_file: &Path,
+        output_mode: OutputMode,
+    ) -> Result<()> {
+        if !Self::is_environment_prefix(prefix) {
+            return Err(EnvError::Execution(format!(
+                "Environment prefix is not a valid conda-style environment: {}",
+                prefix.display()
+            )));
+        }
+        let environment_yaml = Self::parse_environment_yaml(yaml_file)?;
+        let issues = Self::environment_issues(&environment_yaml);
+        if !issues.is_empty() {
+            return Err(EnvError::Validation(format!(
+                "Invalid environment file {}: {}",
+                yaml_file.display(),
+                issues.join("; ")
+            )));
         }
+
+        let installed = Self::collect_installed_prefix_records(prefix)?;
+        let _cache_lock = self.acquire_cache_lock(LockOperation::CacheUse).await?;
+        let progress = if matches!(output_mode, OutputMode::Summary) {
+            Some(Self::summary_spinner(format!(
+                "Solving {} for {}...",
+                yaml_file.display(),
+                prefix.display()
+            ))?)
+        } else {
+            None
+        };
         if matches!(output_mode, OutputMode::Stream) {
             println!(
-                "Installing {} solved packages into {}...",
-                solved_records.len(),
-                target_prefix.display()
+                "Solving {} for {} with rattler...",
+                yaml_file.display(),
+                prefix.display()
             );
         }
 
-        let cache_root = Self::cache_root_dir()?;
-        let staged_prefix = StagedPrefix::prepare(&target_prefix)?;
-        let staging_path = staged_prefix.path().to_path_buf();
-        let install_result = Installer::new()
-            .with_package_cache(PackageCache::new(Self::package_cache_dir(&cache_root)))
-            .with_requested_specs(requested_specs)
-            .with_alternative_target_prefix(&target_prefix)
-            .install(&staging_path, solved_records)
-            .await
-            .map(|_| ())
-            .map_err(|error| {
-                EnvError::Execution(format!(
-                    "Failed to install solved packages into staging prefix {}: {}",
-                    staging_path.display(),
-                    error
-                ))
-            })
-            .and_then(|()| write_rattler_ownership_record(&staging_path, None).map(|_| ()))
-            .and_then(|()| {
-                validate_staged_prefix_for_publication(&staging_path, &target_prefix).map(|_| ())
-            })
-            .and_then(|()| staged_prefix.commit());
+        let requested_specs = Self::conda_specs(&environment_yaml);
+        let locked_packages = installed
+            .iter()
+            .map(|record| record.repodata_record.clone())
+            .collect::<Vec<RepoDataRecord>>();
+        let channel_priority = self.resolve_channel_priority(Some(yaml_file), Some(prefix))?;
+        let mut metadata = read_environment_metadata(prefix)?;
+        let solved = async {
+            // The channels recorded at creation win over the YAML's, so editing the
+            // YAML's channels does not silently move the environment elsewhere.
+            let channels = if !self.channels.is_empty() || !metadata.channels.is_empty() {
+                Self::resolve_channels_for_prefix(
+                    prefix,
+                    self.prefix_channels(prefix, &installed, &[])?,
+                )?
+            } else {
+                let channels = Self::resolve_channels(yaml_file, &environment_yaml)?;
+                metadata.channels = Self::channel_urls(&channels);
+                channels
+            };
+            let repo_data_sets = self.fetch_repodata(channels, &requested_specs).await?;
+            Self::solve_with_repodata(
+                requested_specs.clone(),
+                &repo_data_sets,
+                &locked_packages,
+                channel_priority,
+            )
+        }
+        .await;
+        if let Some(pb) = &progress {
+            pb.finish_and_clear();
+        }
+        let solved_records = solved?;
 
-        match install_result {
-            Ok(()) => {
-                if let Some(pb) = progress {
-                    pb.finish_and_clear();
-                }
-                if matches!(output_mode, OutputMode::Summary) {
-                    println!("✓ Environment {} created", env_name);
-                }
-                Ok(())
+        metadata.yaml_source = Some(YamlSource::from_file(yaml_file)?);
+        metadata.channel_priority = Some(channel_priority);
+        let changes = diff_package_records(
+            installed.iter().map(|record| &record.repodata_record),
+            &solved_records,
+        );
+        if changes.is_empty() {
+            write_environment_metadata(prefix, &metadata)?;
+            if matches!(output_mode, OutputMode::Summary | OutputMode::Stream) {
+                println!(
+                    "✓ {} is already in sync with {}",
+                    prefix.display(),
+                    yaml_file.display()
+                );
             }
-            Err(error) => {
-                if let Some(pb) = progress {
-                    pb.abandon_with_message(format!(
-                        "✗ Failed to create environment {}: {}",
-                        env_name, error
-                    ));
-                }
-                Err(error)
+            return Ok(());
+        }
+
+        let summary = summarize_package_changes(&changes);
+        if matches!(output_mode, OutputMode::Summary | OutputMode::Stream) {
+            println!(
+                "Sync plan for {} from {} ({}):",
+                prefix.display(),
+                yaml_file.display(),
+                summary
+            );
+            for change in &changes {
+                println!("  {}", change);
+            }
+        }
+
+        let progress = if matches!(output_mode, OutputMode::Summary) {
+            Some(Self::summary_spinner(format!(
+                "Applying {} package changes to {}...",
+                changes.len(),
+                prefix.display()
+            ))?)
+        } else {
+            None
+        };
+        let result = self
+            .apply_solved_records_to_prefix(
+                prefix,
+                installed,
+                requested_specs,
+                solved_records,
+                RevisionOperation::Sync,
+                Some(&metadata),
+                output_mode,
+            )
+            .await;
+
+        if let Some(pb) = progress {
+            match &result {
+                Ok(()) => pb.finish_and_clear(),
+                Err(error) => pb.abandon_with_message(format!(
+                    "✗ Failed to sync {}: {}",
+                    prefix.display(),
+                    error
+                )),
             }
         }
+
+        if result.is_ok() && matches!(output_mode, OutputMode::Summary) {
+            println!(
+                "✓ Synced {} with {} ({})",
+                prefix.display(),
+                yaml_file.display(),
+                summary
+            );
+        }
+
+        result
     }
 
-    async fn validate_yaml(&self, yaml_file: &Path) -> Result<ValidationResult> {
-        let environment_yaml = Self::parse_environment_yaml(yaml_file)?;
-        let issues = Self::environment_issues(&environment_yaml);
-        let syntax_valid = issues.is_empty();
-        let estimated_packages = environment_yaml.dependencies.len();
+    /// Re-links the packages whose files fail verification from a package cache that
+    /// validates every entry, so cache entries sharing the tampered inodes are extracted
+    /// again and entries removed by `enva cache clean` are downloaded again.
+    async fn repair_prefix_natively(
+        &self,
+        prefix: &Path,
+        output_mode: OutputMode,
+    ) -> Result<PrefixVerification> {
+        if !Self::is_environment_prefix(prefix) {
+            return Err(EnvError::Execution(format!(
+                "Environment prefix is not a valid conda-style environment: {}",
+                prefix.display()
+            )));
+        }
 
-        Ok(ValidationResult {
-            dry_run: true,
-            environment: environment_yaml
-                .name
-                .clone()
-                .unwrap_or_else(|| "unknown".to_string()),
-            yaml_file: yaml_file.to_path_buf(),
-            validation: ValidationDetails {
-                syntax_valid,
-                dependencies_resolvable: issues.is_empty(),
-                version_conflicts: issues,
-                channels_accessible: !environment_yaml.channels.is_empty(),
-            },
-            estimated_packages,
-            estimated_size_mb: (estimated_packages as u64) * 10,
-            channels_accessible: Self::extract_string_list(&environment_yaml),
-        })
+        let installed = Self::collect_installed_prefix_records(prefix)?;
+        let verification = verify_prefix(prefix, &installed)?;
+        if verification.damaged_packages.is_empty() {
+            if matches!(output_mode, OutputMode::Summary | OutputMode::Stream) {
+                println!(
+                    "✓ {}: {} packages verified, nothing to repair",
+                    prefix.display(),
+                    verification.packages_checked
+                );
+            }
+            return Ok(verification);
+        }
+
+        let damaged = verification
+            .damaged_packages
+            .iter()
+            .map(|package| PackageName::new_unchecked(package.name.as_str()))
+            .collect::<HashSet<PackageName>>();
+        if matches!(output_mode, OutputMode::Summary | OutputMode::Stream) {
+            println!(
+                "Repair plan for {} ({} damaged packages):",
+                prefix.display(),
+                damaged.len()
+            );
+            for package in &verification.damaged_packages {
+                println!(
+                    "  ~ {} {} ({}): {} missing, {} modified",
+                    package.name,
+                    package.version,
+                    package.build,
+                    package.missing.len(),
+                    package.modified.len()
+                );
+            }
+        }
+
+        let requested_specs = Self::parse_match_specs(
+            &Self::requested_spec_strings_from_prefix_records(&installed),
+        )?;
+        let records = installed
+            .iter()
+            .map(|record| record.repodata_record.clone())
+            .collect::<Vec<RepoDataRecord>>();
+        let ownership_record = read_ownership_record(prefix)?;
+        let cache_root = Self::cache_root_dir()?;
+        let _cache_lock = self.acquire_cache_lock(LockOperation::CacheUse).await?;
+        let progress = if matches!(output_mode, OutputMode::Summary) {
+            Some(Self::summary_spinner(format!(
+                "Re-linking {} packages into {}...",
+                damaged.len(),
+                prefix.display()
+            ))?)
+        } else {
+            None
+        };
+
+        let staged_prefix = StagedPrefix::prepare(prefix)?;
+        let staging_path = staged_prefix.path().to_path_buf();
+        let result = async {
+            clone_prefix_for_staging(prefix, &staging_path)?;
+            Self::remove_ownership_marker_before_installation(&staging_path)?;
+            Installer::new()
+                .with_download_client(download_client())
+                .with_package_cache(PackageCache::new_layered(
+                    [Self::package_cache_dir(&cache_root)],
+                    false,
+                    ValidationMode::Full,
+                ))
+                .with_installed_packages(installed)
+                .with_reinstall_packages(damaged)
+                .with_requested_specs(requested_specs)
+                .with_alternative_target_prefix(prefix)
+                .install(&staging_path, records)
+                .await
+                .map_err(|error| {
+                    EnvError::Execution(format!(
+                        "Failed to re-link damaged packages into staging prefix {}: {}",
+                        staging_path.display(),
+                        error
+                    ))
+                })?;
+
+            let repaired = verify_prefix(
+                &staging_path,
+                &Self::collect_installed_prefix_records(&staging_path)?,
+            )?;
+            if !repaired.damaged_packages.is_empty() {
+                return Err(EnvError::Execution(format!(
+                    "Packages are still damaged after re-linking them into {}: {}",
+                    staging_path.display(),
+                    repaired
+                        .damaged_packages
+                        .iter()
+                        .map(|package| package.name.as_str())
+                        .collect::<Vec<&str>>()
+                        .join(", ")
+                )));
+            }
+
+            let adopted_from: Option<&str> = ownership_record
+                .as_ref()
+                .and_then(|record| record.adopted_from.as_deref());
+            write_rattler_ownership_record(&staging_path, adopted_from)?;
+            validate_staged_prefix_for_publication(&staging_path, prefix)?;
+            staged_prefix.commit()
+        }
+        .await;
+
+        if let Some(pb) = progress {
+            match &result {
+                Ok(()) => pb.finish_and_clear(),
+                Err(error) => pb.abandon_with_message(format!(
+                    "✗ Failed to repair {}: {}",
+                    prefix.display(),
+                    error
+                )),
+            }
+        }
+        result?;
+
+        if matches!(output_mode, OutputMode::Summary) {
+            println!(
+                "✓ Repaired {} packages in {}",
+                verification.damaged_packages.len(),
+                prefix.display()
+            );
+        }
+        if !verification.extra_files.is_empty()
+            && matches!(output_mode, OutputMode::Summary | OutputMode::Stream)
+        {
+            println!(
+                "  {} unowned files were left in place; list them with `enva verify`",
+                verification.extra_files.len()
+            );
+        }
+
+        Ok(verification)
     }
 
-    async fn environment_exists(&self, env_name: &str) -> Result<bool> {
-        Ok(!self.find_environment_prefixes(env_name).await?.is_empty())
+    /// Copies `source` into a staging prefix for `target`, rewrites the files that embed
+    /// the source path and publishes the copy with a fresh ownership record.
+    fn clone_prefix_natively(
+        source: &Path,
+        target: &Path,
+        adopted_from: Option<&str>,
+        output_mode: OutputMode,
+    ) -> Result<PrefixRelocation> {
+        if !Self::is_environment_prefix(source) {
+            return Err(EnvError::Execution(format!(
+                "Environment prefix is not a valid conda-style environment: {}",
+                source.display()
+            )));
+        }
+
+        let staged_prefix = StagedPrefix::prepare(target)?;
+        let staging_path = staged_prefix.path().to_path_buf();
+        let clone_result = clone_prefix_for_staging(source, &staging_path)?;
+        Self::remove_ownership_marker_before_installation(&staging_path)?;
+        if matches!(output_mode, OutputMode::Stream) {
+            println!(
+                "Cloned {} files ({} bytes, {} hard links) into staging in {} ms",
+                clone_result.files_copied,
+                clone_result.bytes_copied,
+                clone_result.hard_links_preserved,
+                clone_result.elapsed_millis
+            );
+        }
+
+        let relocation = relocate_prefix_records(
+            &staging_path,
+            Self::collect_installed_prefix_records(&staging_path)?,
+            source,
+            target,
+        )?;
+        if matches!(output_mode, OutputMode::Stream) {
+            println!(
+                "Rewrote {} files of {} packages from {} to {}",
+                relocation.files_rewritten,
+                relocation.records_updated,
+                source.display(),
+                target.display()
+            );
+        }
+        write_rattler_ownership_record(&staging_path, adopted_from)?;
+        validate_staged_prefix_for_publication(&staging_path, target)?;
+        staged_prefix.commit()?;
+        Ok(relocation)
     }
 
-    async fn install_packages(
+    /// Publishes a relocated copy of a rattler-owned environment at `destination` and then
+    /// removes the original, holding the locks of both prefixes throughout.
+    async fn relocate_resolved_environment(
         &self,
-        env_name: &str,
-        packages: &[String],
+        environment: DiscoveredEnvironment,
+        destination: PathBuf,
         output_mode: OutputMode,
-    ) -> Result<()> {
-        let mut environment = self.resolve_unique_record_by_name(env_name).await?;
-        let _prefix_lock =
-            Self::acquire_prefix_lock(&environment.prefix, LockOperation::Install).await?;
-        StagedPrefix::recover(&environment.prefix)?;
+    ) -> Result<PathBuf> {
+        let prefix = environment.prefix.clone();
+        if self.root_prefixes.iter().any(|root| root == &prefix) {
+            return Err(EnvError::Execution(
+                "Refusing to move the rattler base environment".to_string(),
+            ));
+        }
+        if destination == prefix {
+            return Err(EnvError::Validation(format!(
+                "Environment is already at {}",
+                prefix.display()
+            )));
+        }
+        if std::env::var_os("CONDA_PREFIX").is_some_and(|active| Path::new(&active) == prefix) {
+            return Err(EnvError::Execution(format!(
+                "Refusing to move the active environment {}; deactivate it first",
+                prefix.display()
+            )));
+        }
+
+        let _prefix_locks = Self::acquire_prefix_lock_pair(
+            (&prefix, LockOperation::Relocate),
+            (&destination, LockOperation::Relocate),
+        )
+        .await?;
+        StagedPrefix::recover(&prefix)?;
+        StagedPrefix::recover(&destination)?;
         if !environment.rattler_managed() {
-            environment = self
-                .adopt_discovered_environment(&environment, output_mode)
-                .await?;
+            return Err(EnvError::Execution(format!(
+                "Cannot move {}: only rattler-owned environments can be moved; adopt it first with `enva adopt`",
+                prefix.display()
+            )));
+        }
+        if let Some(package_manager) = Self::helper_package_manager(&environment) {
+            return Err(EnvError::Execution(format!(
+                "Cannot move {}: its packages are managed by {}, which would lose track of it",
+                prefix.display(),
+                package_manager
+            )));
+        }
+        if fs::symlink_metadata(&destination).is_ok() {
+            return Err(EnvError::Execution(format!(
+                "Cannot move {}: {} already exists",
+                prefix.display(),
+                destination.display()
+            )));
         }
 
-        if Self::helper_package_manager(&environment).is_none() {
-            return self
-                .install_packages_by_prefix_natively(&environment.prefix, packages, output_mode)
-                .await;
+        let adopted_from = read_ownership_record(&prefix)?.and_then(|record| record.adopted_from);
+        let progress = if matches!(output_mode, OutputMode::Summary) {
+            Some(Self::summary_spinner(format!(
+                "Moving {} to {}...",
+                prefix.display(),
+                destination.display()
+            ))?)
+        } else {
+            None
+        };
+        let result = Self::clone_prefix_natively(
+            &prefix,
+            &destination,
+            adopted_from.as_deref(),
+            output_mode,
+        );
+        if let Some(pb) = progress {
+            match &result {
+                Ok(_) => pb.finish_and_clear(),
+                Err(error) => pb.abandon_with_message(format!(
+                    "✗ Failed to move {}: {}",
+                    prefix.display(),
+                    error
+                )),
+            }
+        }
+        let relocation = result?;
+
+        async_fs::remove_dir_all(&prefix).await.map_err(|error| {
+            EnvError::FileOperation(format!(
+                "Moved {} to {} but failed to remove the original: {}",
+                prefix.display(),
+                destination.display(),
+                error
+            ))
+        })?;
+        if let Some(registry) = &self.environment_registry {
+            replace_registered_prefix(registry, &prefix, &destination)?;
         }
 
-        let manager = self.helper_manager_for_environment(&environment).await?;
-        manager
-            .install_packages_by_prefix(&environment.prefix, packages, output_mode)
-            .await
+        if matches!(output_mode, OutputMode::Summary | OutputMode::Stream) {
+            println!(
+                "✓ Moved {} to {} ({} files relocated)",
+                prefix.display(),
+                destination.display(),
+                relocation.files_rewritten
+            );
+        }
+        Ok(destination)
+    }
+
+    /// Extracts `archive` into a staging prefix for `target`, rewrites the files that embed
+    /// the prefix it was packed from and publishes it with a fresh ownership record.
+    fn unpack_prefix_natively(
+        archive: &Path,
+        target: &Path,
+        output_mode: OutputMode,
+    ) -> Result<(PackManifest, PrefixRelocation)> {
+        let staged_prefix = StagedPrefix::prepare(target)?;
+        let staging_path = staged_prefix.path().to_path_buf();
+        let manifest = extract_pack_archive(archive, &staging_path, target)?;
+        if !Self::is_environment_prefix(&staging_path) {
+            return Err(EnvError::Validation(format!(
+                "Archive {} does not contain a conda-style environment",
+                archive.display()
+            )));
+        }
+        Self::remove_ownership_marker_before_installation(&staging_path)?;
+        if matches!(output_mode, OutputMode::Stream) {
+            println!(
+                "Extracted {} packages packed from {} into staging",
+                manifest.packages.len(),
+                manifest.original_prefix.display()
+            );
+        }
+
+        let relocation = relocate_prefix_records(
+            &staging_path,
+            Self::collect_installed_prefix_records(&staging_path)?,
+            &manifest.original_prefix,
+            target,
+        )?;
+        if matches!(output_mode, OutputMode::Stream) {
+            println!(
+                "Rewrote {} files of {} packages from {} to {}",
+                relocation.files_rewritten,
+                relocation.records_updated,
+                manifest.original_prefix.display(),
+                target.display()
+            );
+        }
+        write_rattler_ownership_record(&staging_path, None)?;
+        validate_staged_prefix_for_publication(&staging_path, target)?;
+        staged_prefix.commit()?;
+        Ok((manifest, relocation))
+    }
+
+    /// Resolves and locks the prefix `enva unpack` publishes into, checking that it may
+    /// receive a new environment.
+    async fn prepare_unpack_target(
+        &self,
+        destination: &EnvironmentTarget,
+        force: bool,
+        output_mode: OutputMode,
+        progress: Option<&ProgressBar>,
+    ) -> Result<(PathBuf, OperationLock)> {
+        let prefix = match destination {
+            EnvironmentTarget::Name(env_name) => {
+                return self
+                    .prepare_create_target(env_name, force, output_mode, progress)
+                    .await
+            }
+            EnvironmentTarget::Prefix(prefix) => prefix,
+        };
+
+        if let Some(parent) = prefix.parent().filter(|_| prefix.is_absolute()) {
+            fs::create_dir_all(parent)
+                .map_err(|error| io_error("Failed to create directory", parent, error))?;
+        }
+        let target_prefix = Self::canonical_or_absolute(prefix)?;
+        let prefix_lock = Self::acquire_prefix_lock(&target_prefix, LockOperation::Create).await?;
+        StagedPrefix::recover(&target_prefix)?;
+        if let Ok(metadata) = fs::symlink_metadata(&target_prefix) {
+            if metadata.file_type().is_symlink() || !metadata.is_dir() {
+                return Err(EnvError::PermissionDenied(format!(
+                    "Refusing to replace non-directory or symlink environment target: {}",
+                    target_prefix.display()
+                )));
+            }
+            if !Self::is_environment_prefix(&target_prefix) {
+                return Err(EnvError::Execution(format!(
+                    "Failed to unpack environment: Non-conda folder exists at prefix {}",
+                    target_prefix.display()
+                )));
+            }
+            if !force {
+                return Err(EnvError::Execution(format!(
+                    "Environment {} already exists. Re-run with --force to replace it.",
+                    target_prefix.display()
+                )));
+            }
+        }
+        Ok((target_prefix, prefix_lock))
+    }
+
+    /// Installs `solved_records` into a staged clone of `prefix` and publishes it, removing
+    /// any installed package that is no longer part of the solution.
+    #[allow(clippy::too_many_arguments)]
+    async fn apply_solved_records_to_prefix(
+        &self,
+        prefix: &Path,
+        installed: Vec<PrefixRecord>,
+        requested_specs: Vec<MatchSpec>,
+        solved_records: Vec<RepoDataRecord>,
+        operation: RevisionOperation,
+        metadata: Option<&EnvironmentMetadata>,
+        output_mode: OutputMode,
+    ) -> Result<()> {
+        self.ensure_records_available_offline(&solved_records, &installed)?;
+        let ownership_record = read_ownership_record(prefix)?;
+        let cache_root = Self::cache_root_dir()?;
+        let staged_prefix = StagedPrefix::prepare(prefix)?;
+        let staging_path = staged_prefix.path().to_path_buf();
+        let clone_result = clone_prefix_for_staging(prefix, &staging_path)?;
+        Self::remove_ownership_marker_before_installation(&staging_path)?;
+        if matches!(output_mode, OutputMode::Stream) {
+            println!(
+                "Cloned {} files ({} bytes, {} hard links) into staging in {} ms",
+                clone_result.files_copied,
+                clone_result.bytes_copied,
+                clone_result.hard_links_preserved,
+                clone_result.elapsed_millis
+            );
+        }
+        if !has_revisions(&staging_path)? && !installed.is_empty() {
+            record_revision(
+                &staging_path,
+                RevisionOperation::Baseline,
+                Self::requested_spec_strings_from_prefix_records(&installed),
+                installed
+                    .iter()
+                    .map(|record| record.repodata_record.clone())
+                    .collect(),
+            )?;
+        }
+
+        Installer::new()
+            .with_download_client(download_client())
+            .with_package_cache(PackageCache::new(Self::package_cache_dir(&cache_root)))
+            .with_installed_packages(installed)
+            .with_requested_specs(requested_specs.clone())
+            .with_alternative_target_prefix(prefix)
+            .install(&staging_path, solved_records)
+            .await
+            .map(|_| ())
+            .map_err(|error| {
+                EnvError::Execution(format!(
+                    "Failed to install solved packages into staging prefix {}: {}",
+                    staging_path.display(),
+                    error
+                ))
+            })
+            .and_then(|()| Self::record_staged_revision(&staging_path, operation, &requested_specs))
+            .and_then(|()| match metadata {
+                Some(metadata) => write_environment_metadata(&staging_path, metadata),
+                None => Ok(()),
+            })
+            .and_then(|()| {
+                let adopted_from: Option<&str> = ownership_record
+                    .as_ref()
+                    .and_then(|record| record.adopted_from.as_deref());
+                write_rattler_ownership_record(&staging_path, adopted_from).map(|_| ())
+            })
+            .and_then(|()| {
+                validate_staged_prefix_for_publication(&staging_path, prefix).map(|_| ())
+            })
+            .and_then(|()| staged_prefix.commit())
+    }
+
+    async fn prepare_create_target(
+        &self,
+        env_name: &str,
+        force: bool,
+        output_mode: OutputMode,
+        progress: Option<&ProgressBar>,
+    ) -> Result<(PathBuf, OperationLock)> {
+        if let Some(pb) = progress {
+            pb.set_message(format!("Resolving target prefix for {}...", env_name));
+        }
+        let target_prefix = self.target_prefix_for_env_name(env_name)?;
+        let prefix_lock = Self::acquire_prefix_lock(&target_prefix, LockOperation::Create).await?;
+        self.prepare_locked_create_target(env_name, &target_prefix, force, output_mode, progress)
+            .await?;
+        Ok((target_prefix, prefix_lock))
+    }
+
+    /// Acquires the operation locks of two prefixes in path order, so that operations
+    /// touching the same pair from opposite ends cannot deadlock.
+    async fn acquire_prefix_lock_pair(
+        first: (&Path, LockOperation),
+        second: (&Path, LockOperation),
+    ) -> Result<(OperationLock, OperationLock)> {
+        if first.0 <= second.0 {
+            let first_lock = Self::acquire_prefix_lock(first.0, first.1).await?;
+            let second_lock = Self::acquire_prefix_lock(second.0, second.1).await?;
+            Ok((first_lock, second_lock))
+        } else {
+            let second_lock = Self::acquire_prefix_lock(second.0, second.1).await?;
+            let first_lock = Self::acquire_prefix_lock(first.0, first.1).await?;
+            Ok((first_lock, second_lock))
+        }
+    }
+
+    /// Checks that `target_prefix` may receive a new environment named `env_name`; the
+    /// caller must already hold its operation lock.
+    async fn prepare_locked_create_target(
+        &self,
+        env_name: &str,
+        target_prefix: &Path,
+        force: bool,
+        output_mode: OutputMode,
+        progress: Option<&ProgressBar>,
+    ) -> Result<()> {
+        let target_prefix = target_prefix.to_path_buf();
+        StagedPrefix::recover(&target_prefix)?;
+        let conflicting_environments =
+            Self::prioritize_named_records(env_name, self.accessible_environment_records().await?)
+                .into_iter()
+                .filter(|environment| environment.prefix != target_prefix)
+                .collect::<Vec<DiscoveredEnvironment>>();
+
+        let native_owned_conflicts = conflicting_environments
+            .iter()
+            .filter(|environment| Self::has_native_rattler_conflict(environment))
+            .collect::<Vec<&DiscoveredEnvironment>>();
+        if !native_owned_conflicts.is_empty() {
+            return Err(EnvError::Execution(format!(
+                "Environment '{}' already exists in other native rattler-owned prefixes: {}. Use --prefix to disambiguate.",
+                env_name,
+                native_owned_conflicts
+                    .iter()
+                    .map(|environment| environment.prefix.display().to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            )));
+        }
+
+        if !conflicting_environments.is_empty() {
+            if !force {
+                return Err(EnvError::Execution(format!(
+                    "Environment '{}' already exists in other tool-managed or adopted prefixes: {}. Re-run with --force to remove them via their original package manager before recreating with rattler.",
+                    env_name,
+                    conflicting_environments
+                        .iter()
+                        .map(|environment| environment.prefix.display().to_string())
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                )));
+            }
+
+            for environment in &conflicting_environments {
+                if let Some(pb) = progress {
+                    pb.set_message(format!(
+                        "Removing conflicting {} environment '{}'...",
+                        Self::removable_conflict_manager_label(environment)
+                            .unwrap_or_else(|| "rattler".to_string()),
+                        env_name
+                    ));
+                }
+                if matches!(output_mode, OutputMode::Stream) {
+                    println!(
+                        "Removing conflicting {} environment '{}' at {} before rattler create...",
+                        Self::removable_conflict_manager_label(environment)
+                            .unwrap_or_else(|| "rattler".to_string()),
+                        env_name,
+                        environment.prefix.display()
+                    );
+                }
+                self.remove_conflicting_environment(environment, output_mode)
+                    .await?;
+            }
+        }
+
+        if target_prefix.exists() {
+            let metadata = fs::symlink_metadata(&target_prefix).map_err(|error| {
+                io_error(
+                    "Failed to inspect existing environment",
+                    &target_prefix,
+                    error,
+                )
+            })?;
+            if metadata.file_type().is_symlink() || !metadata.is_dir() {
+                return Err(EnvError::PermissionDenied(format!(
+                    "Refusing to replace non-directory or symlink environment target: {}",
+                    target_prefix.display()
+                )));
+            }
+            if !Self::is_environment_prefix(&target_prefix) {
+                return Err(EnvError::Execution(format!(
+                    "Failed to create environment: Non-conda folder exists at prefix {}",
+                    target_prefix.display()
+                )));
+            }
+            if !force {
+                return Err(EnvError::Execution(format!(
+                    "Environment {} already exists. Re-run with --force to replace it.",
+                    env_name
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn record_staged_revision(
+        staging_path: &Path,
+        operation: RevisionOperation,
+        requested_specs: &[MatchSpec],
+    ) -> Result<()> {
+        let records = Self::collect_installed_prefix_records(staging_path)?
+            .into_iter()
+            .map(|record| record.repodata_record)
+            .collect();
+        record_revision(
+            staging_path,
+            operation,
+            requested_specs.iter().map(ToString::to_string).collect(),
+            records,
+        )
+        .map(|_| ())
+    }
+
+    async fn install_records_into_fresh_prefix(
+        &self,
+        target_prefix: &Path,
+        requested_specs: Vec<MatchSpec>,
+        records: Vec<RepoDataRecord>,
+        metadata: Option<&EnvironmentMetadata>,
+        variables: &EnvironmentVariables,
+    ) -> Result<()> {
+        self.ensure_records_available_offline(&records, &[])?;
+        let cache_root = Self::cache_root_dir()?;
+        let staged_prefix = StagedPrefix::prepare(target_prefix)?;
+        let staging_path = staged_prefix.path().to_path_buf();
+        Installer::new()
+            .with_download_client(download_client())
+            .with_package_cache(PackageCache::new(Self::package_cache_dir(&cache_root)))
+            .with_requested_specs(requested_specs.clone())
+            .with_alternative_target_prefix(target_prefix)
+            .install(&staging_path, records)
+            .await
+            .map(|_| ())
+            .map_err(|error| {
+                EnvError::Execution(format!(
+                    "Failed to install solved packages into staging prefix {}: {}",
+                    staging_path.display(),
+                    error
+                ))
+            })
+            .and_then(|()| {
+                Self::record_staged_revision(
+                    &staging_path,
+                    RevisionOperation::Create,
+                    &requested_specs,
+                )
+            })
+            .and_then(|()| match metadata {
+                Some(metadata) => write_environment_metadata(&staging_path, metadata),
+                None => Ok(()),
+            })
+            .and_then(|()| write_environment_variables(&staging_path, variables))
+            .and_then(|()| write_rattler_ownership_record(&staging_path, None).map(|_| ()))
+            .and_then(|()| {
+                validate_staged_prefix_for_publication(&staging_path, target_prefix).map(|_| ())
+            })
+            .and_then(|()| staged_prefix.commit())
+    }
+
+    fn build_prefixed_path(&self, prefix: &Path) -> Result<OsString> {
+        let mut path_entries = Vec::new();
+        path_entries.push(prefix.join("bin"));
+
+        #[cfg(target_os = "windows")]
+        {
+            path_entries.push(prefix.join("Scripts"));
+            path_entries.push(prefix.join("Library").join("bin"));
+        }
+
+        path_entries.extend(std::env::split_paths(
+            &std::env::var_os("PATH").unwrap_or_default(),
+        ));
+
+        std::env::join_paths(path_entries).map_err(|error| {
+            EnvError::Environment(format!(
+                "Failed to construct PATH for environment {}: {}",
+                prefix.display(),
+                error
+            ))
+        })
+    }
+
+    async fn run_command_in_prefix(&self, prefix: &Path, request: &RunRequest) -> Result<()> {
+        if !Self::is_environment_prefix(prefix) {
+            return Err(EnvError::Execution(format!(
+                "Environment prefix is not a valid conda-style environment: {}",
+                prefix.display()
+            )));
+        }
+
+        let env_name = self.environment_name_for_prefix(prefix);
+        let mut cmd = build_environment_run_command(&request.command)?;
+        cmd.current_dir(&request.cwd);
+        cmd.env("PATH", self.build_prefixed_path(prefix)?);
+        cmd.env("CONDA_PREFIX", prefix);
+        cmd.env("CONDA_DEFAULT_ENV", &env_name);
+        cmd.env("CONDA_SHLVL", "1");
+        cmd.env("RATTLER_ENV_PREFIX", prefix);
+        for (name, value) in read_environment_variables(prefix)? {
+            cmd.env(name, value);
+        }
+        // `run` always behaves like a first activation, whatever the caller's level.
+        cmd.envs(activation_script_changes(
+            prefix,
+            std::env::vars().collect(),
+        )?);
+
+        for env_pair in &request.env_vars {
+            let (key, value) = env_pair.split_once('=').ok_or_else(|| {
+                EnvError::Validation(format!("Invalid environment variable format: {}", env_pair))
+            })?;
+            cmd.env(key, value);
+        }
+
+        if request.capture_output {
+            cmd.stdout(std::process::Stdio::piped())
+                .stderr(std::process::Stdio::piped());
+        } else {
+            cmd.stdout(std::process::Stdio::inherit())
+                .stderr(std::process::Stdio::inherit());
+        }
+
+        let output = if request.capture_output {
+            let output = cmd.output().await.map_err(|error| {
+                EnvError::Execution(format!("Failed to execute command: {}", error))
+            })?;
+
+            if !output.stdout.is_empty() {
+                print!("{}", String::from_utf8_lossy(&output.stdout));
+            }
+            if !output.stderr.is_empty() {
+                eprint!("{}", String::from_utf8_lossy(&output.stderr));
+            }
+
+            output
+        } else {
+            let status = cmd.status().await.map_err(|error| {
+                EnvError::Execution(format!("Failed to execute command: {}", error))
+            })?;
+            std::process::Output {
+                status,
+                stdout: Vec::new(),
+                stderr: Vec::new(),
+            }
+        };
+
+        if !output.status.success() {
+            return Err(EnvError::Execution(format!(
+                "Command failed with exit code {:?}",
+                output.status.code()
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl EnvironmentBackend for RattlerBackend {
+    fn kind(&self) -> BackendKind {
+        BackendKind::Rattler
+    }
+
+    fn capabilities(&self) -> BackendCapabilities {
+        BackendCapabilities::rattler()
+    }
+
+    async fn clean_package_cache(&self, dry_run: bool, output_mode: OutputMode) -> Result<()> {
+        let cache_root = Self::cache_root_dir()?;
+        if !dry_run {
+            let _cache_lock = self.acquire_cache_lock(LockOperation::CacheClean).await?;
+        }
+        let cache_directories = Self::cache_directory_entries(&cache_root);
+
+        if dry_run {
+            if matches!(output_mode, OutputMode::Summary | OutputMode::Stream) {
+                println!(
+                    "[DRY-RUN] Would clean rattler caches under {}",
+                    cache_root.display()
+                );
+                for (label, path) in &cache_directories {
+                    println!("  - {}: {}", label, path.display());
+                }
+            }
+            return Ok(());
+        }
+
+        if matches!(output_mode, OutputMode::Summary | OutputMode::Stream) {
+            println!("Cleaning rattler caches under {}", cache_root.display());
+        }
+
+        let mut removed = Vec::new();
+        let mut missing = Vec::new();
+        for (label, path) in cache_directories {
+            if path.exists() {
+                Self::clear_cache_directory(&path).await?;
+                removed.push((label, path));
+            } else {
+                missing.push((label, path));
+            }
+        }
+
+        if matches!(output_mode, OutputMode::Summary | OutputMode::Stream) {
+            for (label, path) in &removed {
+                println!("  ✓ Removed {} cache: {}", label, path.display());
+            }
+            if matches!(output_mode, OutputMode::Stream) {
+                for (label, path) in &missing {
+                    println!("  - No {} cache present: {}", label, path.display());
+                }
+            }
+            if matches!(output_mode, OutputMode::Summary) {
+                println!("✓ Rattler cache cleanup complete");
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn create_environment(
+        &self,
+        env_name: &str,
+        yaml_file: &Path,
+        dry_run: bool,
+        force: bool,
+        output_mode: OutputMode,
+    ) -> Result<()> {
+        self.create_environment_with_channels(env_name, yaml_file, &[], dry_run, force, output_mode)
+            .await
+    }
+
+    async fn create_environment_with_channels(
+        &self,
+        env_name: &str,
+        yaml_file: &Path,
+        channels: &[String],
+        dry_run: bool,
+        force: bool,
+        output_mode: OutputMode,
+    ) -> Result<()> {
+        let progress = if matches!(output_mode, OutputMode::Summary) {
+            Some(Self::summary_spinner(format!(
+                "Preparing environment {}...",
+                env_name
+            ))?)
+        } else {
+            None
+        };
+
+        if dry_run {
+            if let Some(pb) = &progress {
+                pb.set_message(format!("Validating YAML for {}...", env_name));
+            }
+            let validation = self.validate_yaml(yaml_file).await?;
+            if let Some(pb) = progress {
+                pb.finish_and_clear();
+            }
+            println!("{}", serde_json::to_string_pretty(&validation)?);
+            return Ok(());
+        }
+
+        if let Some(pb) = &progress {
+            pb.set_message(format!("Validating YAML for {}...", env_name));
+        }
+        let mut environment_yaml = Self::parse_environment_yaml(yaml_file)?;
+        if !channels.is_empty() {
+            environment_yaml.channels = Self::channel_overrides(channels)?;
+        }
+        let issues = Self::environment_issues(&environment_yaml);
+        if !issues.is_empty() {
+            return Err(EnvError::Validation(issues.join("; ")));
+        }
+
+        let (target_prefix, _prefix_lock) = self
+            .prepare_create_target(env_name, force, output_mode, progress.as_ref())
+            .await?;
+
+        if let Some(pb) = &progress {
+            pb.set_message(format!("Solving environment {} with rattler...", env_name));
+        }
+        if matches!(output_mode, OutputMode::Stream) {
+            println!("Solving environment {} with rattler...", env_name);
+        }
+        let _cache_lock = self.acquire_cache_lock(LockOperation::CacheUse).await?;
+        let (requested_specs, solved_records) =
+            self.solve_environment(yaml_file, &environment_yaml).await?;
+        let metadata = EnvironmentMetadata {
+            channel_priority: Some(self.resolve_channel_priority(Some(yaml_file), None)?),
+            channels: Self::channel_urls(&Self::resolve_channels(yaml_file, &environment_yaml)?),
+            channel_alias: Self::yaml_solver_settings(yaml_file)?.channel_alias,
+            ..EnvironmentMetadata::default()
+        };
+
+        let replaced = if Self::is_environment_prefix(&target_prefix) {
+            Self::collect_installed_prefix_records(&target_prefix)?
+        } else {
+            Vec::new()
+        };
+        let plan = Self::transaction_plan(
+            &target_prefix,
+            replaced.iter().map(|record| &record.repodata_record),
+            &solved_records,
+        )?;
+        if !self.approve_transaction(&plan, progress.as_ref(), output_mode)? {
+            if let Some(pb) = progress {
+                pb.finish_and_clear();
+            }
+            return Ok(());
+        }
+
+        if let Some(pb) = &progress {
+            pb.set_message(format!(
+                "Installing {} solved packages into {}...",
+                solved_records.len(),
+                target_prefix.display()
+            ));
+        }
+        if matches!(output_mode, OutputMode::Stream) {
+            println!(
+                "Installing {} solved packages into {}...",
+                solved_records.len(),
+                target_prefix.display()
+            );
+        }
+
+        let install_result = self
+            .install_records_into_fresh_prefix(
+                &target_prefix,
+                requested_specs,
+                solved_records,
+                Some(&metadata),
+                &Self::yaml_variables(&environment_yaml),
+            )
+            .await;
+
+        match install_result {
+            Ok(()) => {
+                if let Some(pb) = progress {
+                    pb.finish_and_clear();
+                }
+                if matches!(output_mode, OutputMode::Summary) {
+                    println!("✓ Environment {} created", env_name);
+                }
+                Ok(())
+            }
+            Err(error) => {
+                if let Some(pb) = progress {
+                    pb.abandon_with_message(format!(
+                        "✗ Failed to create environment {}: {}",
+                        env_name, error
+                    ));
+                }
+                Err(error)
+            }
+        }
+    }
+
+    async fn lock_environment(
+        &self,
+        yaml_file: &Path,
+        lock_file: &Path,
+        output_mode: OutputMode,
+    ) -> Result<()> {
+        let environment_yaml = Self::parse_environment_yaml(yaml_file)?;
+        let issues = Self::environment_issues(&environment_yaml);
+        if !issues.is_empty() {
+            return Err(EnvError::Validation(issues.join("; ")));
+        }
+
+        let progress = if matches!(output_mode, OutputMode::Summary) {
+            Some(Self::summary_spinner(format!(
+                "Solving {} with rattler...",
+                yaml_file.display()
+            ))?)
+        } else {
+            None
+        };
+        if matches!(output_mode, OutputMode::Stream) {
+            println!("Solving {} with rattler...", yaml_file.display());
+        }
+
+        let platform = Platform::current();
+        let result = async {
+            let _cache_lock = self.acquire_cache_lock(LockOperation::CacheUse).await?;
+            let (requested_specs, solved_records) =
+                self.solve_environment(yaml_file, &environment_yaml).await?;
+            let package_count = solved_records.len();
+            let channels = Self::resolve_channels(yaml_file, &environment_yaml)?;
+            let virtual_packages = Self::detect_virtual_packages()?
+                .iter()
+                .map(ToString::to_string)
+                .collect();
+            write_lock_file(
+                lock_file,
+                &channels,
+                platform,
+                virtual_packages,
+                &requested_specs
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<String>>(),
+                solved_records,
+            )?;
+            Ok(package_count)
+        }
+        .await;
+
+        match result {
+            Ok(package_count) => {
+                if let Some(pb) = progress {
+                    pb.finish_and_clear();
+                }
+                if matches!(output_mode, OutputMode::Summary | OutputMode::Stream) {
+                    println!(
+                        "✓ Locked {} packages for {} into {}",
+                        package_count,
+                        platform,
+                        lock_file.display()
+                    );
+                }
+                Ok(())
+            }
+            Err(error) => {
+                if let Some(pb) = progress {
+                    pb.abandon_with_message(format!(
+                        "✗ Failed to lock {}: {}",
+                        yaml_file.display(),
+                        error
+                    ));
+                }
+                Err(error)
+            }
+        }
+    }
+
+    async fn create_environment_from_lock(
+        &self,
+        env_name: &str,
+        lock_file: &Path,
+        dry_run: bool,
+        force: bool,
+        output_mode: OutputMode,
+    ) -> Result<()> {
+        let platform = Platform::current();
+        let locked = read_locked_environment(lock_file, platform)?;
+        if locked.records.is_empty() {
+            return Err(EnvError::Validation(format!(
+                "Lockfile {} has no conda packages for platform {}",
+                lock_file.display(),
+                platform
+            )));
+        }
+        let requested_specs = Self::parse_match_specs(&locked.requested_specs)?;
+
+        if dry_run {
+            println!(
+                "[DRY-RUN] Would create {} from {} with {} locked packages:",
+                env_name,
+                lock_file.display(),
+                locked.records.len()
+            );
+            if !locked.channels.is_empty() {
+                println!("[DRY-RUN] Locked channels: {}", locked.channels.join(", "));
+            }
+            for record in &locked.records {
+                println!(
+                    "  - {} {} {} ({})",
+                    record.package_record.name.as_normalized(),
+                    record.package_record.version,
+                    record.package_record.build,
+                    record.url
+                );
+            }
+            return Ok(());
+        }
+
+        let progress = if matches!(output_mode, OutputMode::Summary) {
+            Some(Self::summary_spinner(format!(
+                "Preparing environment {}...",
+                env_name
+            ))?)
+        } else {
+            None
+        };
+
+        let (target_prefix, _prefix_lock) = self
+            .prepare_create_target(env_name, force, output_mode, progress.as_ref())
+            .await?;
+
+        if let Some(pb) = &progress {
+            pb.set_message(format!(
+                "Installing {} locked packages into {}...",
+                locked.records.len(),
+                target_prefix.display()
+            ));
+        }
+        if matches!(output_mode, OutputMode::Stream) {
+            println!(
+                "Installing {} locked packages from {} into {}...",
+                locked.records.len(),
+                lock_file.display(),
+                target_prefix.display()
+            );
+        }
+
+        let _cache_lock = self.acquire_cache_lock(LockOperation::CacheUse).await?;
+        let metadata = EnvironmentMetadata {
+            channels: locked.channels,
+            ..EnvironmentMetadata::default()
+        };
+        let install_result = self
+            .install_records_into_fresh_prefix(
+                &target_prefix,
+                requested_specs,
+                locked.records,
+                Some(&metadata),
+                &EnvironmentVariables::new(),
+            )
+            .await;
+
+        match install_result {
+            Ok(()) => {
+                if let Some(pb) = progress {
+                    pb.finish_and_clear();
+                }
+                if matches!(output_mode, OutputMode::Summary) {
+                    println!(
+                        "✓ Environment {} created from {}",
+                        env_name,
+                        lock_file.display()
+                    );
+                }
+                Ok(())
+            }
+            Err(error) => {
+                if let Some(pb) = progress {
+                    pb.abandon_with_message(format!(
+                        "✗ Failed to create environment {}: {}",
+                        env_name, error
+                    ));
+                }
+                Err(error)
+            }
+        }
+    }
+
+    async fn validate_yaml(&self, yaml_file: &Path) -> Result<ValidationResult> {
+        self.validate_yaml_with_packages(yaml_file, &[]).await
+    }
+
+    async fn validate_yaml_with_packages(
+        &self,
+        yaml_file: &Path,
+        additional_packages: &[String],
+    ) -> Result<ValidationResult> {
+        let environment_yaml = Self::parse_environment_yaml(yaml_file)?;
+        let issues = Self::environment_issues(&environment_yaml);
+        let mut result = ValidationResult {
+            dry_run: true,
+            environment: environment_yaml
+                .name
+                .clone()
+                .unwrap_or_else(|| "unknown".to_string()),
+            yaml_file: yaml_file.to_path_buf(),
+            validation: ValidationDetails {
+                syntax_valid: issues.is_empty(),
+                dependencies_resolvable: false,
+                version_conflicts: issues,
+                channels_accessible: false,
+            },
+            estimated_packages: environment_yaml.dependencies.len() + additional_packages.len(),
+            estimated_size_mb: 0,
+            channels_accessible: Vec::new(),
+            solved_packages: None,
+            download_size_bytes: None,
+            install_size_bytes: None,
+            solver_message: None,
+            solve_conflict: None,
+        };
+        if !result.validation.syntax_valid {
+            return Ok(result);
+        }
+
+        let mut specs = Self::conda_specs(&environment_yaml);
+        specs.extend(Self::parse_match_specs(additional_packages)?);
+        let channels = Self::resolve_channels(yaml_file, &environment_yaml)?;
+
+        let _cache_lock = self.acquire_cache_lock(LockOperation::CacheUse).await?;
+        let repo_data_sets = match self.fetch_repodata(channels, &specs).await {
+            Ok(repo_data_sets) => repo_data_sets,
+            Err(error) => {
+                result.validation.version_conflicts.push(error.to_string());
+                return Ok(result);
+            }
+        };
+        result.channels_accessible = Self::channels_with_repodata(&repo_data_sets);
+        result.validation.channels_accessible = true;
+
+        let channel_priority = self.resolve_channel_priority(Some(yaml_file), None)?;
+        match Self::solve_with_repodata(specs, &repo_data_sets, &[], channel_priority) {
+            Ok(records) => {
+                let download_size_bytes = records
+                    .iter()
+                    .filter_map(|record| record.package_record.size)
+                    .sum::<u64>();
+                let install_size_bytes = Self::cached_install_size_bytes(&records)?;
+                let size_bytes = install_size_bytes.unwrap_or(download_size_bytes);
+
+                result.validation.dependencies_resolvable = true;
+                result.estimated_packages = records.len();
+                result.estimated_size_mb = size_bytes.div_ceil(1024 * 1024);
+                result.solved_packages = Some(records.len());
+                result.download_size_bytes = Some(download_size_bytes);
+                result.install_size_bytes = install_size_bytes;
+            }
+            Err(error) => {
+                let message = error.to_string();
+                result.validation.version_conflicts.push(message.clone());
+                result.solver_message = Some(message);
+                if let EnvError::SolveConflict(conflict) = error {
+                    result.solve_conflict = Some(*conflict);
+                }
+            }
+        }
+
+        Ok(result)
+    }
+
+    async fn environment_exists(&self, env_name: &str) -> Result<bool> {
+        Ok(!self.find_environment_prefixes(env_name).await?.is_empty())
+    }
+
+    async fn install_packages(
+        &self,
+        env_name: &str,
+        packages: &[String],
+        output_mode: OutputMode,
+    ) -> Result<()> {
+        let environment = self.resolve_unique_record_by_name(env_name).await?;
+        let _prefix_lock =
+            Self::acquire_prefix_lock(&environment.prefix, LockOperation::Install).await?;
+        StagedPrefix::recover(&environment.prefix)?;
+        let environment = self.adopt_for_transaction(environment, output_mode).await?;
+
+        if Self::helper_package_manager(&environment).is_none() {
+            return self
+                .install_packages_by_prefix_natively(&environment.prefix, packages, output_mode)
+                .await;
+        }
+
+        let manager = self.helper_manager_for_environment(&environment).await?;
+        manager
+            .install_packages_by_prefix(&environment.prefix, packages, output_mode)
+            .await
+    }
+
+    async fn uninstall_packages_for_target(
+        &self,
+        target: &EnvironmentTarget,
+        packages: &[String],
+        output_mode: OutputMode,
+    ) -> Result<()> {
+        let mut environment = self.resolve_environment_target(target).await?;
+        let _prefix_lock =
+            Self::acquire_prefix_lock(&environment.prefix, LockOperation::Uninstall).await?;
+        StagedPrefix::recover(&environment.prefix)?;
+        if !environment.rattler_managed() {
+            environment = self
+                .adopt_discovered_environment(&environment, output_mode)
+                .await?;
+        }
+
+        if Self::helper_package_manager(&environment).is_none() {
+            return self
+                .uninstall_packages_by_prefix_natively(&environment.prefix, packages, output_mode)
+                .await;
+        }
+
+        let manager = self.helper_manager_for_environment(&environment).await?;
+        manager
+            .uninstall_packages_by_prefix(&environment.prefix, packages, output_mode)
+            .await
+    }
+
+    async fn update_packages_for_target(
+        &self,
+        target: &EnvironmentTarget,
+        packages: &[String],
+        output_mode: OutputMode,
+    ) -> Result<()> {
+        let environment = self.resolve_environment_target(target).await?;
+        let _prefix_lock =
+            Self::acquire_prefix_lock(&environment.prefix, LockOperation::Update).await?;
+        StagedPrefix::recover(&environment.prefix)?;
+        let environment = self.adopt_for_transaction(environment, output_mode).await?;
+
+        self.update_discovered_environment(&environment, packages, true, output_mode)
+            .await
+    }
+
+    async fn sync_environment(
+        &self,
+        target: &EnvironmentTarget,
+        yaml_file: &Path,
+        output_mode: OutputMode,
+    ) -> Result<()> {
+        let environment = self.resolve_environment_target(target).await?;
+        let _prefix_lock =
+            Self::acquire_prefix_lock(&environment.prefix, LockOperation::Sync).await?;
+        StagedPrefix::recover(&environment.prefix)?;
+        let environment = self.adopt_for_transaction(environment, output_mode).await?;
+        if let Some(package_manager) = Self::helper_package_manager(&environment) {
+            return Err(EnvError::Execution(format!(
+                "Cannot sync {}: its packages are managed by {}; recreate it with `enva create --force` instead",
+                environment.prefix.display(),
+                package_manager
+            )));
+        }
+
+        self.sync_prefix_natively(&environment.prefix, yaml_file, output_mode)
+            .await
+    }
+
+    async fn update_all_environments(
+        &self,
+        packages: &[String],
+        output_mode: OutputMode,
+    ) -> Result<()> {
+        let environments = self
+            .owned_environment_records()?
+            .into_iter()
+            .filter(DiscoveredEnvironment::rattler_managed)
+            .collect::<Vec<DiscoveredEnvironment>>();
+        if environments.is_empty() {
+            if matches!(output_mode, OutputMode::Summary | OutputMode::Stream) {
+                println!("No rattler-owned environments to update");
+            }
+            return Ok(());
+        }
+
+        let mut failures = Vec::new();
+        for environment in &environments {
+            if matches!(output_mode, OutputMode::Summary | OutputMode::Stream) {
+                println!(
+                    "==> {} ({})",
+                    environment.name,
+                    environment.prefix.display()
+                );
+            }
+            let result = async {
+                let _prefix_lock =
+                    Self::acquire_prefix_lock(&environment.prefix, LockOperation::Update).await?;
+                StagedPrefix::recover(&environment.prefix)?;
+                self.update_discovered_environment(environment, packages, false, output_mode)
+                    .await
+            }
+            .await;
+            if let Err(error) = result {
+                if matches!(output_mode, OutputMode::Summary | OutputMode::Stream) {
+                    eprintln!("✗ Failed to update {}: {}", environment.name, error);
+                }
+                failures.push(format!("{}: {}", environment.name, error));
+            }
+        }
+
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(EnvError::Execution(format!(
+                "Failed to update {} of {} environments: {}",
+                failures.len(),
+                environments.len(),
+                failures.join("; ")
+            )))
+        }
+    }
+
+    async fn environment_history(
+        &self,
+        target: &EnvironmentTarget,
+    ) -> Result<Vec<EnvironmentRevision>> {
+        let environment = self.resolve_environment_target(target).await?;
+        let _prefix_lock =
+            Self::acquire_prefix_lock(&environment.prefix, LockOperation::History).await?;
+        StagedPrefix::recover(&environment.prefix)?;
+        list_revisions(&environment.prefix)
+    }
+
+    async fn installed_package_records(
+        &self,
+        target: &EnvironmentTarget,
+    ) -> Result<Vec<RepoDataRecord>> {
+        let environment = self.resolve_environment_target(target).await?;
+        let _prefix_lock =
+            Self::acquire_prefix_lock(&environment.prefix, LockOperation::Diff).await?;
+        StagedPrefix::recover(&environment.prefix)?;
+        if !Self::is_environment_prefix(&environment.prefix) {
+            return Err(EnvError::Execution(format!(
+                "Environment prefix is not a valid conda-style environment: {}",
+                environment.prefix.display()
+            )));
+        }
+
+        Ok(Self::collect_installed_prefix_records(&environment.prefix)?
+            .into_iter()
+            .map(|record| record.repodata_record)
+            .collect())
+    }
+
+    async fn solve_yaml_records(&self, yaml_file: &Path) -> Result<Vec<RepoDataRecord>> {
+        let environment_yaml = Self::parse_environment_yaml(yaml_file)?;
+        let issues = Self::environment_issues(&environment_yaml);
+        if !issues.is_empty() {
+            return Err(EnvError::Validation(format!(
+                "Invalid environment file {}: {}",
+                yaml_file.display(),
+                issues.join("; ")
+            )));
+        }
+
+        let _cache_lock = self.acquire_cache_lock(LockOperation::CacheUse).await?;
+        let (_, records) = self.solve_environment(yaml_file, &environment_yaml).await?;
+        Ok(records)
+    }
+
+    async fn bundle_environment(
+        &self,
+        yaml_file: &Path,
+        output: &Path,
+        output_mode: OutputMode,
+    ) -> Result<usize> {
+        let environment_yaml = Self::parse_environment_yaml(yaml_file)?;
+        let issues = Self::environment_issues(&environment_yaml);
+        if !issues.is_empty() {
+            return Err(EnvError::Validation(format!(
+                "Invalid environment file {}: {}",
+                yaml_file.display(),
+                issues.join("; ")
+            )));
+        }
+        fs::create_dir_all(output)
+            .map_err(|error| io_error("Failed to create bundle directory", output, error))?;
+        let output = fs::canonicalize(output)
+            .map_err(|error| io_error("Failed to canonicalize bundle directory", output, error))?;
+
+        let progress = if matches!(output_mode, OutputMode::Summary) {
+            Some(Self::summary_spinner(format!(
+                "Solving {}...",
+                yaml_file.display()
+            ))?)
+        } else {
+            None
+        };
+        let result = async {
+            let cache_root = Self::cache_root_dir()?;
+            let _cache_lock = self.acquire_cache_lock(LockOperation::CacheUse).await?;
+            let (_, records) = self.solve_environment(yaml_file, &environment_yaml).await?;
+
+            let archives = Self::fetch_records_into_cache(
+                &cache_root,
+                &records,
+                progress.as_ref(),
+                output_mode,
+            )
+            .await?;
+            let mut packages = Vec::with_capacity(records.len());
+            let mut bytes = 0;
+            for (record, cached_archive) in records.iter().zip(archives) {
+                let file_name = record.identifier.to_string();
+                let subdir = output.join(&record.package_record.subdir);
+                fs::create_dir_all(&subdir)
+                    .map_err(|error| io_error("Failed to create channel subdir", &subdir, error))?;
+                bytes += fs::copy(&cached_archive, subdir.join(&file_name)).map_err(|error| {
+                    io_error("Failed to copy package archive into", &subdir, error)
+                })?;
+                packages.push(ChannelPackage {
+                    file_name,
+                    record: record.package_record.clone(),
+                });
+            }
+            write_channel_repodata(&output, &packages)?;
+            Ok((packages.len(), bytes))
+        }
+        .await;
+
+        let (count, bytes) = match result {
+            Ok(bundled) => {
+                if let Some(pb) = progress {
+                    pb.finish_and_clear();
+                }
+                bundled
+            }
+            Err(error) => {
+                if let Some(pb) = progress {
+                    pb.abandon_with_message(format!(
+                        "✗ Failed to bundle {}: {}",
+                        yaml_file.display(),
+                        error
+                    ));
+                }
+                return Err(error);
+            }
+        };
+        if matches!(output_mode, OutputMode::Summary | OutputMode::Stream) {
+            println!(
+                "✓ Bundled {} packages ({} bytes) into {}",
+                count,
+                bytes,
+                output.display()
+            );
+            println!(
+                "  Create from it with `enva create --yaml {} --name <ENV> --channel {}`",
+                yaml_file.display(),
+                output.display()
+            );
+        }
+        Ok(count)
+    }
+
+    async fn fetch_environment(&self, yaml_file: &Path, output_mode: OutputMode) -> Result<usize> {
+        if self.offline {
+            return Err(EnvError::Validation(
+                "enva fetch populates the caches and needs network access; run it without \
+                 --offline"
+                    .to_string(),
+            ));
+        }
+        let environment_yaml = Self::parse_environment_yaml(yaml_file)?;
+        let issues = Self::environment_issues(&environment_yaml);
+        if !issues.is_empty() {
+            return Err(EnvError::Validation(format!(
+                "Invalid environment file {}: {}",
+                yaml_file.display(),
+                issues.join("; ")
+            )));
+        }
+
+        let progress = if matches!(output_mode, OutputMode::Summary) {
+            Some(Self::summary_spinner(format!(
+                "Solving {}...",
+                yaml_file.display()
+            ))?)
+        } else {
+            None
+        };
+        let result = async {
+            let cache_root = Self::cache_root_dir()?;
+            let _cache_lock = self.acquire_cache_lock(LockOperation::CacheUse).await?;
+            let (_, records) = self.solve_environment(yaml_file, &environment_yaml).await?;
+            Self::fetch_records_into_cache(&cache_root, &records, progress.as_ref(), output_mode)
+                .await?;
+            Ok((records.len(), cache_root))
+        }
+        .await;
+
+        let (count, cache_root) = match result {
+            Ok(fetched) => {
+                if let Some(pb) = progress {
+                    pb.finish_and_clear();
+                }
+                fetched
+            }
+            Err(error) => {
+                if let Some(pb) = progress {
+                    pb.abandon_with_message(format!(
+                        "✗ Failed to fetch {}: {}",
+                        yaml_file.display(),
+                        error
+                    ));
+                }
+                return Err(error);
+            }
+        };
+        if matches!(output_mode, OutputMode::Summary | OutputMode::Stream) {
+            println!(
+                "✓ Fetched repodata and {} packages for {} into {}",
+                count,
+                yaml_file.display(),
+                cache_root.display()
+            );
+        }
+        Ok(count)
+    }
+
+    async fn verify_environment(&self, target: &EnvironmentTarget) -> Result<PrefixVerification> {
+        let environment = self.resolve_environment_target(target).await?;
+        let _prefix_lock =
+            Self::acquire_prefix_lock(&environment.prefix, LockOperation::Verify).await?;
+        StagedPrefix::recover(&environment.prefix)?;
+        if !Self::is_environment_prefix(&environment.prefix) {
+            return Err(EnvError::Execution(format!(
+                "Environment prefix is not a valid conda-style environment: {}",
+                environment.prefix.display()
+            )));
+        }
+
+        let installed = Self::collect_installed_prefix_records(&environment.prefix)?;
+        verify_prefix(&environment.prefix, &installed)
+    }
+
+    async fn clone_environment(
+        &self,
+        source: &EnvironmentTarget,
+        env_name: &str,
+        force: bool,
+        output_mode: OutputMode,
+    ) -> Result<()> {
+        let source_environment = self.resolve_environment_target(source).await?;
+        let target_prefix = self.target_prefix_for_env_name(env_name)?;
+        if target_prefix == source_environment.prefix {
+            return Err(EnvError::Validation(format!(
+                "Cannot clone {} onto itself",
+                source_environment.prefix.display()
+            )));
+        }
+        if force && source_environment.name == env_name {
+            return Err(EnvError::Validation(format!(
+                "Refusing to clone {} with --force into an environment of the same name, which would remove the source; choose a different name",
+                source_environment.prefix.display()
+            )));
+        }
+
+        let progress = if matches!(output_mode, OutputMode::Summary) {
+            Some(Self::summary_spinner(format!(
+                "Cloning {} to {}...",
+                source_environment.prefix.display(),
+                env_name
+            ))?)
+        } else {
+            None
+        };
+        let result = async {
+            let _prefix_locks = Self::acquire_prefix_lock_pair(
+                (&source_environment.prefix, LockOperation::Clone),
+                (&target_prefix, LockOperation::Create),
+            )
+            .await?;
+            StagedPrefix::recover(&source_environment.prefix)?;
+            self.prepare_locked_create_target(
+                env_name,
+                &target_prefix,
+                force,
+                output_mode,
+                progress.as_ref(),
+            )
+            .await?;
+            Self::clone_prefix_natively(
+                &source_environment.prefix,
+                &target_prefix,
+                None,
+                output_mode,
+            )
+        }
+        .await;
+
+        match result {
+            Ok(relocation) => {
+                if let Some(pb) = progress {
+                    pb.finish_and_clear();
+                }
+                if matches!(output_mode, OutputMode::Summary) {
+                    println!(
+                        "✓ Cloned {} to {} ({} files relocated)",
+                        source_environment.prefix.display(),
+                        target_prefix.display(),
+                        relocation.files_rewritten
+                    );
+                }
+                Ok(())
+            }
+            Err(error) => {
+                if let Some(pb) = progress {
+                    pb.abandon_with_message(format!(
+                        "✗ Failed to clone {} to {}: {}",
+                        source_environment.prefix.display(),
+                        env_name,
+                        error
+                    ));
+                }
+                Err(error)
+            }
+        }
+    }
+
+    async fn rename_environment(
+        &self,
+        target: &EnvironmentTarget,
+        new_name: &str,
+        output_mode: OutputMode,
+    ) -> Result<PathBuf> {
+        let new_name = EnvironmentName::parse(new_name.to_string())?;
+        let environment = self.resolve_environment_target(target).await?;
+        let existing = Self::prioritize_named_records(
+            new_name.as_str(),
+            self.accessible_environment_records().await?,
+        );
+        if let Some(existing) = existing.first() {
+            return Err(EnvError::Execution(format!(
+                "Environment '{}' already exists at {}",
+                new_name.as_str(),
+                existing.prefix.display()
+            )));
+        }
+
+        let destination = environment.prefix.with_file_name(new_name.as_str());
+        self.relocate_resolved_environment(environment, destination, output_mode)
+            .await
+    }
+
+    async fn move_environment(
+        &self,
+        target: &EnvironmentTarget,
+        root_prefix: &Path,
+        output_mode: OutputMode,
+    ) -> Result<PathBuf> {
+        let environment = self.resolve_environment_target(target).await?;
+        let root_prefix = if root_prefix.is_absolute() {
+            root_prefix.to_path_buf()
+        } else {
+            std::env::current_dir()
+                .map_err(|error| {
+                    EnvError::FileOperation(format!(
+                        "Failed to resolve the current directory: {}",
+                        error
+                    ))
+                })?
+                .join(root_prefix)
+        };
+        let environments_directory = root_prefix.join("envs");
+        fs::create_dir_all(&environments_directory).map_err(|error| {
+            io_error(
+                "Failed to create environment directory",
+                &environments_directory,
+                error,
+            )
+        })?;
+        let environments_directory =
+            fs::canonicalize(&environments_directory).map_err(|error| {
+                io_error(
+                    "Failed to canonicalize environment directory",
+                    &environments_directory,
+                    error,
+                )
+            })?;
+        let name = environment.prefix.file_name().ok_or_else(|| {
+            EnvError::Validation(format!(
+                "Environment prefix has no name: {}",
+                environment.prefix.display()
+            ))
+        })?;
+        let destination = environments_directory.join(name);
+
+        self.relocate_resolved_environment(environment, destination, output_mode)
+            .await
+    }
+
+    async fn pack_environment(
+        &self,
+        target: &EnvironmentTarget,
+        output: &Path,
+        output_mode: OutputMode,
+    ) -> Result<usize> {
+        let environment = self.resolve_environment_target(target).await?;
+        let _prefix_lock =
+            Self::acquire_prefix_lock(&environment.prefix, LockOperation::Pack).await?;
+        StagedPrefix::recover(&environment.prefix)?;
+        if !Self::is_environment_prefix(&environment.prefix) {
+            return Err(EnvError::Execution(format!(
+                "Environment prefix is not a valid conda-style environment: {}",
+                environment.prefix.display()
+            )));
+        }
+        if !environment.rattler_managed() {
+            return Err(EnvError::Execution(format!(
+                "Cannot pack {}: only rattler-owned environments can be packed; adopt it first with `enva adopt`",
+                environment.prefix.display()
+            )));
+        }
+        if let Some(package_manager) = Self::helper_package_manager(&environment) {
+            return Err(EnvError::Execution(format!(
+                "Cannot pack {}: its packages are managed by {}",
+                environment.prefix.display(),
+                package_manager
+            )));
+        }
+        let output = Self::canonical_or_absolute(&if output.is_absolute() {
+            output.to_path_buf()
+        } else {
+            std::env::current_dir()
+                .map_err(|error| {
+                    EnvError::FileOperation(format!(
+                        "Failed to resolve the current directory: {}",
+                        error
+                    ))
+                })?
+                .join(output)
+        })?;
+        if output.starts_with(&environment.prefix) {
+            return Err(EnvError::Validation(format!(
+                "Refusing to write the archive of {} inside the environment itself: {}",
+                environment.prefix.display(),
+                output.display()
+            )));
+        }
+
+        let installed = Self::collect_installed_prefix_records(&environment.prefix)?;
+        let manifest = PackManifest::from_records(&environment.prefix, &installed);
+        let progress = if matches!(output_mode, OutputMode::Summary) {
+            Some(Self::summary_spinner(format!(
+                "Packing {} into {}...",
+                environment.prefix.display(),
+                output.display()
+            ))?)
+        } else {
+            None
+        };
+        let result = write_pack_archive(
+            &environment.prefix,
+            &manifest,
+            &[ownership_record_path(&environment.prefix)],
+            &output,
+        );
+        if let Some(pb) = progress {
+            match &result {
+                Ok(_) => pb.finish_and_clear(),
+                Err(error) => pb.abandon_with_message(format!(
+                    "✗ Failed to pack {}: {}",
+                    environment.prefix.display(),
+                    error
+                )),
+            }
+        }
+        let files = result?;
+
+        if matches!(output_mode, OutputMode::Summary | OutputMode::Stream) {
+            println!(
+                "✓ Packed {} into {} ({} packages, {} files, {} relocatable)",
+                environment.prefix.display(),
+                output.display(),
+                manifest.packages.len(),
+                files,
+                manifest.placeholders.len()
+            );
+        }
+        Ok(files)
+    }
+
+    async fn unpack_environment(
+        &self,
+        archive: &Path,
+        destination: &EnvironmentTarget,
+        force: bool,
+        output_mode: OutputMode,
+    ) -> Result<PathBuf> {
+        if !archive.is_file() {
+            return Err(EnvError::FileNotFound(archive.to_path_buf()));
+        }
+
+        let progress = if matches!(output_mode, OutputMode::Summary) {
+            Some(Self::summary_spinner(format!(
+                "Unpacking {} to {}...",
+                archive.display(),
+                destination
+            ))?)
+        } else {
+            None
+        };
+        let result = async {
+            let (target_prefix, _prefix_lock) = self
+                .prepare_unpack_target(destination, force, output_mode, progress.as_ref())
+                .await?;
+            let (manifest, relocation) =
+                Self::unpack_prefix_natively(archive, &target_prefix, output_mode)?;
+            Ok((target_prefix, manifest, relocation))
+        }
+        .await;
+
+        match result {
+            Ok((target_prefix, manifest, relocation)) => {
+                if let Some(pb) = progress {
+                    pb.finish_and_clear();
+                }
+                if matches!(output_mode, OutputMode::Summary | OutputMode::Stream) {
+                    println!(
+                        "✓ Unpacked {} packages from {} to {} ({} files relocated)",
+                        manifest.packages.len(),
+                        archive.display(),
+                        target_prefix.display(),
+                        relocation.files_rewritten
+                    );
+                }
+                Ok(target_prefix)
+            }
+            Err(error) => {
+                if let Some(pb) = progress {
+                    pb.abandon_with_message(format!(
+                        "✗ Failed to unpack {}: {}",
+                        archive.display(),
+                        error
+                    ));
+                }
+                Err(error)
+            }
+        }
+    }
+
+    async fn repair_environment(
+        &self,
+        target: &EnvironmentTarget,
+        output_mode: OutputMode,
+    ) -> Result<PrefixVerification> {
+        let mut environment = self.resolve_environment_target(target).await?;
+        let _prefix_lock =
+            Self::acquire_prefix_lock(&environment.prefix, LockOperation::Repair).await?;
+        StagedPrefix::recover(&environment.prefix)?;
+        if !environment.rattler_managed() {
+            environment = self
+                .adopt_discovered_environment(&environment, output_mode)
+                .await?;
+        }
+        if let Some(package_manager) = Self::helper_package_manager(&environment) {
+            return Err(EnvError::Execution(format!(
+                "Cannot repair {}: its packages are managed by {}; recreate it with `enva create --force` instead",
+                environment.prefix.display(),
+                package_manager
+            )));
+        }
+
+        self.repair_prefix_natively(&environment.prefix, output_mode)
+            .await
+    }
+
+    async fn rollback_environment(
+        &self,
+        target: &EnvironmentTarget,
+        revision: u32,
+        output_mode: OutputMode,
+    ) -> Result<()> {
+        let mut environment = self.resolve_environment_target(target).await?;
+        let _prefix_lock =
+            Self::acquire_prefix_lock(&environment.prefix, LockOperation::Rollback).await?;
+        StagedPrefix::recover(&environment.prefix)?;
+        if !environment.rattler_managed() {
+            environment = self
+                .adopt_discovered_environment(&environment, output_mode)
+                .await?;
+        }
+        if let Some(package_manager) = Self::helper_package_manager(&environment) {
+            return Err(EnvError::Execution(format!(
+                "Cannot roll back {}: its packages are managed by {}, which does not record enva revisions",
+                environment.prefix.display(),
+                package_manager
+            )));
+        }
+
+        self.rollback_prefix_natively(&environment.prefix, revision, output_mode)
+            .await
+    }
+
+    async fn export_environment(
+        &self,
+        target: &EnvironmentTarget,
+        format: ExportFormat,
+    ) -> Result<String> {
+        let environment = self.resolve_environment_target(target).await?;
+        let _prefix_lock =
+            Self::acquire_prefix_lock(&environment.prefix, LockOperation::Export).await?;
+        StagedPrefix::recover(&environment.prefix)?;
+        if !Self::is_environment_prefix(&environment.prefix) {
+            return Err(EnvError::Execution(format!(
+                "Environment prefix is not a valid conda-style environment: {}",
+                environment.prefix.display()
+            )));
+        }
+
+        let installed = Self::collect_installed_prefix_records(&environment.prefix)?;
+        let requested_specs = exported_requested_specs(&environment.prefix, &installed);
+        render_environment_export(
+            &environment.name,
+            &environment.prefix,
+            &installed,
+            &requested_specs,
+            format,
+        )
+    }
+
+    async fn adopt_environment(
+        &self,
+        target: &EnvironmentTarget,
+        output_mode: OutputMode,
+    ) -> Result<()> {
+        let environment = self.resolve_environment_target(target).await?;
+        let _prefix_lock =
+            Self::acquire_prefix_lock(&environment.prefix, LockOperation::Adopt).await?;
+        StagedPrefix::recover(&environment.prefix)?;
+        if !environment.rattler_managed() {
+            self.adopt_discovered_environment(&environment, output_mode)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn remove_environment_with_output(
+        &self,
+        env_name: &str,
+        output_mode: OutputMode,
+    ) -> Result<()> {
+        let environment = self
+            .ensure_removable_environment(
+                &EnvironmentTarget::Name(env_name.to_string()),
+                output_mode,
+            )
+            .await?;
+        self.remove_resolved_environment(environment, env_name, output_mode)
+            .await
+    }
+
+    async fn remove_environment_by_prefix_with_output(
+        &self,
+        prefix: &Path,
+        output_mode: OutputMode,
+    ) -> Result<()> {
+        let environment = self
+            .ensure_removable_environment(
+                &EnvironmentTarget::Prefix(prefix.to_path_buf()),
+                output_mode,
+            )
+            .await?;
+        let display_name = environment.name.clone();
+        self.remove_resolved_environment(environment, &display_name, output_mode)
+            .await
+    }
+
+    async fn get_all_conda_environments(&self) -> Result<Vec<CondaEnvironment>> {
+        let mut environments = self
+            .accessible_environment_records()
+            .await?
+            .into_iter()
+            .map(|environment| {
+                let source = Self::source_label(&environment.source);
+                let owner = environment.owner_label().to_string();
+                let adopted_from = environment.adopted_from_label();
+                CondaEnvironment {
+                    name: environment.name,
+                    is_active: environment.is_active,
+                    prefix: environment.prefix.display().to_string(),
+                    source: Some(source),
+                    owner: Some(owner),
+                    adopted_from,
+                }
+            })
+            .collect::<Vec<CondaEnvironment>>();
+
+        environments.sort_by(|left, right| {
+            left.name
+                .cmp(&right.name)
+                .then(left.prefix.cmp(&right.prefix))
+        });
+        Ok(environments)
+    }
+
+    async fn find_environment_prefixes(&self, env_name: &str) -> Result<Vec<PathBuf>> {
+        Ok(
+            Self::prioritize_named_records(env_name, self.accessible_environment_records().await?)
+                .into_iter()
+                .map(|environment| environment.prefix)
+                .collect(),
+        )
+    }
+
+    async fn run(&self, target: &EnvironmentTarget, request: &RunRequest) -> Result<()> {
+        let mut environment = self.resolve_environment_target(target).await?;
+        let _prefix_lock =
+            Self::acquire_prefix_lock(&environment.prefix, LockOperation::Run).await?;
+        StagedPrefix::recover(&environment.prefix)?;
+        if !environment.rattler_managed() {
+            environment = self
+                .adopt_discovered_environment(&environment, OutputMode::Summary)
+                .await?;
+        }
+        self.run_command_in_prefix(&environment.prefix, request)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        benchmark_prefix_clone, clone_prefix_for_staging, validate_staged_prefix_for_publication,
+        RattlerBackend,
+    };
+    use crate::backend::{
+        ChannelPriorityMode, EnvironmentBackend, EnvironmentTarget, ExportFormat, OutputMode,
+        RunCommand, RunRequest, TransactionApproval,
+    };
+    use crate::environment_metadata::read_environment_metadata;
+    use crate::environment_variables::read_environment_variables;
+    use crate::error::EnvError;
+    use crate::ownership::{read_ownership_record, write_rattler_ownership_record};
+    use crate::package_changes::diff_package_records;
+    use crate::package_manager::PackageManager;
+    use crate::prefix_registry::{DiscoveredEnvironment, EnvironmentOwner, EnvironmentSource};
+    use crate::revisions::RevisionOperation;
+    use crate::test_fixtures::repodata_record;
+    use rattler_conda_types::PrefixRecord;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::sync::{Mutex, OnceLock};
+    use tempfile::tempdir;
+
+    fn env_lock() -> &'static Mutex<()> {
+        static LOCK: OnceLock<Mutex<()>> = OnceLock::new();
+        LOCK.get_or_init(|| Mutex::new(()))
+    }
+
+    fn backend_with_root(root: &Path) -> RattlerBackend {
+        RattlerBackend::with_root_prefixes(vec![root.to_path_buf()])
+    }
+
+    fn create_fake_environment(prefix: &Path) {
+        fs::create_dir_all(prefix.join("conda-meta")).unwrap();
+        fs::write(
+            prefix.join("conda-meta").join("history"),
+            "created-by-test\n",
+        )
+        .unwrap();
+    }
+
+    fn write_fake_prefix_record(prefix: &Path, name: &str) {
+        let repodata = repodata_record(name, "1.0.0", "h123_0", "conda-forge", "linux-64");
+        PrefixRecord::from_repodata_record(repodata, vec![])
+            .write_to_path(
+                prefix
+                    .join("conda-meta")
+                    .join(format!("{name}-1.0.0-h123_0.json")),
+                true,
+            )
+            .unwrap();
+    }
+
+    fn discovered_environment(
+        name: &str,
+        prefix: &str,
+        source: EnvironmentSource,
+    ) -> DiscoveredEnvironment {
+        discovered_environment_with_owner(name, prefix, source, EnvironmentOwner::External, None)
+    }
+
+    fn discovered_environment_with_owner(
+        name: &str,
+        prefix: &str,
+        source: EnvironmentSource,
+        owner: EnvironmentOwner,
+        adopted_from: Option<EnvironmentSource>,
+    ) -> DiscoveredEnvironment {
+        DiscoveredEnvironment {
+            name: name.to_string(),
+            prefix: PathBuf::from(prefix),
+            is_active: false,
+            source,
+            owner,
+            adopted_from,
+        }
+    }
+    fn write_local_channel(channel: &Path, packages: &[(&str, &str, &[&str])]) {
+        let mut entries = serde_json::Map::new();
+        for (name, version, depends) in packages {
+            entries.insert(
+                format!("{name}-{version}-h0_0.tar.bz2"),
+                serde_json::json!({
+                    "name": name,
+                    "version": version,
+                    "build": "h0_0",
+                    "build_number": 0,
+                    "depends": depends,
+                    "subdir": "noarch",
+                    "noarch": "generic",
+                    "size": 1024,
+                    "sha256": "5b04be5e2a1ad6e4ad8fd2c7a0d0d3bbff7c1b2c6a7b6e0dfb9a0d3ac1a9c3e1",
+                }),
+            );
+        }
+        for (subdir, packages) in [
+            (
+                rattler_conda_types::Platform::current().as_str(),
+                serde_json::Map::new(),
+            ),
+            ("noarch", entries),
+        ] {
+            fs::create_dir_all(channel.join(subdir)).unwrap();
+            fs::write(
+                channel.join(subdir).join("repodata.json"),
+                serde_json::to_string(&serde_json::json!({
+                    "info": { "subdir": subdir },
+                    "packages": packages,
+                    "packages.conda": {},
+                }))
+                .unwrap(),
+            )
+            .unwrap();
+        }
+    }
+
+    /// Points the rattler cache at a test directory until dropped.
+    struct CacheDirOverride(Option<std::ffi::OsString>);
+
+    impl CacheDirOverride {
+        fn set(path: &Path) -> Self {
+            let previous = std::env::var_os("RATTLER_CACHE_DIR");
+            std::env::set_var("RATTLER_CACHE_DIR", path);
+            Self(previous)
+        }
+    }
+
+    impl Drop for CacheDirOverride {
+        fn drop(&mut self) {
+            match self.0.take() {
+                Some(value) => std::env::set_var("RATTLER_CACHE_DIR", value),
+                None => std::env::remove_var("RATTLER_CACHE_DIR"),
+            }
+        }
+    }
+
+    const FIXTURE_PLACEHOLDER: &str = "/opt/anaconda1anaconda2anaconda3";
+
+    /// Like `write_local_channel`, but builds real `.tar.bz2` archives so the
+    /// solved packages can be linked into a prefix. Each package ships
+    /// `share/enva-fixtures/<name>.txt` and `share/enva-fixtures/<name>.prefix`, a text
+    /// file whose placeholder is replaced by the prefix it is installed into.
+    fn write_installable_channel(channel: &Path, packages: &[(&str, &str, &[&str])]) {
+        use rattler_conda_types::compression_level::CompressionLevel;
+
+        let noarch = channel.join("noarch");
+        fs::create_dir_all(&noarch).unwrap();
+        for (name, version, depends) in packages {
+            let build_dir = tempdir().unwrap();
+            let payload = format!("share/enva-fixtures/{name}.txt");
+            let payload_contents = format!("{name} {version}\n");
+            let prefix_file = format!("share/enva-fixtures/{name}.prefix");
+            let prefix_contents = format!("{FIXTURE_PLACEHOLDER}/share/enva-fixtures\n");
+            fs::create_dir_all(build_dir.path().join("share/enva-fixtures")).unwrap();
+            fs::write(build_dir.path().join(&payload), &payload_contents).unwrap();
+            fs::write(build_dir.path().join(&prefix_file), &prefix_contents).unwrap();
+            let index = serde_json::json!({
+                "name": name,
+                "version": version,
+                "build": "h0_0",
+                "build_number": 0,
+                "depends": depends,
+                "subdir": "noarch",
+                "noarch": "generic",
+            });
+            fs::create_dir_all(build_dir.path().join("info")).unwrap();
+            fs::write(
+                build_dir.path().join("info/index.json"),
+                serde_json::to_string(&index).unwrap(),
+            )
+            .unwrap();
+            fs::write(
+                build_dir.path().join("info/paths.json"),
+                serde_json::to_string(&serde_json::json!({
+                    "paths": [{
+                        "_path": payload,
+                        "path_type": "hardlink",
+                        "size_in_bytes": payload_contents.len(),
+                    }, {
+                        "_path": prefix_file,
+                        "path_type": "hardlink",
+                        "file_mode": "text",
+                        "prefix_placeholder": FIXTURE_PLACEHOLDER,
+                        "size_in_bytes": prefix_contents.len(),
+                    }],
+                    "paths_version": 1,
+                }))
+                .unwrap(),
+            )
+            .unwrap();
+
+            let file_name = format!("{name}-{version}-h0_0.tar.bz2");
+            let archive_path = noarch.join(&file_name);
+            rattler_package_streaming::write::write_tar_bz2_package(
+                fs::File::create(&archive_path).unwrap(),
+                build_dir.path(),
+                &[
+                    build_dir.path().join("info/index.json"),
+                    build_dir.path().join("info/paths.json"),
+                    build_dir.path().join(&payload),
+                    build_dir.path().join(&prefix_file),
+                ],
+                CompressionLevel::Default,
+                None,
+                None,
+            )
+            .unwrap();
+        }
+
+        crate::local_channel::index_local_channel(channel, false, false).unwrap();
+    }
+
+    fn write_environment_yaml(path: &Path, channel: &Path, dependencies: &[&str]) {
+        let mut yaml = format!(
+            "name: demo\nchannels:\n  - {}\ndependencies:\n",
+            channel.display()
+        );
+        for dependency in dependencies {
+            yaml.push_str(&format!("  - {}\n", dependency));
+        }
+        fs::write(path, yaml).unwrap();
+    }
+
+    #[cfg(unix)]
+    fn make_executable(path: &Path) {
+        use std::os::unix::fs::PermissionsExt;
+        let mut permissions = fs::metadata(path).unwrap().permissions();
+        permissions.set_mode(0o755);
+        fs::set_permissions(path, permissions).unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn prefix_clone_preserves_internal_hard_links_without_linking_to_source() {
+        let _guard = env_lock().lock().unwrap();
+        use std::os::unix::fs::MetadataExt;
+
+        let temporary_directory = tempdir().unwrap();
+        let source = temporary_directory.path().join("source");
+        let destination = temporary_directory.path().join("staging");
+        fs::create_dir_all(source.join("bin")).unwrap();
+        let source_first = source.join("bin/tool");
+        let source_second = source.join("bin/tool-alias");
+        fs::write(&source_first, b"payload\n").unwrap();
+        fs::hard_link(&source_first, &source_second).unwrap();
+
+        let result = clone_prefix_for_staging(&source, &destination).unwrap();
+        let destination_first = destination.join("bin/tool");
+        let destination_second = destination.join("bin/tool-alias");
+        let source_metadata = fs::metadata(&source_first).unwrap();
+        let destination_first_metadata = fs::metadata(&destination_first).unwrap();
+        let destination_second_metadata = fs::metadata(&destination_second).unwrap();
+
+        assert_eq!(result.hard_links_preserved, 1);
+        assert_eq!(
+            destination_first_metadata.ino(),
+            destination_second_metadata.ino()
+        );
+        assert_ne!(source_metadata.ino(), destination_first_metadata.ino());
+        fs::write(&destination_first, b"staged\n").unwrap();
+        assert_eq!(fs::read(&source_first).unwrap(), b"payload\n");
+        assert_eq!(fs::read(&destination_second).unwrap(), b"staged\n");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn prefix_clone_preserves_internal_symlinks_and_rejects_escaping_targets() {
+        let _guard = env_lock().lock().unwrap();
+        let temporary_directory = tempdir().unwrap();
+        let source = temporary_directory.path().join("source");
+        let destination = temporary_directory.path().join("staging");
+        fs::create_dir_all(source.join("lib")).unwrap();
+        fs::write(source.join("lib/library.so"), b"library").unwrap();
+        std::os::unix::fs::symlink("library.so", source.join("lib/current.so")).unwrap();
+        std::os::unix::fs::symlink(
+            source.join("lib/library.so"),
+            source.join("absolute-library.so"),
+        )
+        .unwrap();
+
+        let result = clone_prefix_for_staging(&source, &destination).unwrap();
+        assert_eq!(result.symlinks_copied, 2);
+        assert_eq!(
+            fs::read_link(destination.join("lib/current.so")).unwrap(),
+            PathBuf::from("library.so")
+        );
+        assert_eq!(
+            fs::read_link(destination.join("absolute-library.so")).unwrap(),
+            destination.join("lib/library.so")
+        );
+
+        let outside = temporary_directory.path().join("outside.txt");
+        fs::write(&outside, b"outside").unwrap();
+        let escaping_source = temporary_directory.path().join("escaping-source");
+        fs::create_dir(&escaping_source).unwrap();
+        std::os::unix::fs::symlink("../outside.txt", escaping_source.join("relative-escape"))
+            .unwrap();
+        let error = clone_prefix_for_staging(
+            &escaping_source,
+            &temporary_directory.path().join("escaping-stage"),
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("escaping source prefix"));
+
+        fs::remove_file(escaping_source.join("relative-escape")).unwrap();
+        std::os::unix::fs::symlink(&outside, escaping_source.join("absolute-escape")).unwrap();
+        let error = clone_prefix_for_staging(
+            &escaping_source,
+            &temporary_directory.path().join("absolute-stage"),
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("escaping source prefix"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn publication_rewrites_absolute_symlinks_and_rejects_all_file_residuals() {
+        let _guard = env_lock().lock().unwrap();
+        let temporary_directory = tempdir().unwrap();
+        let staging = temporary_directory.path().join("staging-prefix");
+        let final_prefix = temporary_directory
+            .path()
+            .join("final-prefix-with-longer-name");
+        fs::create_dir_all(staging.join("bin")).unwrap();
+        fs::write(
+            staging.join("bin/tool"),
+            b"prefix already patched for final path\n",
+        )
+        .unwrap();
+        std::os::unix::fs::symlink(staging.join("bin/tool"), staging.join("absolute-tool"))
+            .unwrap();
+
+        let result = validate_staged_prefix_for_publication(&staging, &final_prefix).unwrap();
+        assert_eq!(result.files_scanned, 1);
+        assert_eq!(result.symlinks_rewritten, 1);
+        assert_eq!(
+            fs::read_link(staging.join("absolute-tool")).unwrap(),
+            final_prefix.join("bin/tool")
+        );
+
+        let text_staging = temporary_directory.path().join("text-staging");
+        fs::create_dir(&text_staging).unwrap();
+        fs::write(
+            text_staging.join("script"),
+            format!("#!{}/bin/python\n", text_staging.display()),
+        )
+        .unwrap();
+        let text_error =
+            validate_staged_prefix_for_publication(&text_staging, &final_prefix).unwrap_err();
+        assert!(text_error
+            .to_string()
+            .contains("File contains staging prefix residual after target-prefix installation"));
+
+        let binary_staging = temporary_directory.path().join("binary-staging");
+        fs::create_dir(&binary_staging).unwrap();
+        let mut binary: Vec<u8> = vec![0_u8, 1, 2];
+        binary.extend_from_slice(binary_staging.to_string_lossy().as_bytes());
+        binary.push(0);
+        fs::write(binary_staging.join("binary"), binary).unwrap();
+        let binary_error =
+            validate_staged_prefix_for_publication(&binary_staging, &final_prefix).unwrap_err();
+        assert!(binary_error
+            .to_string()
+            .contains("File contains staging prefix residual after target-prefix installation"));
+    }
+
+    #[test]
+    fn large_prefix_clone_without_reflinks_completes_with_expected_accounting() {
+        let _guard = env_lock().lock().unwrap();
+        let temporary_directory = tempdir().unwrap();
+        let source = temporary_directory.path().join("large-source");
+        let destination = temporary_directory.path().join("large-stage");
+        fs::create_dir(&source).unwrap();
+        let payload = vec![b'x'; 4096];
+        for file_index in 0..2_000_u32 {
+            fs::write(source.join(format!("file-{file_index:04}.dat")), &payload).unwrap();
+        }
+
+        let result = benchmark_prefix_clone(&source, &destination).unwrap();
+        assert_eq!(result.files_copied, 2_000);
+        assert_eq!(result.bytes_copied, 2_000 * 4096);
+        assert!(
+            result.elapsed_millis < 30_000,
+            "clone took {} ms",
+            result.elapsed_millis
+        );
+        assert_eq!(
+            fs::read(destination.join("file-1999.dat")).unwrap(),
+            payload
+        );
+    }
+
+    #[tokio::test]
+    #[allow(clippy::await_holding_lock)]
+    async fn validate_yaml_accepts_basic_environment_file() {
+        let _guard = env_lock().lock().unwrap();
+        let tempdir = tempdir().unwrap();
+        let _cache_dir = CacheDirOverride::set(&tempdir.path().join("cache"));
+        let channel = tempdir.path().join("channel");
+        write_local_channel(
+            &channel,
+            &[
+                ("samtools", "1.20", &["htslib >=1.20"]),
+                ("htslib", "1.20", &[]),
+            ],
+        );
+        let yaml_file = tempdir.path().join("env.yaml");
+        write_environment_yaml(&yaml_file, &channel, &["samtools"]);
+
+        let result = RattlerBackend::new()
+            .validate_yaml(&yaml_file)
+            .await
+            .unwrap();
+
+        assert_eq!(result.environment, "demo");
+        assert!(result.validation.syntax_valid);
+        assert!(result.validation.dependencies_resolvable);
+        assert!(result.validation.channels_accessible);
+        assert_eq!(result.estimated_packages, 2);
+        assert_eq!(result.solved_packages, Some(2));
+        assert_eq!(result.download_size_bytes, Some(2048));
+        assert_eq!(result.install_size_bytes, None);
+        assert_eq!(result.channels_accessible.len(), 1);
+        assert!(result.channels_accessible[0].starts_with("file://"));
+        assert!(result.solver_message.is_none());
+    }
+
+    #[tokio::test]
+    #[allow(clippy::await_holding_lock)]
+    async fn validate_yaml_reports_solver_conflict_for_unsatisfiable_specs() {
+        let _guard = env_lock().lock().unwrap();
+        let tempdir = tempdir().unwrap();
+        let _cache_dir = CacheDirOverride::set(&tempdir.path().join("cache"));
+        let channel = tempdir.path().join("channel");
+        write_local_channel(&channel, &[("samtools", "1.20", &[])]);
+        let yaml_file = tempdir.path().join("env.yaml");
+        write_environment_yaml(&yaml_file, &channel, &["samtools"]);
+
+        let result = RattlerBackend::new()
+            .validate_yaml_with_packages(&yaml_file, &["samtools >=2".to_string()])
+            .await
+            .unwrap();
+
+        assert!(result.validation.syntax_valid);
+        assert!(!result.validation.dependencies_resolvable);
+        assert!(result.solved_packages.is_none());
+        let message = result.solver_message.unwrap();
+        assert!(message.contains("samtools"));
+        assert!(result.validation.version_conflicts.contains(&message));
+        let conflict = result.solve_conflict.unwrap();
+        assert_eq!(conflict.conflicting_specs, vec!["samtools", "samtools >=2"]);
+        assert_eq!(conflict.suggested_relaxations, vec!["samtools >=2"]);
+    }
+
+    #[tokio::test]
+    #[allow(clippy::await_holding_lock)]
+    async fn validate_yaml_reports_missing_dependencies_section() {
+        let _guard = env_lock().lock().unwrap();
+        let tempdir = tempdir().unwrap();
+        let yaml_file = tempdir.path().join("env.yaml");
+        fs::write(&yaml_file, "name: test-env\nchannels:\n  - conda-forge\n").unwrap();
+
+        let backend = RattlerBackend::new();
+        let result = backend.validate_yaml(&yaml_file).await.unwrap();
+
+        assert!(!result.validation.syntax_valid);
+        assert!(result
+            .validation
+            .version_conflicts
+            .iter()
+            .any(|issue| issue.contains("Missing required 'dependencies' section")));
+    }
+
+    #[tokio::test]
+    #[allow(clippy::await_holding_lock)]
+    async fn validate_yaml_reports_pip_subsection_as_unsupported() {
+        let _guard = env_lock().lock().unwrap();
+        let tempdir = tempdir().unwrap();
+        let yaml_file = tempdir.path().join("env.yaml");
+        fs::write(
+            &yaml_file,
+            "name: test-env\nchannels:\n  - conda-forge\ndependencies:\n  - python=3.10\n  - pip:\n    - requests\n",
+        )
+        .unwrap();
+
+        let backend = RattlerBackend::new();
+        let result = backend.validate_yaml(&yaml_file).await.unwrap();
+
+        assert!(!result.validation.syntax_valid);
+        assert!(result
+            .validation
+            .version_conflicts
+            .iter()
+            .any(|issue| issue.contains("pip subsection")));
+    }
+
+    #[test]
+    fn dedupe_paths_preserves_detection_order() {
+        let ordered = RattlerBackend::dedupe_paths(vec![
+            PathBuf::from("/preferred-root"),
+            PathBuf::from("/fallback-root"),
+            PathBuf::from("/preferred-root"),
+        ]);
+
+        assert_eq!(
+            ordered,
+            vec![
+                PathBuf::from("/preferred-root"),
+                PathBuf::from("/fallback-root"),
+            ]
+        );
+    }
+
+    #[test]
+    fn base_prefix_with_conda_layout_is_detected_without_default_env() {
+        let tempdir = tempdir().unwrap();
+        let root = tempdir.path().join("active-conda-root");
+        fs::create_dir_all(root.join("conda-meta")).unwrap();
+        fs::create_dir_all(root.join("envs")).unwrap();
+
+        assert_eq!(
+            RattlerBackend::classify_conda_prefix(&root, None),
+            super::CondaPrefixLayout::BaseRoot(root)
+        );
+    }
+
+    #[test]
+    fn preferred_root_prefix_uses_first_existing_root() {
+        let _guard = env_lock().lock().unwrap();
+        let tempdir = tempdir().unwrap();
+        let preferred = tempdir.path().join("preferred-root");
+        let fallback = tempdir.path().join("fallback-root");
+        fs::create_dir_all(&preferred).unwrap();
+        fs::create_dir_all(&fallback).unwrap();
+
+        let backend = RattlerBackend::with_root_prefixes(vec![
+            preferred.clone(),
+            fallback.clone(),
+            preferred.clone(),
+        ]);
+
+        assert_eq!(backend.preferred_root_prefix(), preferred);
+    }
+
+    #[test]
+    fn target_prefix_uses_envs_subdirectory() {
+        let _guard = env_lock().lock().unwrap();
+        let tempdir = tempdir().unwrap();
+        let root = tempdir.path().join("rattler-root");
+        let backend = backend_with_root(&root);
+
+        let target_prefix = backend.target_prefix_for_env_name("test-env").unwrap();
+        assert_eq!(target_prefix, root.join("envs").join("test-env"));
+    }
+
+    #[tokio::test]
+    #[allow(clippy::await_holding_lock)]
+    async fn find_environment_prefixes_returns_named_environment() {
+        let _guard = env_lock().lock().unwrap();
+        let tempdir = tempdir().unwrap();
+        let root = tempdir.path().join("rattler-root");
+        let env_prefix = root.join("envs").join("test-env");
+        create_fake_environment(&env_prefix);
+
+        let backend = backend_with_root(&root);
+        let prefixes = backend.find_environment_prefixes("test-env").await.unwrap();
+
+        assert_eq!(prefixes, vec![env_prefix]);
     }
 
-    async fn adopt_environment(
-        &self,
-        target: &EnvironmentTarget,
-        output_mode: OutputMode,
-    ) -> Result<()> {
-        let environment = self.resolve_environment_target(target).await?;
-        let _prefix_lock =
-            Self::acquire_prefix_lock(&environment.prefix, LockOperation::Adopt).await?;
-        StagedPrefix::recover(&environment.prefix)?;
-        if !environment.rattler_managed() {
-            self.adopt_discovered_environment(&environment, output_mode)
-                .await?;
-        }
-        Ok(())
-    }
+    #[tokio::test]
+    #[allow(clippy::await_holding_lock)]
+    async fn export_environment_renders_installed_records_for_prefix() {
+        let _guard = env_lock().lock().unwrap();
+        let tempdir = tempdir().unwrap();
+        let root = tempdir.path().join("rattler-root");
+        let env_prefix = root.join("envs").join("test-env");
+        create_fake_environment(&env_prefix);
+        write_fake_prefix_record(&env_prefix, "seqtk");
+        write_rattler_ownership_record(&env_prefix, None).unwrap();
 
-    async fn remove_environment_with_output(
-        &self,
-        env_name: &str,
-        output_mode: OutputMode,
-    ) -> Result<()> {
-        let environment = self
-            .ensure_removable_environment(
-                &EnvironmentTarget::Name(env_name.to_string()),
-                output_mode,
+        let backend = backend_with_root(&root);
+        let pinned = backend
+            .export_environment(
+                &EnvironmentTarget::Prefix(env_prefix.clone()),
+                ExportFormat::Pinned,
             )
-            .await?;
-        self.remove_resolved_environment(environment, env_name, output_mode)
             .await
-    }
-
-    async fn remove_environment_by_prefix_with_output(
-        &self,
-        prefix: &Path,
-        output_mode: OutputMode,
-    ) -> Result<()> {
-        let environment = self
-            .ensure_removable_environment(
-                &EnvironmentTarget::Prefix(prefix.to_path_buf()),
-                output_mode,
+            .unwrap();
+        let explicit = backend
+            .export_environment(
+                &EnvironmentTarget::Name("test-env".to_string()),
+                ExportFormat::Explicit,
             )
-            .await?;
-        let display_name = environment.name.clone();
-        self.remove_resolved_environment(environment, &display_name, output_mode)
             .await
-    }
-
-    async fn get_all_conda_environments(&self) -> Result<Vec<CondaEnvironment>> {
-        let mut environments = self
-            .accessible_environment_records()
-            .await?
-            .into_iter()
-            .map(|environment| {
-                let source = Self::source_label(&environment.source);
-                let owner = environment.owner_label().to_string();
-                let adopted_from = environment.adopted_from_label();
-                CondaEnvironment {
-                    name: environment.name,
-                    is_active: environment.is_active,
-                    prefix: environment.prefix.display().to_string(),
-                    source: Some(source),
-                    owner: Some(owner),
-                    adopted_from,
-                }
-            })
-            .collect::<Vec<CondaEnvironment>>();
+            .unwrap();
 
-        environments.sort_by(|left, right| {
-            left.name
-                .cmp(&right.name)
-                .then(left.prefix.cmp(&right.prefix))
-        });
-        Ok(environments)
+        assert!(pinned.contains("name: test-env"));
+        assert!(pinned.contains("- conda-forge"));
+        assert!(pinned.contains("- seqtk=1.0.0=h123_0"));
+        assert!(!pinned.contains("enva-rattler"));
+        assert!(explicit
+            .contains("https://conda.anaconda.org/conda-forge/linux-64/seqtk-1.0.0-h123_0.conda"));
     }
 
-    async fn find_environment_prefixes(&self, env_name: &str) -> Result<Vec<PathBuf>> {
-        Ok(
-            Self::prioritize_named_records(env_name, self.accessible_environment_records().await?)
-                .into_iter()
-                .map(|environment| environment.prefix)
-                .collect(),
-        )
-    }
+    #[tokio::test]
+    #[allow(clippy::await_holding_lock)]
+    async fn dry_run_install_and_create_stop_after_the_transaction_plan() {
+        let _guard = env_lock().lock().unwrap();
+        let tempdir = tempdir().unwrap();
+        let _cache_dir = CacheDirOverride::set(&tempdir.path().join("cache"));
+        let root = tempdir.path().join("rattler-root");
+        let channel = tempdir.path().join("channel");
+        write_installable_channel(
+            &channel,
+            &[
+                ("samtools", "1.20", &["htslib >=1.20"]),
+                ("htslib", "1.20", &[]),
+                ("seqtk", "1.4", &[]),
+            ],
+        );
+        let yaml_file = tempdir.path().join("demo.yaml");
+        write_environment_yaml(&yaml_file, &channel, &["samtools"]);
+        let dry_run =
+            backend_with_root(&root).with_transaction_approval(TransactionApproval::DryRun);
 
-    async fn run(&self, target: &EnvironmentTarget, request: &RunRequest) -> Result<()> {
-        let mut environment = self.resolve_environment_target(target).await?;
-        let _prefix_lock =
-            Self::acquire_prefix_lock(&environment.prefix, LockOperation::Run).await?;
-        StagedPrefix::recover(&environment.prefix)?;
-        if !environment.rattler_managed() {
-            environment = self
-                .adopt_discovered_environment(&environment, OutputMode::Summary)
-                .await?;
-        }
-        self.run_command_in_prefix(&environment.prefix, request)
+        dry_run
+            .create_environment("demo", &yaml_file, false, false, OutputMode::Quiet)
             .await
-    }
-}
+            .unwrap();
+        assert!(!root.join("envs/demo").exists());
 
-#[cfg(test)]
-mod tests {
-    use super::{
-        benchmark_prefix_clone, clone_prefix_for_staging, validate_staged_prefix_for_publication,
-        RattlerBackend,
-    };
-    use crate::backend::{
-        EnvironmentBackend, EnvironmentTarget, OutputMode, RunCommand, RunRequest,
-    };
-    use crate::ownership::write_rattler_ownership_record;
-    use crate::package_manager::PackageManager;
-    use crate::prefix_registry::{DiscoveredEnvironment, EnvironmentOwner, EnvironmentSource};
-    use rattler_conda_types::{PackageName, PackageRecord, PrefixRecord, RepoDataRecord, Version};
-    use rattler_solve::ChannelPriority;
-    use std::fs;
-    use std::path::{Path, PathBuf};
-    use std::str::FromStr;
-    use std::sync::{Mutex, OnceLock};
-    use tempfile::tempdir;
+        backend_with_root(&root)
+            .with_transaction_approval(TransactionApproval::AssumeYes)
+            .create_environment("demo", &yaml_file, false, false, OutputMode::Quiet)
+            .await
+            .unwrap();
+        let prefix = root.join("envs/demo");
+        let before = RattlerBackend::collect_installed_prefix_records(&prefix).unwrap();
+        dry_run
+            .install_packages_for_target(
+                &EnvironmentTarget::Name("demo".to_string()),
+                &["seqtk".to_string()],
+                OutputMode::Quiet,
+            )
+            .await
+            .unwrap();
 
-    fn env_lock() -> &'static Mutex<()> {
-        static LOCK: OnceLock<Mutex<()>> = OnceLock::new();
-        LOCK.get_or_init(|| Mutex::new(()))
+        assert_eq!(
+            RattlerBackend::collect_installed_prefix_records(&prefix)
+                .unwrap()
+                .len(),
+            before.len()
+        );
+        assert!(!prefix.join("share/enva-fixtures/seqtk.txt").exists());
+        assert_eq!(crate::revisions::list_revisions(&prefix).unwrap().len(), 1);
     }
 
-    fn backend_with_root(root: &Path) -> RattlerBackend {
-        RattlerBackend::with_root_prefixes(vec![root.to_path_buf()])
-    }
+    #[tokio::test]
+    #[allow(clippy::await_holding_lock)]
+    async fn uninstall_packages_resolves_without_removed_specs_and_drops_orphans() {
+        let _guard = env_lock().lock().unwrap();
+        let tempdir = tempdir().unwrap();
+        let _cache_dir = CacheDirOverride::set(&tempdir.path().join("cache"));
+        let root = tempdir.path().join("rattler-root");
+        let channel = tempdir.path().join("channel");
+        write_installable_channel(
+            &channel,
+            &[
+                ("samtools", "1.20", &["htslib >=1.20"]),
+                ("htslib", "1.20", &[]),
+                ("seqtk", "1.4", &[]),
+            ],
+        );
+        let yaml_file = tempdir.path().join("demo.yaml");
+        write_environment_yaml(&yaml_file, &channel, &["samtools", "seqtk"]);
+        let backend = backend_with_root(&root);
+        backend
+            .create_environment("demo", &yaml_file, false, false, OutputMode::Quiet)
+            .await
+            .unwrap();
+        let prefix = root.join("envs").join("demo");
+        let target = EnvironmentTarget::Name("demo".to_string());
 
-    fn create_fake_environment(prefix: &Path) {
-        fs::create_dir_all(prefix.join("conda-meta")).unwrap();
-        fs::write(
-            prefix.join("conda-meta").join("history"),
-            "created-by-test\n",
-        )
-        .unwrap();
+        let required = backend
+            .uninstall_packages_for_target(&target, &["htslib".to_string()], OutputMode::Quiet)
+            .await
+            .unwrap_err();
+        let missing = backend
+            .uninstall_packages_for_target(&target, &["bwa".to_string()], OutputMode::Quiet)
+            .await
+            .unwrap_err();
+        backend
+            .uninstall_packages_for_target(
+                &EnvironmentTarget::Prefix(prefix.clone()),
+                &["samtools".to_string()],
+                OutputMode::Quiet,
+            )
+            .await
+            .unwrap();
+
+        assert!(required.to_string().contains("still required"));
+        assert!(missing.to_string().contains("not installed"));
+        let mut names = RattlerBackend::collect_installed_prefix_records(&prefix)
+            .unwrap()
+            .iter()
+            .map(|record| {
+                record
+                    .repodata_record
+                    .package_record
+                    .name
+                    .as_normalized()
+                    .to_string()
+            })
+            .collect::<Vec<String>>();
+        names.sort();
+        assert_eq!(names, vec!["seqtk"]);
+        assert!(!prefix.join("share/enva-fixtures/samtools.txt").exists());
+        assert!(prefix.join("share/enva-fixtures/seqtk.txt").exists());
+        assert!(crate::ownership::read_ownership_record(&prefix)
+            .unwrap()
+            .is_some());
+    }
+
+    fn installed_versions(prefix: &Path) -> Vec<String> {
+        let mut versions = RattlerBackend::collect_installed_prefix_records(prefix)
+            .unwrap()
+            .iter()
+            .map(|record| {
+                let package = &record.repodata_record.package_record;
+                format!("{}={}", package.name.as_normalized(), package.version)
+            })
+            .collect::<Vec<String>>();
+        versions.sort();
+        versions
     }
 
-    fn write_fake_prefix_record(prefix: &Path, name: &str) {
-        let package_record = PackageRecord::new(
-            PackageName::new_unchecked(name),
-            Version::from_str("1.0.0").unwrap(),
-            "h123_0".to_string(),
-        );
-        let repodata_record = RepoDataRecord {
-            package_record,
-            identifier: format!("{name}-1.0.0-h123_0.conda").parse().unwrap(),
-            url: format!(
-                "https://conda.anaconda.org/conda-forge/linux-64/{name}-1.0.0-h123_0.conda"
+    #[tokio::test]
+    #[allow(clippy::await_holding_lock)]
+    async fn update_unlocks_selected_packages_and_keeps_requested_pins() {
+        let _guard = env_lock().lock().unwrap();
+        let tempdir = tempdir().unwrap();
+        let _cache_dir = CacheDirOverride::set(&tempdir.path().join("cache"));
+        let root = tempdir.path().join("rattler-root");
+        let channel = tempdir.path().join("channel");
+        write_installable_channel(
+            &channel,
+            &[
+                ("samtools", "1.19", &["htslib"]),
+                ("htslib", "1.19", &[]),
+                ("seqtk", "1.4", &[]),
+            ],
+        );
+        let yaml_file = tempdir.path().join("demo.yaml");
+        write_environment_yaml(
+            &yaml_file,
+            &channel,
+            &["samtools >=1.19,<2", "htslib", "seqtk 1.4.*"],
+        );
+        let backend = backend_with_root(&root);
+        backend
+            .create_environment("demo", &yaml_file, false, false, OutputMode::Quiet)
+            .await
+            .unwrap();
+        let prefix = root.join("envs").join("demo");
+        let requested_before = RattlerBackend::requested_spec_strings_from_prefix_records(
+            &RattlerBackend::collect_installed_prefix_records(&prefix).unwrap(),
+        );
+        write_installable_channel(
+            &channel,
+            &[
+                ("samtools", "1.20", &["htslib"]),
+                ("htslib", "1.20", &[]),
+                ("seqtk", "1.5", &[]),
+            ],
+        );
+
+        let missing = backend
+            .update_packages_for_target(
+                &EnvironmentTarget::Name("demo".to_string()),
+                &["bwa".to_string()],
+                OutputMode::Quiet,
             )
-            .parse()
-            .unwrap(),
-            channel: Some("https://conda.anaconda.org/conda-forge/".to_string()),
-        };
-        PrefixRecord::from_repodata_record(repodata_record, vec![])
-            .write_to_path(
-                prefix
-                    .join("conda-meta")
-                    .join(format!("{name}-1.0.0-h123_0.json")),
-                true,
+            .await
+            .unwrap_err();
+        backend
+            .update_packages_for_target(
+                &EnvironmentTarget::Prefix(prefix.clone()),
+                &["samtools".to_string()],
+                OutputMode::Quiet,
             )
+            .await
+            .unwrap();
+        let after_single = installed_versions(&prefix);
+        backend
+            .update_all_environments(&[], OutputMode::Quiet)
+            .await
             .unwrap();
-    }
 
-    fn discovered_environment(
-        name: &str,
-        prefix: &str,
-        source: EnvironmentSource,
-    ) -> DiscoveredEnvironment {
-        discovered_environment_with_owner(name, prefix, source, EnvironmentOwner::External, None)
+        assert!(missing.to_string().contains("not installed"));
+        assert_eq!(
+            after_single,
+            vec!["htslib=1.19", "samtools=1.20", "seqtk=1.4"]
+        );
+        assert_eq!(
+            installed_versions(&prefix),
+            vec!["htslib=1.20", "samtools=1.20", "seqtk=1.4"]
+        );
+        let requested = RattlerBackend::requested_spec_strings_from_prefix_records(
+            &RattlerBackend::collect_installed_prefix_records(&prefix).unwrap(),
+        );
+        assert_eq!(requested, requested_before);
+        assert!(requested.iter().any(|spec| spec.starts_with("seqtk 1.4")));
     }
 
-    fn discovered_environment_with_owner(
-        name: &str,
-        prefix: &str,
-        source: EnvironmentSource,
-        owner: EnvironmentOwner,
-        adopted_from: Option<EnvironmentSource>,
-    ) -> DiscoveredEnvironment {
-        DiscoveredEnvironment {
-            name: name.to_string(),
-            prefix: PathBuf::from(prefix),
-            is_active: false,
-            source,
-            owner,
-            adopted_from,
-        }
-    }
-    #[cfg(unix)]
-    fn make_executable(path: &Path) {
-        use std::os::unix::fs::PermissionsExt;
-        let mut permissions = fs::metadata(path).unwrap().permissions();
-        permissions.set_mode(0o755);
-        fs::set_permissions(path, permissions).unwrap();
+    #[tokio::test]
+    #[allow(clippy::await_holding_lock)]
+    async fn rollback_restores_recorded_revision_through_staged_prefix() {
+        let _guard = env_lock().lock().unwrap();
+        let tempdir = tempdir().unwrap();
+        let _cache_dir = CacheDirOverride::set(&tempdir.path().join("cache"));
+        let root = tempdir.path().join("rattler-root");
+        let channel = tempdir.path().join("channel");
+        write_installable_channel(
+            &channel,
+            &[("samtools", "1.19", &[]), ("seqtk", "1.4", &[])],
+        );
+        let yaml_file = tempdir.path().join("demo.yaml");
+        write_environment_yaml(&yaml_file, &channel, &["samtools"]);
+        let backend = backend_with_root(&root);
+        backend
+            .create_environment("demo", &yaml_file, false, false, OutputMode::Quiet)
+            .await
+            .unwrap();
+        let prefix = root.join("envs").join("demo");
+        let target = EnvironmentTarget::Name("demo".to_string());
+        write_installable_channel(&channel, &[("samtools", "1.20", &[])]);
+        backend
+            .update_packages_for_target(&target, &["samtools".to_string()], OutputMode::Quiet)
+            .await
+            .unwrap();
+        backend
+            .install_packages("demo", &["seqtk".to_string()], OutputMode::Quiet)
+            .await
+            .unwrap();
+
+        backend
+            .rollback_environment(&target, 0, OutputMode::Quiet)
+            .await
+            .unwrap();
+
+        assert_eq!(installed_versions(&prefix), vec!["samtools=1.19"]);
+        assert!(!prefix.join("share/enva-fixtures/seqtk.txt").exists());
+        let history = backend.environment_history(&target).await.unwrap();
+        assert_eq!(
+            history
+                .iter()
+                .map(|revision| (revision.revision, revision.operation))
+                .collect::<Vec<_>>(),
+            vec![
+                (0, RevisionOperation::Create),
+                (1, RevisionOperation::Update),
+                (2, RevisionOperation::Install),
+                (3, RevisionOperation::Rollback),
+            ]
+        );
+        assert_eq!(history[3].requested_specs, history[0].requested_specs);
+        assert!(backend
+            .rollback_environment(&target, 9, OutputMode::Quiet)
+            .await
+            .unwrap_err()
+            .to_string()
+            .contains("does not exist"));
     }
 
-    #[test]
-    #[cfg(unix)]
-    fn prefix_clone_preserves_internal_hard_links_without_linking_to_source() {
+    #[tokio::test]
+    #[allow(clippy::await_holding_lock)]
+    async fn installed_records_match_the_solved_yaml_they_were_created_from() {
         let _guard = env_lock().lock().unwrap();
-        use std::os::unix::fs::MetadataExt;
-
-        let temporary_directory = tempdir().unwrap();
-        let source = temporary_directory.path().join("source");
-        let destination = temporary_directory.path().join("staging");
-        fs::create_dir_all(source.join("bin")).unwrap();
-        let source_first = source.join("bin/tool");
-        let source_second = source.join("bin/tool-alias");
-        fs::write(&source_first, b"payload\n").unwrap();
-        fs::hard_link(&source_first, &source_second).unwrap();
+        let tempdir = tempdir().unwrap();
+        let _cache_dir = CacheDirOverride::set(&tempdir.path().join("cache"));
+        let root = tempdir.path().join("rattler-root");
+        let channel = tempdir.path().join("channel");
+        write_installable_channel(
+            &channel,
+            &[
+                ("samtools", "1.19", &["htslib"]),
+                ("samtools", "1.20", &["htslib"]),
+                ("htslib", "1.19", &[]),
+            ],
+        );
+        let yaml_file = tempdir.path().join("demo.yaml");
+        write_environment_yaml(&yaml_file, &channel, &["samtools 1.19.*"]);
+        let newer_yaml_file = tempdir.path().join("newer.yaml");
+        write_environment_yaml(&newer_yaml_file, &channel, &["samtools 1.20.*"]);
+        let backend = backend_with_root(&root);
+        backend
+            .create_environment("demo", &yaml_file, false, false, OutputMode::Quiet)
+            .await
+            .unwrap();
+        let target = EnvironmentTarget::Name("demo".to_string());
 
-        let result = clone_prefix_for_staging(&source, &destination).unwrap();
-        let destination_first = destination.join("bin/tool");
-        let destination_second = destination.join("bin/tool-alias");
-        let source_metadata = fs::metadata(&source_first).unwrap();
-        let destination_first_metadata = fs::metadata(&destination_first).unwrap();
-        let destination_second_metadata = fs::metadata(&destination_second).unwrap();
+        let installed = backend.installed_package_records(&target).await.unwrap();
+        let solved = backend.solve_yaml_records(&yaml_file).await.unwrap();
+        let newer = backend.solve_yaml_records(&newer_yaml_file).await.unwrap();
 
-        assert_eq!(result.hard_links_preserved, 1);
+        assert_eq!(installed.len(), 2);
+        assert!(diff_package_records(&installed, &solved).is_empty());
+        let changes = diff_package_records(&installed, &newer);
+        assert_eq!(changes.len(), 1);
         assert_eq!(
-            destination_first_metadata.ino(),
-            destination_second_metadata.ino()
+            changes[0].to_string(),
+            "↑ samtools 1.19 (h0_0) -> 1.20 (h0_0)"
         );
-        assert_ne!(source_metadata.ino(), destination_first_metadata.ino());
-        fs::write(&destination_first, b"staged\n").unwrap();
-        assert_eq!(fs::read(&source_first).unwrap(), b"payload\n");
-        assert_eq!(fs::read(&destination_second).unwrap(), b"staged\n");
     }
 
-    #[test]
-    #[cfg(unix)]
-    fn prefix_clone_preserves_internal_symlinks_and_rejects_escaping_targets() {
+    #[tokio::test]
+    #[allow(clippy::await_holding_lock)]
+    async fn sync_applies_only_the_yaml_delta_and_records_its_source() {
         let _guard = env_lock().lock().unwrap();
-        let temporary_directory = tempdir().unwrap();
-        let source = temporary_directory.path().join("source");
-        let destination = temporary_directory.path().join("staging");
-        fs::create_dir_all(source.join("lib")).unwrap();
-        fs::write(source.join("lib/library.so"), b"library").unwrap();
-        std::os::unix::fs::symlink("library.so", source.join("lib/current.so")).unwrap();
-        std::os::unix::fs::symlink(
-            source.join("lib/library.so"),
-            source.join("absolute-library.so"),
-        )
-        .unwrap();
+        let tempdir = tempdir().unwrap();
+        let _cache_dir = CacheDirOverride::set(&tempdir.path().join("cache"));
+        let root = tempdir.path().join("rattler-root");
+        let channel = tempdir.path().join("channel");
+        write_installable_channel(
+            &channel,
+            &[
+                ("samtools", "1.19", &["htslib"]),
+                ("samtools", "1.20", &["htslib"]),
+                ("htslib", "1.19", &[]),
+                ("htslib", "1.20", &[]),
+                ("seqtk", "1.4", &[]),
+                ("bwa", "0.7.18", &[]),
+            ],
+        );
+        let yaml_file = tempdir.path().join("demo.yaml");
+        write_environment_yaml(
+            &yaml_file,
+            &channel,
+            &["samtools 1.19.*", "htslib 1.19.*", "seqtk"],
+        );
+        let backend = backend_with_root(&root);
+        backend
+            .create_environment("demo", &yaml_file, false, false, OutputMode::Quiet)
+            .await
+            .unwrap();
+        let prefix = root.join("envs").join("demo");
+        let target = EnvironmentTarget::Name("demo".to_string());
+
+        write_environment_yaml(&yaml_file, &channel, &["samtools 1.20.*", "htslib", "bwa"]);
+        backend
+            .sync_environment(&target, &yaml_file, OutputMode::Quiet)
+            .await
+            .unwrap();
 
-        let result = clone_prefix_for_staging(&source, &destination).unwrap();
-        assert_eq!(result.symlinks_copied, 2);
         assert_eq!(
-            fs::read_link(destination.join("lib/current.so")).unwrap(),
-            PathBuf::from("library.so")
+            installed_versions(&prefix),
+            vec!["bwa=0.7.18", "htslib=1.19", "samtools=1.20"]
         );
+        assert!(!prefix.join("share/enva-fixtures/seqtk.txt").exists());
+        let source = read_environment_metadata(&prefix)
+            .unwrap()
+            .yaml_source
+            .unwrap();
+        assert_eq!(source.path, fs::canonicalize(&yaml_file).unwrap());
         assert_eq!(
-            fs::read_link(destination.join("absolute-library.so")).unwrap(),
-            destination.join("lib/library.so")
+            source.sha256,
+            hex::encode(
+                rattler_digest::compute_file_digest::<rattler_digest::Sha256>(&yaml_file).unwrap()
+            )
+        );
+        let history = backend.environment_history(&target).await.unwrap();
+        assert_eq!(history.last().unwrap().operation, RevisionOperation::Sync);
+        assert_eq!(
+            history.last().unwrap().requested_specs,
+            vec!["samtools 1.20.*", "htslib", "bwa"]
         );
 
-        let outside = temporary_directory.path().join("outside.txt");
-        fs::write(&outside, b"outside").unwrap();
-        let escaping_source = temporary_directory.path().join("escaping-source");
-        fs::create_dir(&escaping_source).unwrap();
-        std::os::unix::fs::symlink("../outside.txt", escaping_source.join("relative-escape"))
+        backend
+            .sync_environment(&target, &yaml_file, OutputMode::Quiet)
+            .await
             .unwrap();
-        let error = clone_prefix_for_staging(
-            &escaping_source,
-            &temporary_directory.path().join("escaping-stage"),
-        )
-        .unwrap_err();
-        assert!(error.to_string().contains("escaping source prefix"));
+        assert_eq!(backend.environment_history(&target).await.unwrap().len(), 2);
+    }
 
-        fs::remove_file(escaping_source.join("relative-escape")).unwrap();
-        std::os::unix::fs::symlink(&outside, escaping_source.join("absolute-escape")).unwrap();
-        let error = clone_prefix_for_staging(
-            &escaping_source,
-            &temporary_directory.path().join("absolute-stage"),
-        )
-        .unwrap_err();
-        assert!(error.to_string().contains("escaping source prefix"));
+    #[tokio::test]
+    #[allow(clippy::await_holding_lock)]
+    async fn verify_reports_clean_prefix_and_tampered_files() {
+        let _guard = env_lock().lock().unwrap();
+        let tempdir = tempdir().unwrap();
+        let _cache_dir = CacheDirOverride::set(&tempdir.path().join("cache"));
+        let root = tempdir.path().join("rattler-root");
+        let channel = tempdir.path().join("channel");
+        write_installable_channel(
+            &channel,
+            &[("samtools", "1.20", &[]), ("seqtk", "1.4", &[])],
+        );
+        let yaml_file = tempdir.path().join("demo.yaml");
+        write_environment_yaml(&yaml_file, &channel, &["samtools", "seqtk"]);
+        let backend = backend_with_root(&root);
+        backend
+            .create_environment("demo", &yaml_file, false, false, OutputMode::Quiet)
+            .await
+            .unwrap();
+        let prefix = root.join("envs").join("demo");
+        let target = EnvironmentTarget::Name("demo".to_string());
+
+        let clean = backend.verify_environment(&target).await.unwrap();
+        fs::write(prefix.join("share/enva-fixtures/samtools.txt"), "tampered").unwrap();
+        fs::write(prefix.join("share/enva-fixtures/stray.txt"), "pip").unwrap();
+        let tampered = backend.verify_environment(&target).await.unwrap();
+
+        assert!(!clean.has_drift(), "{:?}", clean);
+        assert_eq!(clean.packages_checked, 2);
+        assert_eq!(tampered.damaged_packages.len(), 1);
+        assert_eq!(tampered.damaged_packages[0].name, "samtools");
+        assert_eq!(
+            tampered.extra_files,
+            vec![PathBuf::from("share/enva-fixtures/stray.txt")]
+        );
     }
 
-    #[test]
-    #[cfg(unix)]
-    fn publication_rewrites_absolute_symlinks_and_rejects_all_file_residuals() {
+    #[tokio::test]
+    #[allow(clippy::await_holding_lock)]
+    async fn repair_relinks_damaged_packages_even_after_the_cache_was_cleaned() {
         let _guard = env_lock().lock().unwrap();
-        let temporary_directory = tempdir().unwrap();
-        let staging = temporary_directory.path().join("staging-prefix");
-        let final_prefix = temporary_directory
-            .path()
-            .join("final-prefix-with-longer-name");
-        fs::create_dir_all(staging.join("bin")).unwrap();
-        fs::write(
-            staging.join("bin/tool"),
-            b"prefix already patched for final path\n",
-        )
+        let tempdir = tempdir().unwrap();
+        let _cache_dir = CacheDirOverride::set(&tempdir.path().join("cache"));
+        let root = tempdir.path().join("rattler-root");
+        let channel = tempdir.path().join("channel");
+        write_installable_channel(
+            &channel,
+            &[("samtools", "1.20", &[]), ("seqtk", "1.4", &[])],
+        );
+        let yaml_file = tempdir.path().join("demo.yaml");
+        write_environment_yaml(&yaml_file, &channel, &["samtools", "seqtk"]);
+        let backend = backend_with_root(&root);
+        backend
+            .create_environment("demo", &yaml_file, false, false, OutputMode::Quiet)
+            .await
+            .unwrap();
+        let prefix = root.join("envs").join("demo");
+        let target = EnvironmentTarget::Name("demo".to_string());
+        let samtools = prefix.join("share/enva-fixtures/samtools.txt");
+        let seqtk = prefix.join("share/enva-fixtures/seqtk.txt");
+
+        let clean = backend
+            .repair_environment(&target, OutputMode::Quiet)
+            .await
+            .unwrap();
+        assert!(clean.damaged_packages.is_empty());
+
+        fs::write(&samtools, "tampered").unwrap();
+        fs::remove_file(&seqtk).unwrap();
+        let repaired = backend
+            .repair_environment(&target, OutputMode::Quiet)
+            .await
+            .unwrap();
+        assert_eq!(repaired.damaged_packages.len(), 2);
+        assert_eq!(fs::read_to_string(&samtools).unwrap(), "samtools 1.20\n");
+        assert_eq!(fs::read_to_string(&seqtk).unwrap(), "seqtk 1.4\n");
+        assert!(!backend
+            .verify_environment(&target)
+            .await
+            .unwrap()
+            .has_drift());
+
+        fs::write(&samtools, "tampered again").unwrap();
+        fs::remove_dir_all(RattlerBackend::package_cache_dir(
+            &RattlerBackend::cache_root_dir().unwrap(),
+        ))
         .unwrap();
-        std::os::unix::fs::symlink(staging.join("bin/tool"), staging.join("absolute-tool"))
+        backend
+            .repair_environment(&target, OutputMode::Quiet)
+            .await
             .unwrap();
+        assert_eq!(fs::read_to_string(&samtools).unwrap(), "samtools 1.20\n");
+        assert!(!backend
+            .verify_environment(&target)
+            .await
+            .unwrap()
+            .has_drift());
+    }
 
-        let result = validate_staged_prefix_for_publication(&staging, &final_prefix).unwrap();
-        assert_eq!(result.files_scanned, 1);
-        assert_eq!(result.symlinks_rewritten, 1);
+    #[tokio::test]
+    #[allow(clippy::await_holding_lock)]
+    async fn clone_relocates_prefix_placeholders_into_the_new_environment() {
+        let _guard = env_lock().lock().unwrap();
+        let tempdir = tempdir().unwrap();
+        let _cache_dir = CacheDirOverride::set(&tempdir.path().join("cache"));
+        let root = tempdir.path().join("rattler-root");
+        let channel = tempdir.path().join("channel");
+        write_installable_channel(&channel, &[("samtools", "1.20", &[])]);
+        let yaml_file = tempdir.path().join("demo.yaml");
+        write_environment_yaml(&yaml_file, &channel, &["samtools"]);
+        let backend = backend_with_root(&root);
+        backend
+            .create_environment("demo", &yaml_file, false, false, OutputMode::Quiet)
+            .await
+            .unwrap();
+        let source = root.join("envs").join("demo");
+        let copy = root.join("envs").join("demo-experiment");
+        let prefix_file = "share/enva-fixtures/samtools.prefix";
         assert_eq!(
-            fs::read_link(staging.join("absolute-tool")).unwrap(),
-            final_prefix.join("bin/tool")
+            fs::read_to_string(source.join(prefix_file)).unwrap(),
+            format!("{}/share/enva-fixtures\n", source.display())
         );
 
-        let text_staging = temporary_directory.path().join("text-staging");
-        fs::create_dir(&text_staging).unwrap();
-        fs::write(
-            text_staging.join("script"),
-            format!("#!{}/bin/python\n", text_staging.display()),
-        )
-        .unwrap();
-        let text_error =
-            validate_staged_prefix_for_publication(&text_staging, &final_prefix).unwrap_err();
-        assert!(text_error
-            .to_string()
-            .contains("File contains staging prefix residual after target-prefix installation"));
+        backend
+            .clone_environment(
+                &EnvironmentTarget::Name("demo".to_string()),
+                "demo-experiment",
+                false,
+                OutputMode::Quiet,
+            )
+            .await
+            .unwrap();
 
-        let binary_staging = temporary_directory.path().join("binary-staging");
-        fs::create_dir(&binary_staging).unwrap();
-        let mut binary: Vec<u8> = vec![0_u8, 1, 2];
-        binary.extend_from_slice(binary_staging.to_string_lossy().as_bytes());
-        binary.push(0);
-        fs::write(binary_staging.join("binary"), binary).unwrap();
-        let binary_error =
-            validate_staged_prefix_for_publication(&binary_staging, &final_prefix).unwrap_err();
-        assert!(binary_error
+        assert_eq!(
+            fs::read_to_string(copy.join(prefix_file)).unwrap(),
+            format!("{}/share/enva-fixtures\n", copy.display())
+        );
+        assert_eq!(
+            fs::read_to_string(source.join(prefix_file)).unwrap(),
+            format!("{}/share/enva-fixtures\n", source.display())
+        );
+        assert!(read_ownership_record(&copy)
+            .unwrap()
+            .unwrap()
+            .is_rattler_owned());
+        let copy_target = EnvironmentTarget::Name("demo-experiment".to_string());
+        assert!(!backend
+            .verify_environment(&copy_target)
+            .await
+            .unwrap()
+            .has_drift());
+        assert!(backend
+            .clone_environment(
+                &EnvironmentTarget::Prefix(source.clone()),
+                "demo-experiment",
+                false,
+                OutputMode::Quiet,
+            )
+            .await
+            .unwrap_err()
             .to_string()
-            .contains("File contains staging prefix residual after target-prefix installation"));
+            .contains("already exists"));
     }
 
-    #[test]
-    fn large_prefix_clone_without_reflinks_completes_with_expected_accounting() {
+    #[tokio::test]
+    #[allow(clippy::await_holding_lock)]
+    async fn rename_and_move_relocate_the_prefix_and_register_it() {
         let _guard = env_lock().lock().unwrap();
-        let temporary_directory = tempdir().unwrap();
-        let source = temporary_directory.path().join("large-source");
-        let destination = temporary_directory.path().join("large-stage");
-        fs::create_dir(&source).unwrap();
-        let payload = vec![b'x'; 4096];
-        for file_index in 0..2_000_u32 {
-            fs::write(source.join(format!("file-{file_index:04}.dat")), &payload).unwrap();
-        }
+        let tempdir = tempdir().unwrap();
+        let _cache_dir = CacheDirOverride::set(&tempdir.path().join("cache"));
+        let root = tempdir.path().join("rattler-root");
+        let project_root = tempdir.path().join("project");
+        let registry = tempdir.path().join("data/enva/environments.txt");
+        let channel = tempdir.path().join("channel");
+        write_installable_channel(&channel, &[("samtools", "1.20", &[])]);
+        let yaml_file = tempdir.path().join("demo.yaml");
+        write_environment_yaml(&yaml_file, &channel, &["samtools"]);
+        let backend = backend_with_root(&root).with_environment_registry(registry.clone());
+        backend
+            .create_environment("demo", &yaml_file, false, false, OutputMode::Quiet)
+            .await
+            .unwrap();
+        let prefix_file = "share/enva-fixtures/samtools.prefix";
 
-        let result = benchmark_prefix_clone(&source, &destination).unwrap();
-        assert_eq!(result.files_copied, 2_000);
-        assert_eq!(result.bytes_copied, 2_000 * 4096);
-        assert!(
-            result.elapsed_millis < 30_000,
-            "clone took {} ms",
-            result.elapsed_millis
+        let renamed = backend
+            .rename_environment(
+                &EnvironmentTarget::Name("demo".to_string()),
+                "qc",
+                OutputMode::Quiet,
+            )
+            .await
+            .unwrap();
+        assert_eq!(renamed, root.join("envs").join("qc"));
+        assert!(!root.join("envs").join("demo").exists());
+        assert_eq!(
+            fs::read_to_string(renamed.join(prefix_file)).unwrap(),
+            format!("{}/share/enva-fixtures\n", renamed.display())
         );
+
+        let moved = backend
+            .move_environment(
+                &EnvironmentTarget::Name("qc".to_string()),
+                &project_root,
+                OutputMode::Quiet,
+            )
+            .await
+            .unwrap();
+        let expected = fs::canonicalize(&project_root).unwrap().join("envs/qc");
+        assert_eq!(moved, expected);
+        assert!(!renamed.exists());
         assert_eq!(
-            fs::read(destination.join("file-1999.dat")).unwrap(),
-            payload
+            fs::read_to_string(moved.join(prefix_file)).unwrap(),
+            format!("{}/share/enva-fixtures\n", moved.display())
         );
+        assert_eq!(
+            fs::read_to_string(&registry).unwrap(),
+            format!("{}\n", moved.display())
+        );
+
+        let target = EnvironmentTarget::Name("qc".to_string());
+        let verification = backend.verify_environment(&target).await.unwrap();
+        assert_eq!(verification.prefix, moved);
+        assert!(!verification.has_drift());
     }
 
     #[tokio::test]
     #[allow(clippy::await_holding_lock)]
-    async fn validate_yaml_accepts_basic_environment_file() {
+    async fn unpack_relocates_a_packed_environment_without_cache_or_channel() {
         let _guard = env_lock().lock().unwrap();
         let tempdir = tempdir().unwrap();
-        let yaml_file = tempdir.path().join("env.yaml");
-        fs::write(
-            &yaml_file,
-            "name: test-env\nchannels:\n  - conda-forge\ndependencies:\n  - python=3.10\n  - pip\n",
-        )
-        .unwrap();
-
-        let backend = RattlerBackend::new();
-        let result = backend.validate_yaml(&yaml_file).await.unwrap();
+        let cache = tempdir.path().join("cache");
+        let _cache_dir = CacheDirOverride::set(&cache);
+        let root = tempdir.path().join("rattler-root");
+        let channel = tempdir.path().join("channel");
+        write_installable_channel(&channel, &[("samtools", "1.20", &[])]);
+        let yaml_file = tempdir.path().join("demo.yaml");
+        write_environment_yaml(&yaml_file, &channel, &["samtools"]);
+        let registry = tempdir.path().join("data/enva/environments.txt");
+        let backend = backend_with_root(&root).with_environment_registry(registry.clone());
+        backend
+            .create_environment("demo", &yaml_file, false, false, OutputMode::Quiet)
+            .await
+            .unwrap();
 
-        assert_eq!(result.environment, "test-env");
-        assert!(result.validation.syntax_valid);
-        assert_eq!(result.estimated_packages, 2);
-        assert_eq!(result.channels_accessible, vec!["conda-forge".to_string()]);
+        let archive = tempdir.path().join("demo.tar.zst");
+        let files = backend
+            .pack_environment(
+                &EnvironmentTarget::Name("demo".to_string()),
+                &archive,
+                OutputMode::Quiet,
+            )
+            .await
+            .unwrap();
+        assert!(files > 0);
+        fs::remove_dir_all(&cache).unwrap();
+        fs::remove_dir_all(&channel).unwrap();
+
+        let prefix_file = "share/enva-fixtures/samtools.prefix";
+        let named = backend
+            .unpack_environment(
+                &archive,
+                &EnvironmentTarget::Name("copy".to_string()),
+                false,
+                OutputMode::Quiet,
+            )
+            .await
+            .unwrap();
+        assert_eq!(named, root.join("envs").join("copy"));
+        assert_eq!(
+            fs::read_to_string(named.join(prefix_file)).unwrap(),
+            format!("{}/share/enva-fixtures\n", named.display())
+        );
+        assert!(read_ownership_record(&named).unwrap().is_some());
+        let verification = backend
+            .verify_environment(&EnvironmentTarget::Name("copy".to_string()))
+            .await
+            .unwrap();
+        assert!(!verification.has_drift());
+
+        let external = tempdir.path().join("project/envs/demo");
+        let unpacked = backend
+            .unpack_environment(
+                &archive,
+                &EnvironmentTarget::Prefix(external.clone()),
+                false,
+                OutputMode::Quiet,
+            )
+            .await
+            .unwrap();
+        assert_eq!(unpacked, fs::canonicalize(&external).unwrap());
+        assert!(!registry.exists());
+        assert!(backend
+            .unpack_environment(
+                &archive,
+                &EnvironmentTarget::Prefix(external),
+                false,
+                OutputMode::Quiet,
+            )
+            .await
+            .is_err());
     }
 
     #[tokio::test]
     #[allow(clippy::await_holding_lock)]
-    async fn validate_yaml_reports_missing_dependencies_section() {
+    async fn bundle_writes_a_local_channel_that_create_can_use_instead_of_the_yaml_channels() {
         let _guard = env_lock().lock().unwrap();
         let tempdir = tempdir().unwrap();
-        let yaml_file = tempdir.path().join("env.yaml");
-        fs::write(&yaml_file, "name: test-env\nchannels:\n  - conda-forge\n").unwrap();
-
-        let backend = RattlerBackend::new();
-        let result = backend.validate_yaml(&yaml_file).await.unwrap();
+        let cache = tempdir.path().join("cache");
+        let _cache_dir = CacheDirOverride::set(&cache);
+        let root = tempdir.path().join("rattler-root");
+        let channel = tempdir.path().join("channel");
+        write_installable_channel(
+            &channel,
+            &[("samtools", "1.20", &["htslib"]), ("htslib", "1.20", &[])],
+        );
+        let yaml_file = tempdir.path().join("demo.yaml");
+        write_environment_yaml(&yaml_file, &channel, &["samtools"]);
+        let backend = backend_with_root(&root);
 
-        assert!(!result.validation.syntax_valid);
-        assert!(result
-            .validation
-            .version_conflicts
-            .iter()
-            .any(|issue| issue.contains("Missing required 'dependencies' section")));
+        let bundle = tempdir.path().join("bundle");
+        let bundled = backend
+            .bundle_environment(&yaml_file, &bundle, OutputMode::Quiet)
+            .await
+            .unwrap();
+        assert_eq!(bundled, 2);
+        assert!(bundle.join("noarch/samtools-1.20-h0_0.tar.bz2").is_file());
+        assert!(bundle
+            .join(rattler_conda_types::Platform::current().as_str())
+            .join("repodata.json")
+            .is_file());
+        let repodata =
+            rattler_conda_types::RepoData::from_path(bundle.join("noarch/repodata.json")).unwrap();
+        assert_eq!(repodata.packages.len(), 2);
+        assert!(cache.join("pkgs/htslib-1.20-h0_0.tar.bz2").is_file());
+
+        fs::remove_dir_all(&channel).unwrap();
+        fs::remove_dir_all(&cache).unwrap();
+        backend
+            .create_environment_with_channels(
+                "demo",
+                &yaml_file,
+                &[format!("file://{}", bundle.display())],
+                false,
+                false,
+                OutputMode::Quiet,
+            )
+            .await
+            .unwrap();
+        let installed =
+            RattlerBackend::collect_installed_prefix_records(&root.join("envs/demo")).unwrap();
+        assert_eq!(installed.len(), 2);
     }
 
     #[tokio::test]
     #[allow(clippy::await_holding_lock)]
-    async fn validate_yaml_reports_pip_subsection_as_unsupported() {
+    async fn offline_create_installs_from_a_fetched_cache_and_lists_missing_packages() {
         let _guard = env_lock().lock().unwrap();
         let tempdir = tempdir().unwrap();
-        let yaml_file = tempdir.path().join("env.yaml");
-        fs::write(
-            &yaml_file,
-            "name: test-env\nchannels:\n  - conda-forge\ndependencies:\n  - python=3.10\n  - pip:\n    - requests\n",
-        )
-        .unwrap();
-
-        let backend = RattlerBackend::new();
-        let result = backend.validate_yaml(&yaml_file).await.unwrap();
-
-        assert!(!result.validation.syntax_valid);
-        assert!(result
-            .validation
-            .version_conflicts
-            .iter()
-            .any(|issue| issue.contains("pip subsection")));
-    }
+        let cache = tempdir.path().join("cache");
+        let cache_dir = CacheDirOverride::set(&cache);
+        let root = tempdir.path().join("rattler-root");
+        let channel = tempdir.path().join("channel");
+        write_installable_channel(
+            &channel,
+            &[("samtools", "1.20", &["htslib"]), ("htslib", "1.20", &[])],
+        );
+        let yaml_file = tempdir.path().join("demo.yaml");
+        write_environment_yaml(&yaml_file, &channel, &["samtools"]);
 
-    #[test]
-    fn dedupe_paths_preserves_detection_order() {
-        let ordered = RattlerBackend::dedupe_paths(vec![
-            PathBuf::from("/preferred-root"),
-            PathBuf::from("/fallback-root"),
-            PathBuf::from("/preferred-root"),
-        ]);
+        let fetched = backend_with_root(&root)
+            .fetch_environment(&yaml_file, OutputMode::Quiet)
+            .await
+            .unwrap();
+        assert_eq!(fetched, 2);
+        for entry in fs::read_dir(channel.join("noarch")).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|extension| extension == "bz2") {
+                fs::remove_file(path).unwrap();
+            }
+        }
 
+        let backend = backend_with_root(&root).with_offline(true);
+        backend
+            .create_environment("demo", &yaml_file, false, false, OutputMode::Quiet)
+            .await
+            .unwrap();
         assert_eq!(
-            ordered,
-            vec![
-                PathBuf::from("/preferred-root"),
-                PathBuf::from("/fallback-root"),
-            ]
+            RattlerBackend::collect_installed_prefix_records(&root.join("envs/demo"))
+                .unwrap()
+                .len(),
+            2
         );
+
+        drop(cache_dir);
+        let _cache_dir = CacheDirOverride::set(&tempdir.path().join("empty-cache"));
+        let error = backend
+            .create_environment("demo-2", &yaml_file, false, false, OutputMode::Quiet)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("2 package(s)"), "{error}");
+        assert!(error.contains("samtools-1.20-h0_0.tar.bz2"), "{error}");
+        assert!(error.contains("htslib-1.20-h0_0.tar.bz2"), "{error}");
+        assert!(!root.join("envs/demo-2").exists());
+        assert!(backend
+            .fetch_environment(&yaml_file, OutputMode::Quiet)
+            .await
+            .is_err());
     }
 
-    #[test]
-    fn base_prefix_with_conda_layout_is_detected_without_default_env() {
+    #[tokio::test]
+    #[allow(clippy::await_holding_lock)]
+    async fn channel_priority_follows_cli_yaml_and_global_config_and_is_recorded() {
+        let _guard = env_lock().lock().unwrap();
         let tempdir = tempdir().unwrap();
-        let root = tempdir.path().join("active-conda-root");
-        fs::create_dir_all(root.join("conda-meta")).unwrap();
-        fs::create_dir_all(root.join("envs")).unwrap();
+        let _cache_dir = CacheDirOverride::set(&tempdir.path().join("cache"));
+        let root = tempdir.path().join("rattler-root");
+        let preferred = tempdir.path().join("preferred");
+        let fallback = tempdir.path().join("fallback");
+        write_installable_channel(
+            &preferred,
+            &[
+                ("samtools", "1.20", &["htslib >=1.20"]),
+                ("htslib", "1.19", &[]),
+            ],
+        );
+        write_installable_channel(&fallback, &[("htslib", "1.20", &[])]);
+        let write_yaml = |path: &Path, priority: Option<&str>| {
+            let mut yaml = format!(
+                "name: demo\nchannels:\n  - {}\n  - {}\ndependencies:\n  - samtools >=1.20\n",
+                preferred.display(),
+                fallback.display()
+            );
+            if let Some(priority) = priority {
+                yaml.push_str(&format!("channel_priority: {}\n", priority));
+            }
+            fs::write(path, yaml).unwrap();
+        };
+        let strict_yaml = tempdir.path().join("strict.yaml");
+        write_yaml(&strict_yaml, Some("strict"));
+        let plain_yaml = tempdir.path().join("plain.yaml");
+        write_yaml(&plain_yaml, None);
+
+        let error = backend_with_root(&root)
+            .create_environment("strict", &strict_yaml, false, false, OutputMode::Quiet)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, EnvError::SolveConflict(_)), "{error}");
 
+        backend_with_root(&root)
+            .with_channel_priority(Some(ChannelPriorityMode::Flexible))
+            .create_environment("flexible", &strict_yaml, false, false, OutputMode::Quiet)
+            .await
+            .unwrap();
         assert_eq!(
-            RattlerBackend::classify_conda_prefix(&root, None),
-            super::CondaPrefixLayout::BaseRoot(root)
+            read_environment_metadata(&root.join("envs/flexible"))
+                .unwrap()
+                .channel_priority,
+            Some(ChannelPriorityMode::Flexible)
         );
-    }
 
-    #[test]
-    fn preferred_root_prefix_uses_first_existing_root() {
-        let _guard = env_lock().lock().unwrap();
-        let tempdir = tempdir().unwrap();
-        let preferred = tempdir.path().join("preferred-root");
-        let fallback = tempdir.path().join("fallback-root");
-        fs::create_dir_all(&preferred).unwrap();
-        fs::create_dir_all(&fallback).unwrap();
+        let config = tempdir.path().join("config.yaml");
+        fs::write(&config, "channel_priority: strict\n").unwrap();
+        assert!(backend_with_root(&root)
+            .with_global_config(config.clone())
+            .create_environment("global", &plain_yaml, false, false, OutputMode::Quiet)
+            .await
+            .is_err());
 
-        let backend = RattlerBackend::with_root_prefixes(vec![
-            preferred.clone(),
-            fallback.clone(),
-            preferred.clone(),
-        ]);
+        fs::write(&config, "channel_priority: disabled\n").unwrap();
+        backend_with_root(&root)
+            .with_global_config(config)
+            .create_environment("disabled", &plain_yaml, false, false, OutputMode::Quiet)
+            .await
+            .unwrap();
+        assert_eq!(
+            read_environment_metadata(&root.join("envs/disabled"))
+                .unwrap()
+                .channel_priority,
+            Some(ChannelPriorityMode::Disabled)
+        );
 
-        assert_eq!(backend.preferred_root_prefix(), preferred);
+        write_yaml(&strict_yaml, Some("sometimes"));
+        assert!(backend_with_root(&root)
+            .create_environment("invalid", &strict_yaml, false, false, OutputMode::Quiet)
+            .await
+            .unwrap_err()
+            .to_string()
+            .contains("channel_priority"));
     }
 
-    #[test]
-    fn target_prefix_uses_envs_subdirectory() {
+    #[tokio::test]
+    #[allow(clippy::await_holding_lock)]
+    async fn later_solves_use_the_channels_recorded_at_creation() {
         let _guard = env_lock().lock().unwrap();
         let tempdir = tempdir().unwrap();
+        let _cache_dir = CacheDirOverride::set(&tempdir.path().join("cache"));
         let root = tempdir.path().join("rattler-root");
-        let backend = backend_with_root(&root);
+        let tools = tempdir.path().join("channels/tools");
+        let extras = tempdir.path().join("channels/extras");
+        write_installable_channel(&tools, &[("samtools", "1.20", &[])]);
+        write_installable_channel(&extras, &[("seqtk", "1.4", &[]), ("bwa", "0.7.18", &[])]);
+        let yaml_file = tempdir.path().join("channels/demo.yaml");
+        fs::write(
+            &yaml_file,
+            "name: demo\nchannels:\n  - ./tools\n  - ./extras\ndependencies:\n  - samtools\n",
+        )
+        .unwrap();
 
-        let target_prefix = backend.target_prefix_for_env_name("test-env").unwrap();
-        assert_eq!(target_prefix, root.join("envs").join("test-env"));
+        backend_with_root(&root)
+            .create_environment("demo", &yaml_file, false, false, OutputMode::Quiet)
+            .await
+            .unwrap();
+        let prefix = root.join("envs/demo");
+        let channels = read_environment_metadata(&prefix).unwrap().channels;
+        assert_eq!(channels.len(), 2);
+        assert!(channels[0].starts_with("file://"), "{channels:?}");
+        assert!(channels[0].ends_with("/channels/tools/"), "{channels:?}");
+        assert!(channels[1].ends_with("/channels/extras/"), "{channels:?}");
+
+        // No installed package comes from `extras`, so only the recorded channels find seqtk.
+        fs::remove_file(&yaml_file).unwrap();
+        backend_with_root(&root)
+            .install_packages("demo", &["seqtk".to_string()], OutputMode::Quiet)
+            .await
+            .unwrap();
+        assert_eq!(
+            installed_versions(&prefix),
+            vec!["samtools=1.20", "seqtk=1.4"]
+        );
+
+        assert!(backend_with_root(&root)
+            .with_channels(vec![tools.display().to_string()])
+            .install_packages("demo", &["bwa".to_string()], OutputMode::Quiet)
+            .await
+            .is_err());
+        assert_eq!(
+            read_environment_metadata(&prefix).unwrap().channels,
+            channels
+        );
     }
 
+    #[cfg(unix)]
     #[tokio::test]
     #[allow(clippy::await_holding_lock)]
-    async fn find_environment_prefixes_returns_named_environment() {
+    async fn create_stores_yaml_variables_and_run_sets_them() {
         let _guard = env_lock().lock().unwrap();
         let tempdir = tempdir().unwrap();
+        let _cache_dir = CacheDirOverride::set(&tempdir.path().join("cache"));
         let root = tempdir.path().join("rattler-root");
-        let env_prefix = root.join("envs").join("test-env");
-        create_fake_environment(&env_prefix);
+        let channel = tempdir.path().join("channel");
+        write_installable_channel(&channel, &[("picard", "3.1", &[])]);
+        let yaml_file = tempdir.path().join("demo.yaml");
+        write_environment_yaml(&yaml_file, &channel, &["picard"]);
+        let mut yaml = fs::read_to_string(&yaml_file).unwrap();
+        yaml.push_str("variables:\n  JAVA_HOME: /opt/java\n  R_LIBS_USER: ''\n");
+        fs::write(&yaml_file, yaml).unwrap();
 
         let backend = backend_with_root(&root);
-        let prefixes = backend.find_environment_prefixes("test-env").await.unwrap();
+        backend
+            .create_environment("demo", &yaml_file, false, false, OutputMode::Quiet)
+            .await
+            .unwrap();
+        let prefix = root.join("envs/demo");
+        let variables = read_environment_variables(&prefix).unwrap();
+        assert_eq!(variables["JAVA_HOME"], "/opt/java");
+        assert_eq!(variables["R_LIBS_USER"], "");
+
+        let run = |command: &str, env_vars: Vec<String>| RunRequest {
+            command: RunCommand::Shell(command.to_string()),
+            env_vars,
+            cwd: tempdir.path().to_path_buf(),
+            capture_output: true,
+        };
+        let target = EnvironmentTarget::Prefix(prefix.clone());
+        backend
+            .run(
+                &target,
+                &run(
+                    "test \"$JAVA_HOME\" = /opt/java && test \"${R_LIBS_USER+x}\" = x",
+                    vec![],
+                ),
+            )
+            .await
+            .unwrap();
+        // Variables given to `run -e` win over the environment's own.
+        backend
+            .run(
+                &target,
+                &run(
+                    "test \"$JAVA_HOME\" = /usr/lib/jvm",
+                    vec!["JAVA_HOME=/usr/lib/jvm".to_string()],
+                ),
+            )
+            .await
+            .unwrap();
 
-        assert_eq!(prefixes, vec![env_prefix]);
+        let invalid_yaml = tempdir.path().join("invalid.yaml");
+        write_environment_yaml(&invalid_yaml, &channel, &["picard"]);
+        let mut yaml = fs::read_to_string(&invalid_yaml).unwrap();
+        yaml.push_str("variables:\n  BAD-NAME: x\n");
+        fs::write(&invalid_yaml, yaml).unwrap();
+        let error = backend
+            .create_environment("invalid", &invalid_yaml, false, false, OutputMode::Quiet)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("BAD-NAME"), "{error}");
+    }
+
+    #[test]
+    fn remove_requested_specs_matches_package_names_across_spec_forms() {
+        let remaining = RattlerBackend::remove_requested_specs(
+            vec![
+                "bioconda::samtools >=1.20".to_string(),
+                "seqtk".to_string(),
+                "htslib ==1.20 h0_0".to_string(),
+            ],
+            &["samtools".to_string(), "htslib".to_string()],
+        );
+
+        assert_eq!(remaining, vec!["seqtk"]);
     }
 
     #[tokio::test]
@@ -2732,6 +6658,44 @@ mod tests {
         assert!(!RattlerBackend::has_native_rattler_conflict(&environment));
     }
 
+    #[tokio::test]
+    async fn dry_run_transactions_do_not_adopt_foreign_environments() {
+        let tempdir = tempdir().unwrap();
+        let prefix = tempdir.path().join("envs").join("demo");
+        fs::create_dir_all(prefix.join("conda-meta")).unwrap();
+        let environment = discovered_environment_with_owner(
+            "demo",
+            prefix.to_str().unwrap(),
+            EnvironmentSource::PackageManager(PackageManager::Micromamba),
+            EnvironmentOwner::External,
+            None,
+        );
+
+        let unadopted = backend_with_root(tempdir.path())
+            .with_transaction_approval(TransactionApproval::DryRun)
+            .adopt_for_transaction(environment.clone(), OutputMode::Quiet)
+            .await
+            .unwrap();
+        assert!(!unadopted.rattler_managed());
+        assert_eq!(
+            RattlerBackend::helper_package_manager(&unadopted),
+            Some(PackageManager::Micromamba)
+        );
+        assert!(crate::ownership::read_ownership_record(&prefix)
+            .unwrap()
+            .is_none());
+
+        let adopted = backend_with_root(tempdir.path())
+            .with_transaction_approval(TransactionApproval::AssumeYes)
+            .adopt_for_transaction(environment, OutputMode::Quiet)
+            .await
+            .unwrap();
+        assert!(adopted.rattler_managed());
+        assert!(crate::ownership::read_ownership_record(&prefix)
+            .unwrap()
+            .is_some());
+    }
+
     #[test]
     fn has_native_rattler_conflict_rejects_unadopted_rattler_environment() {
         let environment = discovered_environment_with_owner(
@@ -2881,7 +6845,25 @@ mod tests {
     fn default_channel_priority_is_disabled() {
         assert_eq!(
             RattlerBackend::default_channel_priority(),
-            ChannelPriority::Disabled
+            ChannelPriorityMode::Disabled
+        );
+    }
+
+    #[test]
+    fn yaml_channel_alias_resolves_named_channels() {
+        let tempdir = tempdir().unwrap();
+        let yaml_file = tempdir.path().join("demo.yaml");
+        fs::write(
+            &yaml_file,
+            "name: demo\nchannel_alias: https://mirror.example.org/\nchannels:\n  - bioconda\ndependencies:\n  - samtools\n",
+        )
+        .unwrap();
+        let environment_yaml = RattlerBackend::parse_environment_yaml(&yaml_file).unwrap();
+
+        let channels = RattlerBackend::resolve_channels(&yaml_file, &environment_yaml).unwrap();
+        assert_eq!(
+            RattlerBackend::channel_urls(&channels),
+            vec!["https://mirror.example.org/bioconda/"]
         );
     }
 
@@ -2973,6 +6955,83 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    #[allow(clippy::await_holding_lock)]
+    async fn lock_environment_pins_solved_records_from_local_channel() {
+        let _guard = env_lock().lock().unwrap();
+        let tempdir = tempdir().unwrap();
+        let _cache_dir = CacheDirOverride::set(&tempdir.path().join("cache"));
+        let channel = tempdir.path().join("channel");
+        write_local_channel(
+            &channel,
+            &[
+                ("samtools", "1.20", &["htslib >=1.20"]),
+                ("htslib", "1.20", &[]),
+            ],
+        );
+        let yaml_file = tempdir.path().join("demo.yaml");
+        write_environment_yaml(&yaml_file, &channel, &["samtools"]);
+        let lock_file = tempdir.path().join("demo.lock");
+
+        RattlerBackend::new()
+            .lock_environment(&yaml_file, &lock_file, OutputMode::Quiet)
+            .await
+            .unwrap();
+
+        let locked = crate::lockfile::read_locked_environment(
+            &lock_file,
+            rattler_conda_types::Platform::current(),
+        )
+        .unwrap();
+        let mut names = locked
+            .records
+            .iter()
+            .map(|record| record.package_record.name.as_normalized().to_string())
+            .collect::<Vec<String>>();
+        names.sort();
+        assert_eq!(names, vec!["htslib", "samtools"]);
+        assert!(locked
+            .records
+            .iter()
+            .all(|record| record.url.as_str().starts_with("file://")));
+    }
+
+    #[tokio::test]
+    #[allow(clippy::await_holding_lock)]
+    async fn environments_created_from_a_lock_keep_the_yaml_requested_specs() {
+        let _guard = env_lock().lock().unwrap();
+        let tempdir = tempdir().unwrap();
+        let _cache_dir = CacheDirOverride::set(&tempdir.path().join("cache"));
+        let root = tempdir.path().join("rattler-root");
+        let channel = tempdir.path().join("channel");
+        write_installable_channel(
+            &channel,
+            &[("samtools", "1.20", &["htslib"]), ("htslib", "1.20", &[])],
+        );
+        let yaml_file = tempdir.path().join("demo.yaml");
+        write_environment_yaml(&yaml_file, &channel, &["samtools >=1.19"]);
+        let lock_file = tempdir.path().join("demo.lock");
+        let backend = backend_with_root(&root);
+        backend
+            .lock_environment(&yaml_file, &lock_file, OutputMode::Quiet)
+            .await
+            .unwrap();
+
+        backend
+            .create_environment_from_lock("demo", &lock_file, false, false, OutputMode::Quiet)
+            .await
+            .unwrap();
+
+        let installed =
+            RattlerBackend::collect_installed_prefix_records(&root.join("envs").join("demo"))
+                .unwrap();
+        assert_eq!(installed.len(), 2);
+        assert_eq!(
+            RattlerBackend::requested_spec_strings_from_prefix_records(&installed),
+            vec!["samtools >=1.19"]
+        );
+    }
+
     #[cfg(unix)]
     #[tokio::test]
     #[allow(clippy::await_holding_lock)]
@@ -3005,4 +7064,43 @@ mod tests {
             .await
             .unwrap();
     }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    #[allow(clippy::await_holding_lock)]
+    async fn run_applies_activate_d_scripts() {
+        let _guard = env_lock().lock().unwrap();
+        let tempdir = tempdir().unwrap();
+        let root = tempdir.path().join("rattler-root");
+        let env_prefix = root.join("envs").join("test-env");
+        create_fake_environment(&env_prefix);
+        let activate_d = env_prefix.join("etc/conda/activate.d");
+        fs::create_dir_all(&activate_d).unwrap();
+        fs::write(
+            activate_d.join("openjdk.sh"),
+            "export JAVA_HOME=\"$CONDA_PREFIX/lib/jvm\"\nexport PATH=\"$JAVA_HOME/bin:$PATH\"\n",
+        )
+        .unwrap();
+        fs::create_dir_all(env_prefix.join("lib/jvm/bin")).unwrap();
+        let java = env_prefix.join("lib/jvm/bin/java-test-tool");
+        fs::write(&java, "#!/usr/bin/env bash\nexit 0\n").unwrap();
+        make_executable(&java);
+
+        backend_with_root(&root)
+            .run(
+                &EnvironmentTarget::Prefix(env_prefix.clone()),
+                &RunRequest {
+                    command: RunCommand::Shell(format!(
+                        "test \"$JAVA_HOME\" = '{}/lib/jvm' && test \"$CONDA_SHLVL\" = 1 && \
+                         java-test-tool",
+                        env_prefix.display()
+                    )),
+                    env_vars: vec![],
+                    cwd: tempdir.path().to_path_buf(),
+                    capture_output: true,
+                },
+            )
+            .await
+            .unwrap();
+    }
 }
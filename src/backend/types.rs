@@ -1,6 +1,7 @@
 use crate::error::{EnvError, Result};
 use crate::package_manager::PackageManager;
 use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
 use std::ffi::{OsStr, OsString};
 use std::fmt;
 use std::path::{Component, Path, PathBuf};
@@ -13,6 +14,74 @@ pub enum OutputMode {
     Quiet,
 }
 
+/// Whether a package may come from any channel or only from the first channel that has it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChannelPriorityMode {
+    /// Take every package from the highest-priority channel that provides it.
+    Strict,
+    /// Solve strictly first and fall back to any channel when that is unsatisfiable.
+    Flexible,
+    /// Take the best version from any channel.
+    #[default]
+    Disabled,
+}
+
+impl fmt::Display for ChannelPriorityMode {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str(match self {
+            Self::Strict => "strict",
+            Self::Flexible => "flexible",
+            Self::Disabled => "disabled",
+        })
+    }
+}
+
+/// Per-invocation settings the CLI hands to the backend it builds.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct BackendOptions {
+    pub transaction_approval: TransactionApproval,
+    /// Overrides the channel priority of the environment YAML, the environment and the
+    /// global config.
+    pub channel_priority: Option<ChannelPriorityMode>,
+    /// Overrides the channels an existing environment recorded when it was created.
+    pub channels: Vec<String>,
+    /// Solves only from cached repodata and installs only from the package cache, like
+    /// `ENVA_OFFLINE`.
+    pub offline: bool,
+}
+
+/// How a solved transaction that changes a prefix gets approved before it is applied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TransactionApproval {
+    /// Ask `[y/N]` when standard input and error are terminals; proceed otherwise.
+    #[default]
+    Prompt,
+    AssumeYes,
+    /// Print the plan and stop without changing anything.
+    DryRun,
+}
+
+impl TransactionApproval {
+    pub fn from_flags(yes: bool, dry_run: bool) -> Self {
+        if dry_run {
+            Self::DryRun
+        } else if yes {
+            Self::AssumeYes
+        } else {
+            Self::Prompt
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExportFormat {
+    #[default]
+    Pinned,
+    FromHistory,
+    Explicit,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BackendKind {
     Cli,
@@ -36,12 +105,31 @@ pub enum BackendCapability {
     ValidateYamlWithPackages,
     InstallByName,
     InstallByPrefix,
+    UninstallByName,
+    UninstallByPrefix,
+    UpdateByName,
+    UpdateByPrefix,
+    UpdateAllEnvironments,
+    SyncEnvironment,
     AdoptEnvironment,
     RemoveByName,
     RemoveByPrefix,
     DiscoverEnvironments,
     RunByName,
     RunByPrefix,
+    LockEnvironment,
+    CreateFromLock,
+    ExportEnvironment,
+    EnvironmentHistory,
+    RollbackEnvironment,
+    DiffEnvironments,
+    VerifyEnvironment,
+    RepairEnvironment,
+    CloneEnvironment,
+    RelocateEnvironment,
+    PackEnvironment,
+    BundleEnvironment,
+    FetchEnvironment,
 }
 
 impl fmt::Display for BackendCapability {
@@ -53,12 +141,31 @@ impl fmt::Display for BackendCapability {
             Self::ValidateYamlWithPackages => "validate YAML with additional packages",
             Self::InstallByName => "install packages by name",
             Self::InstallByPrefix => "install packages by prefix",
+            Self::UninstallByName => "uninstall packages by name",
+            Self::UninstallByPrefix => "uninstall packages by prefix",
+            Self::UpdateByName => "update packages by name",
+            Self::UpdateByPrefix => "update packages by prefix",
+            Self::UpdateAllEnvironments => "update all environments",
+            Self::SyncEnvironment => "sync environment with YAML",
             Self::AdoptEnvironment => "adopt environment",
             Self::RemoveByName => "remove environment by name",
             Self::RemoveByPrefix => "remove environment by prefix",
             Self::DiscoverEnvironments => "discover environments",
             Self::RunByName => "run by environment name",
             Self::RunByPrefix => "run by environment prefix",
+            Self::LockEnvironment => "lock environment",
+            Self::CreateFromLock => "create environment from lockfile",
+            Self::ExportEnvironment => "export environment",
+            Self::EnvironmentHistory => "show environment history",
+            Self::RollbackEnvironment => "roll back environment",
+            Self::DiffEnvironments => "diff environments",
+            Self::VerifyEnvironment => "verify environment files",
+            Self::RepairEnvironment => "repair environment files",
+            Self::CloneEnvironment => "clone environment",
+            Self::RelocateEnvironment => "rename or move environment",
+            Self::PackEnvironment => "pack or unpack environment archive",
+            Self::BundleEnvironment => "bundle environment packages as a local channel",
+            Self::FetchEnvironment => "fetch environment packages into the cache",
         };
         formatter.write_str(label)
     }
@@ -86,12 +193,31 @@ pub struct BackendCapabilities {
     pub validate_yaml_with_packages: CapabilitySupport,
     pub install_by_name: CapabilitySupport,
     pub install_by_prefix: CapabilitySupport,
+    pub uninstall_by_name: CapabilitySupport,
+    pub uninstall_by_prefix: CapabilitySupport,
+    pub update_by_name: CapabilitySupport,
+    pub update_by_prefix: CapabilitySupport,
+    pub update_all_environments: CapabilitySupport,
+    pub sync_environment: CapabilitySupport,
     pub adopt_environment: CapabilitySupport,
     pub remove_by_name: CapabilitySupport,
     pub remove_by_prefix: CapabilitySupport,
     pub discover_environments: CapabilitySupport,
     pub run_by_name: CapabilitySupport,
     pub run_by_prefix: CapabilitySupport,
+    pub lock_environment: CapabilitySupport,
+    pub create_from_lock: CapabilitySupport,
+    pub export_environment: CapabilitySupport,
+    pub environment_history: CapabilitySupport,
+    pub rollback_environment: CapabilitySupport,
+    pub diff_environments: CapabilitySupport,
+    pub verify_environment: CapabilitySupport,
+    pub repair_environment: CapabilitySupport,
+    pub clone_environment: CapabilitySupport,
+    pub relocate_environment: CapabilitySupport,
+    pub pack_environment: CapabilitySupport,
+    pub bundle_environment: CapabilitySupport,
+    pub fetch_environment: CapabilitySupport,
 }
 
 impl BackendCapabilities {
@@ -103,12 +229,31 @@ impl BackendCapabilities {
             validate_yaml_with_packages: CapabilitySupport::Native,
             install_by_name: CapabilitySupport::Hybrid,
             install_by_prefix: CapabilitySupport::Hybrid,
+            uninstall_by_name: CapabilitySupport::Hybrid,
+            uninstall_by_prefix: CapabilitySupport::Hybrid,
+            update_by_name: CapabilitySupport::Hybrid,
+            update_by_prefix: CapabilitySupport::Hybrid,
+            update_all_environments: CapabilitySupport::Hybrid,
+            sync_environment: CapabilitySupport::Native,
             adopt_environment: CapabilitySupport::Native,
             remove_by_name: CapabilitySupport::Hybrid,
             remove_by_prefix: CapabilitySupport::Hybrid,
             discover_environments: CapabilitySupport::Hybrid,
             run_by_name: CapabilitySupport::Native,
             run_by_prefix: CapabilitySupport::Native,
+            lock_environment: CapabilitySupport::Native,
+            create_from_lock: CapabilitySupport::Native,
+            export_environment: CapabilitySupport::Native,
+            environment_history: CapabilitySupport::Native,
+            rollback_environment: CapabilitySupport::Native,
+            diff_environments: CapabilitySupport::Native,
+            verify_environment: CapabilitySupport::Native,
+            repair_environment: CapabilitySupport::Native,
+            clone_environment: CapabilitySupport::Native,
+            relocate_environment: CapabilitySupport::Native,
+            pack_environment: CapabilitySupport::Native,
+            bundle_environment: CapabilitySupport::Native,
+            fetch_environment: CapabilitySupport::Native,
         }
     }
 
@@ -120,12 +265,31 @@ impl BackendCapabilities {
             validate_yaml_with_packages: CapabilitySupport::Unsupported,
             install_by_name: CapabilitySupport::Delegated,
             install_by_prefix: CapabilitySupport::Delegated,
+            uninstall_by_name: CapabilitySupport::Delegated,
+            uninstall_by_prefix: CapabilitySupport::Delegated,
+            update_by_name: CapabilitySupport::Delegated,
+            update_by_prefix: CapabilitySupport::Delegated,
+            update_all_environments: CapabilitySupport::Unsupported,
+            sync_environment: CapabilitySupport::Unsupported,
             adopt_environment: CapabilitySupport::Unsupported,
             remove_by_name: CapabilitySupport::Delegated,
             remove_by_prefix: CapabilitySupport::Delegated,
             discover_environments: CapabilitySupport::Delegated,
             run_by_name: CapabilitySupport::Delegated,
             run_by_prefix: CapabilitySupport::Delegated,
+            lock_environment: CapabilitySupport::Unsupported,
+            create_from_lock: CapabilitySupport::Unsupported,
+            export_environment: CapabilitySupport::Unsupported,
+            environment_history: CapabilitySupport::Unsupported,
+            rollback_environment: CapabilitySupport::Unsupported,
+            diff_environments: CapabilitySupport::Unsupported,
+            verify_environment: CapabilitySupport::Unsupported,
+            repair_environment: CapabilitySupport::Unsupported,
+            clone_environment: CapabilitySupport::Unsupported,
+            relocate_environment: CapabilitySupport::Unsupported,
+            pack_environment: CapabilitySupport::Unsupported,
+            bundle_environment: CapabilitySupport::Unsupported,
+            fetch_environment: CapabilitySupport::Unsupported,
         }
     }
 
@@ -137,12 +301,31 @@ impl BackendCapabilities {
             BackendCapability::ValidateYamlWithPackages => self.validate_yaml_with_packages,
             BackendCapability::InstallByName => self.install_by_name,
             BackendCapability::InstallByPrefix => self.install_by_prefix,
+            BackendCapability::UninstallByName => self.uninstall_by_name,
+            BackendCapability::UninstallByPrefix => self.uninstall_by_prefix,
+            BackendCapability::UpdateByName => self.update_by_name,
+            BackendCapability::UpdateByPrefix => self.update_by_prefix,
+            BackendCapability::UpdateAllEnvironments => self.update_all_environments,
+            BackendCapability::SyncEnvironment => self.sync_environment,
             BackendCapability::AdoptEnvironment => self.adopt_environment,
             BackendCapability::RemoveByName => self.remove_by_name,
             BackendCapability::RemoveByPrefix => self.remove_by_prefix,
             BackendCapability::DiscoverEnvironments => self.discover_environments,
             BackendCapability::RunByName => self.run_by_name,
             BackendCapability::RunByPrefix => self.run_by_prefix,
+            BackendCapability::LockEnvironment => self.lock_environment,
+            BackendCapability::CreateFromLock => self.create_from_lock,
+            BackendCapability::ExportEnvironment => self.export_environment,
+            BackendCapability::EnvironmentHistory => self.environment_history,
+            BackendCapability::RollbackEnvironment => self.rollback_environment,
+            BackendCapability::DiffEnvironments => self.diff_environments,
+            BackendCapability::VerifyEnvironment => self.verify_environment,
+            BackendCapability::RepairEnvironment => self.repair_environment,
+            BackendCapability::CloneEnvironment => self.clone_environment,
+            BackendCapability::RelocateEnvironment => self.relocate_environment,
+            BackendCapability::PackEnvironment => self.pack_environment,
+            BackendCapability::BundleEnvironment => self.bundle_environment,
+            BackendCapability::FetchEnvironment => self.fetch_environment,
         }
     }
 
@@ -302,6 +485,15 @@ pub enum EnvironmentTarget {
     Prefix(PathBuf),
 }
 
+impl fmt::Display for EnvironmentTarget {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Name(env_name) => formatter.write_str(env_name),
+            Self::Prefix(prefix) => write!(formatter, "{}", prefix.display()),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct RunRequest {
     pub command: RunCommand,
@@ -1,12 +1,25 @@
 //! Environment management commands
 
-use crate::backend::factory::build_default_backend;
-use crate::backend::{BackendCapability, EnvironmentName, EnvironmentTarget, OutputMode};
+use crate::backend::factory::{build_default_backend, build_default_backend_with_options};
+use crate::backend::{
+    BackendCapability, BackendOptions, ChannelPriorityMode, EnvironmentName, EnvironmentTarget,
+    ExportFormat, OutputMode, TransactionApproval,
+};
+use crate::environment_variables::{
+    activation_script_changes, environment_state_path, parse_variable_assignment,
+    read_environment_variables, validate_variable_name, write_environment_variables,
+    EnvironmentVariables,
+};
 use crate::error::{EnvError, Result};
-use crate::micromamba::CondaEnvironment;
+use crate::local_channel::index_local_channel;
+use crate::micromamba::{CondaEnvironment, ValidationResult};
+use crate::package_changes::format_size_bytes;
 use crate::package_manager::PackageManager;
+use crate::revisions::{render_revision_history, revision_changes};
+use crate::verify::render_verification;
 use crate::{BUILT_IN_ENV_NAMES, CORE_ENV_NAME, EXTRA_ENV_NAME, SNAKEMAKE_ENV_NAME};
 use clap::{Args, Subcommand, ValueEnum};
+use std::collections::{BTreeMap, HashMap};
 use std::path::{Path, PathBuf};
 use tracing::{error, info, warn};
 
@@ -53,6 +66,10 @@ pub struct EnvCreateArgs {
     #[arg(short, long)]
     pub yaml: Option<PathBuf>,
 
+    /// Install the exact packages pinned in a lockfile instead of solving a YAML
+    #[arg(long, value_name = "LOCKFILE", conflicts_with_all = ["yaml", "all", "with"])]
+    pub from_lock: Option<PathBuf>,
+
     /// Environment name (for custom environments)
     #[arg(long)]
     pub name: Option<String>,
@@ -69,6 +86,83 @@ pub struct EnvCreateArgs {
     #[arg(long = "with", value_name = "PKG")]
     pub with: Vec<String>,
 
+    /// Solve against this channel instead of the YAML's channels (repeat for multiple)
+    #[arg(
+        long = "channel",
+        short = 'c',
+        value_name = "CHANNEL",
+        conflicts_with = "from_lock"
+    )]
+    pub channels: Vec<String>,
+
+    /// Apply the transaction plan without asking for confirmation
+    #[arg(long)]
+    pub yes: bool,
+
+    /// Channel priority for the solve; overrides the YAML, the environment and the global config
+    #[arg(long, value_enum, value_name = "MODE")]
+    pub channel_priority: Option<ChannelPriorityMode>,
+
+    /// Terminal output mode: stream full logs, show a concise summary, or stay quiet
+    #[arg(long, value_enum, default_value_t = OutputMode::Summary)]
+    pub output: OutputMode,
+}
+
+/// Environment lock arguments
+#[derive(Debug, Clone, Args)]
+pub struct EnvLockArgs {
+    /// Environment name; its YAML is resolved like `create --name` unless --yaml is given
+    #[arg(long, required_unless_present = "yaml")]
+    pub name: Option<String>,
+
+    /// YAML configuration file to solve
+    #[arg(short, long)]
+    pub yaml: Option<PathBuf>,
+
+    /// Lockfile to write (defaults to `<name>.lock` in the current directory)
+    #[arg(long, value_name = "LOCKFILE")]
+    pub lockfile: Option<PathBuf>,
+
+    /// Channel priority for the solve; overrides the YAML, the environment and the global config
+    #[arg(long, value_enum, value_name = "MODE")]
+    pub channel_priority: Option<ChannelPriorityMode>,
+
+    /// Terminal output mode: stream full logs, show a concise summary, or stay quiet
+    #[arg(long, value_enum, default_value_t = OutputMode::Summary)]
+    pub output: OutputMode,
+}
+
+/// Environment bundle arguments
+#[derive(Debug, Clone, Args)]
+pub struct EnvBundleArgs {
+    /// Environment name; its YAML is resolved like `create --name` unless --yaml is given
+    #[arg(long, required_unless_present = "yaml")]
+    pub name: Option<String>,
+
+    /// YAML configuration file to solve
+    #[arg(short, long)]
+    pub yaml: Option<PathBuf>,
+
+    /// Directory to write the local channel to
+    #[arg(short = 'o', long = "output-dir", value_name = "DIR")]
+    pub output_dir: PathBuf,
+
+    /// Terminal output mode: stream full logs, show a concise summary, or stay quiet
+    #[arg(long, value_enum, default_value_t = OutputMode::Summary)]
+    pub output: OutputMode,
+}
+
+/// Environment fetch arguments
+#[derive(Debug, Clone, Args)]
+pub struct EnvFetchArgs {
+    /// Environment name; its YAML is resolved like `create --name` unless --yaml is given
+    #[arg(long, required_unless_present = "yaml")]
+    pub name: Option<String>,
+
+    /// YAML configuration file to solve
+    #[arg(short, long)]
+    pub yaml: Option<PathBuf>,
+
     /// Terminal output mode: stream full logs, show a concise summary, or stay quiet
     #[arg(long, value_enum, default_value_t = OutputMode::Summary)]
     pub output: OutputMode,
@@ -84,6 +178,10 @@ pub struct EnvValidateArgs {
     /// Environment name to validate
     #[arg(long)]
     pub name: Option<String>,
+
+    /// Solve an environment YAML file instead of checking installed environments
+    #[arg(short, long, conflicts_with_all = ["all", "name"])]
+    pub yaml: Option<PathBuf>,
 }
 
 /// Environment list arguments
@@ -108,6 +206,299 @@ pub struct EnvInstallArgs {
     /// Explicit environment prefix; required when a name is ambiguous
     #[arg(long, value_name = "PREFIX", conflicts_with = "name")]
     pub prefix: Option<PathBuf>,
+
+    /// Apply the transaction plan without asking for confirmation
+    #[arg(long, conflicts_with = "dry_run")]
+    pub yes: bool,
+
+    /// Print the transaction plan and stop without installing anything
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Solve against this channel instead of the environment's recorded channels (repeat for multiple)
+    #[arg(long = "channel", short = 'c', value_name = "CHANNEL")]
+    pub channels: Vec<String>,
+
+    /// Channel priority for the solve; overrides the YAML, the environment and the global config
+    #[arg(long, value_enum, value_name = "MODE")]
+    pub channel_priority: Option<ChannelPriorityMode>,
+}
+
+/// Package removal arguments
+#[derive(Debug, Clone, Args)]
+pub struct EnvUninstallArgs {
+    /// Package names to remove (provide one name per argument)
+    #[arg(required = true)]
+    pub packages: Vec<String>,
+
+    /// Environment name
+    #[arg(long, required_unless_present = "prefix", conflicts_with = "prefix")]
+    pub name: Option<String>,
+
+    /// Explicit environment prefix; required when a name is ambiguous
+    #[arg(long, value_name = "PREFIX", conflicts_with = "name")]
+    pub prefix: Option<PathBuf>,
+}
+
+/// Package update arguments
+#[derive(Debug, Clone, Args)]
+pub struct EnvUpdateArgs {
+    /// Package names to update
+    #[arg(
+        value_name = "PACKAGE",
+        required_unless_present = "all",
+        conflicts_with = "all"
+    )]
+    pub packages: Vec<String>,
+
+    /// Update every installed package
+    #[arg(long)]
+    pub all: bool,
+
+    /// Environment name
+    #[arg(
+        long,
+        required_unless_present_any = ["prefix", "all_envs"],
+        conflicts_with_all = ["prefix", "all_envs"]
+    )]
+    pub name: Option<String>,
+
+    /// Explicit environment prefix; required when a name is ambiguous
+    #[arg(long, value_name = "PREFIX", conflicts_with_all = ["name", "all_envs"])]
+    pub prefix: Option<PathBuf>,
+
+    /// Apply the same update to every rattler-owned environment
+    #[arg(long)]
+    pub all_envs: bool,
+
+    /// Apply the transaction plan without asking for confirmation
+    #[arg(long)]
+    pub yes: bool,
+
+    /// Solve against this channel instead of the environment's recorded channels (repeat for multiple)
+    #[arg(long = "channel", short = 'c', value_name = "CHANNEL")]
+    pub channels: Vec<String>,
+
+    /// Channel priority for the solve; overrides the YAML, the environment and the global config
+    #[arg(long, value_enum, value_name = "MODE")]
+    pub channel_priority: Option<ChannelPriorityMode>,
+}
+
+/// Environment sync arguments
+#[derive(Debug, Clone, Args)]
+pub struct EnvSyncArgs {
+    /// Environment YAML file the environment should match
+    #[arg(short, long, value_name = "YAML")]
+    pub yaml: PathBuf,
+
+    /// Environment name
+    #[arg(long, required_unless_present = "prefix", conflicts_with = "prefix")]
+    pub name: Option<String>,
+
+    /// Explicit environment prefix; required when a name is ambiguous
+    #[arg(long, value_name = "PREFIX", conflicts_with = "name")]
+    pub prefix: Option<PathBuf>,
+
+    /// Solve against this channel instead of the environment's recorded channels (repeat for multiple)
+    #[arg(long = "channel", short = 'c', value_name = "CHANNEL")]
+    pub channels: Vec<String>,
+
+    /// Channel priority for the solve; overrides the YAML, the environment and the global config
+    #[arg(long, value_enum, value_name = "MODE")]
+    pub channel_priority: Option<ChannelPriorityMode>,
+}
+
+/// Environment export arguments
+#[derive(Debug, Clone, Args)]
+pub struct EnvExportArgs {
+    /// Environment name
+    #[arg(long, value_name = "ENV", conflicts_with_all = ["prefix", "env"])]
+    pub name: Option<String>,
+
+    /// Explicit environment prefix; required when a name is ambiguous
+    #[arg(long, value_name = "PREFIX", conflicts_with_all = ["name", "env"])]
+    pub prefix: Option<PathBuf>,
+
+    /// Only export the specs that were explicitly requested
+    #[arg(long, conflicts_with = "explicit")]
+    pub from_history: bool,
+
+    /// Export an @EXPLICIT URL list with package hashes
+    #[arg(long, conflicts_with = "from_history")]
+    pub explicit: bool,
+
+    /// Write the export to a file instead of standard output
+    #[arg(short, long, value_name = "FILE")]
+    pub file: Option<PathBuf>,
+
+    /// Positional environment name
+    #[arg(value_name = "ENV", conflicts_with_all = ["name", "prefix"])]
+    pub env: Option<String>,
+}
+
+/// Environment history arguments
+#[derive(Debug, Clone, Args)]
+pub struct EnvHistoryArgs {
+    /// Environment name
+    #[arg(long, value_name = "ENV", conflicts_with_all = ["prefix", "env"])]
+    pub name: Option<String>,
+
+    /// Explicit environment prefix; required when a name is ambiguous
+    #[arg(long, value_name = "PREFIX", conflicts_with_all = ["name", "env"])]
+    pub prefix: Option<PathBuf>,
+
+    /// Positional environment name
+    #[arg(value_name = "ENV", conflicts_with_all = ["name", "prefix"])]
+    pub env: Option<String>,
+}
+
+/// Environment verification arguments
+#[derive(Debug, Clone, Args)]
+pub struct EnvVerifyArgs {
+    /// Environment name
+    #[arg(long, value_name = "ENV", conflicts_with_all = ["prefix", "env", "all"])]
+    pub name: Option<String>,
+
+    /// Explicit environment prefix; required when a name is ambiguous
+    #[arg(long, value_name = "PREFIX", conflicts_with_all = ["name", "env", "all"])]
+    pub prefix: Option<PathBuf>,
+
+    /// Positional environment name
+    #[arg(value_name = "ENV", conflicts_with_all = ["name", "prefix", "all"])]
+    pub env: Option<String>,
+
+    /// Verify every discovered environment
+    #[arg(long)]
+    pub all: bool,
+}
+
+/// Environment repair arguments
+#[derive(Debug, Clone, Args)]
+pub struct EnvRepairArgs {
+    /// Environment name
+    #[arg(long, value_name = "ENV", conflicts_with_all = ["prefix", "env"])]
+    pub name: Option<String>,
+
+    /// Explicit environment prefix; required when a name is ambiguous
+    #[arg(long, value_name = "PREFIX", conflicts_with_all = ["name", "env"])]
+    pub prefix: Option<PathBuf>,
+
+    /// Positional environment name
+    #[arg(value_name = "ENV", conflicts_with_all = ["name", "prefix"])]
+    pub env: Option<String>,
+}
+
+/// Environment clone arguments
+#[derive(Debug, Clone, Args)]
+pub struct EnvCloneArgs {
+    /// Environment name or prefix path to copy
+    #[arg(value_name = "SOURCE")]
+    pub source: String,
+
+    /// Name of the new environment
+    #[arg(value_name = "NAME")]
+    pub name: String,
+
+    /// Replace an existing environment with the same name
+    #[arg(long)]
+    pub force: bool,
+}
+
+/// Environment rename arguments
+#[derive(Debug, Clone, Args)]
+pub struct EnvRenameArgs {
+    /// Environment name or prefix path to rename
+    #[arg(value_name = "ENV")]
+    pub env: String,
+
+    /// New environment name
+    #[arg(value_name = "NEW_NAME")]
+    pub new_name: String,
+}
+
+/// Environment move arguments
+#[derive(Debug, Clone, Args)]
+pub struct EnvMoveArgs {
+    /// Root prefix to move the environment into, as `<ROOT>/envs/<name>`
+    #[arg(long, value_name = "ROOT")]
+    pub to_root: PathBuf,
+
+    /// Environment name
+    #[arg(long, value_name = "ENV", conflicts_with_all = ["prefix", "env"])]
+    pub name: Option<String>,
+
+    /// Explicit environment prefix; required when a name is ambiguous
+    #[arg(long, value_name = "PREFIX", conflicts_with_all = ["name", "env"])]
+    pub prefix: Option<PathBuf>,
+
+    /// Positional environment name
+    #[arg(value_name = "ENV", conflicts_with_all = ["name", "prefix"])]
+    pub env: Option<String>,
+}
+
+/// Environment pack arguments
+#[derive(Debug, Clone, Args)]
+pub struct EnvPackArgs {
+    /// Archive to write, conventionally ending in `.tar.zst`
+    #[arg(short, long, value_name = "FILE")]
+    pub output: PathBuf,
+
+    /// Environment name
+    #[arg(long, value_name = "ENV", conflicts_with_all = ["prefix", "env"])]
+    pub name: Option<String>,
+
+    /// Explicit environment prefix; required when a name is ambiguous
+    #[arg(long, value_name = "PREFIX", conflicts_with_all = ["name", "env"])]
+    pub prefix: Option<PathBuf>,
+
+    /// Positional environment name
+    #[arg(value_name = "ENV", conflicts_with_all = ["name", "prefix"])]
+    pub env: Option<String>,
+}
+
+/// Environment unpack arguments
+#[derive(Debug, Clone, Args)]
+pub struct EnvUnpackArgs {
+    /// Archive written by `enva pack`
+    #[arg(value_name = "ARCHIVE")]
+    pub archive: PathBuf,
+
+    /// Name of the new environment
+    #[arg(
+        long,
+        value_name = "ENV",
+        required_unless_present = "prefix",
+        conflicts_with = "prefix"
+    )]
+    pub name: Option<String>,
+
+    /// Explicit prefix of the new environment
+    #[arg(long, value_name = "PREFIX", conflicts_with = "name")]
+    pub prefix: Option<PathBuf>,
+
+    /// Replace an existing environment at the destination
+    #[arg(long)]
+    pub force: bool,
+}
+
+/// Environment rollback arguments
+#[derive(Debug, Clone, Args)]
+pub struct EnvRollbackArgs {
+    /// Revision number to restore, as listed by `enva history`
+    #[arg(long, value_name = "REVISION")]
+    pub to: u32,
+
+    /// Environment name
+    #[arg(long, value_name = "ENV", conflicts_with_all = ["prefix", "env"])]
+    pub name: Option<String>,
+
+    /// Explicit environment prefix; required when a name is ambiguous
+    #[arg(long, value_name = "PREFIX", conflicts_with_all = ["name", "env"])]
+    pub prefix: Option<PathBuf>,
+
+    /// Positional environment name
+    #[arg(value_name = "ENV", conflicts_with_all = ["name", "prefix"])]
+    pub env: Option<String>,
 }
 
 /// Environment adoption arguments
@@ -129,28 +520,91 @@ pub enum ActivationShell {
     Zsh,
     Fish,
     Powershell,
+    #[value(alias = "csh")]
+    Tcsh,
+    Xonsh,
+    #[value(alias = "nu")]
+    Nushell,
 }
 
 impl ActivationShell {
     fn resolved(self) -> Self {
         match self {
-            Self::Auto => {
-                let shell = std::env::var("SHELL")
-                    .unwrap_or_default()
-                    .to_ascii_lowercase();
-                if shell.contains("zsh") {
-                    Self::Zsh
-                } else if shell.contains("fish") {
-                    Self::Fish
-                } else if shell.contains("pwsh") || shell.contains("powershell") {
-                    Self::Powershell
-                } else {
-                    Self::Bash
-                }
-            }
+            Self::Auto => Self::from_shell_path(&std::env::var("SHELL").unwrap_or_default()),
             shell => shell,
         }
     }
+
+    /// The shell a `$SHELL` value names; anything unknown is treated as Bash.
+    fn from_shell_path(shell: &str) -> Self {
+        let shell = shell.to_ascii_lowercase();
+        let name = Path::new(&shell)
+            .file_stem()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default();
+        match name {
+            "zsh" => Self::Zsh,
+            "fish" => Self::Fish,
+            "pwsh" | "powershell" => Self::Powershell,
+            "xonsh" => Self::Xonsh,
+            "nu" => Self::Nushell,
+            name if name.ends_with("csh") => Self::Tcsh,
+            _ => Self::Bash,
+        }
+    }
+
+    /// Extension of the `activate.d` and `deactivate.d` scripts this shell can source.
+    /// nushell only sources files known when a script is parsed, so its activation runs
+    /// the `.sh` scripts itself instead (see [`render_nushell_activation`]).
+    fn script_extension(self) -> Option<&'static str> {
+        match self {
+            Self::Bash | Self::Zsh => Some("sh"),
+            Self::Fish => Some("fish"),
+            Self::Powershell => Some("ps1"),
+            Self::Tcsh => Some("csh"),
+            Self::Xonsh => Some("xsh"),
+            Self::Nushell => None,
+            Self::Auto => unreachable!(),
+        }
+    }
+
+    fn render_source(self, script: &Path) -> String {
+        let script = script.to_string_lossy();
+        match self {
+            Self::Bash | Self::Zsh => format!(". {}\n", sh_quote(&script)),
+            Self::Fish => format!("source {}\n", sh_quote(&script)),
+            Self::Powershell => format!(". {}\n", powershell_quote(&script)),
+            Self::Tcsh => format!("source {};\n", csh_quote(&script)),
+            Self::Xonsh => format!("source {}\n", python_quote(&script)),
+            Self::Nushell | Self::Auto => unreachable!(),
+        }
+    }
+}
+
+/// Scripts of `etc/conda/<directory>` that `shell` can source, sorted by file name
+/// like conda runs them.
+fn prefix_shell_scripts(
+    prefix: &Path,
+    directory: &str,
+    shell: ActivationShell,
+) -> Result<Vec<PathBuf>> {
+    let directory = prefix.join("etc").join("conda").join(directory);
+    if !directory.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let entries = std::fs::read_dir(&directory).map_err(|error| {
+        EnvError::FileOperation(format!("Failed to read {}: {}", directory.display(), error))
+    })?;
+    let Some(extension) = shell.resolved().script_extension() else {
+        return Ok(Vec::new());
+    };
+    let mut scripts = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == extension))
+        .collect::<Vec<PathBuf>>();
+    scripts.sort();
+    Ok(scripts)
 }
 
 #[derive(Debug, Clone, Args)]
@@ -174,6 +628,14 @@ pub struct EnvActivateArgs {
     /// Positional environment name
     #[arg(value_name = "ENV", conflicts_with_all = ["name", "prefix"])]
     pub env: Option<String>,
+
+    /// Activate on top of the active environment and keep its executables on PATH
+    #[arg(long)]
+    pub stack: bool,
+
+    /// Prefix the shell prompt with the environment name
+    #[arg(long)]
+    pub prompt: bool,
 }
 
 impl EnvActivateArgs {
@@ -190,9 +652,32 @@ pub struct EnvDeactivateArgs {
 }
 
 #[derive(Debug, Clone, Args)]
+#[command(args_conflicts_with_subcommands = true)]
 pub struct EnvShellArgs {
     #[command(subcommand)]
-    pub command: EnvShellCommand,
+    pub command: Option<EnvShellCommand>,
+
+    /// Environment name
+    #[arg(short, long, value_name = "ENV", conflicts_with_all = ["prefix", "env"])]
+    pub name: Option<String>,
+
+    /// Explicit compatibility package manager for CLI fallback mode
+    #[arg(long, value_enum)]
+    pub pm: Option<PackageManager>,
+
+    /// Explicit environment prefix path
+    #[arg(long, value_name = "PREFIX", conflicts_with_all = ["name", "env"])]
+    pub prefix: Option<PathBuf>,
+
+    /// Positional environment name
+    #[arg(value_name = "ENV", conflicts_with_all = ["name", "prefix"])]
+    pub env: Option<String>,
+}
+
+impl EnvShellArgs {
+    fn requested_env_name(&self) -> Option<String> {
+        self.name.clone().or_else(|| self.env.clone())
+    }
 }
 
 #[derive(Debug, Clone, Subcommand)]
@@ -208,12 +693,113 @@ pub struct EnvShellHookArgs {
     pub shell: ActivationShell,
 }
 
+#[derive(Debug, Clone, Args)]
+pub struct EnvConfigArgs {
+    #[command(subcommand)]
+    pub command: EnvConfigCommand,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum EnvConfigCommand {
+    /// Environment variables set by `run` and `activate`, stored in `conda-meta/state`
+    Vars(EnvConfigVarsArgs),
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct EnvConfigVarsArgs {
+    #[command(subcommand)]
+    pub command: EnvConfigVarsCommand,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum EnvConfigVarsCommand {
+    /// Set environment variables of an environment
+    Set(EnvConfigVarsSetArgs),
+
+    /// Remove environment variables from an environment
+    Unset(EnvConfigVarsUnsetArgs),
+
+    /// List the environment variables of an environment
+    List(EnvConfigVarsTarget),
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct EnvConfigVarsTarget {
+    /// Environment name
+    #[arg(short, long, value_name = "ENV", required_unless_present = "prefix")]
+    pub name: Option<String>,
+
+    /// Explicit environment prefix; required when a name is ambiguous
+    #[arg(long, value_name = "PREFIX", conflicts_with = "name")]
+    pub prefix: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct EnvConfigVarsSetArgs {
+    #[command(flatten)]
+    pub target: EnvConfigVarsTarget,
+
+    /// Variables to set
+    #[arg(value_name = "NAME=VALUE", required = true)]
+    pub assignments: Vec<String>,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct EnvConfigVarsUnsetArgs {
+    #[command(flatten)]
+    pub target: EnvConfigVarsTarget,
+
+    /// Variables to remove
+    #[arg(value_name = "NAME", required = true)]
+    pub names: Vec<String>,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct EnvChannelArgs {
+    #[command(subcommand)]
+    pub command: EnvChannelCommand,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum EnvChannelCommand {
+    /// Write repodata.json for a directory of .conda and .tar.bz2 packages
+    Index(EnvChannelIndexArgs),
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct EnvChannelIndexArgs {
+    /// Channel directory; archives must already sit in the subdir they were built for
+    #[arg(value_name = "DIR")]
+    pub directory: PathBuf,
+
+    /// Also write zstd-compressed repodata.json.zst files
+    #[arg(long)]
+    pub zstd: bool,
+
+    /// Move archives that are not in the subdir they were built for into it
+    #[arg(long)]
+    pub organize: bool,
+}
+
 /// Environment command subcommands
 #[derive(Subcommand, Debug)]
 pub enum EnvCommand {
     /// Create conda environments
     Create(EnvCreateArgs),
 
+    /// Solve an environment YAML and write a lockfile with pinned URLs and hashes
+    Lock(EnvLockArgs),
+
+    /// Download the packages of an environment YAML into a local channel directory
+    Bundle(EnvBundleArgs),
+
+    /// Local channel helpers
+    Channel(EnvChannelArgs),
+
+    /// Download the repodata and packages of an environment YAML into the caches for
+    /// later `--offline` use
+    Fetch(EnvFetchArgs),
+
     /// List conda environments
     List(EnvListArgs),
 
@@ -223,6 +809,51 @@ pub enum EnvCommand {
     /// Install components in environment
     Install(EnvInstallArgs),
 
+    /// Remove packages from an environment without recreating it
+    Uninstall(EnvUninstallArgs),
+
+    /// Upgrade packages within an existing environment
+    Update(EnvUpdateArgs),
+
+    /// Apply only the changes needed to make an environment match its YAML
+    Sync(EnvSyncArgs),
+
+    /// Export an environment as YAML or an explicit package list
+    Export(EnvExportArgs),
+
+    /// List the recorded revisions of an environment
+    History(EnvHistoryArgs),
+
+    /// Compare two environments, revisions, YAML files or lockfiles
+    ///
+    /// Exits with status 0 when both sides have the same packages, 1 when they differ and
+    /// 2 when a side cannot be read, resolved or solved.
+    Diff(crate::env_diff::EnvDiffArgs),
+
+    /// Check installed files for missing, modified and unowned files
+    Verify(EnvVerifyArgs),
+
+    /// Re-link packages whose files are missing or modified from the package cache
+    Repair(EnvRepairArgs),
+
+    /// Reinstall the exact package set of an earlier revision
+    Rollback(EnvRollbackArgs),
+
+    /// Copy an environment under a new name without solving or downloading
+    Clone(EnvCloneArgs),
+
+    /// Rename an environment, rewriting the prefix embedded in its files
+    Rename(EnvRenameArgs),
+
+    /// Move an environment into another root prefix
+    Move(EnvMoveArgs),
+
+    /// Write an environment to a relocatable archive
+    Pack(EnvPackArgs),
+
+    /// Create an environment from an archive written by `enva pack`
+    Unpack(EnvUnpackArgs),
+
     /// Adopt an existing environment into rattler ownership
     Adopt(EnvAdoptArgs),
 
@@ -241,13 +872,16 @@ pub enum EnvCommand {
         prefix: Option<PathBuf>,
     },
 
+    /// Per-environment configuration such as environment variables
+    Config(EnvConfigArgs),
+
     /// Emit shell code to activate an environment in the current shell
     Activate(EnvActivateArgs),
 
     /// Emit shell code to deactivate the current enva-managed shell state
     Deactivate(EnvDeactivateArgs),
 
-    /// Shell integration helpers
+    /// Start a subshell with an environment active, or emit shell integration code
     Shell(EnvShellArgs),
 
     /// Run command or script in environment
@@ -261,14 +895,38 @@ pub async fn execute_env_command(
     _config: Option<PathBuf>,
     dry_run: bool,
     json: bool,
+    offline: bool,
 ) -> Result<()> {
     match command {
-        EnvCommand::Create(args) => execute_env_create(args, verbose, dry_run, json).await,
+        EnvCommand::Create(args) => execute_env_create(args, verbose, dry_run, json, offline).await,
+        EnvCommand::Lock(args) => execute_env_lock(args, verbose, offline).await,
+        EnvCommand::Bundle(args) => execute_env_bundle(args, verbose, offline).await,
+        EnvCommand::Channel(args) => execute_env_channel(args, verbose, json),
+        EnvCommand::Fetch(args) => execute_env_fetch(args, verbose, offline).await,
         EnvCommand::List(args) => execute_env_list(args, verbose, json).await,
-        EnvCommand::Validate(args) => execute_env_validate(args, verbose, dry_run, json).await,
-        EnvCommand::Install(args) => execute_env_install(args, verbose).await,
+        EnvCommand::Validate(args) => {
+            execute_env_validate(args, verbose, dry_run, json, offline).await
+        }
+        EnvCommand::Install(args) => execute_env_install(args, verbose, dry_run, offline).await,
+        EnvCommand::Uninstall(args) => execute_env_uninstall(args, verbose).await,
+        EnvCommand::Update(args) => execute_env_update(args, verbose, offline).await,
+        EnvCommand::Sync(args) => execute_env_sync(args, verbose, offline).await,
+        EnvCommand::Export(args) => execute_env_export(args, verbose).await,
+        EnvCommand::History(args) => execute_env_history(args, verbose, json).await,
+        EnvCommand::Rollback(args) => execute_env_rollback(args, verbose, offline).await,
+        EnvCommand::Clone(args) => execute_env_clone(args, verbose).await,
+        EnvCommand::Rename(args) => execute_env_rename(args, verbose).await,
+        EnvCommand::Move(args) => execute_env_move(args, verbose).await,
+        EnvCommand::Pack(args) => execute_env_pack(args, verbose).await,
+        EnvCommand::Unpack(args) => execute_env_unpack(args, verbose).await,
+        EnvCommand::Diff(args) => {
+            crate::env_diff::execute_env_diff(args, verbose, json, offline).await
+        }
+        EnvCommand::Verify(args) => execute_env_verify(args, verbose, json).await,
+        EnvCommand::Repair(args) => execute_env_repair(args, verbose, json, offline).await,
         EnvCommand::Adopt(args) => execute_env_adopt(args, verbose).await,
         EnvCommand::Remove { names, prefix } => execute_env_remove(names, prefix, verbose).await,
+        EnvCommand::Config(args) => execute_env_config(args, verbose, json).await,
         EnvCommand::Activate(args) => execute_env_activate(args, verbose).await,
         EnvCommand::Deactivate(args) => execute_env_deactivate(args, verbose).await,
         EnvCommand::Shell(args) => execute_env_shell(args, verbose).await,
@@ -341,6 +999,7 @@ async fn execute_env_create(
     verbose: bool,
     dry_run: bool,
     json: bool,
+    offline: bool,
 ) -> Result<()> {
     if dry_run {
         info!("Starting conda environment creation (dry-run mode)...");
@@ -348,7 +1007,13 @@ async fn execute_env_create(
         info!("Starting conda environment creation...");
     }
 
-    let backend = build_default_backend().await?;
+    let backend = build_default_backend_with_options(BackendOptions {
+        transaction_approval: TransactionApproval::from_flags(args.yes, false),
+        channel_priority: args.channel_priority,
+        channels: args.channels.clone(),
+        offline,
+    })
+    .await?;
     let packages_to_install = parse_package_specs(&args.with);
 
     let mut environments_to_create = Vec::new();
@@ -404,6 +1069,23 @@ async fn execute_env_create(
         }
     }
 
+    if let Some(lock_file) = args.from_lock.as_ref() {
+        return execute_env_create_from_lock(
+            &environments_to_create,
+            lock_file,
+            &args,
+            dry_run,
+            offline,
+        )
+        .await;
+    }
+
+    if dry_run && !args.channels.is_empty() {
+        return Err(EnvError::Validation(
+            "--channel cannot be combined with --dry-run".to_string(),
+        ));
+    }
+
     if dry_run {
         let validation_capability = if packages_to_install.is_empty() {
             BackendCapability::ValidateYaml
@@ -424,12 +1106,14 @@ async fn execute_env_create(
     if dry_run {
         use serde_json::{json, Value};
         let mut results = Vec::new();
+        let mut failures = Vec::new();
 
         for env_name in &environments_to_create {
             let yaml_file = resolve_yaml_file(env_name, args.yaml.as_ref())?;
             let validation = backend
                 .validate_yaml_with_packages(&yaml_file, &packages_to_install)
                 .await?;
+            let failure = validation_failure(&validation);
             let mut result = serde_json::to_value(validation)?;
             if let Value::Object(ref mut fields) = result {
                 fields.insert(
@@ -440,16 +1124,28 @@ async fn execute_env_create(
 
             if !json {
                 println!("[DRY-RUN] Environment: {}", env_name);
-                println!("[DRY-RUN] YAML validation and dependency solve succeeded");
+                match failure.as_deref() {
+                    None => println!("[DRY-RUN] YAML validation and dependency solve succeeded"),
+                    Some(reason) => println!("[DRY-RUN] YAML validation failed: {}", reason),
+                }
                 println!("{}", serde_json::to_string_pretty(&result)?);
                 println!("{}", "-".repeat(50));
             }
+            if let Some(reason) = failure {
+                failures.push(format!("{}: {}", env_name, reason));
+            }
             results.push(result);
         }
 
         if json {
             println!("{}", serde_json::to_string_pretty(&Value::Array(results))?);
         }
+        if !failures.is_empty() {
+            return Err(EnvError::Validation(format!(
+                "Dry-run validation failed for {}",
+                failures.join("; ")
+            )));
+        }
         return Ok(());
     }
 
@@ -465,7 +1161,14 @@ async fn execute_env_create(
         let yaml_file = resolve_yaml_file(env_name, args.yaml.as_ref())?;
 
         match backend
-            .create_environment(env_name, &yaml_file, dry_run, args.force, args.output)
+            .create_environment_with_channels(
+                env_name,
+                &yaml_file,
+                &args.channels,
+                dry_run,
+                args.force,
+                args.output,
+            )
             .await
         {
             Ok(_) => {
@@ -534,6 +1237,175 @@ async fn execute_env_create(
     Ok(())
 }
 
+async fn execute_env_create_from_lock(
+    environments_to_create: &[&str],
+    lock_file: &Path,
+    args: &EnvCreateArgs,
+    dry_run: bool,
+    offline: bool,
+) -> Result<()> {
+    let env_name = match environments_to_create {
+        [env_name] => *env_name,
+        _ => {
+            return Err(EnvError::Validation(
+                "--from-lock creates exactly one environment; select a single --name or built-in environment".to_string(),
+            ))
+        }
+    };
+
+    let backend = build_default_backend_with_options(BackendOptions {
+        offline,
+        ..BackendOptions::default()
+    })
+    .await?;
+    backend.require_capability(BackendCapability::CreateFromLock)?;
+    if args.clean_cache && !dry_run {
+        backend.require_capability(BackendCapability::CleanPackageCache)?;
+        backend.clean_package_cache(dry_run, args.output).await?;
+    }
+
+    backend
+        .create_environment_from_lock(env_name, lock_file, dry_run, args.force, args.output)
+        .await?;
+    info!(
+        "Successfully created environment {} from lockfile {}",
+        env_name,
+        lock_file.display()
+    );
+    Ok(())
+}
+
+/// Execute environment lock
+async fn execute_env_lock(args: EnvLockArgs, verbose: bool, offline: bool) -> Result<()> {
+    let env_name = match (&args.name, &args.yaml) {
+        (Some(name), _) => name.clone(),
+        (None, Some(yaml_file)) => yaml_file
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .map(ToOwned::to_owned)
+            .ok_or_else(|| {
+                EnvError::Validation(format!(
+                    "Cannot derive a lockfile name from {}; pass --lockfile",
+                    yaml_file.display()
+                ))
+            })?,
+        (None, None) => {
+            return Err(EnvError::Validation(
+                "Must specify --name or --yaml".to_string(),
+            ))
+        }
+    };
+    let yaml_file = resolve_yaml_file(&env_name, args.yaml.as_ref())?;
+    let lock_file = args
+        .lockfile
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(format!("{}.lock", env_name)));
+
+    if verbose {
+        info!(
+            "Locking {} into {}",
+            yaml_file.display(),
+            lock_file.display()
+        );
+    }
+
+    let backend = build_default_backend_with_options(BackendOptions {
+        channel_priority: args.channel_priority,
+        offline,
+        ..BackendOptions::default()
+    })
+    .await?;
+    backend.require_capability(BackendCapability::LockEnvironment)?;
+    backend
+        .lock_environment(&yaml_file, &lock_file, args.output)
+        .await
+}
+
+/// Execute environment bundle
+async fn execute_env_bundle(args: EnvBundleArgs, verbose: bool, offline: bool) -> Result<()> {
+    if let Some(name) = &args.name {
+        EnvironmentName::parse(name.clone())?;
+    }
+    let yaml_file =
+        resolve_yaml_file(args.name.as_deref().unwrap_or_default(), args.yaml.as_ref())?;
+    if !yaml_file.exists() {
+        return Err(EnvError::FileNotFound(yaml_file));
+    }
+    if verbose {
+        info!(
+            "Bundling {} into {}",
+            yaml_file.display(),
+            args.output_dir.display()
+        );
+    }
+
+    let backend = build_default_backend_with_options(BackendOptions {
+        offline,
+        ..BackendOptions::default()
+    })
+    .await?;
+    backend.require_capability(BackendCapability::BundleEnvironment)?;
+    backend
+        .bundle_environment(&yaml_file, &args.output_dir, args.output)
+        .await
+        .map(|_| ())
+}
+
+/// Execute environment fetch
+async fn execute_env_fetch(args: EnvFetchArgs, verbose: bool, offline: bool) -> Result<()> {
+    if let Some(name) = &args.name {
+        EnvironmentName::parse(name.clone())?;
+    }
+    let yaml_file =
+        resolve_yaml_file(args.name.as_deref().unwrap_or_default(), args.yaml.as_ref())?;
+    if !yaml_file.exists() {
+        return Err(EnvError::FileNotFound(yaml_file));
+    }
+    if verbose {
+        info!(
+            "Fetching packages of {} into the cache",
+            yaml_file.display()
+        );
+    }
+
+    let backend = build_default_backend_with_options(BackendOptions {
+        offline,
+        ..BackendOptions::default()
+    })
+    .await?;
+    backend.require_capability(BackendCapability::FetchEnvironment)?;
+    backend
+        .fetch_environment(&yaml_file, args.output)
+        .await
+        .map(|_| ())
+}
+
+/// Execute local channel helpers
+fn execute_env_channel(args: EnvChannelArgs, verbose: bool, json: bool) -> Result<()> {
+    match args.command {
+        EnvChannelCommand::Index(args) => {
+            if verbose {
+                info!("Indexing local channel {}", args.directory.display());
+            }
+            let index = index_local_channel(&args.directory, args.zstd, args.organize)?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&index)?);
+                return Ok(());
+            }
+            for archive in &index.moved {
+                println!("  moved {}", archive.display());
+            }
+            println!(
+                "✓ Indexed {} packages in {} ({})",
+                index.packages,
+                index.channel.display(),
+                index.subdirs.join(", ")
+            );
+            Ok(())
+        }
+    }
+}
+
 /// Execute environment list
 async fn execute_env_list(args: EnvListArgs, _verbose: bool, json: bool) -> Result<()> {
     info!("Listing conda environments...");
@@ -548,6 +1420,7 @@ async fn execute_env_validate(
     verbose: bool,
     dry_run: bool,
     json: bool,
+    offline: bool,
 ) -> Result<()> {
     if dry_run {
         info!("Validating conda environment configuration (dry-run mode)...");
@@ -555,7 +1428,33 @@ async fn execute_env_validate(
         info!("Validating conda environment configuration...");
     }
 
-    let backend = build_default_backend().await?;
+    let backend = build_default_backend_with_options(BackendOptions {
+        offline,
+        ..BackendOptions::default()
+    })
+    .await?;
+
+    if let Some(yaml_file) = args.yaml.as_ref() {
+        backend.require_capability(BackendCapability::ValidateYaml)?;
+        let validation = backend.validate_yaml(yaml_file).await?;
+        let failure = validation_failure(&validation);
+
+        if json {
+            println!("{}", serde_json::to_string_pretty(&validation)?);
+        } else {
+            print_validation_summary(&validation);
+        }
+
+        return match failure {
+            None => Ok(()),
+            Some(reason) => Err(EnvError::Validation(format!(
+                "YAML validation failed for {}: {}",
+                yaml_file.display(),
+                reason
+            ))),
+        };
+    }
+
     backend.require_capability(BackendCapability::DiscoverEnvironments)?;
 
     if args.all || args.name.is_none() {
@@ -633,11 +1532,86 @@ async fn execute_env_validate(
     }
 }
 
+/// Returns the reason a YAML validation cannot be used to create the environment.
+fn validation_failure(validation: &ValidationResult) -> Option<String> {
+    let details = &validation.validation;
+    if details.syntax_valid && details.dependencies_resolvable {
+        return None;
+    }
+
+    let reason = validation
+        .solver_message
+        .clone()
+        .or_else(|| details.version_conflicts.first().cloned())
+        .unwrap_or_else(|| "dependencies are not resolvable".to_string());
+    Some(reason)
+}
+
+fn print_validation_summary(validation: &ValidationResult) {
+    let details = &validation.validation;
+    println!(
+        "Environment: {} ({})",
+        validation.environment,
+        validation.yaml_file.display()
+    );
+    println!(
+        "  Syntax valid:            {}",
+        if details.syntax_valid { "yes" } else { "no" }
+    );
+    println!(
+        "  Dependencies resolvable: {}",
+        if details.dependencies_resolvable {
+            "yes"
+        } else {
+            "no"
+        }
+    );
+    if let Some(solved_packages) = validation.solved_packages {
+        println!("  Solved packages:         {}", solved_packages);
+    }
+    if let Some(download_size_bytes) = validation.download_size_bytes {
+        println!(
+            "  Download size:           {}",
+            format_size_bytes(download_size_bytes)
+        );
+    }
+    match validation.install_size_bytes {
+        Some(install_size_bytes) => println!(
+            "  Install size:            {}",
+            format_size_bytes(install_size_bytes)
+        ),
+        None if details.dependencies_resolvable => {
+            println!("  Install size:            unknown (packages not cached)")
+        }
+        None => {}
+    }
+    if !validation.channels_accessible.is_empty() {
+        println!(
+            "  Channels with repodata:  {}",
+            validation.channels_accessible.join(", ")
+        );
+    }
+    for conflict in &details.version_conflicts {
+        println!("  Issue: {}", conflict);
+    }
+}
+
 /// Execute environment installation
-async fn execute_env_install(args: EnvInstallArgs, verbose: bool) -> Result<()> {
+async fn execute_env_install(
+    args: EnvInstallArgs,
+    verbose: bool,
+    dry_run: bool,
+    offline: bool,
+) -> Result<()> {
     info!("Installing packages in conda environment...");
 
-    let backend = build_default_backend().await?;
+    let backend = build_default_backend_with_options(BackendOptions {
+        transaction_approval: TransactionApproval::from_flags(args.yes, args.dry_run || dry_run),
+        channel_priority: args.channel_priority,
+        channels: args.channels.clone(),
+        offline,
+    })
+    .await?;
 
     let target = match (args.name.as_deref(), args.prefix.as_ref()) {
         (Some(env_name), None) => {
@@ -701,6 +1675,493 @@ async fn execute_env_install(args: EnvInstallArgs, verbose: bool) -> Result<()>
     }
 }
 
+/// Execute package removal
+async fn execute_env_uninstall(args: EnvUninstallArgs, verbose: bool) -> Result<()> {
+    let target = match (args.name.as_deref(), args.prefix.as_ref()) {
+        (Some(env_name), None) => {
+            EnvironmentName::parse(env_name.to_string())?;
+            EnvironmentTarget::Name(env_name.to_string())
+        }
+        (None, Some(prefix)) => EnvironmentTarget::Prefix(prefix.clone()),
+        _ => {
+            return Err(EnvError::Validation(
+                "Must specify exactly one of --name or --prefix".to_string(),
+            ))
+        }
+    };
+
+    let backend = build_default_backend().await?;
+    let uninstall_capability = match &target {
+        EnvironmentTarget::Name(_) => BackendCapability::UninstallByName,
+        EnvironmentTarget::Prefix(_) => BackendCapability::UninstallByPrefix,
+    };
+    backend.require_capability(uninstall_capability)?;
+
+    let packages_to_remove = parse_package_specs(&args.packages);
+    if packages_to_remove.is_empty() {
+        return Err(EnvError::Validation(
+            "No packages specified for removal".to_string(),
+        ));
+    }
+
+    if verbose {
+        info!(
+            "Removing packages from {}: {:?}",
+            target, packages_to_remove
+        );
+    }
+
+    backend
+        .uninstall_packages_for_target(&target, &packages_to_remove, execution_output_mode(verbose))
+        .await
+        .inspect_err(|error| error!("Failed to uninstall packages: {}", error))
+}
+
+/// Execute package update
+async fn execute_env_update(args: EnvUpdateArgs, verbose: bool, offline: bool) -> Result<()> {
+    let packages_to_update = if args.all {
+        Vec::new()
+    } else {
+        let packages = parse_package_specs(&args.packages);
+        if packages.is_empty() {
+            return Err(EnvError::Validation(
+                "No packages specified for update; pass package names or --all".to_string(),
+            ));
+        }
+        packages
+    };
+
+    let backend = build_default_backend_with_options(BackendOptions {
+        transaction_approval: TransactionApproval::from_flags(args.yes, false),
+        channel_priority: args.channel_priority,
+        channels: args.channels.clone(),
+        offline,
+    })
+    .await?;
+    if args.all_envs {
+        backend.require_capability(BackendCapability::UpdateAllEnvironments)?;
+        return backend
+            .update_all_environments(&packages_to_update, execution_output_mode(verbose))
+            .await;
+    }
+
+    let target = match (args.name.as_deref(), args.prefix.as_ref()) {
+        (Some(env_name), None) => {
+            EnvironmentName::parse(env_name.to_string())?;
+            EnvironmentTarget::Name(env_name.to_string())
+        }
+        (None, Some(prefix)) => EnvironmentTarget::Prefix(prefix.clone()),
+        _ => {
+            return Err(EnvError::Validation(
+                "Must specify exactly one of --name, --prefix or --all-envs".to_string(),
+            ))
+        }
+    };
+    let update_capability = match &target {
+        EnvironmentTarget::Name(_) => BackendCapability::UpdateByName,
+        EnvironmentTarget::Prefix(_) => BackendCapability::UpdateByPrefix,
+    };
+    backend.require_capability(update_capability)?;
+
+    if verbose {
+        if packages_to_update.is_empty() {
+            info!("Updating all packages in {}", target);
+        } else {
+            info!("Updating packages in {}: {:?}", target, packages_to_update);
+        }
+    }
+
+    backend
+        .update_packages_for_target(&target, &packages_to_update, execution_output_mode(verbose))
+        .await
+        .inspect_err(|error| error!("Failed to update packages: {}", error))
+}
+
+/// Execute environment sync
+async fn execute_env_sync(args: EnvSyncArgs, verbose: bool, offline: bool) -> Result<()> {
+    let target =
+        environment_target_from_arguments(args.name.as_deref(), args.prefix.as_ref(), "sync")?;
+    if !args.yaml.is_file() {
+        return Err(EnvError::FileNotFound(args.yaml));
+    }
+    if verbose {
+        info!("Syncing {} with {}", target, args.yaml.display());
+    }
+
+    let backend = build_default_backend_with_options(BackendOptions {
+        channel_priority: args.channel_priority,
+        channels: args.channels.clone(),
+        offline,
+        ..BackendOptions::default()
+    })
+    .await?;
+    backend.require_capability(BackendCapability::SyncEnvironment)?;
+    backend
+        .sync_environment(&target, &args.yaml, execution_output_mode(verbose))
+        .await
+        .inspect_err(|error| error!("Failed to sync {}: {}", target, error))
+}
+
+/// Resolves the `ENV` / `--name` / `--prefix` arguments of single-environment commands.
+fn environment_target_from_arguments(
+    name: Option<&str>,
+    prefix: Option<&PathBuf>,
+    action: &str,
+) -> Result<EnvironmentTarget> {
+    match (name, prefix) {
+        (Some(env_name), None) => {
+            EnvironmentName::parse(env_name.to_string())?;
+            Ok(EnvironmentTarget::Name(env_name.to_string()))
+        }
+        (None, Some(prefix)) => Ok(EnvironmentTarget::Prefix(prefix.clone())),
+        (None, None) => Err(EnvError::Validation(format!(
+            "Must specify an environment name or --prefix to {}",
+            action
+        ))),
+        (Some(_), Some(_)) => Err(EnvError::Validation(
+            "Must specify at most one of --name or --prefix".to_string(),
+        )),
+    }
+}
+
+/// Treats a positional value containing a path separator as a prefix, otherwise as a name.
+fn environment_target_from_value(value: &str) -> Result<EnvironmentTarget> {
+    if value.contains('/') || value.contains(std::path::MAIN_SEPARATOR) {
+        Ok(EnvironmentTarget::Prefix(PathBuf::from(value)))
+    } else {
+        EnvironmentName::parse(value.to_string())?;
+        Ok(EnvironmentTarget::Name(value.to_string()))
+    }
+}
+
+/// Execute environment history listing
+async fn execute_env_history(args: EnvHistoryArgs, verbose: bool, json: bool) -> Result<()> {
+    let target = environment_target_from_arguments(
+        args.name.as_deref().or(args.env.as_deref()),
+        args.prefix.as_ref(),
+        "show history for",
+    )?;
+    if verbose {
+        info!("Listing revisions of {}", target);
+    }
+
+    let backend = build_default_backend().await?;
+    backend.require_capability(BackendCapability::EnvironmentHistory)?;
+    let revisions = backend.environment_history(&target).await?;
+
+    if json {
+        let entries = revisions
+            .iter()
+            .zip(revision_changes(&revisions))
+            .map(|(revision, changes)| {
+                serde_json::json!({
+                    "revision": revision.revision,
+                    "timestamp": revision.timestamp,
+                    "operation": revision.operation,
+                    "requested_specs": revision.requested_specs,
+                    "packages": revision.records.len(),
+                    "changes": changes,
+                })
+            })
+            .collect::<Vec<serde_json::Value>>();
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+    } else if revisions.is_empty() {
+        println!("No revisions recorded for {}", target);
+    } else {
+        print!("{}", render_revision_history(&revisions));
+    }
+    Ok(())
+}
+
+/// Execute environment verification; exits with status 1 when drift is found
+async fn execute_env_verify(args: EnvVerifyArgs, verbose: bool, json: bool) -> Result<()> {
+    let backend = build_default_backend().await?;
+    backend.require_capability(BackendCapability::VerifyEnvironment)?;
+
+    let targets = if args.all {
+        let mut prefixes = backend
+            .get_all_conda_environments()
+            .await?
+            .into_iter()
+            .map(|environment| PathBuf::from(environment.prefix))
+            .collect::<Vec<PathBuf>>();
+        prefixes.sort();
+        prefixes.dedup();
+        prefixes
+            .into_iter()
+            .map(EnvironmentTarget::Prefix)
+            .collect::<Vec<EnvironmentTarget>>()
+    } else {
+        vec![environment_target_from_arguments(
+            args.name.as_deref().or(args.env.as_deref()),
+            args.prefix.as_ref(),
+            "verify",
+        )?]
+    };
+
+    let mut verifications = Vec::new();
+    let mut failures = Vec::new();
+    for target in &targets {
+        if verbose {
+            info!("Verifying {}", target);
+        }
+        match backend.verify_environment(target).await {
+            Ok(verification) => {
+                if !json {
+                    print!("{}", render_verification(&verification));
+                }
+                verifications.push(verification);
+            }
+            Err(error) => {
+                error!("Failed to verify {}: {}", target, error);
+                failures.push(format!("{}: {}", target, error));
+            }
+        }
+    }
+
+    if json {
+        let report = if args.all {
+            serde_json::to_string_pretty(&verifications)?
+        } else {
+            match verifications.first() {
+                Some(verification) => serde_json::to_string_pretty(verification)?,
+                None => String::new(),
+            }
+        };
+        if !report.is_empty() {
+            println!("{}", report);
+        }
+    }
+
+    if !failures.is_empty() {
+        return Err(EnvError::Execution(format!(
+            "Failed to verify {} of {} environments: {}",
+            failures.len(),
+            targets.len(),
+            failures.join("; ")
+        )));
+    }
+    if verifications
+        .iter()
+        .any(|verification| verification.has_drift())
+    {
+        return Err(EnvError::ProcessExit { code: Some(1) });
+    }
+    Ok(())
+}
+
+/// Execute environment repair
+async fn execute_env_repair(
+    args: EnvRepairArgs,
+    verbose: bool,
+    json: bool,
+    offline: bool,
+) -> Result<()> {
+    let target = environment_target_from_arguments(
+        args.name.as_deref().or(args.env.as_deref()),
+        args.prefix.as_ref(),
+        "repair",
+    )?;
+    if verbose {
+        info!("Repairing {}", target);
+    }
+
+    let backend = build_default_backend_with_options(BackendOptions {
+        offline,
+        ..BackendOptions::default()
+    })
+    .await?;
+    backend.require_capability(BackendCapability::RepairEnvironment)?;
+    let output_mode = if json {
+        OutputMode::Quiet
+    } else {
+        execution_output_mode(verbose)
+    };
+    let verification = backend
+        .repair_environment(&target, output_mode)
+        .await
+        .inspect_err(|error| error!("Failed to repair {}: {}", target, error))?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&verification)?);
+    }
+    Ok(())
+}
+
+/// Execute environment rollback
+async fn execute_env_rollback(args: EnvRollbackArgs, verbose: bool, offline: bool) -> Result<()> {
+    let target = environment_target_from_arguments(
+        args.name.as_deref().or(args.env.as_deref()),
+        args.prefix.as_ref(),
+        "roll back",
+    )?;
+    if verbose {
+        info!("Rolling back {} to revision {}", target, args.to);
+    }
+
+    let backend = build_default_backend_with_options(BackendOptions {
+        offline,
+        ..BackendOptions::default()
+    })
+    .await?;
+    backend.require_capability(BackendCapability::RollbackEnvironment)?;
+    backend
+        .rollback_environment(&target, args.to, execution_output_mode(verbose))
+        .await
+        .inspect_err(|error| error!("Failed to roll back {}: {}", target, error))
+}
+
+/// Execute environment clone
+async fn execute_env_clone(args: EnvCloneArgs, verbose: bool) -> Result<()> {
+    let source = environment_target_from_value(&args.source)?;
+    EnvironmentName::parse(args.name.clone())?;
+    if verbose {
+        info!("Cloning {} to {}", source, args.name);
+    }
+
+    let backend = build_default_backend().await?;
+    backend.require_capability(BackendCapability::CloneEnvironment)?;
+    backend
+        .clone_environment(
+            &source,
+            &args.name,
+            args.force,
+            execution_output_mode(verbose),
+        )
+        .await
+        .inspect_err(|error| error!("Failed to clone {} to {}: {}", source, args.name, error))
+}
+
+/// Execute environment rename
+async fn execute_env_rename(args: EnvRenameArgs, verbose: bool) -> Result<()> {
+    let target = environment_target_from_value(&args.env)?;
+    EnvironmentName::parse(args.new_name.clone())?;
+    if verbose {
+        info!("Renaming {} to {}", target, args.new_name);
+    }
+
+    let backend = build_default_backend().await?;
+    backend.require_capability(BackendCapability::RelocateEnvironment)?;
+    backend
+        .rename_environment(&target, &args.new_name, execution_output_mode(verbose))
+        .await
+        .map(|_| ())
+        .inspect_err(|error| error!("Failed to rename {}: {}", target, error))
+}
+
+/// Execute environment move
+async fn execute_env_move(args: EnvMoveArgs, verbose: bool) -> Result<()> {
+    let target = environment_target_from_arguments(
+        args.name.as_deref().or(args.env.as_deref()),
+        args.prefix.as_ref(),
+        "move",
+    )?;
+    if verbose {
+        info!("Moving {} to {}", target, args.to_root.display());
+    }
+
+    let backend = build_default_backend().await?;
+    backend.require_capability(BackendCapability::RelocateEnvironment)?;
+    backend
+        .move_environment(&target, &args.to_root, execution_output_mode(verbose))
+        .await
+        .map(|_| ())
+        .inspect_err(|error| error!("Failed to move {}: {}", target, error))
+}
+
+/// Execute environment pack
+async fn execute_env_pack(args: EnvPackArgs, verbose: bool) -> Result<()> {
+    let target = environment_target_from_arguments(
+        args.name.as_deref().or(args.env.as_deref()),
+        args.prefix.as_ref(),
+        "pack",
+    )?;
+    if verbose {
+        info!("Packing {} into {}", target, args.output.display());
+    }
+
+    let backend = build_default_backend().await?;
+    backend.require_capability(BackendCapability::PackEnvironment)?;
+    backend
+        .pack_environment(&target, &args.output, execution_output_mode(verbose))
+        .await
+        .map(|_| ())
+        .inspect_err(|error| error!("Failed to pack {}: {}", target, error))
+}
+
+/// Execute environment unpack
+async fn execute_env_unpack(args: EnvUnpackArgs, verbose: bool) -> Result<()> {
+    let destination = environment_target_from_arguments(
+        args.name.as_deref(),
+        args.prefix.as_ref(),
+        "unpack into",
+    )?;
+    if !args.archive.is_file() {
+        return Err(EnvError::FileNotFound(args.archive));
+    }
+    if verbose {
+        info!("Unpacking {} to {}", args.archive.display(), destination);
+    }
+
+    let backend = build_default_backend().await?;
+    backend.require_capability(BackendCapability::PackEnvironment)?;
+    backend
+        .unpack_environment(
+            &args.archive,
+            &destination,
+            args.force,
+            execution_output_mode(verbose),
+        )
+        .await
+        .map(|_| ())
+        .inspect_err(|error| {
+            error!(
+                "Failed to unpack {} to {}: {}",
+                args.archive.display(),
+                destination,
+                error
+            )
+        })
+}
+
+/// Execute environment export
+async fn execute_env_export(args: EnvExportArgs, verbose: bool) -> Result<()> {
+    let target = environment_target_from_arguments(
+        args.name.as_deref().or(args.env.as_deref()),
+        args.prefix.as_ref(),
+        "export",
+    )?;
+    let format = if args.explicit {
+        ExportFormat::Explicit
+    } else if args.from_history {
+        ExportFormat::FromHistory
+    } else {
+        ExportFormat::Pinned
+    };
+
+    if verbose {
+        info!("Exporting environment {} as {:?}", target, format);
+    }
+
+    let backend = build_default_backend().await?;
+    backend.require_capability(BackendCapability::ExportEnvironment)?;
+    let rendered = backend.export_environment(&target, format).await?;
+
+    match args.file {
+        Some(path) => {
+            std::fs::write(&path, rendered).map_err(|error| {
+                EnvError::FileOperation(format!(
+                    "Failed to write export {}: {}",
+                    path.display(),
+                    error
+                ))
+            })?;
+            info!("Exported environment {} to {}", target, path.display());
+        }
+        None => print!("{}", rendered),
+    }
+    Ok(())
+}
+
 /// Execute environment adoption
 async fn execute_env_adopt(args: EnvAdoptArgs, verbose: bool) -> Result<()> {
     info!("Adopting environment into rattler ownership...");
@@ -776,7 +2237,8 @@ async fn execute_env_remove(
     }
 }
 
-fn build_activation_path_entries(prefix: &Path) -> Vec<PathBuf> {
+/// Directories of `prefix` that activation puts in front of PATH.
+fn prefix_path_entries(prefix: &Path) -> Vec<PathBuf> {
     let mut path_entries = Vec::new();
 
     #[cfg(not(target_os = "windows"))]
@@ -790,14 +2252,26 @@ fn build_activation_path_entries(prefix: &Path) -> Vec<PathBuf> {
         path_entries.push(prefix.join("Library").join("bin"));
     }
 
-    path_entries.extend(std::env::split_paths(
-        &std::env::var_os("PATH").unwrap_or_default(),
-    ));
     path_entries
 }
 
-fn build_activation_path(prefix: &Path) -> Result<String> {
-    std::env::join_paths(build_activation_path_entries(prefix))
+/// PATH entries for activating `prefix` on top of `current_path`. The directories of
+/// `replaced_prefix` are dropped, so only a stacked activation keeps the outer
+/// environment's executables reachable.
+fn build_activation_path_entries(
+    prefix: &Path,
+    current_path: &str,
+    replaced_prefix: Option<&Path>,
+) -> Vec<PathBuf> {
+    let replaced = replaced_prefix.map(prefix_path_entries).unwrap_or_default();
+    let mut path_entries = prefix_path_entries(prefix);
+    path_entries
+        .extend(std::env::split_paths(current_path).filter(|entry| !replaced.contains(entry)));
+    path_entries
+}
+
+fn build_activation_path(prefix: &Path, path_entries: &[PathBuf]) -> Result<String> {
+    std::env::join_paths(path_entries)
         .map(|value| value.to_string_lossy().into_owned())
         .map_err(|error| {
             EnvError::Environment(format!(
@@ -816,6 +2290,31 @@ fn powershell_quote(value: &str) -> String {
     format!("'{}'", value.replace('\'', "''"))
 }
 
+/// Single quotes for tcsh, which still expands `!` inside them.
+fn csh_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''").replace('!', "\\!"))
+}
+
+/// A Python string literal, which xonsh also takes in subprocess mode.
+fn python_quote(value: &str) -> String {
+    let mut quoted = String::from("'");
+    for character in value.chars() {
+        match character {
+            '\\' => quoted.push_str("\\\\"),
+            '\'' => quoted.push_str("\\'"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            character => quoted.push(character),
+        }
+    }
+    quoted.push('\'');
+    quoted
+}
+
+fn nushell_quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
 fn current_binary_path() -> Result<PathBuf> {
     std::env::current_exe().map_err(|error| {
         EnvError::Execution(format!(
@@ -908,6 +2407,66 @@ fn render_shell_hook(shell: ActivationShell, binary_path: &Path) -> String {
                 binary
             )
         }
+        ActivationShell::Tcsh => {
+            let binary = csh_quote(&binary_path.to_string_lossy());
+            format!(
+                "setenv __ENVA_BIN {};\n\
+                 alias enva 'set __enva_args = ( \\!* \"\" ); \
+                 if ( \"$__enva_args[1]\" == activate || \"$__enva_args[1]\" == deactivate ) \
+                 eval \"`$__ENVA_BIN \\!* --shell tcsh`\"; \
+                 if ( \"$__enva_args[1]\" != activate && \"$__enva_args[1]\" != deactivate ) \
+                 \"$__ENVA_BIN\" \\!*; \
+                 unset __enva_args';\n",
+                binary
+            )
+        }
+        ActivationShell::Xonsh => {
+            let binary = python_quote(&binary_path.to_string_lossy());
+            format!(
+                "$__ENVA_BIN = {}\n\
+                 $PROMPT_FIELDS['enva_prompt'] = lambda: ${{...}}.get('ENVA_ACTIVE_PROMPT', '')\n\
+                 if isinstance($PROMPT, str) and '{{enva_prompt}}' not in $PROMPT:\n\
+                 \x20   $PROMPT = '{{enva_prompt}}' + $PROMPT\n\
+                 \n\
+                 def _enva(args):\n\
+                 \x20   if args and args[0] in ('activate', 'deactivate'):\n\
+                 \x20       execx($(@($__ENVA_BIN) @(args) --shell xonsh))\n\
+                 \x20   else:\n\
+                 \x20       @($__ENVA_BIN) @(args)\n\
+                 \n\
+                 aliases['enva'] = _enva\n\
+                 del _enva\n",
+                binary
+            )
+        }
+        // nushell cannot evaluate generated code: activation prints the change as JSON
+        // and the hook loads it.
+        ActivationShell::Nushell => {
+            let binary = nushell_quote(&binary_path.to_string_lossy());
+            format!(
+                "$env.__ENVA_BIN = {}\n\
+                 let __enva_prompt = ($env.PROMPT_COMMAND? | default \"\")\n\
+                 $env.PROMPT_COMMAND = {{||\n\
+                 \x20 let prompt = if ($__enva_prompt | describe | str starts-with \"closure\") {{ do $__enva_prompt }} else {{ $__enva_prompt }}\n\
+                 \x20 ($env.ENVA_ACTIVE_PROMPT? | default \"\") + $prompt\n\
+                 }}\n\
+                 \n\
+                 def --env --wrapped enva [...args] {{\n\
+                 \x20 if ($args | is-empty) {{\n\
+                 \x20   ^$env.__ENVA_BIN\n\
+                 \x20 }} else if $args.0 in [activate deactivate] {{\n\
+                 \x20   let delta = (^$env.__ENVA_BIN ...$args --shell nushell | from json)\n\
+                 \x20   if not ($delta.unset | is-empty) {{\n\
+                 \x20     hide-env --ignore-errors ...$delta.unset\n\
+                 \x20   }}\n\
+                 \x20   load-env $delta.set\n\
+                 \x20 }} else {{\n\
+                 \x20   ^$env.__ENVA_BIN ...$args\n\
+                 \x20 }}\n\
+                 }}\n",
+                binary
+            )
+        }
         ActivationShell::Auto => unreachable!(),
     }
 }
@@ -940,6 +2499,22 @@ fn render_posix_saved_var(name: &str, value: Option<String>) -> String {
     }
 }
 
+/// fish keeps variables named `*PATH` as lists, so their entries are set separately.
+fn render_fish_saved_var(name: &str, value: Option<String>) -> String {
+    match value {
+        Some(value) if name.ends_with("PATH") => format!(
+            "set -gx {} {}\n",
+            name,
+            std::env::split_paths(&value)
+                .map(|entry| sh_quote(&entry.to_string_lossy()))
+                .collect::<Vec<String>>()
+                .join(" ")
+        ),
+        Some(value) => format!("set -gx {} {}\n", name, sh_quote(&value)),
+        None => format!("set -e {}\n", name),
+    }
+}
+
 fn render_powershell_saved_var(name: &str, value: Option<String>) -> String {
     match value {
         Some(value) => format!(
@@ -956,116 +2531,461 @@ fn render_powershell_saved_var(name: &str, value: Option<String>) -> String {
     }
 }
 
-fn render_activation_script(
-    shell: ActivationShell,
-    prefix: &Path,
-    env_name: &str,
-) -> Result<String> {
-    let shell = shell.resolved();
-    let old_path = std::env::var("PATH").unwrap_or_default();
-    let old_conda_prefix = std::env::var("CONDA_PREFIX").ok();
-    let old_conda_default_env = std::env::var("CONDA_DEFAULT_ENV").ok();
-    let old_conda_shlvl = std::env::var("CONDA_SHLVL").ok();
-    let new_path = build_activation_path(prefix)?;
-    let prefix_str = prefix.to_string_lossy().to_string();
+/// Exports `name` with `value` in `shell`, or unsets it when there is no value.
+fn render_saved_var(shell: ActivationShell, name: &str, value: Option<String>) -> String {
+    match shell {
+        ActivationShell::Bash | ActivationShell::Zsh => render_posix_saved_var(name, value),
+        ActivationShell::Fish => render_fish_saved_var(name, value),
+        ActivationShell::Powershell => render_powershell_saved_var(name, value),
+        // tcsh reads `eval` input as a single line, so every command ends with `;`.
+        ActivationShell::Tcsh => match value {
+            Some(value) => format!("setenv {} {};\n", name, csh_quote(&value)),
+            None => format!("unsetenv {};\n", name),
+        },
+        ActivationShell::Xonsh => match value {
+            Some(value) => format!("${} = {}\n", name, python_quote(&value)),
+            None => format!("${{...}}.pop({}, None)\n", python_quote(name)),
+        },
+        ActivationShell::Nushell | ActivationShell::Auto => unreachable!(),
+    }
+}
 
-    let script = match shell {
+/// An environment variable to set, or to unset when there is no value.
+type EnvironmentChange = (String, Option<String>);
+
+fn render_changes(shell: ActivationShell, changes: Vec<EnvironmentChange>) -> String {
+    changes
+        .into_iter()
+        .map(|(name, value)| render_saved_var(shell, &name, value))
+        .collect()
+}
+
+/// nushell cannot evaluate generated code, so its hook loads the net change instead:
+/// `{"set": {...}, "unset": [...]}`, with PATH as the list nushell keeps it as.
+fn render_nushell_changes(changes: Vec<EnvironmentChange>) -> Result<String> {
+    let mut set = serde_json::Map::new();
+    let mut unset = Vec::new();
+    for (name, value) in changes.into_iter().collect::<BTreeMap<_, _>>() {
+        match value {
+            Some(value) if name == "PATH" => {
+                let entries = std::env::split_paths(&value)
+                    .map(|entry| entry.to_string_lossy().into_owned())
+                    .collect::<Vec<String>>();
+                set.insert(name, entries.into());
+            }
+            Some(value) => {
+                set.insert(name, value.into());
+            }
+            None => unset.push(name),
+        }
+    }
+    Ok(serde_json::to_string(&serde_json::json!({ "set": set, "unset": unset }))? + "\n")
+}
+
+/// The calling shell's environment, which activation and deactivation build on.
+fn current_shell_environment() -> HashMap<String, String> {
+    let mut environment = std::env::vars().collect::<HashMap<String, String>>();
+    // Windows spells it `Path`; the lookup through std::env ignores the case.
+    if let Ok(path) = std::env::var("PATH") {
+        environment.insert("PATH".to_string(), path);
+    }
+    environment
+}
+
+/// The activation level stored in `name`, such as `CONDA_SHLVL`; 0 when unset.
+fn shell_level(environment: &HashMap<String, String>, name: &str) -> u32 {
+    environment
+        .get(name)
+        .and_then(|level| level.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+/// Whether `name` holds state of the innermost enva activation.
+fn is_activation_state(name: &str) -> bool {
+    name.starts_with("ENVA_OLD_") || name.starts_with("ENVA_ACTIVE_")
+}
+
+fn stack_backup_prefix(level: u32) -> String {
+    format!("ENVA_SHLVL_{}_", level)
+}
+
+/// Moves the state of the activation at `level` to `ENVA_SHLVL_<level>_*`, so the
+/// activation pushed on top of it can save its own state under the usual names.
+fn stack_backup_changes(
+    level: u32,
+    environment: &HashMap<String, String>,
+) -> Vec<EnvironmentChange> {
+    let mut names = environment
+        .keys()
+        .filter(|name| is_activation_state(name))
+        .collect::<Vec<&String>>();
+    names.sort();
+    names
+        .into_iter()
+        .flat_map(|name| {
+            [
+                (
+                    format!("{}{}", stack_backup_prefix(level), name),
+                    Some(environment[name].clone()),
+                ),
+                (name.clone(), None),
+            ]
+        })
+        .collect()
+}
+
+/// Brings back the state [`stack_backup_changes`] moved aside for `level`.
+fn stack_restore_changes(
+    level: u32,
+    environment: &HashMap<String, String>,
+) -> Vec<EnvironmentChange> {
+    let prefix = stack_backup_prefix(level);
+    let mut backups = environment
+        .iter()
+        .filter_map(|(backup, value)| {
+            backup
+                .strip_prefix(&prefix)
+                .filter(|name| is_activation_state(name))
+                .map(|name| (backup, name, value))
+        })
+        .collect::<Vec<_>>();
+    backups.sort();
+    backups
+        .into_iter()
+        .flat_map(|(backup, name, value)| {
+            [
+                (name.to_string(), Some(value.clone())),
+                (backup.clone(), None),
+            ]
+        })
+        .collect()
+}
+
+/// Sets `name` back to the value saved in `saved`, or unsets it when nothing was saved.
+fn restore_changes(
+    name: &str,
+    saved: &str,
+    environment: &HashMap<String, String>,
+) -> [EnvironmentChange; 2] {
+    [
+        (name.to_string(), environment.get(saved).cloned()),
+        (saved.to_string(), None),
+    ]
+}
+
+/// Prefixes the prompt with `(env_name) ` and keeps the previous prompt for the
+/// deactivation of `level`. Prompts live in the shell itself rather than in exported
+/// variables, so they are saved per level.
+fn render_prompt_activation(shell: ActivationShell, level: u32, env_name: &str) -> String {
+    let label = format!("({}) ", env_name);
+    match shell {
         ActivationShell::Bash | ActivationShell::Zsh => format!(
-            "export ENVA_OLD_PATH={}
-{}{}{}export PATH={}
-export CONDA_PREFIX={}
-export CONDA_DEFAULT_ENV={}
-export CONDA_SHLVL='1'
-export ENVA_ACTIVE_PREFIX={}
-export ENVA_ACTIVE_NAME={}
-hash -r 2>/dev/null || true
-",
-            sh_quote(&old_path),
-            render_posix_saved_var("ENVA_OLD_CONDA_PREFIX", old_conda_prefix),
-            render_posix_saved_var("ENVA_OLD_CONDA_DEFAULT_ENV", old_conda_default_env),
-            render_posix_saved_var("ENVA_OLD_CONDA_SHLVL", old_conda_shlvl),
-            sh_quote(&new_path),
-            sh_quote(&prefix_str),
-            sh_quote(env_name),
-            sh_quote(&prefix_str),
-            sh_quote(env_name),
+            "ENVA_OLD_PS1_{level}=\"${{PS1-}}\"\nPS1={}\"${{PS1-}}\"\n",
+            sh_quote(&label)
+        ),
+        ActivationShell::Fish => format!(
+            "if functions -q fish_prompt\n    functions -c fish_prompt __enva_fish_prompt_{level}\n    function fish_prompt\n        printf '%s' {}\n        __enva_fish_prompt_{level}\n    end\nend\n",
+            sh_quote(&label)
         ),
-        ActivationShell::Fish => {
-            let path_entries = build_activation_path_entries(prefix)
-                .into_iter()
-                .map(|entry| sh_quote(&entry.to_string_lossy()))
-                .collect::<Vec<String>>()
-                .join(" ");
-            format!(
-                "set -gx ENVA_OLD_PATH $PATH
-{}{}{}set -gx PATH {}
-set -gx CONDA_PREFIX {}
-set -gx CONDA_DEFAULT_ENV {}
-set -gx CONDA_SHLVL 1
-set -gx ENVA_ACTIVE_PREFIX {}
-set -gx ENVA_ACTIVE_NAME {}
-",
-                if old_conda_prefix.is_some() {
-                    "set -gx ENVA_OLD_CONDA_PREFIX $CONDA_PREFIX
-"
-                    .to_string()
-                } else {
-                    "set -e ENVA_OLD_CONDA_PREFIX
-"
-                    .to_string()
-                },
-                if old_conda_default_env.is_some() {
-                    "set -gx ENVA_OLD_CONDA_DEFAULT_ENV $CONDA_DEFAULT_ENV
-"
-                    .to_string()
-                } else {
-                    "set -e ENVA_OLD_CONDA_DEFAULT_ENV
-"
-                    .to_string()
-                },
-                if old_conda_shlvl.is_some() {
-                    "set -gx ENVA_OLD_CONDA_SHLVL $CONDA_SHLVL
-"
-                    .to_string()
-                } else {
-                    "set -e ENVA_OLD_CONDA_SHLVL
-"
-                    .to_string()
-                },
-                path_entries,
-                sh_quote(&prefix_str),
-                sh_quote(env_name),
-                sh_quote(&prefix_str),
-                sh_quote(env_name),
-            )
-        }
         ActivationShell::Powershell => format!(
-            "$env:ENVA_OLD_PATH = {}
-{}{}{}$env:PATH = {}
-$env:CONDA_PREFIX = {}
-$env:CONDA_DEFAULT_ENV = {}
-$env:CONDA_SHLVL = '1'
-$env:ENVA_ACTIVE_PREFIX = {}
-$env:ENVA_ACTIVE_NAME = {}
-",
-            powershell_quote(&old_path),
-            render_powershell_saved_var("ENVA_OLD_CONDA_PREFIX", old_conda_prefix),
-            render_powershell_saved_var("ENVA_OLD_CONDA_DEFAULT_ENV", old_conda_default_env),
-            render_powershell_saved_var("ENVA_OLD_CONDA_SHLVL", old_conda_shlvl),
-            powershell_quote(&new_path),
-            powershell_quote(&prefix_str),
-            powershell_quote(env_name),
-            powershell_quote(&prefix_str),
-            powershell_quote(env_name),
+            "if (Test-Path Function:\\prompt) {{\n    $global:ENVA_OLD_PROMPT_{level} = $function:prompt\n    function global:prompt {{ {} + (& $global:ENVA_OLD_PROMPT_{level}) }}\n}}\n",
+            powershell_quote(&label)
+        ),
+        ActivationShell::Tcsh => format!(
+            "if ( $?prompt ) set ENVA_OLD_PROMPT_{level} = \"$prompt\";\nif ( $?prompt ) set prompt = {}\"$prompt\";\n",
+            csh_quote(&label)
         ),
+        // Their hooks put `ENVA_ACTIVE_PROMPT` in front of the prompt.
+        ActivationShell::Xonsh | ActivationShell::Nushell => String::new(),
         ActivationShell::Auto => unreachable!(),
-    };
+    }
+}
+
+fn render_prompt_deactivation(shell: ActivationShell, level: u32) -> String {
+    match shell {
+        ActivationShell::Bash | ActivationShell::Zsh => format!(
+            "if [ \"${{ENVA_OLD_PS1_{level}+x}}\" = x ]; then PS1=\"$ENVA_OLD_PS1_{level}\"; unset ENVA_OLD_PS1_{level}; fi\n"
+        ),
+        ActivationShell::Fish => format!(
+            "if functions -q __enva_fish_prompt_{level}\n    functions -e fish_prompt\n    functions -c __enva_fish_prompt_{level} fish_prompt\n    functions -e __enva_fish_prompt_{level}\nend\n"
+        ),
+        ActivationShell::Powershell => format!(
+            "if (Test-Path Variable:global:ENVA_OLD_PROMPT_{level}) {{ $function:global:prompt = $global:ENVA_OLD_PROMPT_{level}; Remove-Variable ENVA_OLD_PROMPT_{level} -Scope Global }}\n"
+        ),
+        ActivationShell::Tcsh => format!(
+            "if ( $?ENVA_OLD_PROMPT_{level} ) set prompt = \"$ENVA_OLD_PROMPT_{level}\";\nif ( $?ENVA_OLD_PROMPT_{level} ) unset ENVA_OLD_PROMPT_{level};\n"
+        ),
+        ActivationShell::Xonsh | ActivationShell::Nushell => String::new(),
+        ActivationShell::Auto => unreachable!(),
+    }
+}
+
+/// Sets the environment's variables after saving the values they replace, and lists
+/// their names in `ENVA_ACTIVE_VARS` for the next deactivation.
+fn variable_activation_changes(
+    variables: &EnvironmentVariables,
+    environment: &HashMap<String, String>,
+) -> Vec<EnvironmentChange> {
+    let mut changes = Vec::new();
+    for (name, value) in variables {
+        changes.push((
+            format!("ENVA_OLD_VAR_{}", name),
+            environment.get(name).cloned(),
+        ));
+        changes.push((name.clone(), Some(value.clone())));
+    }
+
+    let names = variables.keys().cloned().collect::<Vec<String>>().join(" ");
+    changes.push((
+        "ENVA_ACTIVE_VARS".to_string(),
+        (!names.is_empty()).then_some(names),
+    ));
+    changes
+}
+
+/// Restores or unsets the variables the last activation set, as listed in
+/// `ENVA_ACTIVE_VARS`.
+fn render_variable_deactivation(shell: ActivationShell, names: &[String]) -> String {
+    let mut script = String::new();
+    for name in names {
+        script.push_str(&match shell {
+            ActivationShell::Bash | ActivationShell::Zsh => format!(
+                "if [ \"${{ENVA_OLD_VAR_{name}+x}}\" = x ]; then export {name}=\"$ENVA_OLD_VAR_{name}\"; else unset {name}; fi\nunset ENVA_OLD_VAR_{name}\n"
+            ),
+            ActivationShell::Fish => format!(
+                "if set -q ENVA_OLD_VAR_{name}\n    set -gx {name} $ENVA_OLD_VAR_{name}\nelse\n    set -e {name}\nend\nset -e ENVA_OLD_VAR_{name}\n"
+            ),
+            ActivationShell::Powershell => format!(
+                "if (Test-Path Env:ENVA_OLD_VAR_{name}) {{ $env:{name} = $env:ENVA_OLD_VAR_{name} }} else {{ Remove-Item Env:{name} -ErrorAction SilentlyContinue }}\nRemove-Item Env:ENVA_OLD_VAR_{name} -ErrorAction SilentlyContinue\n"
+            ),
+            ActivationShell::Tcsh
+            | ActivationShell::Xonsh
+            | ActivationShell::Nushell
+            | ActivationShell::Auto => unreachable!(),
+        });
+    }
+    script.push_str(match shell {
+        ActivationShell::Bash | ActivationShell::Zsh => "unset ENVA_ACTIVE_VARS\n",
+        ActivationShell::Fish => "set -e ENVA_ACTIVE_VARS\n",
+        ActivationShell::Powershell => {
+            "Remove-Item Env:ENVA_ACTIVE_VARS -ErrorAction SilentlyContinue\n"
+        }
+        ActivationShell::Tcsh
+        | ActivationShell::Xonsh
+        | ActivationShell::Nushell
+        | ActivationShell::Auto => unreachable!(),
+    });
+    script
+}
+
+/// Variable names the shell's `ENVA_ACTIVE_VARS` lists; names that could not have
+/// come from an activation are ignored.
+fn active_variable_names(environment: &HashMap<String, String>) -> Vec<String> {
+    environment
+        .get("ENVA_ACTIVE_VARS")
+        .map(String::as_str)
+        .unwrap_or_default()
+        .split_whitespace()
+        .filter(|name| validate_variable_name(name).is_ok())
+        .map(str::to_string)
+        .collect()
+}
+
+/// An environment `enva activate` pushes onto the shell's activation stack.
+struct Activation<'a> {
+    prefix: &'a Path,
+    env_name: &'a str,
+    variables: &'a EnvironmentVariables,
+    activate_scripts: &'a [PathBuf],
+    /// Keeps the outer environment's directories on PATH, like `conda activate --stack`.
+    stack: bool,
+    prompt: bool,
+}
+
+/// Changes that activate `activation` one level above the `CONDA_SHLVL` of
+/// `environment`, saving what they replace for the deactivation.
+fn activation_changes(
+    shell: ActivationShell,
+    activation: &Activation,
+    environment: &HashMap<String, String>,
+) -> Result<Vec<EnvironmentChange>> {
+    let prefix = activation.prefix;
+    let outer_level = shell_level(environment, "CONDA_SHLVL");
+    let level = outer_level + 1;
+    let old_path = environment.get("PATH").cloned().unwrap_or_default();
+    let old_conda_prefix = environment.get("CONDA_PREFIX").cloned();
+    let replaced_prefix = old_conda_prefix
+        .as_deref()
+        .filter(|_| !activation.stack)
+        .map(Path::new);
+    let path_entries = build_activation_path_entries(prefix, &old_path, replaced_prefix);
+    let new_path = build_activation_path(prefix, &path_entries)?;
+    let prefix_str = prefix.to_string_lossy().to_string();
+    let set = |name: &str, value: &str| (name.to_string(), Some(value.to_string()));
+
+    // The outer enva activation, if any, is saved first so this one can reuse the names.
+    let mut changes = Vec::new();
+    if environment.contains_key("ENVA_ACTIVE_PREFIX") {
+        changes.extend(stack_backup_changes(outer_level, environment));
+    }
+    changes.push(set("ENVA_OLD_PATH", &old_path));
+    for name in ["CONDA_PREFIX", "CONDA_DEFAULT_ENV", "CONDA_SHLVL"] {
+        changes.push((format!("ENVA_OLD_{}", name), environment.get(name).cloned()));
+    }
+    changes.extend([
+        set("PATH", &new_path),
+        set("CONDA_PREFIX", &prefix_str),
+        set("CONDA_DEFAULT_ENV", activation.env_name),
+        set("CONDA_SHLVL", &level.to_string()),
+        set("ENVA_ACTIVE_PREFIX", &prefix_str),
+        set("ENVA_ACTIVE_NAME", activation.env_name),
+        set("ENVA_ACTIVE_SHLVL", &level.to_string()),
+    ]);
+    if let Some(old_conda_prefix) = old_conda_prefix.filter(|_| outer_level > 0) {
+        changes.push(set(
+            &format!("CONDA_PREFIX_{}", outer_level),
+            &old_conda_prefix,
+        ));
+    }
+    if activation.stack {
+        changes.push(set(&format!("CONDA_STACKED_{}", level), "true"));
+    }
+    if activation.prompt && matches!(shell, ActivationShell::Xonsh | ActivationShell::Nushell) {
+        let outer_prompt = environment
+            .get("ENVA_ACTIVE_PROMPT")
+            .map(String::as_str)
+            .unwrap_or_default();
+        changes.push(set(
+            "ENVA_ACTIVE_PROMPT",
+            &format!("({}) {}", activation.env_name, outer_prompt),
+        ));
+    }
+    changes.extend(variable_activation_changes(
+        activation.variables,
+        environment,
+    ));
+    Ok(changes)
+}
+
+/// Renders the activation of `activation` one level above the `CONDA_SHLVL` of
+/// `environment`. The outer environment stays saved and comes back on deactivation;
+/// without `stack` its directories leave PATH as they do with conda.
+fn render_activation_script(
+    shell: ActivationShell,
+    activation: &Activation,
+    environment: &HashMap<String, String>,
+) -> Result<String> {
+    let shell = shell.resolved();
+    if shell == ActivationShell::Nushell {
+        return render_nushell_activation(activation, environment);
+    }
+    let changes = activation_changes(shell, activation, environment)?;
 
+    let mut script = render_changes(shell, changes);
+    if activation.prompt {
+        let level = shell_level(environment, "CONDA_SHLVL") + 1;
+        script.push_str(&render_prompt_activation(shell, level, activation.env_name));
+    }
+    for activate_script in activation.activate_scripts {
+        script.push_str(&shell.render_source(activate_script));
+    }
+    script.push_str(&render_rehash(shell));
     Ok(script)
 }
 
-fn render_deactivation_script(shell: ActivationShell) -> String {
-    match shell.resolved() {
+/// nushell cannot source the prefix's `activate.d` scripts, so they run here in the
+/// platform's default shell on top of the activated environment. What they set joins the
+/// configured variables, which deactivation restores.
+fn render_nushell_activation(
+    activation: &Activation,
+    environment: &HashMap<String, String>,
+) -> Result<String> {
+    let shell = ActivationShell::Nushell;
+    let changes = activation_changes(shell, activation, environment)?;
+    let mut activated = environment.clone();
+    for (name, value) in &changes {
+        match value {
+            Some(value) => activated.insert(name.clone(), value.clone()),
+            None => activated.remove(name),
+        };
+    }
+
+    let mut variables = activation.variables.clone();
+    let mut script_path = None;
+    for (name, value) in activation_script_changes(activation.prefix, activated)? {
+        if name == "PATH" {
+            script_path = Some(value);
+        } else if !changes.iter().any(|(changed, _)| *changed == name)
+            && validate_variable_name(&name).is_ok()
+        {
+            variables.insert(name, value);
+        }
+    }
+    if variables == *activation.variables && script_path.is_none() {
+        return render_nushell_changes(changes);
+    }
+
+    let mut changes = activation_changes(
+        shell,
+        &Activation {
+            variables: &variables,
+            ..*activation
+        },
+        environment,
+    )?;
+    if let Some(script_path) = script_path {
+        for (name, value) in &mut changes {
+            if name == "PATH" {
+                *value = Some(script_path.clone());
+            }
+        }
+    }
+    render_nushell_changes(changes)
+}
+
+/// Makes the shell forget executables it found through the previous PATH.
+fn render_rehash(shell: ActivationShell) -> String {
+    match shell {
+        ActivationShell::Bash | ActivationShell::Zsh => "hash -r 2>/dev/null || true\n",
+        ActivationShell::Tcsh => "rehash;\n",
+        _ => "",
+    }
+    .to_string()
+}
+
+/// Pops the innermost activation of `environment`: runs `deactivate_scripts`, which
+/// must already be in reverse order, while the environment is still active, then
+/// restores the state from before the activation, including an outer enva activation.
+fn render_deactivation_script(
+    shell: ActivationShell,
+    environment: &HashMap<String, String>,
+    deactivate_scripts: &[PathBuf],
+) -> Result<String> {
+    let shell = shell.resolved();
+    let level = shell_level(environment, "CONDA_SHLVL");
+    if shell_level(environment, "ENVA_ACTIVE_SHLVL") != level {
+        return Err(EnvError::Validation(format!(
+            "The innermost environment of this shell (CONDA_SHLVL={}) was not activated by \
+             enva; deactivate it with the tool that activated it",
+            level
+        )));
+    }
+
+    let mut stack = Vec::new();
+    if level > 0 {
+        stack.push((format!("CONDA_STACKED_{}", level), None));
+    }
+    let mut outer = Vec::new();
+    if level > 1 {
+        outer.push((format!("CONDA_PREFIX_{}", level - 1), None));
+        outer.extend(stack_restore_changes(level - 1, environment));
+    }
+    let scripts = deactivate_scripts
+        .iter()
+        .map(|script| shell.render_source(script))
+        .collect::<String>();
+
+    let script = match shell {
         ActivationShell::Bash | ActivationShell::Zsh => "if [ \"${ENVA_OLD_PATH+x}\" = x ]; then export PATH=\"$ENVA_OLD_PATH\"; fi
 unset ENVA_OLD_PATH
 if [ \"${ENVA_OLD_CONDA_PREFIX+x}\" = x ]; then export CONDA_PREFIX=\"$ENVA_OLD_CONDA_PREFIX\"; else unset CONDA_PREFIX; fi
@@ -1074,7 +2994,7 @@ if [ \"${ENVA_OLD_CONDA_DEFAULT_ENV+x}\" = x ]; then export CONDA_DEFAULT_ENV=\"
 unset ENVA_OLD_CONDA_DEFAULT_ENV
 if [ \"${ENVA_OLD_CONDA_SHLVL+x}\" = x ]; then export CONDA_SHLVL=\"$ENVA_OLD_CONDA_SHLVL\"; else unset CONDA_SHLVL; fi
 unset ENVA_OLD_CONDA_SHLVL
-unset ENVA_ACTIVE_PREFIX ENVA_ACTIVE_NAME
+unset ENVA_ACTIVE_PREFIX ENVA_ACTIVE_NAME ENVA_ACTIVE_SHLVL
 hash -r 2>/dev/null || true
 ".to_string(),
         ActivationShell::Fish => "if set -q ENVA_OLD_PATH
@@ -1098,7 +3018,7 @@ if set -q ENVA_OLD_CONDA_SHLVL
 else
     set -e CONDA_SHLVL
 end
-set -e ENVA_OLD_CONDA_SHLVL ENVA_ACTIVE_PREFIX ENVA_ACTIVE_NAME
+set -e ENVA_OLD_CONDA_SHLVL ENVA_ACTIVE_PREFIX ENVA_ACTIVE_NAME ENVA_ACTIVE_SHLVL
 ".to_string(),
         ActivationShell::Powershell => "if (Test-Path Env:ENVA_OLD_PATH) { $env:PATH = $env:ENVA_OLD_PATH }
 Remove-Item Env:ENVA_OLD_PATH -ErrorAction SilentlyContinue
@@ -1110,25 +3030,78 @@ if (Test-Path Env:ENVA_OLD_CONDA_SHLVL) { $env:CONDA_SHLVL = $env:ENVA_OLD_CONDA
 Remove-Item Env:ENVA_OLD_CONDA_SHLVL -ErrorAction SilentlyContinue
 Remove-Item Env:ENVA_ACTIVE_PREFIX -ErrorAction SilentlyContinue
 Remove-Item Env:ENVA_ACTIVE_NAME -ErrorAction SilentlyContinue
+Remove-Item Env:ENVA_ACTIVE_SHLVL -ErrorAction SilentlyContinue
 ".to_string(),
+        // The newer shells get the saved values resolved here rather than tested in
+        // the shell, which nushell could not evaluate anyway.
+        ActivationShell::Tcsh | ActivationShell::Xonsh | ActivationShell::Nushell => {
+            let mut changes = Vec::new();
+            if let Some(old_path) = environment.get("ENVA_OLD_PATH") {
+                changes.push(("PATH".to_string(), Some(old_path.clone())));
+            }
+            changes.push(("ENVA_OLD_PATH".to_string(), None));
+            for name in ["CONDA_PREFIX", "CONDA_DEFAULT_ENV", "CONDA_SHLVL"] {
+                changes.extend(restore_changes(
+                    name,
+                    &format!("ENVA_OLD_{}", name),
+                    environment,
+                ));
+            }
+            for name in [
+                "ENVA_ACTIVE_PREFIX",
+                "ENVA_ACTIVE_NAME",
+                "ENVA_ACTIVE_SHLVL",
+                "ENVA_ACTIVE_PROMPT",
+            ] {
+                changes.push((name.to_string(), None));
+            }
+            changes.append(&mut stack);
+            for name in active_variable_names(environment) {
+                changes.extend(restore_changes(
+                    &name,
+                    &format!("ENVA_OLD_VAR_{}", name),
+                    environment,
+                ));
+            }
+            changes.push(("ENVA_ACTIVE_VARS".to_string(), None));
+            changes.append(&mut outer);
+            if shell == ActivationShell::Nushell {
+                return render_nushell_changes(changes);
+            }
+            return Ok(scripts
+                + &render_changes(shell, changes)
+                + &render_prompt_deactivation(shell, level)
+                + &render_rehash(shell));
+        }
         ActivationShell::Auto => unreachable!(),
+    };
+
+    let mut script = scripts + &script + &render_changes(shell, stack);
+    if level > 0 {
+        script.push_str(&render_prompt_deactivation(shell, level));
     }
+    script.push_str(&render_variable_deactivation(
+        shell,
+        &active_variable_names(environment),
+    ));
+    script.push_str(&render_changes(shell, outer));
+    Ok(script)
 }
 
-async fn execute_env_activate(args: EnvActivateArgs, verbose: bool) -> Result<()> {
-    let requested_name = args.requested_env_name();
-    if requested_name.is_none() && args.prefix.is_none() {
+/// Resolves the environment `activate` and `shell` work on, with the name they show.
+async fn resolve_activation_prefix(
+    requested_name: Option<&str>,
+    prefix: Option<&Path>,
+    pm: Option<PackageManager>,
+) -> Result<(PathBuf, String)> {
+    if requested_name.is_none() && prefix.is_none() {
         return Err(EnvError::Validation(
             "Must specify an environment name or --prefix".to_string(),
         ));
     }
 
-    let resolved = crate::env_run::resolve_environment_reference(
-        requested_name.as_deref(),
-        args.prefix.as_deref(),
-        args.pm,
-    )
-    .await?;
+    let resolved =
+        crate::env_run::resolve_environment_reference(requested_name, prefix, pm).await?;
 
     if !resolved.prefix.join("conda-meta").is_dir() {
         return Err(EnvError::Validation(format!(
@@ -1137,34 +3110,258 @@ async fn execute_env_activate(args: EnvActivateArgs, verbose: bool) -> Result<()
         )));
     }
 
-    let env_name = activation_env_name(&resolved.prefix, requested_name.as_deref());
+    let env_name = activation_env_name(&resolved.prefix, requested_name);
+    Ok((resolved.prefix, env_name))
+}
+
+async fn execute_env_activate(args: EnvActivateArgs, verbose: bool) -> Result<()> {
+    let requested_name = args.requested_env_name();
+    let (prefix, env_name) =
+        resolve_activation_prefix(requested_name.as_deref(), args.prefix.as_deref(), args.pm)
+            .await?;
     if verbose {
         info!(
             "Generating activation script for environment '{}' at {}",
             env_name,
-            resolved.prefix.display()
+            prefix.display()
         );
     }
 
+    let variables = read_environment_variables(&prefix)?;
+    let activate_scripts = prefix_shell_scripts(&prefix, "activate.d", args.shell)?;
     print!(
         "{}",
-        render_activation_script(args.shell, &resolved.prefix, &env_name)?
+        render_activation_script(
+            args.shell,
+            &Activation {
+                prefix: &prefix,
+                env_name: &env_name,
+                variables: &variables,
+                activate_scripts: &activate_scripts,
+                stack: args.stack,
+                prompt: args.prompt,
+            },
+            &current_shell_environment(),
+        )?
     );
     Ok(())
 }
 
+/// The variables `activate.d` scripts set come back on deactivation anyway, but anything
+/// else a `deactivate.d` script does cannot happen under nushell.
+fn warn_skipped_nushell_scripts(prefix: &Path) -> Result<()> {
+    let scripts = prefix_shell_scripts(prefix, "deactivate.d", ActivationShell::Bash)?;
+    if !scripts.is_empty() {
+        eprintln!(
+            "Warning: nushell cannot run the deactivate.d scripts of {} ({}); the variables \
+             set on activation are restored without them",
+            prefix.display(),
+            scripts
+                .iter()
+                .filter_map(|script| script.file_name())
+                .map(|name| name.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+    Ok(())
+}
+
 async fn execute_env_deactivate(args: EnvDeactivateArgs, verbose: bool) -> Result<()> {
     if verbose {
-        info!("Generating deactivation script");
+        info!("Generating deactivation script");
+    }
+
+    let environment = current_shell_environment();
+    // conda runs deactivation scripts in the reverse order of their activation scripts.
+    let mut deactivate_scripts = match environment.get("ENVA_ACTIVE_PREFIX") {
+        Some(prefix) => {
+            if args.shell.resolved() == ActivationShell::Nushell {
+                warn_skipped_nushell_scripts(Path::new(prefix))?;
+            }
+            prefix_shell_scripts(Path::new(prefix), "deactivate.d", args.shell)?
+        }
+        None => Vec::new(),
+    };
+    deactivate_scripts.reverse();
+    print!(
+        "{}",
+        render_deactivation_script(args.shell, &environment, &deactivate_scripts)?
+    );
+    Ok(())
+}
+
+async fn execute_env_config(args: EnvConfigArgs, verbose: bool, json: bool) -> Result<()> {
+    match args.command {
+        EnvConfigCommand::Vars(vars_args) => match vars_args.command {
+            EnvConfigVarsCommand::Set(set_args) => {
+                let assignments = set_args
+                    .assignments
+                    .iter()
+                    .map(|assignment| parse_variable_assignment(assignment))
+                    .collect::<Result<Vec<(String, String)>>>()?;
+                update_environment_variables(&set_args.target, verbose, |variables| {
+                    variables.extend(assignments)
+                })
+                .await
+            }
+            EnvConfigVarsCommand::Unset(unset_args) => {
+                update_environment_variables(&unset_args.target, verbose, |variables| {
+                    for name in &unset_args.names {
+                        if variables.remove(name).is_none() {
+                            warn!("Environment variable {} is not set", name);
+                        }
+                    }
+                })
+                .await
+            }
+            EnvConfigVarsCommand::List(target) => {
+                let prefix = resolve_config_vars_prefix(&target).await?;
+                let variables = read_environment_variables(&prefix)?;
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&variables)?);
+                } else {
+                    for (name, value) in &variables {
+                        println!("{}={}", name, value);
+                    }
+                }
+                Ok(())
+            }
+        },
+    }
+}
+
+async fn resolve_config_vars_prefix(target: &EnvConfigVarsTarget) -> Result<PathBuf> {
+    let resolved = crate::env_run::resolve_environment_reference(
+        target.name.as_deref(),
+        target.prefix.as_deref(),
+        None,
+    )
+    .await?;
+    if !resolved.prefix.join("conda-meta").is_dir() {
+        return Err(EnvError::Validation(format!(
+            "Environment prefix is not a valid conda-style environment: {}",
+            resolved.prefix.display()
+        )));
+    }
+    Ok(resolved.prefix)
+}
+
+async fn update_environment_variables(
+    target: &EnvConfigVarsTarget,
+    verbose: bool,
+    update: impl FnOnce(&mut EnvironmentVariables),
+) -> Result<()> {
+    let prefix = resolve_config_vars_prefix(target).await?;
+    let mut variables = read_environment_variables(&prefix)?;
+    update(&mut variables);
+    write_environment_variables(&prefix, &variables)?;
+    if verbose {
+        info!(
+            "Wrote {} environment variable(s) to {}",
+            variables.len(),
+            environment_state_path(&prefix).display()
+        );
+    }
+    println!("Reactivate the environment for the changes to take effect.");
+    Ok(())
+}
+
+async fn execute_env_shell(args: EnvShellArgs, verbose: bool) -> Result<()> {
+    match args.command {
+        Some(EnvShellCommand::Hook(hook_args)) => execute_env_shell_hook(hook_args, verbose).await,
+        None => execute_env_subshell(args, verbose).await,
+    }
+}
+
+/// The environment a subshell for `prefix` starts with: `environment` activated one
+/// level up, then whatever the prefix's `activate.d` scripts change.
+fn subshell_environment(
+    shell: ActivationShell,
+    prefix: &Path,
+    env_name: &str,
+    environment: &HashMap<String, String>,
+) -> Result<HashMap<String, String>> {
+    let variables = read_environment_variables(prefix)?;
+    let activation = Activation {
+        prefix,
+        env_name,
+        variables: &variables,
+        activate_scripts: &[],
+        stack: false,
+        prompt: false,
+    };
+    let mut subshell = environment.clone();
+    for (name, value) in activation_changes(shell, &activation, environment)? {
+        match value {
+            Some(value) => subshell.insert(name, value),
+            None => subshell.remove(&name),
+        };
+    }
+
+    let script_changes = activation_script_changes(prefix, subshell.clone())?;
+    subshell.extend(script_changes);
+    Ok(subshell)
+}
+
+/// The interactive shell to start: `$SHELL`, or the platform's command interpreter.
+fn subshell_program() -> std::ffi::OsString {
+    std::env::var_os("SHELL")
+        .filter(|shell| !shell.is_empty())
+        .or_else(|| {
+            cfg!(target_os = "windows")
+                .then(|| std::env::var_os("COMSPEC"))
+                .flatten()
+        })
+        .unwrap_or_else(|| {
+            if cfg!(target_os = "windows") {
+                "cmd.exe".into()
+            } else {
+                "/bin/sh".into()
+            }
+        })
+}
+
+async fn execute_env_subshell(args: EnvShellArgs, verbose: bool) -> Result<()> {
+    let requested_name = args.requested_env_name();
+    let (prefix, env_name) =
+        resolve_activation_prefix(requested_name.as_deref(), args.prefix.as_deref(), args.pm)
+            .await?;
+    let program = subshell_program();
+    if verbose {
+        info!(
+            "Starting {} with environment '{}' at {}",
+            Path::new(&program).display(),
+            env_name,
+            prefix.display()
+        );
     }
 
-    print!("{}", render_deactivation_script(args.shell));
-    Ok(())
-}
-
-async fn execute_env_shell(args: EnvShellArgs, verbose: bool) -> Result<()> {
-    match args.command {
-        EnvShellCommand::Hook(hook_args) => execute_env_shell_hook(hook_args, verbose).await,
+    let environment = subshell_environment(
+        ActivationShell::Auto.resolved(),
+        &prefix,
+        &env_name,
+        &current_shell_environment(),
+    )?;
+    let status = tokio::process::Command::new(&program)
+        .env_clear()
+        .envs(environment)
+        .status()
+        .await
+        .map_err(|error| {
+            EnvError::Execution(format!(
+                "Failed to start {}: {}",
+                Path::new(&program).display(),
+                error
+            ))
+        })?;
+    // The subshell's own environment ends with it; the exit status is all that is left.
+    if status.success() {
+        Ok(())
+    } else {
+        Err(EnvError::ProcessExit {
+            code: status.code(),
+        })
     }
 }
 
@@ -1178,6 +3375,9 @@ async fn execute_env_shell_hook(args: EnvShellHookArgs, verbose: bool) -> Result
                 ActivationShell::Zsh => "zsh",
                 ActivationShell::Fish => "fish",
                 ActivationShell::Powershell => "powershell",
+                ActivationShell::Tcsh => "tcsh",
+                ActivationShell::Xonsh => "xonsh",
+                ActivationShell::Nushell => "nushell",
                 ActivationShell::Auto => unreachable!(),
             },
             binary_path.display()
@@ -1359,14 +3559,38 @@ async fn list_all_conda_environments(detailed: bool, json: bool) -> Result<()> {
 #[cfg(test)]
 mod tests {
     use super::{
-        group_conda_environments, owner_priority_label, parse_package_specs, parse_remove_names,
-        render_activation_script, render_deactivation_script, render_shell_hook,
-        source_priority_label, ActivationShell, EnvCommand,
+        activation_changes, group_conda_environments, nushell_quote, owner_priority_label,
+        parse_package_specs, parse_remove_names, prefix_shell_scripts, render_activation_script,
+        render_deactivation_script, render_shell_hook, sh_quote, source_priority_label,
+        subshell_environment, Activation, ActivationShell, EnvChannelArgs, EnvChannelCommand,
+        EnvChannelIndexArgs, EnvCommand, EnvConfigArgs, EnvConfigCommand, EnvConfigVarsArgs,
+        EnvConfigVarsCommand, EnvShellCommand,
     };
+    use crate::backend::{ChannelPriorityMode, TransactionApproval};
+    use crate::environment_variables::{write_environment_variables, EnvironmentVariables};
     use crate::micromamba::CondaEnvironment;
     use clap::Parser;
+    use std::collections::HashMap;
     use std::ffi::OsString;
+    use std::fs;
     use std::path::{Path, PathBuf};
+    use tempfile::tempdir;
+
+    fn activation_for<'a>(
+        prefix: &'a Path,
+        env_name: &'a str,
+        variables: &'a EnvironmentVariables,
+        activate_scripts: &'a [PathBuf],
+    ) -> Activation<'a> {
+        Activation {
+            prefix,
+            env_name,
+            variables,
+            activate_scripts,
+            stack: false,
+            prompt: false,
+        }
+    }
 
     fn environment(
         name: &str,
@@ -1497,50 +3721,530 @@ mod tests {
         let cli = TestCli::try_parse_from(["enva", "remove", "env-a", "env-b"]).unwrap();
 
         match cli.command {
-            EnvCommand::Remove { names, prefix } => {
-                assert_eq!(names, vec!["env-a", "env-b"]);
-                assert_eq!(prefix, None);
+            EnvCommand::Remove { names, prefix } => {
+                assert_eq!(names, vec!["env-a", "env-b"]);
+                assert_eq!(prefix, None);
+            }
+            other => panic!("unexpected command parsed: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn remove_command_accepts_explicit_prefix_without_name() {
+        let cli = TestCli::try_parse_from(["enva", "remove", "--prefix", "/opt/conda/envs/demo"])
+            .unwrap();
+
+        match cli.command {
+            EnvCommand::Remove { names, prefix } => {
+                assert!(names.is_empty());
+                assert_eq!(prefix, Some(PathBuf::from("/opt/conda/envs/demo")));
+            }
+            other => panic!("unexpected command parsed: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn install_command_accepts_explicit_prefix() {
+        let cli = TestCli::try_parse_from([
+            "enva",
+            "install",
+            "seqtk",
+            "--prefix",
+            "/opt/conda/envs/demo",
+        ])
+        .unwrap();
+
+        match cli.command {
+            EnvCommand::Install(arguments) => {
+                assert_eq!(arguments.packages, vec!["seqtk"]);
+                assert_eq!(
+                    arguments.prefix,
+                    Some(PathBuf::from("/opt/conda/envs/demo"))
+                );
+                assert_eq!(arguments.name, None);
+            }
+            other => panic!("unexpected command parsed: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn lock_command_accepts_yaml_and_lockfile() {
+        let cli = TestCli::try_parse_from([
+            "enva",
+            "lock",
+            "--yaml",
+            "otter-core.yaml",
+            "--lockfile",
+            "otter-core.lock",
+        ])
+        .unwrap();
+
+        match cli.command {
+            EnvCommand::Lock(arguments) => {
+                assert_eq!(arguments.yaml, Some(PathBuf::from("otter-core.yaml")));
+                assert_eq!(arguments.lockfile, Some(PathBuf::from("otter-core.lock")));
+                assert_eq!(arguments.name, None);
+            }
+            other => panic!("unexpected command parsed: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn bundle_command_writes_to_directory_and_create_takes_channel_overrides() {
+        let cli = TestCli::try_parse_from([
+            "enva",
+            "bundle",
+            "--yaml",
+            "otter-core.yaml",
+            "-o",
+            "bundle",
+        ])
+        .unwrap();
+        match cli.command {
+            EnvCommand::Bundle(arguments) => {
+                assert_eq!(arguments.yaml, Some(PathBuf::from("otter-core.yaml")));
+                assert_eq!(arguments.output_dir, PathBuf::from("bundle"));
+            }
+            other => panic!("unexpected command parsed: {:?}", other),
+        }
+        assert!(TestCli::try_parse_from(["enva", "bundle", "--yaml", "otter-core.yaml"]).is_err());
+
+        let cli = TestCli::try_parse_from([
+            "enva",
+            "create",
+            "--yaml",
+            "otter-core.yaml",
+            "--name",
+            "otter-core",
+            "--channel",
+            "file:///data/bundle",
+            "-c",
+            "conda-forge",
+        ])
+        .unwrap();
+        match cli.command {
+            EnvCommand::Create(arguments) => {
+                assert_eq!(
+                    arguments.channels,
+                    vec!["file:///data/bundle".to_string(), "conda-forge".to_string()]
+                );
+            }
+            other => panic!("unexpected command parsed: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn install_update_and_create_take_transaction_approval_flags() {
+        let cli =
+            TestCli::try_parse_from(["enva", "install", "samtools", "--name", "qc", "--dry-run"])
+                .unwrap();
+        match cli.command {
+            EnvCommand::Install(arguments) => {
+                assert!(arguments.dry_run);
+                assert!(!arguments.yes);
+            }
+            other => panic!("unexpected command parsed: {:?}", other),
+        }
+        assert!(
+            TestCli::try_parse_from(["enva", "install", "samtools", "--dry-run", "--yes"]).is_err()
+        );
+
+        let cli =
+            TestCli::try_parse_from(["enva", "update", "--all", "--name", "qc", "--yes"]).unwrap();
+        assert!(matches!(cli.command, EnvCommand::Update(arguments) if arguments.yes));
+        let cli = TestCli::try_parse_from(["enva", "create", "--name", "qc", "--yes"]).unwrap();
+        assert!(matches!(cli.command, EnvCommand::Create(arguments) if arguments.yes));
+
+        assert_eq!(
+            TransactionApproval::from_flags(true, true),
+            TransactionApproval::DryRun
+        );
+        assert_eq!(
+            TransactionApproval::from_flags(true, false),
+            TransactionApproval::AssumeYes
+        );
+        assert_eq!(
+            TransactionApproval::from_flags(false, false),
+            TransactionApproval::Prompt
+        );
+    }
+
+    #[test]
+    fn solving_commands_take_a_channel_priority() {
+        let cli = TestCli::try_parse_from([
+            "enva",
+            "install",
+            "samtools",
+            "--name",
+            "qc",
+            "--channel-priority",
+            "strict",
+        ])
+        .unwrap();
+        assert!(matches!(
+            cli.command,
+            EnvCommand::Install(arguments)
+                if arguments.channel_priority == Some(ChannelPriorityMode::Strict)
+        ));
+        let cli = TestCli::try_parse_from([
+            "enva",
+            "sync",
+            "--yaml",
+            "qc.yaml",
+            "--name",
+            "qc",
+            "--channel-priority",
+            "flexible",
+        ])
+        .unwrap();
+        assert!(matches!(
+            cli.command,
+            EnvCommand::Sync(arguments)
+                if arguments.channel_priority == Some(ChannelPriorityMode::Flexible)
+        ));
+        assert!(TestCli::try_parse_from([
+            "enva",
+            "create",
+            "--name",
+            "qc",
+            "--channel-priority",
+            "sometimes",
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn install_update_and_sync_take_channel_overrides() {
+        let cli = TestCli::try_parse_from([
+            "enva", "install", "samtools", "--name", "qc", "-c", "bioconda", "-c", "./local",
+        ])
+        .unwrap();
+        assert!(matches!(
+            cli.command,
+            EnvCommand::Install(arguments) if arguments.channels == ["bioconda", "./local"]
+        ));
+        let cli =
+            TestCli::try_parse_from(["enva", "update", "--all", "--name", "qc", "--channel", "x"])
+                .unwrap();
+        assert!(matches!(
+            cli.command,
+            EnvCommand::Update(arguments) if arguments.channels == ["x"]
+        ));
+        let cli = TestCli::try_parse_from([
+            "enva", "sync", "--yaml", "qc.yaml", "--name", "qc", "-c", "x",
+        ])
+        .unwrap();
+        assert!(matches!(
+            cli.command,
+            EnvCommand::Sync(arguments) if arguments.channels == ["x"]
+        ));
+    }
+
+    #[test]
+    fn fetch_command_takes_a_name_or_yaml() {
+        let cli = TestCli::try_parse_from(["enva", "fetch", "--yaml", "otter-core.yaml"]).unwrap();
+        match cli.command {
+            EnvCommand::Fetch(arguments) => {
+                assert_eq!(arguments.yaml, Some(PathBuf::from("otter-core.yaml")));
+                assert_eq!(arguments.name, None);
+            }
+            other => panic!("unexpected command parsed: {:?}", other),
+        }
+        assert!(TestCli::try_parse_from(["enva", "fetch"]).is_err());
+    }
+
+    #[test]
+    fn channel_index_command_takes_directory() {
+        let cli =
+            TestCli::try_parse_from(["enva", "channel", "index", "./pkgs", "--zstd"]).unwrap();
+        match cli.command {
+            EnvCommand::Channel(EnvChannelArgs {
+                command: EnvChannelCommand::Index(arguments),
+            }) => {
+                assert_eq!(arguments.directory, PathBuf::from("./pkgs"));
+                assert!(arguments.zstd);
+                assert!(!arguments.organize);
+            }
+            other => panic!("unexpected command parsed: {:?}", other),
+        }
+        let cli =
+            TestCli::try_parse_from(["enva", "channel", "index", "./pkgs", "--organize"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            EnvCommand::Channel(EnvChannelArgs {
+                command: EnvChannelCommand::Index(EnvChannelIndexArgs { organize: true, .. }),
+            })
+        ));
+        assert!(TestCli::try_parse_from(["enva", "channel", "index"]).is_err());
+    }
+
+    #[test]
+    fn validate_command_accepts_yaml_but_not_with_name() {
+        let cli = TestCli::try_parse_from(["enva", "validate", "--yaml", "demo.yaml"]).unwrap();
+        match cli.command {
+            EnvCommand::Validate(arguments) => {
+                assert_eq!(arguments.yaml, Some(PathBuf::from("demo.yaml")));
+                assert!(!arguments.all);
+            }
+            other => panic!("unexpected command parsed: {:?}", other),
+        }
+
+        assert!(TestCli::try_parse_from([
+            "enva",
+            "validate",
+            "--yaml",
+            "demo.yaml",
+            "--name",
+            "otter-core",
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn uninstall_command_requires_a_target() {
+        let cli = TestCli::try_parse_from([
+            "enva",
+            "uninstall",
+            "--name",
+            "otter-extra",
+            "samtools",
+            "bcftools",
+        ])
+        .unwrap();
+        match cli.command {
+            EnvCommand::Uninstall(arguments) => {
+                assert_eq!(arguments.name.as_deref(), Some("otter-extra"));
+                assert_eq!(arguments.packages, vec!["samtools", "bcftools"]);
+            }
+            other => panic!("unexpected command parsed: {:?}", other),
+        }
+
+        assert!(TestCli::try_parse_from(["enva", "uninstall", "samtools"]).is_err());
+    }
+
+    #[test]
+    fn update_command_accepts_all_envs_or_a_single_target() {
+        let cli = TestCli::try_parse_from(["enva", "update", "--all", "--all-envs"]).unwrap();
+        match cli.command {
+            EnvCommand::Update(arguments) => {
+                assert!(arguments.all);
+                assert!(arguments.all_envs);
+                assert!(arguments.packages.is_empty());
+            }
+            other => panic!("unexpected command parsed: {:?}", other),
+        }
+
+        assert!(
+            TestCli::try_parse_from(["enva", "update", "--name", "otter-core", "samtools"]).is_ok()
+        );
+        assert!(TestCli::try_parse_from(["enva", "update", "samtools"]).is_err());
+        assert!(TestCli::try_parse_from(["enva", "update", "--name", "otter-core"]).is_err());
+        assert!(TestCli::try_parse_from([
+            "enva",
+            "update",
+            "--all",
+            "--name",
+            "otter-core",
+            "--all-envs",
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn sync_command_requires_yaml_and_one_target() {
+        let cli = TestCli::try_parse_from([
+            "enva",
+            "sync",
+            "--yaml",
+            "otter-core.yaml",
+            "--name",
+            "otter-core",
+        ])
+        .unwrap();
+        match cli.command {
+            EnvCommand::Sync(arguments) => {
+                assert_eq!(arguments.yaml, PathBuf::from("otter-core.yaml"));
+                assert_eq!(arguments.name.as_deref(), Some("otter-core"));
+            }
+            other => panic!("unexpected command parsed: {:?}", other),
+        }
+
+        assert!(TestCli::try_parse_from(["enva", "sync", "--name", "otter-core"]).is_err());
+        assert!(TestCli::try_parse_from(["enva", "sync", "-y", "otter-core.yaml"]).is_err());
+        assert!(TestCli::try_parse_from([
+            "enva",
+            "sync",
+            "-y",
+            "otter-core.yaml",
+            "--name",
+            "otter-core",
+            "--prefix",
+            "/envs/otter-core",
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn verify_command_accepts_one_target_or_all() {
+        let cli = TestCli::try_parse_from(["enva", "verify", "otter-core"]).unwrap();
+        match cli.command {
+            EnvCommand::Verify(arguments) => {
+                assert_eq!(arguments.env.as_deref(), Some("otter-core"));
+                assert!(!arguments.all);
+            }
+            other => panic!("unexpected command parsed: {:?}", other),
+        }
+
+        assert!(TestCli::try_parse_from(["enva", "verify", "--all"]).is_ok());
+        assert!(TestCli::try_parse_from(["enva", "verify", "otter-core", "--all"]).is_err());
+        assert!(TestCli::try_parse_from([
+            "enva",
+            "verify",
+            "--prefix",
+            "/envs/otter-core",
+            "--all"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn clone_command_takes_source_and_new_name() {
+        let cli =
+            TestCli::try_parse_from(["enva", "clone", "otter-core", "otter-core-test"]).unwrap();
+        match cli.command {
+            EnvCommand::Clone(arguments) => {
+                assert_eq!(arguments.source, "otter-core");
+                assert_eq!(arguments.name, "otter-core-test");
+                assert!(!arguments.force);
+            }
+            other => panic!("unexpected command parsed: {:?}", other),
+        }
+
+        assert!(TestCli::try_parse_from(["enva", "clone", "otter-core"]).is_err());
+    }
+
+    #[test]
+    fn move_command_requires_destination_root() {
+        let cli =
+            TestCli::try_parse_from(["enva", "move", "otter-core", "--to-root", "/project/enva"])
+                .unwrap();
+        match cli.command {
+            EnvCommand::Move(arguments) => {
+                assert_eq!(arguments.env.as_deref(), Some("otter-core"));
+                assert_eq!(arguments.to_root, PathBuf::from("/project/enva"));
+            }
+            other => panic!("unexpected command parsed: {:?}", other),
+        }
+
+        assert!(TestCli::try_parse_from(["enva", "move", "otter-core"]).is_err());
+        assert!(TestCli::try_parse_from(["enva", "rename", "otter-core", "qc"]).is_ok());
+    }
+
+    #[test]
+    fn pack_and_unpack_commands_require_archive_and_destination() {
+        let cli =
+            TestCli::try_parse_from(["enva", "pack", "otter-core", "-o", "otter-core.tar.zst"])
+                .unwrap();
+        match cli.command {
+            EnvCommand::Pack(arguments) => {
+                assert_eq!(arguments.env.as_deref(), Some("otter-core"));
+                assert_eq!(arguments.output, PathBuf::from("otter-core.tar.zst"));
+            }
+            other => panic!("unexpected command parsed: {:?}", other),
+        }
+        assert!(TestCli::try_parse_from(["enva", "pack", "otter-core"]).is_err());
+
+        let cli = TestCli::try_parse_from([
+            "enva",
+            "unpack",
+            "otter-core.tar.zst",
+            "--prefix",
+            "/project/envs/core",
+        ])
+        .unwrap();
+        match cli.command {
+            EnvCommand::Unpack(arguments) => {
+                assert_eq!(arguments.archive, PathBuf::from("otter-core.tar.zst"));
+                assert_eq!(arguments.prefix, Some(PathBuf::from("/project/envs/core")));
+                assert!(arguments.name.is_none());
             }
             other => panic!("unexpected command parsed: {:?}", other),
         }
+        assert!(TestCli::try_parse_from(["enva", "unpack", "otter-core.tar.zst"]).is_err());
+        assert!(TestCli::try_parse_from([
+            "enva",
+            "unpack",
+            "otter-core.tar.zst",
+            "--name",
+            "core",
+            "--prefix",
+            "/project/envs/core"
+        ])
+        .is_err());
     }
 
     #[test]
-    fn remove_command_accepts_explicit_prefix_without_name() {
-        let cli = TestCli::try_parse_from(["enva", "remove", "--prefix", "/opt/conda/envs/demo"])
-            .unwrap();
-
+    fn rollback_command_requires_target_revision() {
+        let cli = TestCli::try_parse_from(["enva", "rollback", "otter-core", "--to", "2"]).unwrap();
         match cli.command {
-            EnvCommand::Remove { names, prefix } => {
-                assert!(names.is_empty());
-                assert_eq!(prefix, Some(PathBuf::from("/opt/conda/envs/demo")));
+            EnvCommand::Rollback(arguments) => {
+                assert_eq!(arguments.env.as_deref(), Some("otter-core"));
+                assert_eq!(arguments.to, 2);
             }
             other => panic!("unexpected command parsed: {:?}", other),
         }
+
+        assert!(TestCli::try_parse_from(["enva", "rollback", "otter-core"]).is_err());
+        assert!(TestCli::try_parse_from(["enva", "history", "--prefix", "/envs/demo"]).is_ok());
     }
 
     #[test]
-    fn install_command_accepts_explicit_prefix() {
-        let cli = TestCli::try_parse_from([
+    fn export_command_rejects_conflicting_formats() {
+        let cli =
+            TestCli::try_parse_from(["enva", "export", "otter-core", "--from-history"]).unwrap();
+        match cli.command {
+            EnvCommand::Export(arguments) => {
+                assert_eq!(arguments.env.as_deref(), Some("otter-core"));
+                assert!(arguments.from_history);
+                assert!(!arguments.explicit);
+            }
+            other => panic!("unexpected command parsed: {:?}", other),
+        }
+
+        assert!(TestCli::try_parse_from([
             "enva",
-            "install",
-            "seqtk",
-            "--prefix",
-            "/opt/conda/envs/demo",
+            "export",
+            "otter-core",
+            "--from-history",
+            "--explicit",
         ])
-        .unwrap();
+        .is_err());
+    }
 
+    #[test]
+    fn create_from_lock_conflicts_with_yaml() {
+        let cli =
+            TestCli::try_parse_from(["enva", "create", "--core", "--from-lock", "otter-core.lock"])
+                .unwrap();
         match cli.command {
-            EnvCommand::Install(arguments) => {
-                assert_eq!(arguments.packages, vec!["seqtk"]);
-                assert_eq!(
-                    arguments.prefix,
-                    Some(PathBuf::from("/opt/conda/envs/demo"))
-                );
-                assert_eq!(arguments.name, None);
+            EnvCommand::Create(arguments) => {
+                assert!(arguments.core);
+                assert_eq!(arguments.from_lock, Some(PathBuf::from("otter-core.lock")));
             }
             other => panic!("unexpected command parsed: {:?}", other),
         }
+
+        assert!(TestCli::try_parse_from([
+            "enva",
+            "create",
+            "--name",
+            "demo",
+            "--yaml",
+            "demo.yaml",
+            "--from-lock",
+            "demo.lock",
+        ])
+        .is_err());
     }
 
     #[test]
@@ -1637,9 +4341,13 @@ mod tests {
 
     #[test]
     fn render_activation_script_for_bash_exports_expected_variables() {
-        let script =
-            render_activation_script(ActivationShell::Bash, Path::new("/tmp/demo"), "demo")
-                .unwrap();
+        let variables = EnvironmentVariables::new();
+        let script = render_activation_script(
+            ActivationShell::Bash,
+            &activation_for(Path::new("/tmp/demo"), "demo", &variables, &[]),
+            &HashMap::new(),
+        )
+        .unwrap();
 
         assert!(script.contains("export PATH='"));
         assert!(script.contains("export CONDA_PREFIX='/tmp/demo'"));
@@ -1649,12 +4357,767 @@ mod tests {
 
     #[test]
     fn render_deactivation_script_for_bash_unsets_activation_state() {
-        let script = render_deactivation_script(ActivationShell::Bash);
+        let script =
+            render_deactivation_script(ActivationShell::Bash, &HashMap::new(), &[]).unwrap();
 
         assert!(script.contains("unset ENVA_OLD_PATH"));
         assert!(script.contains("unset ENVA_ACTIVE_PREFIX ENVA_ACTIVE_NAME"));
     }
 
+    #[test]
+    fn activation_sets_environment_variables_and_deactivation_restores_them() {
+        let variables = EnvironmentVariables::from([
+            ("JAVA_HOME".to_string(), "/opt/java 17".to_string()),
+            ("R_LIBS_USER".to_string(), "it's".to_string()),
+        ]);
+        let prefix = Path::new("/tmp/demo");
+
+        let demo = activation_for(prefix, "demo", &variables, &[]);
+        let bash = render_activation_script(ActivationShell::Bash, &demo, &HashMap::new()).unwrap();
+        assert!(bash.contains("export JAVA_HOME='/opt/java 17'\n"));
+        assert!(bash.contains("export R_LIBS_USER='it'\"'\"'s'\n"));
+        assert!(bash.contains("export ENVA_ACTIVE_VARS='JAVA_HOME R_LIBS_USER'\n"));
+        let fish = render_activation_script(ActivationShell::Fish, &demo, &HashMap::new()).unwrap();
+        assert!(fish.contains("set -gx JAVA_HOME '/opt/java 17'\n"));
+        assert!(fish.contains("set -gx ENVA_ACTIVE_VARS 'JAVA_HOME R_LIBS_USER'\n"));
+        let powershell =
+            render_activation_script(ActivationShell::Powershell, &demo, &HashMap::new()).unwrap();
+        assert!(powershell.contains("$env:R_LIBS_USER = 'it''s'\n"));
+
+        let shell_environment = HashMap::from([
+            ("CONDA_SHLVL".to_string(), "1".to_string()),
+            ("ENVA_ACTIVE_SHLVL".to_string(), "1".to_string()),
+            ("ENVA_ACTIVE_VARS".to_string(), "JAVA_HOME".to_string()),
+        ]);
+        let bash =
+            render_deactivation_script(ActivationShell::Bash, &shell_environment, &[]).unwrap();
+        assert!(bash.contains(
+            "if [ \"${ENVA_OLD_VAR_JAVA_HOME+x}\" = x ]; then export JAVA_HOME=\"$ENVA_OLD_VAR_JAVA_HOME\"; else unset JAVA_HOME; fi\n"
+        ));
+        assert!(bash.ends_with("unset ENVA_ACTIVE_VARS\n"));
+        let fish =
+            render_deactivation_script(ActivationShell::Fish, &shell_environment, &[]).unwrap();
+        assert!(fish.contains("    set -gx JAVA_HOME $ENVA_OLD_VAR_JAVA_HOME\n"));
+        let powershell =
+            render_deactivation_script(ActivationShell::Powershell, &shell_environment, &[])
+                .unwrap();
+        assert!(powershell.contains("Remove-Item Env:JAVA_HOME -ErrorAction SilentlyContinue"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn activation_sources_activate_d_and_deactivation_reverses_deactivate_d() {
+        let tempdir = tempdir().unwrap();
+        let prefix = tempdir.path().join("env");
+        let activate_d = prefix.join("etc/conda/activate.d");
+        let deactivate_d = prefix.join("etc/conda/deactivate.d");
+        fs::create_dir_all(&activate_d).unwrap();
+        fs::create_dir_all(&deactivate_d).unwrap();
+        fs::write(
+            activate_d.join("20-openjdk.sh"),
+            "export JAVA_HOME=\"$CONDA_PREFIX/lib/jvm\"\nexport ORDER=\"$ORDER:java\"\n",
+        )
+        .unwrap();
+        fs::write(
+            activate_d.join("10-gdal.sh"),
+            "export GDAL_DATA=\"$CONDA_PREFIX/share/gdal\"\nexport ORDER=gdal\n",
+        )
+        .unwrap();
+        fs::write(activate_d.join("10-gdal.fish"), "set -gx GDAL_DATA x\n").unwrap();
+        fs::write(
+            deactivate_d.join("10-gdal.sh"),
+            "unset GDAL_DATA\nexport ORDER=\"$ORDER:gdal\"\n",
+        )
+        .unwrap();
+        fs::write(
+            deactivate_d.join("20-openjdk.sh"),
+            "unset JAVA_HOME\nexport ORDER=java\n",
+        )
+        .unwrap();
+
+        let activate_scripts =
+            prefix_shell_scripts(&prefix, "activate.d", ActivationShell::Bash).unwrap();
+        assert_eq!(
+            activate_scripts,
+            vec![
+                activate_d.join("10-gdal.sh"),
+                activate_d.join("20-openjdk.sh")
+            ]
+        );
+        assert_eq!(
+            prefix_shell_scripts(&prefix, "activate.d", ActivationShell::Fish).unwrap(),
+            vec![activate_d.join("10-gdal.fish")]
+        );
+        let mut deactivate_scripts =
+            prefix_shell_scripts(&prefix, "deactivate.d", ActivationShell::Bash).unwrap();
+        deactivate_scripts.reverse();
+
+        let variables = EnvironmentVariables::new();
+        let activation = render_activation_script(
+            ActivationShell::Bash,
+            &activation_for(&prefix, "env", &variables, &activate_scripts),
+            &HashMap::new(),
+        )
+        .unwrap();
+        let shell_environment = HashMap::from([
+            ("CONDA_SHLVL".to_string(), "1".to_string()),
+            ("ENVA_ACTIVE_SHLVL".to_string(), "1".to_string()),
+        ]);
+        let deactivation = render_deactivation_script(
+            ActivationShell::Bash,
+            &shell_environment,
+            &deactivate_scripts,
+        )
+        .unwrap();
+        let output = std::process::Command::new("bash")
+            .arg("-c")
+            .arg(format!(
+                "{}echo \"$ORDER $JAVA_HOME\"\n{}echo \"$ORDER ${{JAVA_HOME-unset}} ${{GDAL_DATA-unset}}\"\n",
+                activation, deactivation
+            ))
+            .env_remove("ORDER")
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{output:?}");
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            format!(
+                "gdal:java {}/lib/jvm\njava:gdal unset unset\n",
+                prefix.display()
+            )
+        );
+    }
+
+    /// Runs `script` in a bash with a minimal environment and returns what it exports.
+    #[cfg(unix)]
+    fn bash_environment(script: &str) -> HashMap<String, String> {
+        let output = std::process::Command::new("bash")
+            .arg("-c")
+            .arg(format!("{}env -0", script))
+            .env_clear()
+            .env("PATH", "/usr/bin:/bin")
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{output:?}");
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .split('\0')
+            .filter_map(|entry| entry.split_once('='))
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stacked_activations_nest_and_deactivation_pops_one_level() {
+        let tempdir = tempdir().unwrap();
+        let outer = tempdir.path().join("outer");
+        let inner = tempdir.path().join("inner");
+        let outer_variables =
+            EnvironmentVariables::from([("TOOL_HOME".to_string(), "/outer".to_string())]);
+        let inner_variables =
+            EnvironmentVariables::from([("TOOL_HOME".to_string(), "/inner".to_string())]);
+
+        let base = bash_environment("");
+        let outer_activation = render_activation_script(
+            ActivationShell::Bash,
+            &activation_for(&outer, "outer", &outer_variables, &[]),
+            &base,
+        )
+        .unwrap();
+        let outer_environment = bash_environment(&outer_activation);
+        assert_eq!(outer_environment["CONDA_SHLVL"], "1");
+        assert_eq!(outer_environment["ENVA_ACTIVE_SHLVL"], "1");
+
+        let mut stacked = activation_for(&inner, "inner", &inner_variables, &[]);
+        stacked.stack = true;
+        let script = outer_activation.clone()
+            + &render_activation_script(ActivationShell::Bash, &stacked, &outer_environment)
+                .unwrap();
+        let inner_environment = bash_environment(&script);
+        assert_eq!(inner_environment["CONDA_SHLVL"], "2");
+        assert_eq!(
+            inner_environment["CONDA_PREFIX"],
+            inner.display().to_string()
+        );
+        assert_eq!(
+            inner_environment["CONDA_PREFIX_1"],
+            outer.display().to_string()
+        );
+        assert_eq!(inner_environment["CONDA_STACKED_2"], "true");
+        assert_eq!(inner_environment["ENVA_ACTIVE_NAME"], "inner");
+        assert_eq!(inner_environment["ENVA_SHLVL_1_ENVA_ACTIVE_NAME"], "outer");
+        assert_eq!(inner_environment["TOOL_HOME"], "/inner");
+        assert_eq!(
+            inner_environment["PATH"],
+            format!(
+                "{}/bin:{}/bin:/usr/bin:/bin",
+                inner.display(),
+                outer.display()
+            )
+        );
+
+        let popped_script = script
+            + &render_deactivation_script(ActivationShell::Bash, &inner_environment, &[]).unwrap();
+        let popped = bash_environment(&popped_script);
+        assert_eq!(popped, outer_environment);
+        let deactivated = bash_environment(
+            &(popped_script
+                + &render_deactivation_script(ActivationShell::Bash, &popped, &[]).unwrap()),
+        );
+        assert_eq!(deactivated, base);
+
+        let replaced = render_activation_script(
+            ActivationShell::Bash,
+            &activation_for(&inner, "inner", &inner_variables, &[]),
+            &outer_environment,
+        )
+        .unwrap();
+        let replaced_environment = bash_environment(&(outer_activation + &replaced));
+        assert_eq!(replaced_environment["CONDA_SHLVL"], "2");
+        assert_eq!(
+            replaced_environment["PATH"],
+            format!("{}/bin:/usr/bin:/bin", inner.display())
+        );
+        assert!(!replaced_environment.contains_key("CONDA_STACKED_2"));
+
+        let mut conda_on_top = outer_environment.clone();
+        conda_on_top.insert("CONDA_SHLVL".to_string(), "2".to_string());
+        let error =
+            render_deactivation_script(ActivationShell::Bash, &conda_on_top, &[]).unwrap_err();
+        assert!(error.to_string().contains("not activated by enva"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn prompt_decoration_follows_the_activation_stack() {
+        let variables = EnvironmentVariables::new();
+        let mut outer = activation_for(Path::new("/tmp/outer"), "outer", &variables, &[]);
+        outer.prompt = true;
+        let mut inner = activation_for(Path::new("/tmp/inner"), "inner", &variables, &[]);
+        inner.prompt = true;
+        inner.stack = true;
+
+        let mut script = "PS1='$ '\n".to_string();
+        let mut report = script.clone();
+        let mut push = |script: &mut String, piece: String| {
+            script.push_str(&piece);
+            report.push_str(&piece);
+            report.push_str("echo \"$PS1\"\n");
+        };
+        let piece =
+            render_activation_script(ActivationShell::Bash, &outer, &bash_environment("")).unwrap();
+        push(&mut script, piece);
+        let piece =
+            render_activation_script(ActivationShell::Bash, &inner, &bash_environment(&script))
+                .unwrap();
+        push(&mut script, piece);
+        for _ in 0..2 {
+            let piece =
+                render_deactivation_script(ActivationShell::Bash, &bash_environment(&script), &[])
+                    .unwrap();
+            push(&mut script, piece);
+        }
+        let output = std::process::Command::new("bash")
+            .arg("-c")
+            .arg(&report)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{output:?}");
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "(outer) $ \n(inner) (outer) $ \n(outer) $ \n$ \n"
+        );
+
+        let fish =
+            render_activation_script(ActivationShell::Fish, &outer, &HashMap::new()).unwrap();
+        assert!(fish.contains("    functions -c fish_prompt __enva_fish_prompt_1\n"));
+        let powershell =
+            render_activation_script(ActivationShell::Powershell, &outer, &HashMap::new()).unwrap();
+        assert!(powershell
+            .contains("function global:prompt { '(outer) ' + (& $global:ENVA_OLD_PROMPT_1) }"));
+    }
+
+    #[test]
+    fn tcsh_xonsh_and_nushell_render_with_their_own_quoting() {
+        use clap::ValueEnum;
+        for (path, shell) in [
+            ("/bin/tcsh", ActivationShell::Tcsh),
+            ("/usr/bin/csh", ActivationShell::Tcsh),
+            ("/usr/bin/xonsh", ActivationShell::Xonsh),
+            ("/home/me/.cargo/bin/nu", ActivationShell::Nushell),
+            ("/gnu/store/bash", ActivationShell::Bash),
+            ("/usr/bin/zsh", ActivationShell::Zsh),
+            ("/opt/homebrew/bin/fish", ActivationShell::Fish),
+            ("/usr/local/bin/pwsh", ActivationShell::Powershell),
+            ("/home/fisher/bin/bash", ActivationShell::Bash),
+            ("/home/zshell-fan/bin/bash", ActivationShell::Bash),
+        ] {
+            assert_eq!(ActivationShell::from_shell_path(path), shell, "{path}");
+        }
+        assert_eq!(
+            ActivationShell::from_str("csh", true).unwrap(),
+            ActivationShell::Tcsh
+        );
+        assert_eq!(
+            ActivationShell::from_str("nu", true).unwrap(),
+            ActivationShell::Nushell
+        );
+
+        let variables =
+            EnvironmentVariables::from([("R_LIBS_USER".to_string(), "it's!".to_string())]);
+        let demo = activation_for(Path::new("/tmp/demo"), "demo", &variables, &[]);
+        let tcsh = render_activation_script(ActivationShell::Tcsh, &demo, &HashMap::new()).unwrap();
+        assert!(tcsh.contains("setenv R_LIBS_USER 'it'\\''s\\!';\n"));
+        assert!(tcsh.contains("unsetenv ENVA_OLD_CONDA_PREFIX;\n"));
+        let xonsh =
+            render_activation_script(ActivationShell::Xonsh, &demo, &HashMap::new()).unwrap();
+        assert!(xonsh.contains("$R_LIBS_USER = 'it\\'s!'\n"));
+        assert!(xonsh.contains("${...}.pop('ENVA_OLD_CONDA_PREFIX', None)\n"));
+
+        let nushell: serde_json::Value = serde_json::from_str(
+            &render_activation_script(
+                ActivationShell::Nushell,
+                &demo,
+                &HashMap::from([("PATH".to_string(), "/usr/bin:/bin".to_string())]),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            nushell["set"]["PATH"],
+            serde_json::json!(["/tmp/demo/bin", "/usr/bin", "/bin"])
+        );
+        assert_eq!(nushell["set"]["ENVA_OLD_PATH"], "/usr/bin:/bin");
+        assert_eq!(nushell["set"]["R_LIBS_USER"], "it's!");
+        assert!(nushell["unset"]
+            .as_array()
+            .unwrap()
+            .contains(&"ENVA_OLD_CONDA_PREFIX".into()));
+
+        let active = HashMap::from([
+            ("CONDA_SHLVL".to_string(), "1".to_string()),
+            ("ENVA_ACTIVE_SHLVL".to_string(), "1".to_string()),
+            ("ENVA_OLD_PATH".to_string(), "/usr/bin:/bin".to_string()),
+            ("ENVA_ACTIVE_VARS".to_string(), "R_LIBS_USER".to_string()),
+            ("ENVA_OLD_VAR_R_LIBS_USER".to_string(), "/old".to_string()),
+        ]);
+        let nushell: serde_json::Value = serde_json::from_str(
+            &render_deactivation_script(ActivationShell::Nushell, &active, &[]).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            nushell["set"]["PATH"],
+            serde_json::json!(["/usr/bin", "/bin"])
+        );
+        assert_eq!(nushell["set"]["R_LIBS_USER"], "/old");
+        for name in [
+            "CONDA_PREFIX",
+            "ENVA_ACTIVE_SHLVL",
+            "ENVA_OLD_VAR_R_LIBS_USER",
+        ] {
+            assert!(nushell["unset"].as_array().unwrap().contains(&name.into()));
+        }
+    }
+
+    /// The environment `changes` leave behind when applied to `environment`.
+    fn apply_changes(
+        mut environment: HashMap<String, String>,
+        changes: Vec<(String, Option<String>)>,
+    ) -> HashMap<String, String> {
+        for (name, value) in changes {
+            match value {
+                Some(value) => environment.insert(name, value),
+                None => environment.remove(&name),
+            };
+        }
+        environment
+    }
+
+    /// Runs `script` with `program` in an otherwise empty environment, or returns None
+    /// when `program` is not on PATH. tcsh, xonsh and nu are rarely installed, so the
+    /// `shells` CI job installs them to make sure their round trips run somewhere.
+    #[cfg(unix)]
+    fn run_shell_script(program: &str, args: &[&str], script: &str) -> Option<String> {
+        let Some(executable) = std::env::var_os("PATH").and_then(|path| {
+            std::env::split_paths(&path)
+                .map(|directory| directory.join(program))
+                .find(|candidate| candidate.is_file())
+        }) else {
+            eprintln!("skipped: {program} is not installed");
+            return None;
+        };
+        let tempdir = tempdir().unwrap();
+        let path = tempdir.path().join("script");
+        fs::write(&path, script).unwrap();
+        let output = std::process::Command::new(executable)
+            .args(args)
+            .arg(&path)
+            .env_clear()
+            .env("PATH", "/usr/local/bin:/usr/bin:/bin")
+            .env("HOME", tempdir.path())
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{program}: {output:?}");
+        Some(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    /// Activation and deactivation of `/tmp/demo`, with a variable that needs quoting, on
+    /// top of a bare PATH.
+    fn demo_round_trip_scripts(shell: ActivationShell) -> (String, String) {
+        let variables =
+            EnvironmentVariables::from([("TOOL_HOME".to_string(), "it's $HOME!".to_string())]);
+        let demo = activation_for(Path::new("/tmp/demo"), "demo", &variables, &[]);
+        let base = HashMap::from([(
+            "PATH".to_string(),
+            "/usr/local/bin:/usr/bin:/bin".to_string(),
+        )]);
+        let active = apply_changes(
+            base.clone(),
+            activation_changes(shell, &demo, &base).unwrap(),
+        );
+        (
+            render_activation_script(shell, &demo, &base).unwrap(),
+            render_deactivation_script(shell, &active, &[]).unwrap(),
+        )
+    }
+
+    /// Stands in for the enva binary behind a shell hook: `activate` and `deactivate`
+    /// print the scripts of [`demo_round_trip_scripts`], anything else echoes its arguments.
+    #[cfg(unix)]
+    fn stub_enva(directory: &Path, shell: ActivationShell) -> PathBuf {
+        use std::os::unix::fs::PermissionsExt;
+        let (activation, deactivation) = demo_round_trip_scripts(shell);
+        fs::write(directory.join("activate.out"), activation).unwrap();
+        fs::write(directory.join("deactivate.out"), deactivation).unwrap();
+        let stub = directory.join("enva");
+        fs::write(
+            &stub,
+            format!(
+                "#!/bin/sh\ncase \"$1\" in\n  activate|deactivate) cat {}/\"$1\".out ;;\n  *) echo \"enva $*\" ;;\nesac\n",
+                sh_quote(&directory.to_string_lossy())
+            ),
+        )
+        .unwrap();
+        fs::set_permissions(&stub, fs::Permissions::from_mode(0o755)).unwrap();
+        stub
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn bash_hook_round_trip() {
+        let tempdir = tempdir().unwrap();
+        let stub = stub_enva(tempdir.path(), ActivationShell::Bash);
+        let Some(output) = run_shell_script(
+            "bash",
+            &["--norc", "--noprofile"],
+            &format!(
+                "{}enva list --json\nenva activate demo\necho \"$CONDA_PREFIX $CONDA_SHLVL $TOOL_HOME\"\nenva deactivate\necho \"${{CONDA_PREFIX-unset}} ${{TOOL_HOME-unset}}\"\n",
+                render_shell_hook(ActivationShell::Bash, &stub)
+            ),
+        ) else {
+            return;
+        };
+        assert_eq!(
+            output,
+            "enva list --json\n/tmp/demo 1 it's $HOME!\nunset unset\n"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn tcsh_activation_and_hook_round_trip() {
+        let (activation, deactivation) = demo_round_trip_scripts(ActivationShell::Tcsh);
+        let Some(output) = run_shell_script(
+            "tcsh",
+            &["-f"],
+            &format!(
+                "{}echo \"$CONDA_PREFIX $CONDA_SHLVL\"\nprintenv TOOL_HOME\n{}echo $?CONDA_PREFIX $?TOOL_HOME\n",
+                activation, deactivation
+            ),
+        ) else {
+            return;
+        };
+        assert_eq!(output, "/tmp/demo 1\nit's $HOME!\n0 0\n");
+
+        let tempdir = tempdir().unwrap();
+        let stub = stub_enva(tempdir.path(), ActivationShell::Tcsh);
+        let Some(output) = run_shell_script(
+            "tcsh",
+            &["-f"],
+            &format!(
+                "{}enva list\nenva activate demo\necho \"$CONDA_PREFIX $CONDA_SHLVL\"\nprintenv TOOL_HOME\nenva deactivate\necho $?CONDA_PREFIX $?TOOL_HOME\n",
+                render_shell_hook(ActivationShell::Tcsh, &stub)
+            ),
+        ) else {
+            return;
+        };
+        assert_eq!(output, "enva list\n/tmp/demo 1\nit's $HOME!\n0 0\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn xonsh_activation_and_hook_round_trip() {
+        let (activation, deactivation) = demo_round_trip_scripts(ActivationShell::Xonsh);
+        let Some(output) = run_shell_script(
+            "xonsh",
+            &["--no-rc"],
+            &format!(
+                "{}print($CONDA_PREFIX, $CONDA_SHLVL, $TOOL_HOME)\n{}print('CONDA_PREFIX' in ${{...}}, 'TOOL_HOME' in ${{...}})\n",
+                activation, deactivation
+            ),
+        ) else {
+            return;
+        };
+        assert_eq!(output, "/tmp/demo 1 it's $HOME!\nFalse False\n");
+
+        let tempdir = tempdir().unwrap();
+        let stub = stub_enva(tempdir.path(), ActivationShell::Xonsh);
+        let Some(output) = run_shell_script(
+            "xonsh",
+            &["--no-rc"],
+            &format!(
+                "{}print($(enva list).strip())\nenva activate demo\nprint($CONDA_PREFIX, $CONDA_SHLVL, $TOOL_HOME)\nenva deactivate\nprint('CONDA_PREFIX' in ${{...}}, 'TOOL_HOME' in ${{...}})\n",
+                render_shell_hook(ActivationShell::Xonsh, &stub)
+            ),
+        ) else {
+            return;
+        };
+        assert_eq!(output, "enva list\n/tmp/demo 1 it's $HOME!\nFalse False\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn nushell_activation_and_hook_round_trip() {
+        let (activation, deactivation) = demo_round_trip_scripts(ActivationShell::Nushell);
+        let Some(output) = run_shell_script(
+            "nu",
+            &["--no-config-file"],
+            &format!(
+                "let delta = ({} | from json)\nload-env $delta.set\nprint $\"($env.CONDA_PREFIX) ($env.CONDA_SHLVL) ($env.TOOL_HOME)\"\n\
+                 let delta = ({} | from json)\nhide-env --ignore-errors ...$delta.unset\nload-env $delta.set\n\
+                 print ('CONDA_PREFIX' in ($env | columns)) ('TOOL_HOME' in ($env | columns))\n",
+                nushell_quote(activation.trim_end()),
+                nushell_quote(deactivation.trim_end())
+            ),
+        ) else {
+            return;
+        };
+        assert_eq!(output, "/tmp/demo 1 it's $HOME!\nfalse\nfalse\n");
+
+        let tempdir = tempdir().unwrap();
+        let stub = stub_enva(tempdir.path(), ActivationShell::Nushell);
+        let Some(output) = run_shell_script(
+            "nu",
+            &["--no-config-file"],
+            &format!(
+                "{}print (enva list | str trim)\nenva activate demo\nprint $\"($env.CONDA_PREFIX) ($env.CONDA_SHLVL) ($env.TOOL_HOME)\"\n\
+                 enva deactivate\nprint ('CONDA_PREFIX' in ($env | columns)) ('TOOL_HOME' in ($env | columns))\n",
+                render_shell_hook(ActivationShell::Nushell, &stub)
+            ),
+        ) else {
+            return;
+        };
+        assert_eq!(output, "enva list\n/tmp/demo 1 it's $HOME!\nfalse\nfalse\n");
+    }
+
+    #[test]
+    fn shell_takes_an_environment_or_the_hook_subcommand() {
+        match TestCli::try_parse_from(["enva", "shell", "otter-core"])
+            .unwrap()
+            .command
+        {
+            EnvCommand::Shell(args) => {
+                assert!(args.command.is_none());
+                assert_eq!(args.requested_env_name().as_deref(), Some("otter-core"));
+            }
+            other => panic!("unexpected command parsed: {:?}", other),
+        }
+        match TestCli::try_parse_from(["enva", "shell", "--prefix", "/envs/gis"])
+            .unwrap()
+            .command
+        {
+            EnvCommand::Shell(args) => {
+                assert!(args.command.is_none());
+                assert_eq!(args.prefix, Some(PathBuf::from("/envs/gis")));
+            }
+            other => panic!("unexpected command parsed: {:?}", other),
+        }
+        match TestCli::try_parse_from(["enva", "shell", "hook", "fish"])
+            .unwrap()
+            .command
+        {
+            EnvCommand::Shell(args) => match args.command {
+                Some(EnvShellCommand::Hook(hook)) => {
+                    assert_eq!(hook.shell, ActivationShell::Fish)
+                }
+                other => panic!("unexpected shell command parsed: {:?}", other),
+            },
+            other => panic!("unexpected command parsed: {:?}", other),
+        }
+        assert!(
+            TestCli::try_parse_from(["enva", "shell", "--prefix", "/envs/gis", "hook"]).is_err()
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn subshell_environment_activates_the_prefix_and_runs_activate_d() {
+        let tempdir = tempdir().unwrap();
+        let prefix = tempdir.path().join("gis");
+        let activate_d = prefix.join("etc/conda/activate.d");
+        fs::create_dir_all(prefix.join("conda-meta")).unwrap();
+        fs::create_dir_all(&activate_d).unwrap();
+        fs::write(
+            activate_d.join("gdal.sh"),
+            "export GDAL_DATA=\"$CONDA_PREFIX/share/gdal\"\n",
+        )
+        .unwrap();
+        write_environment_variables(
+            &prefix,
+            &EnvironmentVariables::from([("PROJ_NETWORK".to_string(), "ON".to_string())]),
+        )
+        .unwrap();
+        let parent = HashMap::from([
+            ("PATH".to_string(), "/usr/bin:/bin".to_string()),
+            ("PROJ_NETWORK".to_string(), "OFF".to_string()),
+        ]);
+
+        let subshell =
+            subshell_environment(ActivationShell::Bash, &prefix, "gis", &parent).unwrap();
+        assert_eq!(subshell["CONDA_PREFIX"], prefix.display().to_string());
+        assert_eq!(subshell["CONDA_SHLVL"], "1");
+        assert_eq!(subshell["ENVA_ACTIVE_NAME"], "gis");
+        assert_eq!(
+            subshell["GDAL_DATA"],
+            format!("{}/share/gdal", prefix.display())
+        );
+        assert_eq!(subshell["PROJ_NETWORK"], "ON");
+        assert_eq!(subshell["ENVA_OLD_VAR_PROJ_NETWORK"], "OFF");
+        assert_eq!(
+            subshell["PATH"],
+            format!("{}/bin:/usr/bin:/bin", prefix.display())
+        );
+        assert_eq!(subshell["ENVA_OLD_PATH"], "/usr/bin:/bin");
+        assert!(!subshell
+            .keys()
+            .any(|name| name.starts_with("CONDA_ENV_SHLVL_")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn nushell_activation_carries_what_activate_d_scripts_set() {
+        let tempdir = tempdir().unwrap();
+        let prefix = tempdir.path().join("gis");
+        let activate_d = prefix.join("etc/conda/activate.d");
+        fs::create_dir_all(prefix.join("conda-meta")).unwrap();
+        fs::create_dir_all(&activate_d).unwrap();
+        fs::write(
+            activate_d.join("gdal.sh"),
+            "export GDAL_DATA=\"$CONDA_PREFIX/share/gdal\"\nexport PATH=\"$CONDA_PREFIX/libexec:$PATH\"\n",
+        )
+        .unwrap();
+        let variables = EnvironmentVariables::new();
+        let gis = activation_for(&prefix, "gis", &variables, &[]);
+        let base = HashMap::from([
+            ("PATH".to_string(), "/usr/bin:/bin".to_string()),
+            ("GDAL_DATA".to_string(), "/usr/share/gdal".to_string()),
+        ]);
+
+        let activation: serde_json::Value = serde_json::from_str(
+            &render_activation_script(ActivationShell::Nushell, &gis, &base).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            activation["set"]["GDAL_DATA"],
+            format!("{}/share/gdal", prefix.display())
+        );
+        assert_eq!(
+            activation["set"]["PATH"],
+            serde_json::json!([
+                format!("{}/libexec", prefix.display()),
+                format!("{}/bin", prefix.display()),
+                "/usr/bin",
+                "/bin"
+            ])
+        );
+        assert_eq!(activation["set"]["ENVA_ACTIVE_VARS"], "GDAL_DATA");
+        assert_eq!(
+            activation["set"]["ENVA_OLD_VAR_GDAL_DATA"],
+            "/usr/share/gdal"
+        );
+        assert!(activation["set"]
+            .get("CONDA_ENV_SHLVL_1_CONDA_PREFIX")
+            .is_none());
+
+        // Deactivation gives the scripts' variables back without running any script.
+        let mut active = base.clone();
+        for (name, value) in activation["set"].as_object().unwrap() {
+            let value = match value {
+                serde_json::Value::Array(entries) => entries
+                    .iter()
+                    .map(|entry| entry.as_str().unwrap())
+                    .collect::<Vec<_>>()
+                    .join(":"),
+                value => value.as_str().unwrap().to_string(),
+            };
+            active.insert(name.clone(), value);
+        }
+        let deactivation: serde_json::Value = serde_json::from_str(
+            &render_deactivation_script(ActivationShell::Nushell, &active, &[]).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(deactivation["set"]["GDAL_DATA"], "/usr/share/gdal");
+        assert_eq!(
+            deactivation["set"]["PATH"],
+            serde_json::json!(["/usr/bin", "/bin"])
+        );
+    }
+
+    #[test]
+    fn config_vars_subcommands_take_an_environment_and_variables() {
+        let cli = TestCli::try_parse_from([
+            "enva",
+            "config",
+            "vars",
+            "set",
+            "--name",
+            "qc",
+            "JAVA_HOME=/opt/java",
+            "R_LIBS_USER=",
+        ])
+        .unwrap();
+        match cli.command {
+            EnvCommand::Config(EnvConfigArgs {
+                command:
+                    EnvConfigCommand::Vars(EnvConfigVarsArgs {
+                        command: EnvConfigVarsCommand::Set(arguments),
+                    }),
+            }) => {
+                assert_eq!(arguments.target.name.as_deref(), Some("qc"));
+                assert_eq!(
+                    arguments.assignments,
+                    ["JAVA_HOME=/opt/java", "R_LIBS_USER="]
+                );
+            }
+            other => panic!("unexpected command parsed: {:?}", other),
+        }
+
+        assert!(TestCli::try_parse_from([
+            "enva",
+            "config",
+            "vars",
+            "unset",
+            "--prefix",
+            "/envs/qc",
+            "JAVA_HOME",
+        ])
+        .is_ok());
+        assert!(TestCli::try_parse_from(["enva", "config", "vars", "unset", "-n", "qc"]).is_err());
+        assert!(TestCli::try_parse_from(["enva", "config", "vars", "list"]).is_err());
+        assert!(TestCli::try_parse_from(["enva", "config", "vars", "list", "-n", "qc"]).is_ok());
+    }
+
     #[test]
     fn render_shell_hook_for_bash_wraps_activate_and_deactivate() {
         let script = render_shell_hook(ActivationShell::Bash, Path::new("/tmp/enva"));
@@ -0,0 +1,450 @@
+//! Environment diff command
+
+use crate::backend::factory::build_default_backend_with_options;
+use crate::backend::{
+    BackendCapability, BackendOptions, EnvironmentBackend, EnvironmentName, EnvironmentTarget,
+};
+use crate::error::{EnvError, Result};
+use crate::lockfile::read_locked_environment;
+use crate::package_changes::{
+    diff_package_records, PackageChange, PackageChangeKind, PackageVersion,
+};
+use clap::Args;
+use rattler_conda_types::{Platform, RepoDataRecord};
+use serde::Serialize;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+/// Environment diff arguments
+/// Each side is an environment name, a prefix path, `<env>@<revision>`, an
+/// environment YAML file, or a `.lock` file:
+/// - enva diff otter-core ./otter-core.yaml
+/// - enva diff otter-core --prefix /scratch/envs/otter-core
+/// - enva diff otter-core@2 otter-core
+#[derive(Debug, Clone, Args)]
+pub struct EnvDiffArgs {
+    /// Sides to compare, in order
+    #[arg(value_name = "SIDE", num_args = 0..=2)]
+    pub sides: Vec<String>,
+
+    /// Explicit environment prefix used as a side; may be given twice
+    #[arg(long, value_name = "PREFIX")]
+    pub prefix: Vec<PathBuf>,
+}
+
+/// Where the package records of one diff side come from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiffSource {
+    Environment(EnvironmentTarget),
+    Revision(EnvironmentTarget, u32),
+    Yaml(PathBuf),
+    Lockfile(PathBuf),
+}
+
+impl DiffSource {
+    /// Interprets a positional side. Existing `.yaml`/`.yml` and `.lock` files are
+    /// definitions, values containing a path separator are prefixes, and anything
+    /// else is an environment name; a trailing `@<number>` selects a revision. A YAML
+    /// file with a top-level `version`, such as `conda-lock.yml`, is read as a lockfile.
+    pub fn parse(value: &str) -> Result<Self> {
+        if let Some((environment, revision)) = value.rsplit_once('@') {
+            if let Ok(revision) = revision.parse::<u32>() {
+                return Ok(Self::Revision(
+                    Self::environment_target(environment)?,
+                    revision,
+                ));
+            }
+        }
+
+        let path = Path::new(value);
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("yaml" | "yml") if path.is_file() => {
+                return Ok(if is_lock_file_yaml(path)? {
+                    Self::Lockfile(path.to_path_buf())
+                } else {
+                    Self::Yaml(path.to_path_buf())
+                });
+            }
+            Some("lock") if path.is_file() => return Ok(Self::Lockfile(path.to_path_buf())),
+            Some("yaml" | "yml" | "lock") => {
+                return Err(EnvError::FileNotFound(path.to_path_buf()));
+            }
+            _ => {}
+        }
+
+        Ok(Self::Environment(Self::environment_target(value)?))
+    }
+
+    fn environment_target(value: &str) -> Result<EnvironmentTarget> {
+        if value.contains('/') || value.contains(std::path::MAIN_SEPARATOR) {
+            Ok(EnvironmentTarget::Prefix(PathBuf::from(value)))
+        } else {
+            Ok(EnvironmentTarget::Name(
+                EnvironmentName::parse(value)?.as_str().to_string(),
+            ))
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            Self::Environment(_) => "environment",
+            Self::Revision(_, _) => "revision",
+            Self::Yaml(_) => "yaml",
+            Self::Lockfile(_) => "lockfile",
+        }
+    }
+
+    fn needs_backend(&self) -> bool {
+        !matches!(self, Self::Lockfile(_))
+    }
+}
+
+/// Lockfiles carry a format `version` at the top level, which environment YAMLs never do.
+fn is_lock_file_yaml(path: &Path) -> Result<bool> {
+    let content = fs::read_to_string(path).map_err(|error| {
+        EnvError::FileOperation(format!("Failed to read {}: {}", path.display(), error))
+    })?;
+    Ok(serde_yaml::from_str::<serde_yaml::Value>(&content)
+        .ok()
+        .is_some_and(|document| {
+            document.get("version").is_some() && document.get("dependencies").is_none()
+        }))
+}
+
+impl fmt::Display for DiffSource {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Environment(target) => write!(formatter, "{}", target),
+            Self::Revision(target, revision) => write!(formatter, "{}@{}", target, revision),
+            Self::Yaml(path) | Self::Lockfile(path) => write!(formatter, "{}", path.display()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct DiffSideReport {
+    source: &'static str,
+    label: String,
+    packages: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct DiffReport {
+    a: DiffSideReport,
+    b: DiffSideReport,
+    identical: bool,
+    changes: Vec<PackageChange>,
+}
+
+fn diff_sources(args: &EnvDiffArgs) -> Result<(DiffSource, DiffSource)> {
+    let mut sources = args
+        .sides
+        .iter()
+        .map(|side| DiffSource::parse(side))
+        .collect::<Result<Vec<DiffSource>>>()?;
+    sources.extend(
+        args.prefix
+            .iter()
+            .map(|prefix| DiffSource::Environment(EnvironmentTarget::Prefix(prefix.clone()))),
+    );
+
+    match <[DiffSource; 2]>::try_from(sources) {
+        Ok([a, b]) => Ok((a, b)),
+        Err(sources) => Err(EnvError::Validation(format!(
+            "enva diff compares exactly two sides, got {}; pass names, prefixes, YAML files or lockfiles",
+            sources.len()
+        ))),
+    }
+}
+
+/// Per-side label used in the table, so `diff A B` reads as "how does B differ from A".
+fn difference_label(kind: PackageChangeKind) -> &'static str {
+    match kind {
+        PackageChangeKind::Install => "added",
+        PackageChangeKind::Remove => "removed",
+        PackageChangeKind::Upgrade => "upgraded",
+        PackageChangeKind::Downgrade => "downgraded",
+        PackageChangeKind::Rebuild => "rebuilt",
+        PackageChangeKind::ChannelSwitch => "channel",
+    }
+}
+
+fn version_cell(version: Option<&PackageVersion>) -> String {
+    match version {
+        Some(version) => match &version.channel {
+            Some(channel) => format!("{} {} {}", version.version, version.build, channel),
+            None => format!("{} {}", version.version, version.build),
+        },
+        None => "-".to_string(),
+    }
+}
+
+fn summarize_differences(changes: &[PackageChange]) -> String {
+    let count = |kinds: &[PackageChangeKind]| {
+        changes
+            .iter()
+            .filter(|change| kinds.contains(&change.kind))
+            .count()
+    };
+    let added = count(&[PackageChangeKind::Install]);
+    let removed = count(&[PackageChangeKind::Remove]);
+    let changed = changes.len() - added - removed;
+    format!("{} added, {} removed, {} changed", added, removed, changed)
+}
+
+pub fn render_diff_table(a: &str, b: &str, changes: &[PackageChange]) -> String {
+    let mut rows = vec![[
+        "PACKAGE".to_string(),
+        "CHANGE".to_string(),
+        a.to_string(),
+        b.to_string(),
+    ]];
+    rows.extend(changes.iter().map(|change| {
+        [
+            change.name.clone(),
+            difference_label(change.kind).to_string(),
+            version_cell(change.from.as_ref()),
+            version_cell(change.to.as_ref()),
+        ]
+    }));
+
+    let mut widths = [0usize; 4];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut lines = rows
+        .iter()
+        .map(|row| {
+            let line = row
+                .iter()
+                .zip(widths)
+                .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+                .collect::<Vec<String>>()
+                .join("  ");
+            line.trim_end().to_string()
+        })
+        .collect::<Vec<String>>();
+    lines.push(summarize_differences(changes));
+
+    let mut rendered = lines.join("\n");
+    rendered.push('\n');
+    rendered
+}
+
+async fn load_records(
+    source: &DiffSource,
+    backend: Option<&dyn EnvironmentBackend>,
+) -> Result<Vec<RepoDataRecord>> {
+    let backend = || {
+        backend.ok_or_else(|| {
+            EnvError::Internal(format!("No backend was prepared to read {}", source))
+        })
+    };
+    match source {
+        DiffSource::Environment(target) => backend()?.installed_package_records(target).await,
+        DiffSource::Revision(target, revision) => {
+            let revisions = backend()?.environment_history(target).await?;
+            let latest = revisions.last().map(|entry| entry.revision);
+            revisions
+                .into_iter()
+                .find(|entry| entry.revision == *revision)
+                .map(|entry| entry.records)
+                .ok_or_else(|| {
+                    EnvError::Validation(match latest {
+                        Some(latest) => format!(
+                            "Revision {} does not exist for {}; available revisions are 0..={}",
+                            revision, target, latest
+                        ),
+                        None => format!("No revisions are recorded for {}", target),
+                    })
+                })
+        }
+        DiffSource::Yaml(path) => backend()?.solve_yaml_records(path).await,
+        DiffSource::Lockfile(path) => {
+            Ok(read_locked_environment(path, Platform::current())?.records)
+        }
+    }
+}
+
+/// Exit status of `enva diff` when the sides have different packages.
+pub const DIFF_EXIT_DIFFERENT: i32 = 1;
+/// Exit status of `enva diff` when a side cannot be read, resolved or solved.
+pub const DIFF_EXIT_ERROR: i32 = 2;
+
+/// Execute environment diff; exits with [`DIFF_EXIT_DIFFERENT`] when the sides differ
+pub async fn execute_env_diff(
+    args: EnvDiffArgs,
+    verbose: bool,
+    json: bool,
+    offline: bool,
+) -> Result<()> {
+    let (a, b) = diff_sources(&args)?;
+    if verbose {
+        info!("Comparing {} with {}", a, b);
+    }
+
+    let backend = if a.needs_backend() || b.needs_backend() {
+        let backend = build_default_backend_with_options(BackendOptions {
+            offline,
+            ..BackendOptions::default()
+        })
+        .await?;
+        backend.require_capability(BackendCapability::DiffEnvironments)?;
+        Some(backend)
+    } else {
+        None
+    };
+    let a_records = load_records(&a, backend.as_deref()).await?;
+    let b_records = load_records(&b, backend.as_deref()).await?;
+    let changes = diff_package_records(&a_records, &b_records);
+    let identical = changes.is_empty();
+
+    if json {
+        let report = DiffReport {
+            a: DiffSideReport {
+                source: a.kind(),
+                label: a.to_string(),
+                packages: a_records.len(),
+            },
+            b: DiffSideReport {
+                source: b.kind(),
+                label: b.to_string(),
+                packages: b_records.len(),
+            },
+            identical,
+            changes,
+        };
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else if identical {
+        println!(
+            "✓ {} and {} are identical ({} packages)",
+            a,
+            b,
+            a_records.len()
+        );
+    } else {
+        print!(
+            "{}",
+            render_diff_table(&a.to_string(), &b.to_string(), &changes)
+        );
+    }
+
+    if identical {
+        Ok(())
+    } else {
+        Err(EnvError::ProcessExit {
+            code: Some(DIFF_EXIT_DIFFERENT),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff_sources, render_diff_table, DiffSource, EnvDiffArgs};
+    use crate::backend::EnvironmentTarget;
+    use crate::package_changes::diff_package_records;
+    use crate::test_fixtures::repodata_record;
+    use rattler_conda_types::RepoDataRecord;
+    use std::fs;
+    use std::path::PathBuf;
+    use tempfile::tempdir;
+
+    fn record(name: &str, version: &str, channel: &str) -> RepoDataRecord {
+        repodata_record(name, version, "h0_0", channel, "noarch")
+    }
+
+    #[test]
+    fn sides_are_classified_by_shape() {
+        let tempdir = tempdir().unwrap();
+        let yaml = tempdir.path().join("otter-core.yaml");
+        let lock = tempdir.path().join("enva.lock");
+        let yaml_lock = tempdir.path().join("conda-lock.yml");
+        fs::write(&yaml, "name: otter-core\n").unwrap();
+        fs::write(&lock, "version: 6\n").unwrap();
+        fs::write(&yaml_lock, "version: 6\nenvironments: {}\npackages: []\n").unwrap();
+
+        assert_eq!(
+            DiffSource::parse("otter-core").unwrap(),
+            DiffSource::Environment(EnvironmentTarget::Name("otter-core".to_string()))
+        );
+        assert_eq!(
+            DiffSource::parse("otter-core@3").unwrap(),
+            DiffSource::Revision(EnvironmentTarget::Name("otter-core".to_string()), 3)
+        );
+        assert_eq!(
+            DiffSource::parse("/scratch/envs/otter-core").unwrap(),
+            DiffSource::Environment(EnvironmentTarget::Prefix(PathBuf::from(
+                "/scratch/envs/otter-core"
+            )))
+        );
+        assert_eq!(
+            DiffSource::parse(yaml.to_str().unwrap()).unwrap(),
+            DiffSource::Yaml(yaml.clone())
+        );
+        assert_eq!(
+            DiffSource::parse(lock.to_str().unwrap()).unwrap(),
+            DiffSource::Lockfile(lock.clone())
+        );
+        assert_eq!(
+            DiffSource::parse(yaml_lock.to_str().unwrap()).unwrap(),
+            DiffSource::Lockfile(yaml_lock.clone())
+        );
+        assert!(DiffSource::parse("missing.yaml").is_err());
+    }
+
+    #[test]
+    fn prefixes_fill_the_remaining_sides() {
+        let args = EnvDiffArgs {
+            sides: vec!["otter-core".to_string()],
+            prefix: vec![PathBuf::from("/laptop/envs/otter-core")],
+        };
+        let (a, b) = diff_sources(&args).unwrap();
+        assert_eq!(a.to_string(), "otter-core");
+        assert_eq!(b.to_string(), "/laptop/envs/otter-core");
+
+        let error = diff_sources(&EnvDiffArgs {
+            sides: vec!["otter-core".to_string()],
+            prefix: Vec::new(),
+        })
+        .unwrap_err();
+        assert!(error.to_string().contains("exactly two sides, got 1"));
+    }
+
+    #[test]
+    fn diff_table_lists_version_build_and_channel() {
+        let a = vec![
+            record("samtools", "1.19", "bioconda"),
+            record("pigz", "2.8", "bioconda"),
+            record("seqtk", "1.4", "bioconda"),
+        ];
+        let b = vec![
+            record("samtools", "1.20", "bioconda"),
+            record("pigz", "2.8", "conda-forge"),
+            record("bwa", "0.7.18", "bioconda"),
+        ];
+
+        let rendered = render_diff_table("cluster", "laptop", &diff_package_records(&a, &b));
+        let lines = rendered.lines().collect::<Vec<&str>>();
+
+        assert_eq!(lines[0], "PACKAGE   CHANGE    cluster             laptop");
+        assert_eq!(
+            lines[1],
+            "bwa       added     -                   0.7.18 h0_0 bioconda"
+        );
+        assert_eq!(
+            lines[2],
+            "pigz      channel   2.8 h0_0 bioconda   2.8 h0_0 conda-forge"
+        );
+        assert_eq!(
+            lines[3],
+            "samtools  upgraded  1.19 h0_0 bioconda  1.20 h0_0 bioconda"
+        );
+        assert_eq!(lines[4], "seqtk     removed   1.4 h0_0 bioconda   -");
+        assert_eq!(lines[5], "1 added, 1 removed, 2 changed");
+    }
+}
@@ -0,0 +1,156 @@
+use crate::backend::ChannelPriorityMode;
+use crate::error::{EnvError, Result};
+use crate::ownership::write_file_atomically;
+use chrono::Utc;
+use rattler_digest::{compute_file_digest, Sha256};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use url::Url;
+
+const METADATA_FILE_NAME: &str = "environment.json";
+
+/// Environment YAML a prefix was last reconciled against by `enva sync`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct YamlSource {
+    pub path: PathBuf,
+    pub sha256: String,
+    pub synced_at: String,
+}
+
+impl YamlSource {
+    pub fn from_file(yaml_file: &Path) -> Result<Self> {
+        let path = fs::canonicalize(yaml_file).map_err(|error| {
+            EnvError::FileOperation(format!(
+                "Failed to resolve environment file {}: {}",
+                yaml_file.display(),
+                error
+            ))
+        })?;
+        let sha256 = compute_file_digest::<Sha256>(&path).map_err(|error| {
+            EnvError::FileOperation(format!(
+                "Failed to hash environment file {}: {}",
+                path.display(),
+                error
+            ))
+        })?;
+
+        Ok(Self {
+            path,
+            sha256: hex::encode(sha256),
+            synced_at: Utc::now().to_rfc3339(),
+        })
+    }
+}
+
+/// Settings enva keeps next to a prefix's packages; cloned into staging prefixes
+/// together with `conda-meta/`, so every published prefix carries its own copy.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct EnvironmentMetadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub yaml_source: Option<YamlSource>,
+    /// Channel priority of the last solve, reused by later installs and updates.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel_priority: Option<ChannelPriorityMode>,
+    /// Channel URLs the environment was created from, in priority order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub channels: Vec<String>,
+    /// Channel alias that named channels resolved against at creation time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel_alias: Option<Url>,
+}
+
+pub fn environment_metadata_path(prefix: &Path) -> PathBuf {
+    prefix
+        .join("conda-meta")
+        .join("enva")
+        .join(METADATA_FILE_NAME)
+}
+
+pub fn read_environment_metadata(prefix: &Path) -> Result<EnvironmentMetadata> {
+    let path = environment_metadata_path(prefix);
+    if !path.exists() {
+        return Ok(EnvironmentMetadata::default());
+    }
+
+    let content = fs::read_to_string(&path).map_err(|error| {
+        EnvError::FileOperation(format!(
+            "Failed to read environment metadata {}: {}",
+            path.display(),
+            error
+        ))
+    })?;
+    serde_json::from_str(&content).map_err(|error| {
+        EnvError::Validation(format!(
+            "Failed to parse environment metadata {}: {}",
+            path.display(),
+            error
+        ))
+    })
+}
+
+pub fn write_environment_metadata(prefix: &Path, metadata: &EnvironmentMetadata) -> Result<()> {
+    let path = environment_metadata_path(prefix);
+    let directory = path.parent().unwrap_or(prefix);
+    fs::create_dir_all(directory).map_err(|error| {
+        EnvError::FileOperation(format!(
+            "Failed to create environment metadata directory {}: {}",
+            directory.display(),
+            error
+        ))
+    })?;
+    let mut serialized = serde_json::to_vec_pretty(metadata)?;
+    serialized.push(b'\n');
+    write_file_atomically(&path, &serialized, "environment metadata")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        environment_metadata_path, read_environment_metadata, write_environment_metadata,
+        EnvironmentMetadata, YamlSource,
+    };
+    use crate::backend::ChannelPriorityMode;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn metadata_round_trips_and_defaults_when_missing() {
+        let tempdir = tempdir().unwrap();
+        let prefix = tempdir.path().join("env");
+        fs::create_dir_all(prefix.join("conda-meta")).unwrap();
+        let yaml_file = tempdir.path().join("env.yaml");
+        fs::write(&yaml_file, "name: demo\n").unwrap();
+
+        assert_eq!(
+            read_environment_metadata(&prefix).unwrap(),
+            EnvironmentMetadata::default()
+        );
+
+        let metadata = EnvironmentMetadata {
+            yaml_source: Some(YamlSource::from_file(&yaml_file).unwrap()),
+            channel_priority: Some(ChannelPriorityMode::Strict),
+            channels: vec!["https://mirror.example.org/bioconda/".to_string()],
+            channel_alias: Some("https://mirror.example.org/".parse().unwrap()),
+        };
+        write_environment_metadata(&prefix, &metadata).unwrap();
+
+        assert!(environment_metadata_path(&prefix).is_file());
+        let metadata = read_environment_metadata(&prefix).unwrap();
+        assert_eq!(metadata.channel_priority, Some(ChannelPriorityMode::Strict));
+        assert_eq!(
+            metadata.channels,
+            vec!["https://mirror.example.org/bioconda/"]
+        );
+        assert_eq!(
+            metadata.channel_alias.unwrap().as_str(),
+            "https://mirror.example.org/"
+        );
+        let source = metadata.yaml_source.unwrap();
+        assert_eq!(source.path, fs::canonicalize(&yaml_file).unwrap());
+        assert_eq!(
+            source.sha256,
+            "8789e7eabb7ba5922a5087c25d315a1e5fbb6b0f97510862579d348428dbd9d4"
+        );
+    }
+}
@@ -0,0 +1,104 @@
+use crate::error::{EnvError, Result};
+use crate::ownership::write_file_atomically;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// enva's own list of rattler-owned environments that were moved out of every root prefix.
+/// conda's shared `~/.conda/environments.txt` is left to the tools that own it.
+pub fn default_environment_registry() -> Option<PathBuf> {
+    dirs::data_dir().map(|data| data.join("enva").join("environments.txt"))
+}
+
+pub fn read_registered_prefixes(registry: &Path) -> Result<Vec<PathBuf>> {
+    if !registry.is_file() {
+        return Ok(Vec::new());
+    }
+
+    let content = fs::read_to_string(registry).map_err(|error| {
+        EnvError::FileOperation(format!(
+            "Failed to read environment registry {}: {}",
+            registry.display(),
+            error
+        ))
+    })?;
+    Ok(content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(PathBuf::from)
+        .collect())
+}
+
+/// Replaces `old_prefix` with `new_prefix` in `registry`, registering `new_prefix` even
+/// when `old_prefix` was never listed.
+pub fn replace_registered_prefix(
+    registry: &Path,
+    old_prefix: &Path,
+    new_prefix: &Path,
+) -> Result<()> {
+    let mut prefixes = read_registered_prefixes(registry)?
+        .into_iter()
+        .filter(|prefix| prefix != old_prefix && prefix != new_prefix)
+        .collect::<Vec<PathBuf>>();
+    prefixes.push(new_prefix.to_path_buf());
+
+    if let Some(directory) = registry.parent() {
+        fs::create_dir_all(directory).map_err(|error| {
+            EnvError::FileOperation(format!(
+                "Failed to create environment registry directory {}: {}",
+                directory.display(),
+                error
+            ))
+        })?;
+    }
+    let mut content = prefixes
+        .iter()
+        .map(|prefix| prefix.display().to_string())
+        .collect::<Vec<String>>()
+        .join("\n");
+    content.push('\n');
+    write_file_atomically(registry, content.as_bytes(), "environment registry")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_registered_prefixes, replace_registered_prefix};
+    use std::fs;
+    use std::path::PathBuf;
+    use tempfile::tempdir;
+
+    #[test]
+    fn replacing_a_prefix_keeps_other_entries_and_registers_new_ones() {
+        let tempdir = tempdir().unwrap();
+        let registry = tempdir.path().join("enva/environments.txt");
+        assert!(read_registered_prefixes(&registry).unwrap().is_empty());
+
+        replace_registered_prefix(
+            &registry,
+            &PathBuf::from("/home/ana/envs/old"),
+            &PathBuf::from("/project/envs/old"),
+        )
+        .unwrap();
+        assert_eq!(
+            read_registered_prefixes(&registry).unwrap(),
+            vec![PathBuf::from("/project/envs/old")]
+        );
+
+        fs::write(
+            &registry,
+            "/opt/conda\n/home/ana/envs/old\n\n/opt/conda/envs/qc\n",
+        )
+        .unwrap();
+        replace_registered_prefix(
+            &registry,
+            &PathBuf::from("/home/ana/envs/old"),
+            &PathBuf::from("/project/envs/old"),
+        )
+        .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&registry).unwrap(),
+            "/opt/conda\n/opt/conda/envs/qc\n/project/envs/old\n"
+        );
+    }
+}
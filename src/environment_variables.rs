@@ -0,0 +1,275 @@
+//! Per-environment variables stored in `conda-meta/state`.
+//!
+//! conda keeps the variables of `conda env config vars` and of an environment YAML's
+//! `variables:` section in that file, so enva reads and writes the same `env_vars` map
+//! and leaves any other keys of the file untouched. The variables set by a prefix's
+//! `activate.d` scripts are found by running them.
+
+use crate::error::{EnvError, Result};
+use crate::ownership::write_file_atomically;
+use rattler_conda_types::Platform;
+use rattler_shell::activation::{
+    ActivationError, ActivationVariables, Activator, PathModificationBehavior,
+};
+use rattler_shell::shell::ShellEnum;
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const STATE_FILE_NAME: &str = "state";
+const ENV_VARS_KEY: &str = "env_vars";
+
+/// Variable names mapped to the values `run` and `activate` set.
+pub type EnvironmentVariables = BTreeMap<String, String>;
+
+pub fn environment_state_path(prefix: &Path) -> PathBuf {
+    prefix.join("conda-meta").join(STATE_FILE_NAME)
+}
+
+/// Accepts names a POSIX shell, fish and PowerShell can all assign without quoting.
+pub fn validate_variable_name(name: &str) -> Result<()> {
+    let mut characters = name.chars();
+    let valid = characters
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+        && characters.all(|character| character.is_ascii_alphanumeric() || character == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(EnvError::Validation(format!(
+            "Invalid environment variable name '{}': use letters, digits and underscores, \
+             not starting with a digit",
+            name
+        )))
+    }
+}
+
+/// Parses a `NAME=VALUE` argument; the value may be empty and may contain `=`.
+pub fn parse_variable_assignment(assignment: &str) -> Result<(String, String)> {
+    let (name, value) = assignment.split_once('=').ok_or_else(|| {
+        EnvError::Validation(format!(
+            "Invalid environment variable '{}': expected NAME=VALUE",
+            assignment
+        ))
+    })?;
+    validate_variable_name(name)?;
+    Ok((name.to_string(), value.to_string()))
+}
+
+fn read_state(prefix: &Path) -> Result<Map<String, Value>> {
+    let path = environment_state_path(prefix);
+    if !path.is_file() {
+        return Ok(Map::new());
+    }
+
+    let content = fs::read_to_string(&path).map_err(|error| {
+        EnvError::FileOperation(format!(
+            "Failed to read environment state {}: {}",
+            path.display(),
+            error
+        ))
+    })?;
+    if content.trim().is_empty() {
+        return Ok(Map::new());
+    }
+    serde_json::from_str(&content).map_err(|error| {
+        EnvError::Validation(format!(
+            "Failed to parse environment state {}: {}",
+            path.display(),
+            error
+        ))
+    })
+}
+
+pub fn read_environment_variables(prefix: &Path) -> Result<EnvironmentVariables> {
+    let Some(env_vars) = read_state(prefix)?.remove(ENV_VARS_KEY) else {
+        return Ok(EnvironmentVariables::new());
+    };
+    serde_json::from_value(env_vars).map_err(|error| {
+        EnvError::Validation(format!(
+            "Failed to parse environment variables in {}: {}",
+            environment_state_path(prefix).display(),
+            error
+        ))
+    })
+}
+
+/// Replaces the variables of `prefix`; the state file is removed once it holds nothing.
+pub fn write_environment_variables(prefix: &Path, variables: &EnvironmentVariables) -> Result<()> {
+    let path = environment_state_path(prefix);
+    let mut state = read_state(prefix)?;
+    if variables.is_empty() {
+        state.remove(ENV_VARS_KEY);
+    } else {
+        state.insert(ENV_VARS_KEY.to_string(), serde_json::to_value(variables)?);
+    }
+
+    if state.is_empty() {
+        if path.exists() {
+            fs::remove_file(&path).map_err(|error| {
+                EnvError::FileOperation(format!(
+                    "Failed to remove environment state {}: {}",
+                    path.display(),
+                    error
+                ))
+            })?;
+        }
+        return Ok(());
+    }
+
+    let mut serialized = serde_json::to_vec_pretty(&state)?;
+    serialized.push(b'\n');
+    write_file_atomically(&path, &serialized, "environment state")
+}
+
+/// Variables the prefix's `etc/conda/activate.d` scripts set or change, found by
+/// running them on top of `environment` in the platform's default shell; empty when
+/// there are no scripts.
+fn activation_script_environment(
+    prefix: &Path,
+    environment: HashMap<String, String>,
+) -> Result<HashMap<String, String>> {
+    let activation_error = |error: ActivationError| {
+        EnvError::Execution(format!(
+            "Failed to run the activation scripts of {}: {}",
+            prefix.display(),
+            error
+        ))
+    };
+    let activator = Activator::from_path(prefix, ShellEnum::default(), Platform::current())
+        .map_err(activation_error)?;
+    if activator.activation_scripts.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let script_environment = environment
+        .iter()
+        .map(|(name, value)| (OsStr::new(name), OsStr::new(value)))
+        .collect::<HashMap<&OsStr, &OsStr>>();
+    let variables = ActivationVariables {
+        conda_prefix: None,
+        path: None,
+        path_modification_behavior: PathModificationBehavior::Prepend,
+        current_env: environment.clone(),
+    };
+    activator
+        .run_activation(variables, Some(script_environment))
+        .map_err(activation_error)
+}
+
+/// What the prefix's `activate.d` scripts change on top of `environment`, ready to apply
+/// over it. The activation level is left out because the caller keeps its own, and PATH
+/// loses repeated entries, since the scripts run with the prefix already on PATH and
+/// often put it there once more.
+pub fn activation_script_changes(
+    prefix: &Path,
+    environment: HashMap<String, String>,
+) -> Result<HashMap<String, String>> {
+    let mut changes = activation_script_environment(prefix, environment)?;
+    changes.retain(|name, _| name != "CONDA_SHLVL" && !name.starts_with("CONDA_ENV_SHLVL_"));
+    if let Some(path) = changes.get_mut("PATH") {
+        let mut entries = Vec::new();
+        for entry in std::env::split_paths(path.as_str()) {
+            if !entries.contains(&entry) {
+                entries.push(entry);
+            }
+        }
+        *path = std::env::join_paths(entries)
+            .map(|value| value.to_string_lossy().into_owned())
+            .map_err(|error| {
+                EnvError::Environment(format!(
+                    "Failed to construct PATH for environment {}: {}",
+                    prefix.display(),
+                    error
+                ))
+            })?;
+    }
+    Ok(changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        activation_script_changes, environment_state_path, parse_variable_assignment,
+        read_environment_variables, write_environment_variables, EnvironmentVariables,
+    };
+    use std::collections::HashMap;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn variables_round_trip_and_keep_other_state_keys() {
+        let tempdir = tempdir().unwrap();
+        let prefix = tempdir.path();
+        fs::create_dir_all(prefix.join("conda-meta")).unwrap();
+        assert!(read_environment_variables(prefix).unwrap().is_empty());
+
+        fs::write(
+            environment_state_path(prefix),
+            r#"{"env_vars": {"R_LIBS_USER": "/old"}, "channels": ["bioconda"]}"#,
+        )
+        .unwrap();
+        let mut variables = read_environment_variables(prefix).unwrap();
+        assert_eq!(variables["R_LIBS_USER"], "/old");
+
+        variables.insert("JAVA_HOME".to_string(), "/opt/java".to_string());
+        variables.remove("R_LIBS_USER");
+        write_environment_variables(prefix, &variables).unwrap();
+        let state: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(environment_state_path(prefix)).unwrap())
+                .unwrap();
+        assert_eq!(state["env_vars"]["JAVA_HOME"], "/opt/java");
+        assert!(state["env_vars"].get("R_LIBS_USER").is_none());
+        assert_eq!(state["channels"][0], "bioconda");
+
+        fs::write(
+            environment_state_path(prefix),
+            r#"{"env_vars": {"A": "1"}}"#,
+        )
+        .unwrap();
+        write_environment_variables(prefix, &EnvironmentVariables::new()).unwrap();
+        assert!(!environment_state_path(prefix).exists());
+    }
+
+    #[test]
+    fn assignments_need_a_shell_safe_name() {
+        assert_eq!(
+            parse_variable_assignment("JAVA_OPTS=-Xmx4g -Dx=y").unwrap(),
+            ("JAVA_OPTS".to_string(), "-Xmx4g -Dx=y".to_string())
+        );
+        assert_eq!(
+            parse_variable_assignment("EMPTY=").unwrap(),
+            ("EMPTY".to_string(), String::new())
+        );
+        assert!(parse_variable_assignment("NO_VALUE").is_err());
+        assert!(parse_variable_assignment("1ST=x").is_err());
+        assert!(parse_variable_assignment("A;rm=x").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn activation_script_changes_leave_out_the_level_and_repeated_path_entries() {
+        let tempdir = tempdir().unwrap();
+        let prefix = tempdir.path();
+        let activate_d = prefix.join("etc").join("conda").join("activate.d");
+        fs::create_dir_all(&activate_d).unwrap();
+        fs::write(
+            activate_d.join("demo.sh"),
+            format!(
+                "export DEMO_HOME=/opt/demo\nexport CONDA_SHLVL=7\nexport PATH=\"{}/bin:$PATH\"\n",
+                prefix.display()
+            ),
+        )
+        .unwrap();
+        let path = format!("{}/bin:/usr/bin:/bin", prefix.display());
+        let environment = HashMap::from([("PATH".to_string(), path.clone())]);
+
+        let changes = activation_script_changes(prefix, environment).unwrap();
+
+        assert_eq!(changes["DEMO_HOME"], "/opt/demo");
+        assert!(!changes.contains_key("CONDA_SHLVL"));
+        assert_eq!(changes["PATH"], path);
+    }
+}
@@ -1,5 +1,6 @@
 //! Error handling for enva
 
+use crate::solve_conflict::SolveConflict;
 use chrono::DateTime;
 use std::path::PathBuf;
 use thiserror::Error;
@@ -58,6 +59,9 @@ pub enum EnvError {
     #[error("Execution error: {0}")]
     Execution(String),
 
+    #[error("{0}")]
+    SolveConflict(Box<SolveConflict>),
+
     #[error("Package installation failed: {0}")]
     InstallationFailed(String),
 
@@ -0,0 +1,340 @@
+use crate::backend::ExportFormat;
+use crate::environment_variables::{read_environment_variables, EnvironmentVariables};
+use crate::error::{EnvError, Result};
+use crate::package_changes::channel_display_name;
+use rattler_conda_types::{MatchSpec, PackageRecord, ParseStrictness, Platform, PrefixRecord};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Serialize)]
+struct ExportedEnvironment {
+    name: String,
+    channels: Vec<String>,
+    dependencies: Vec<String>,
+    #[serde(skip_serializing_if = "EnvironmentVariables::is_empty")]
+    variables: EnvironmentVariables,
+    prefix: String,
+}
+
+pub fn render_environment_export(
+    env_name: &str,
+    prefix: &Path,
+    installed: &[PrefixRecord],
+    requested_specs: &[String],
+    format: ExportFormat,
+) -> Result<String> {
+    match format {
+        ExportFormat::Pinned => {
+            let mut records = installed.iter().collect::<Vec<&PrefixRecord>>();
+            records.sort_by(|left, right| record_name(left).cmp(record_name(right)));
+            render_yaml(
+                env_name,
+                prefix,
+                installed,
+                records
+                    .into_iter()
+                    .map(|record| {
+                        let package = &record.repodata_record.package_record;
+                        format!(
+                            "{}={}={}",
+                            package.name.as_normalized(),
+                            package.version,
+                            package.build
+                        )
+                    })
+                    .collect(),
+            )
+        }
+        ExportFormat::FromHistory => {
+            if requested_specs.is_empty() {
+                return Err(EnvError::Validation(format!(
+                    "No requested specs are recorded for {}; export the pinned environment instead",
+                    prefix.display()
+                )));
+            }
+            render_yaml(env_name, prefix, installed, requested_specs.to_vec())
+        }
+        ExportFormat::Explicit => Ok(render_explicit(installed)),
+    }
+}
+
+/// Collects the specs the user asked for, preferring package metadata and falling
+/// back to the `# update specs:` lines that conda writes to `conda-meta/history`.
+pub fn exported_requested_specs(prefix: &Path, installed: &[PrefixRecord]) -> Vec<String> {
+    let mut specs = Vec::new();
+    let mut seen = HashSet::new();
+    for record in installed {
+        #[allow(deprecated)]
+        let legacy_spec = record.requested_spec.iter();
+        for spec in legacy_spec.chain(record.requested_specs.iter()) {
+            if !spec.trim().is_empty() && seen.insert(spec.clone()) {
+                specs.push(spec.clone());
+            }
+        }
+    }
+
+    if specs.is_empty() {
+        specs = requested_specs_from_history(prefix);
+    }
+    specs
+}
+
+fn requested_specs_from_history(prefix: &Path) -> Vec<String> {
+    let Ok(history) = fs::read_to_string(prefix.join("conda-meta").join("history")) else {
+        return Vec::new();
+    };
+
+    let mut specs: Vec<String> = Vec::new();
+    for line in history.lines() {
+        let Some(comment) = line.trim().strip_prefix('#') else {
+            continue;
+        };
+        let Some((action, values)) = comment.trim().split_once("specs:") else {
+            continue;
+        };
+        let values = parse_history_spec_list(values);
+        match action.trim() {
+            "update" | "install" | "create" => {
+                for spec in values {
+                    let name = spec_package_name(&spec);
+                    specs.retain(|existing| spec_package_name(existing) != name);
+                    specs.push(spec);
+                }
+            }
+            "remove" | "uninstall" => {
+                let removed = values
+                    .iter()
+                    .map(|spec| spec_package_name(spec))
+                    .collect::<HashSet<String>>();
+                specs.retain(|existing| !removed.contains(&spec_package_name(existing)));
+            }
+            _ => {}
+        }
+    }
+    specs
+}
+
+/// Reads the spec list of a history comment. conda writes a Python list (`['a', 'b']`) and
+/// micromamba a JSON one (`["a", "b"]`); commas inside a quoted spec stay part of it.
+fn parse_history_spec_list(values: &str) -> Vec<String> {
+    let values = values.trim();
+    let values = values.strip_prefix('[').unwrap_or(values);
+    let values = values.strip_suffix(']').unwrap_or(values);
+
+    let mut specs = Vec::new();
+    let mut current = String::new();
+    let mut quote = None;
+    let mut characters = values.chars();
+    while let Some(character) = characters.next() {
+        match (quote, character) {
+            (Some(_), '\\') => current.extend(characters.next()),
+            (Some(open), character) if character == open => quote = None,
+            (None, '\'' | '"') => quote = Some(character),
+            (None, ',') => specs.push(std::mem::take(&mut current)),
+            (_, character) => current.push(character),
+        }
+    }
+    specs.push(current);
+    specs
+        .into_iter()
+        .map(|spec| spec.trim().to_string())
+        .filter(|spec| !spec.is_empty())
+        .collect()
+}
+
+fn spec_package_name(spec: &str) -> String {
+    MatchSpec::from_str(spec, ParseStrictness::Lenient)
+        .ok()
+        .and_then(|spec| spec.name.as_exact().cloned())
+        .map(|name| name.as_normalized().to_string())
+        .unwrap_or_else(|| spec.to_string())
+}
+
+fn record_name(record: &PrefixRecord) -> &str {
+    record.repodata_record.package_record.name.as_normalized()
+}
+
+fn export_channel_names(installed: &[PrefixRecord]) -> Vec<String> {
+    let mut channels = Vec::new();
+    let mut seen = HashSet::new();
+    for record in installed {
+        if let Some(channel) = record.repodata_record.channel.as_deref() {
+            let channel = channel_display_name(channel);
+            if !channel.is_empty() && seen.insert(channel.clone()) {
+                channels.push(channel);
+            }
+        }
+    }
+    channels
+}
+
+fn render_yaml(
+    env_name: &str,
+    prefix: &Path,
+    installed: &[PrefixRecord],
+    dependencies: Vec<String>,
+) -> Result<String> {
+    let exported = ExportedEnvironment {
+        name: env_name.to_string(),
+        channels: export_channel_names(installed),
+        dependencies,
+        variables: read_environment_variables(prefix)?,
+        prefix: prefix.display().to_string(),
+    };
+    serde_yaml::to_string(&exported).map_err(|error| {
+        EnvError::Serialization(format!(
+            "Failed to render environment export for {}: {}",
+            prefix.display(),
+            error
+        ))
+    })
+}
+
+fn render_explicit(installed: &[PrefixRecord]) -> String {
+    let mut lines = vec![
+        "# This file may be used to create an environment using:".to_string(),
+        "# $ micromamba create --name <env> --file <this file>".to_string(),
+        format!("# platform: {}", Platform::current()),
+        "@EXPLICIT".to_string(),
+    ];
+
+    for record in PackageRecord::sort_topologically(installed.to_vec()) {
+        let package = &record.repodata_record.package_record;
+        let url = record.repodata_record.url.as_str();
+        let line = match (&package.md5, &package.sha256) {
+            (Some(md5), _) => format!("{}#{}", url, hex::encode(md5)),
+            (None, Some(sha256)) => format!("{}#sha256:{}", url, hex::encode(sha256)),
+            (None, None) => url.to_string(),
+        };
+        lines.push(line);
+    }
+
+    let mut rendered = lines.join("\n");
+    rendered.push('\n');
+    rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{exported_requested_specs, parse_history_spec_list, render_environment_export};
+    use crate::backend::ExportFormat;
+    use crate::environment_variables::write_environment_variables;
+    use crate::test_fixtures::repodata_record;
+    use rattler_conda_types::PrefixRecord;
+    use std::fs;
+    use std::path::Path;
+    use tempfile::tempdir;
+
+    fn prefix_record(name: &str, version: &str, requested: Option<&str>) -> PrefixRecord {
+        let mut repodata = repodata_record(name, version, "h123_0", "bioconda", "linux-64");
+        repodata.package_record.md5 = Some(
+            rattler_digest::parse_digest_from_hex::<rattler_digest::Md5>(
+                "0123456789abcdef0123456789abcdef",
+            )
+            .unwrap(),
+        );
+        let mut record = PrefixRecord::from_repodata_record(repodata, vec![]);
+        record.requested_specs = requested.map(str::to_string).into_iter().collect();
+        record
+    }
+
+    #[test]
+    fn pinned_export_lists_every_record_with_channel_names() {
+        let installed = vec![
+            prefix_record("seqtk", "1.4", Some("seqtk")),
+            prefix_record("libzlib", "1.3.1", None),
+        ];
+
+        let rendered = render_environment_export(
+            "demo",
+            Path::new("/envs/demo"),
+            &installed,
+            &[],
+            ExportFormat::Pinned,
+        )
+        .unwrap();
+
+        assert!(rendered.contains("name: demo"));
+        assert!(rendered.contains("- bioconda"));
+        assert!(rendered.contains("- libzlib=1.3.1=h123_0\n- seqtk=1.4=h123_0"));
+    }
+
+    #[test]
+    fn from_history_export_uses_requested_specs_only() {
+        let tempdir = tempdir().unwrap();
+        let installed = vec![
+            prefix_record("seqtk", "1.4", Some("bioconda::seqtk >=1.4")),
+            prefix_record("libzlib", "1.3.1", None),
+        ];
+
+        fs::create_dir_all(tempdir.path().join("conda-meta")).unwrap();
+        write_environment_variables(
+            tempdir.path(),
+            &[("JAVA_HOME".to_string(), "/opt/java".to_string())].into(),
+        )
+        .unwrap();
+
+        let requested = exported_requested_specs(tempdir.path(), &installed);
+        let rendered = render_environment_export(
+            "demo",
+            tempdir.path(),
+            &installed,
+            &requested,
+            ExportFormat::FromHistory,
+        )
+        .unwrap();
+
+        assert_eq!(requested, vec!["bioconda::seqtk >=1.4"]);
+        assert!(rendered.contains("bioconda::seqtk >=1.4"));
+        assert!(!rendered.contains("libzlib"));
+        assert!(rendered.contains("variables:\n  JAVA_HOME: /opt/java\n"));
+    }
+
+    #[test]
+    fn requested_specs_fall_back_to_conda_history() {
+        let tempdir = tempdir().unwrap();
+        fs::create_dir_all(tempdir.path().join("conda-meta")).unwrap();
+        fs::write(
+            tempdir.path().join("conda-meta").join("history"),
+            "==> 2024-01-01 00:00:00 <==\n# cmd: conda create -n demo seqtk samtools\n# update specs: ['seqtk', 'samtools=1.19']\n==> 2024-02-01 00:00:00 <==\n# update specs: [\"samtools>=1.20\", \"htslib\"]\n# remove specs: ['seqtk']\n",
+        )
+        .unwrap();
+
+        let requested =
+            exported_requested_specs(tempdir.path(), &[prefix_record("samtools", "1.20", None)]);
+
+        assert_eq!(requested, vec!["samtools>=1.20", "htslib"]);
+    }
+
+    #[test]
+    fn history_spec_lists_split_python_and_json_lists_on_quoted_items() {
+        assert_eq!(
+            parse_history_spec_list(" ['seqtk', 'samtools >=1.19,<2']"),
+            vec!["seqtk", "samtools >=1.19,<2"]
+        );
+        assert_eq!(
+            parse_history_spec_list(r#" ["bioconda::seqtk", "samtools >=1.19,<2", "htslib"]"#),
+            vec!["bioconda::seqtk", "samtools >=1.19,<2", "htslib"]
+        );
+        assert_eq!(parse_history_spec_list(" []"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn explicit_export_emits_urls_with_md5_fragments() {
+        let rendered = render_environment_export(
+            "demo",
+            Path::new("/envs/demo"),
+            &[prefix_record("seqtk", "1.4", None)],
+            &[],
+            ExportFormat::Explicit,
+        )
+        .unwrap();
+
+        assert!(rendered.contains("@EXPLICIT\n"));
+        assert!(rendered.contains(
+            "https://conda.anaconda.org/bioconda/linux-64/seqtk-1.4-h123_0.conda#0123456789abcdef0123456789abcdef"
+        ));
+    }
+}
@@ -0,0 +1,74 @@
+use crate::backend::ChannelPriorityMode;
+use crate::error::{EnvError, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// User-wide defaults that apply when neither the command line, the environment YAML
+/// nor the environment itself sets a value.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct GlobalConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel_priority: Option<ChannelPriorityMode>,
+}
+
+/// `ENVA_CONFIG` when set, otherwise `<config dir>/enva/config.yaml`.
+pub fn default_global_config_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("ENVA_CONFIG").filter(|path| !path.is_empty()) {
+        return Some(PathBuf::from(path));
+    }
+    dirs::config_dir().map(|config| config.join("enva").join("config.yaml"))
+}
+
+pub fn read_global_config(path: &Path) -> Result<GlobalConfig> {
+    if !path.is_file() {
+        return Ok(GlobalConfig::default());
+    }
+
+    let content = fs::read_to_string(path).map_err(|error| {
+        EnvError::FileOperation(format!(
+            "Failed to read enva config {}: {}",
+            path.display(),
+            error
+        ))
+    })?;
+    if content.trim().is_empty() {
+        return Ok(GlobalConfig::default());
+    }
+    serde_yaml::from_str(&content).map_err(|error| {
+        EnvError::Config(format!(
+            "Failed to parse enva config {}: {}",
+            path.display(),
+            error
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_global_config, GlobalConfig};
+    use crate::backend::ChannelPriorityMode;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn missing_or_empty_config_uses_defaults_and_invalid_values_fail() {
+        let tempdir = tempdir().unwrap();
+        let path = tempdir.path().join("config.yaml");
+        assert_eq!(read_global_config(&path).unwrap(), GlobalConfig::default());
+        fs::write(&path, "\n").unwrap();
+        assert_eq!(read_global_config(&path).unwrap(), GlobalConfig::default());
+
+        fs::write(&path, "channel_priority: strict\n").unwrap();
+        assert_eq!(
+            read_global_config(&path).unwrap().channel_priority,
+            Some(ChannelPriorityMode::Strict)
+        );
+
+        fs::write(&path, "channel_priority: sometimes\n").unwrap();
+        assert!(read_global_config(&path)
+            .unwrap_err()
+            .to_string()
+            .contains("Failed to parse enva config"));
+    }
+}
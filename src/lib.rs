@@ -2,14 +2,30 @@
 
 pub mod backend;
 pub mod env;
+pub mod env_diff;
 pub mod env_run;
+mod environment_metadata;
+mod environment_registry;
+mod environment_variables;
 pub mod error;
+mod export;
+mod global_config;
+mod local_channel;
+mod lockfile;
 pub mod micromamba;
 mod operation_lock;
 mod ownership;
+mod pack;
+mod package_changes;
 pub mod package_manager;
 mod prefix_registry;
+mod prefix_relocation;
+mod revisions;
+pub mod solve_conflict;
 mod staged_prefix;
+#[cfg(test)]
+mod test_fixtures;
+pub mod verify;
 
 // Re-export commonly used types
 pub use backend::{BackendKind, BackendSelector, OutputMode};
@@ -0,0 +1,608 @@
+use crate::error::{EnvError, Result};
+use crate::ownership::write_file_atomically;
+use rattler_conda_types::package::IndexJson;
+use rattler_conda_types::{PackageRecord, Platform, RepoDataRecord};
+use rattler_digest::{compute_file_digest, Md5, Sha256};
+use rattler_networking::{AuthenticationMiddleware, LazyClient};
+use reqwest_middleware::ClientWithMiddleware;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use tracing::warn;
+
+const REPODATA_FILE_NAME: &str = "repodata.json";
+const ZSTD_REPODATA_FILE_NAME: &str = "repodata.json.zst";
+
+type RepodataEntries<'a> = BTreeMap<&'a str, &'a PackageRecord>;
+
+/// A package archive stored in a local channel under `<subdir>/<file_name>`.
+#[derive(Debug, Clone)]
+pub struct ChannelPackage {
+    pub file_name: String,
+    pub record: PackageRecord,
+}
+
+fn io_error(action: &str, path: &Path, error: std::io::Error) -> EnvError {
+    EnvError::FileOperation(format!("{} {}: {}", action, path.display(), error))
+}
+
+/// Checks `archive` against the sha256 (or, failing that, md5) that `record` declares.
+/// Records without either checksum are accepted as they are.
+pub fn archive_matches_record(archive: &Path, record: &PackageRecord) -> Result<bool> {
+    if let Some(expected) = record.sha256 {
+        let actual = compute_file_digest::<Sha256>(archive)
+            .map_err(|error| io_error("Failed to hash", archive, error))?;
+        return Ok(actual == expected);
+    }
+    if let Some(expected) = record.md5 {
+        let actual = compute_file_digest::<Md5>(archive)
+            .map_err(|error| io_error("Failed to hash", archive, error))?;
+        return Ok(actual == expected);
+    }
+    Ok(true)
+}
+
+/// The HTTP client for repodata and package downloads, shared by the whole process. It
+/// attaches the credentials rattler knows about (`RATTLER_AUTH_FILE` and the rattler
+/// credentials store) to every request, so private channels that solve also download.
+pub fn download_client() -> LazyClient {
+    static CLIENT: OnceLock<LazyClient> = OnceLock::new();
+    CLIENT
+        .get_or_init(|| {
+            LazyClient::new(|| {
+                let authentication = AuthenticationMiddleware::from_env_and_defaults()
+                    .map_err(|error| warn!("Channel credentials are unavailable: {}", error))
+                    .ok();
+                authenticated_client(authentication)
+            })
+        })
+        .clone()
+}
+
+fn authenticated_client(authentication: Option<AuthenticationMiddleware>) -> ClientWithMiddleware {
+    let client = reqwest::Client::builder()
+        .user_agent(concat!("enva/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .unwrap_or_default();
+    let builder = reqwest_middleware::ClientBuilder::new(client);
+    match authentication {
+        Some(authentication) => builder.with_arc(Arc::new(authentication)).build(),
+        None => builder.build(),
+    }
+}
+
+/// Downloads (or, for `file://` URLs, copies) the archive of `record` to `destination`
+/// unless a copy with the expected checksum is already there.
+pub async fn fetch_package_archive(
+    client: &ClientWithMiddleware,
+    record: &RepoDataRecord,
+    destination: &Path,
+) -> Result<()> {
+    if destination.is_file() && archive_matches_record(destination, &record.package_record)? {
+        return Ok(());
+    }
+
+    let file_name = destination
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let partial = destination.with_file_name(format!(".{}.part", file_name));
+    let result = async {
+        if record.url.scheme() == "file" {
+            let source = record
+                .url
+                .to_file_path()
+                .map_err(|_| EnvError::Validation(format!("Invalid file URL: {}", record.url)))?;
+            fs::copy(&source, &partial)
+                .map_err(|error| io_error("Failed to copy package archive", &source, error))?;
+        } else {
+            let mut response = client
+                .get(record.url.clone())
+                .send()
+                .await
+                .and_then(|response| response.error_for_status().map_err(Into::into))
+                .map_err(|error| {
+                    EnvError::Execution(format!("Failed to download {}: {}", record.url, error))
+                })?;
+            let mut file = fs::File::create(&partial)
+                .map_err(|error| io_error("Failed to create", &partial, error))?;
+            while let Some(chunk) = response.chunk().await.map_err(|error| {
+                EnvError::Execution(format!("Failed to download {}: {}", record.url, error))
+            })? {
+                file.write_all(&chunk)
+                    .map_err(|error| io_error("Failed to write", &partial, error))?;
+            }
+            file.sync_all()
+                .map_err(|error| io_error("Failed to write", &partial, error))?;
+        }
+
+        if !archive_matches_record(&partial, &record.package_record)? {
+            return Err(EnvError::Validation(format!(
+                "Checksum mismatch for {} downloaded from {}",
+                file_name, record.url
+            )));
+        }
+        fs::rename(&partial, destination)
+            .map_err(|error| io_error("Failed to store package archive", destination, error))
+    }
+    .await;
+
+    if result.is_err() {
+        let _ = fs::remove_file(&partial);
+    }
+    result
+}
+
+/// Writes a `repodata.json` for every subdir of `packages`, plus empty ones for `noarch`,
+/// the current platform and every subdir that already has one, so the directory is a
+/// complete and current channel for this machine.
+/// Returns the subdirs written.
+pub fn write_channel_repodata(channel: &Path, packages: &[ChannelPackage]) -> Result<Vec<String>> {
+    // `.tar.bz2` and `.conda` entries of each subdir, keyed by file name.
+    let mut subdirs: BTreeMap<String, (RepodataEntries, RepodataEntries)> = BTreeMap::new();
+    for subdir in [Platform::NoArch.as_str(), Platform::current().as_str()] {
+        subdirs.entry(subdir.to_string()).or_default();
+    }
+    // Subdirs indexed before are rewritten too, empty once their last package is gone, so
+    // their old repodata cannot point solves at archives that no longer exist.
+    if let Ok(entries) = fs::read_dir(channel) {
+        for entry in entries.flatten() {
+            if entry.path().join(REPODATA_FILE_NAME).is_file() {
+                if let Some(subdir) = entry.file_name().to_str() {
+                    subdirs.entry(subdir.to_string()).or_default();
+                }
+            }
+        }
+    }
+    for package in packages {
+        let (tar_bz2, conda) = subdirs.entry(package.record.subdir.clone()).or_default();
+        if package.file_name.ends_with(".conda") {
+            conda.insert(&package.file_name, &package.record);
+        } else if package.file_name.ends_with(".tar.bz2") {
+            tar_bz2.insert(&package.file_name, &package.record);
+        } else {
+            return Err(EnvError::Validation(format!(
+                "Not a conda package archive: {}",
+                package.file_name
+            )));
+        }
+    }
+
+    for (subdir, (tar_bz2, conda)) in &subdirs {
+        let directory: PathBuf = channel.join(subdir);
+        fs::create_dir_all(&directory)
+            .map_err(|error| io_error("Failed to create channel subdir", &directory, error))?;
+        let repodata = serde_json::json!({
+            "info": { "subdir": subdir },
+            "packages": tar_bz2,
+            "packages.conda": conda,
+            "repodata_version": 1,
+        });
+        write_file_atomically(
+            &directory.join(REPODATA_FILE_NAME),
+            serde_json::to_string_pretty(&repodata)?.as_bytes(),
+            "channel repodata",
+        )?;
+    }
+    Ok(subdirs.into_keys().collect())
+}
+
+/// What `index_local_channel` found and wrote.
+#[derive(Debug, Clone, Serialize)]
+pub struct ChannelIndex {
+    pub channel: PathBuf,
+    pub packages: usize,
+    pub subdirs: Vec<String>,
+    /// Archives that were moved into the subdir their `info/index.json` names (only with
+    /// `organize`).
+    pub moved: Vec<PathBuf>,
+}
+
+fn is_package_archive(path: &Path) -> bool {
+    path.is_file()
+        && path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.ends_with(".conda") || name.ends_with(".tar.bz2"))
+}
+
+fn package_archives_in(directory: &Path) -> Result<Vec<PathBuf>> {
+    let mut archives = fs::read_dir(directory)
+        .map_err(|error| io_error("Failed to read", directory, error))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<Vec<PathBuf>>>()
+        .map_err(|error| io_error("Failed to read", directory, error))?;
+    archives.retain(|path| is_package_archive(path));
+    archives.sort();
+    Ok(archives)
+}
+
+fn package_record_for_archive(archive: &Path) -> Result<PackageRecord> {
+    let index = rattler_package_streaming::seek::read_package_file::<IndexJson>(archive).map_err(
+        |error| {
+            EnvError::Validation(format!(
+                "Failed to read info/index.json from {}: {}",
+                archive.display(),
+                error
+            ))
+        },
+    )?;
+    let size = fs::metadata(archive)
+        .map_err(|error| io_error("Failed to inspect", archive, error))?
+        .len();
+    let sha256 = compute_file_digest::<Sha256>(archive)
+        .map_err(|error| io_error("Failed to hash", archive, error))?;
+    let md5 = compute_file_digest::<Md5>(archive)
+        .map_err(|error| io_error("Failed to hash", archive, error))?;
+    PackageRecord::from_index_json(index, Some(size), Some(sha256), Some(md5)).map_err(|error| {
+        EnvError::Validation(format!(
+            "Cannot determine the subdir of {}: {}",
+            archive.display(),
+            error
+        ))
+    })
+}
+
+/// Turns `channel` into a conda channel: reads `info/index.json` from every package archive
+/// directly in it or in one of its subdirectories and writes each subdir's `repodata.json` (and
+/// `repodata.json.zst` with `write_zstd`). Archives have to sit in the subdir they were built
+/// for; with `organize` misplaced ones are moved there, otherwise they are reported and the
+/// directory is left untouched.
+pub fn index_local_channel(
+    channel: &Path,
+    write_zstd: bool,
+    organize: bool,
+) -> Result<ChannelIndex> {
+    if !channel.is_dir() {
+        return Err(EnvError::FileNotFound(channel.to_path_buf()));
+    }
+
+    let mut archives = package_archives_in(channel)?;
+    for entry in
+        fs::read_dir(channel).map_err(|error| io_error("Failed to read", channel, error))?
+    {
+        let path = entry
+            .map_err(|error| io_error("Failed to read", channel, error))?
+            .path();
+        if path.is_dir() {
+            archives.extend(package_archives_in(&path)?);
+        }
+    }
+
+    let mut packages = Vec::with_capacity(archives.len());
+    let mut misplaced = Vec::new();
+    for archive in archives {
+        let record = package_record_for_archive(&archive)?;
+        let file_name = archive
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let expected = channel.join(&record.subdir).join(&file_name);
+        if archive != expected {
+            misplaced.push((archive, expected));
+        }
+        packages.push(ChannelPackage { file_name, record });
+    }
+
+    // Check everything before touching the directory so a refused index changes nothing.
+    if !misplaced.is_empty() && !organize {
+        let listing = misplaced
+            .iter()
+            .map(|(archive, expected)| {
+                format!(
+                    "  {} (belongs in {})",
+                    archive.display(),
+                    expected.display()
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        return Err(EnvError::Validation(format!(
+            "Package archives are not in the subdir they were built for:\n{}\n\
+             Move them there or pass --organize to let enva move them",
+            listing
+        )));
+    }
+    if let Some((archive, expected)) = misplaced.iter().find(|(_, expected)| expected.exists()) {
+        return Err(EnvError::Validation(format!(
+            "Cannot move {} into place: {} already exists",
+            archive.display(),
+            expected.display()
+        )));
+    }
+    let mut moved = Vec::with_capacity(misplaced.len());
+    for (archive, expected) in misplaced {
+        let subdir = expected.parent().unwrap_or(channel);
+        fs::create_dir_all(subdir)
+            .map_err(|error| io_error("Failed to create channel subdir", subdir, error))?;
+        fs::rename(&archive, &expected)
+            .map_err(|error| io_error("Failed to move package archive", &archive, error))?;
+        moved.push(expected);
+    }
+
+    let subdirs = write_channel_repodata(channel, &packages)?;
+    for subdir in &subdirs {
+        let repodata = channel.join(subdir).join(REPODATA_FILE_NAME);
+        let compressed = channel.join(subdir).join(ZSTD_REPODATA_FILE_NAME);
+        if write_zstd {
+            let content = fs::read(&repodata)
+                .map_err(|error| io_error("Failed to read", &repodata, error))?;
+            let content = zstd::encode_all(content.as_slice(), 0)
+                .map_err(|error| io_error("Failed to compress", &repodata, error))?;
+            write_file_atomically(&compressed, &content, "compressed channel repodata")?;
+        } else if compressed.exists() {
+            // A stale compressed copy would shadow the repodata just written.
+            fs::remove_file(&compressed)
+                .map_err(|error| io_error("Failed to remove stale", &compressed, error))?;
+        }
+    }
+
+    Ok(ChannelIndex {
+        channel: channel.to_path_buf(),
+        packages: packages.len(),
+        subdirs,
+        moved,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        authenticated_client, fetch_package_archive, index_local_channel,
+        package_record_for_archive, write_channel_repodata, ChannelPackage,
+    };
+    use rattler_conda_types::compression_level::CompressionLevel;
+    use rattler_conda_types::{
+        PackageName, PackageRecord, Platform, RepoData, RepoDataRecord, Version,
+    };
+    use rattler_networking::authentication_storage::backends::memory::MemoryStorage;
+    use rattler_networking::{Authentication, AuthenticationMiddleware, AuthenticationStorage};
+    use std::fs;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::path::Path;
+    use std::str::FromStr;
+    use std::sync::Arc;
+    use tempfile::tempdir;
+
+    fn write_package(archive: &Path, name: &str, subdir: &str) {
+        let build_dir = tempdir().unwrap();
+        fs::create_dir_all(build_dir.path().join("info")).unwrap();
+        let index_json = build_dir.path().join("info/index.json");
+        fs::write(
+            &index_json,
+            serde_json::json!({
+                "name": name,
+                "version": "0.3.1",
+                "build": "0",
+                "build_number": 0,
+                "depends": [],
+                "subdir": subdir,
+            })
+            .to_string(),
+        )
+        .unwrap();
+        rattler_package_streaming::write::write_tar_bz2_package(
+            fs::File::create(archive).unwrap(),
+            build_dir.path(),
+            &[index_json],
+            CompressionLevel::Default,
+            None,
+            None,
+        )
+        .unwrap();
+    }
+
+    fn package(name: &str, subdir: &str, file_name: &str) -> ChannelPackage {
+        let mut record = PackageRecord::new(
+            PackageName::new_unchecked(name),
+            Version::from_str("1.0").unwrap(),
+            "h0_0".to_string(),
+        );
+        record.subdir = subdir.to_string();
+        ChannelPackage {
+            file_name: file_name.to_string(),
+            record,
+        }
+    }
+
+    #[test]
+    fn repodata_is_split_by_subdir_and_archive_format() {
+        let tempdir = tempdir().unwrap();
+        let channel = tempdir.path().join("bundle");
+        let subdirs = write_channel_repodata(
+            &channel,
+            &[
+                package("seqtk", "linux-64", "seqtk-1.0-h0_0.conda"),
+                package("zlib", "linux-64", "zlib-1.0-h0_0.tar.bz2"),
+                package("tzdata", "noarch", "tzdata-1.0-h0_0.conda"),
+            ],
+        )
+        .unwrap();
+
+        assert!(subdirs.contains(&"linux-64".to_string()));
+        assert!(subdirs.contains(&Platform::current().to_string()));
+        let linux = RepoData::from_path(channel.join("linux-64/repodata.json")).unwrap();
+        assert_eq!(linux.conda_packages.len(), 1);
+        assert_eq!(linux.packages.len(), 1);
+        let noarch = RepoData::from_path(channel.join("noarch/repodata.json")).unwrap();
+        assert_eq!(noarch.conda_packages.len(), 1);
+        assert!(noarch.packages.is_empty());
+
+        assert!(write_channel_repodata(
+            &channel,
+            &[package("seqtk", "linux-64", "seqtk-1.0-h0_0.zip")]
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn indexing_hashes_archives_in_place_and_leaves_misplaced_ones_alone() {
+        let tempdir = tempdir().unwrap();
+        let channel = tempdir.path().join("lab-channel");
+        fs::create_dir_all(channel.join("noarch")).unwrap();
+        write_package(
+            &channel.join("noarch/qc-report-0.3.1-0.tar.bz2"),
+            "qc-report",
+            "noarch",
+        );
+        fs::write(channel.join("README.txt"), "not a package").unwrap();
+
+        let index = index_local_channel(&channel, true, false).unwrap();
+
+        assert_eq!(index.packages, 1);
+        assert!(index.moved.is_empty());
+        let repodata = RepoData::from_path(channel.join("noarch/repodata.json")).unwrap();
+        assert_eq!(repodata.packages.len(), 1);
+        assert!(repodata
+            .packages
+            .values()
+            .all(|record| record.sha256.is_some()));
+        let compressed = fs::read(channel.join("noarch/repodata.json.zst")).unwrap();
+        assert_eq!(
+            zstd::decode_all(compressed.as_slice()).unwrap(),
+            fs::read(channel.join("noarch/repodata.json")).unwrap()
+        );
+
+        write_package(
+            &channel.join("glue-scripts-0.3.1-0.tar.bz2"),
+            "glue-scripts",
+            "noarch",
+        );
+        let before = fs::read(channel.join("noarch/repodata.json")).unwrap();
+        let error = index_local_channel(&channel, false, false)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("glue-scripts-0.3.1-0.tar.bz2"), "{}", error);
+        assert!(error.contains("--organize"), "{}", error);
+        assert!(channel.join("glue-scripts-0.3.1-0.tar.bz2").is_file());
+        assert!(!channel.join("noarch/glue-scripts-0.3.1-0.tar.bz2").exists());
+        assert_eq!(
+            fs::read(channel.join("noarch/repodata.json")).unwrap(),
+            before
+        );
+        assert!(channel.join("noarch/repodata.json.zst").exists());
+    }
+
+    #[test]
+    fn organizing_moves_loose_archives_into_their_subdir() {
+        let tempdir = tempdir().unwrap();
+        let channel = tempdir.path().join("lab-channel");
+        fs::create_dir_all(&channel).unwrap();
+        write_package(
+            &channel.join("glue-scripts-0.3.1-0.tar.bz2"),
+            "glue-scripts",
+            "noarch",
+        );
+        fs::create_dir_all(channel.join("noarch")).unwrap();
+        fs::write(channel.join("noarch/repodata.json.zst"), b"stale").unwrap();
+
+        let index = index_local_channel(&channel, false, true).unwrap();
+
+        assert_eq!(
+            index.moved,
+            vec![channel.join("noarch/glue-scripts-0.3.1-0.tar.bz2")]
+        );
+        assert!(!channel.join("glue-scripts-0.3.1-0.tar.bz2").exists());
+        let repodata = RepoData::from_path(channel.join("noarch/repodata.json")).unwrap();
+        assert_eq!(repodata.packages.len(), 1);
+        assert!(!channel.join("noarch/repodata.json.zst").exists());
+    }
+
+    #[tokio::test]
+    async fn package_downloads_send_the_stored_channel_credentials() {
+        let tempdir = tempdir().unwrap();
+        let archive = tempdir.path().join("private-tool-0.3.1-0.tar.bz2");
+        write_package(&archive, "private-tool", "noarch");
+        let body = fs::read(&archive).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut authorization = None;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("authorization") {
+                        authorization = Some(value.trim().to_string());
+                    }
+                }
+            }
+            let mut stream = reader.into_inner();
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            )
+            .unwrap();
+            stream.write_all(&body).unwrap();
+            authorization
+        });
+
+        let mut storage = AuthenticationStorage::empty();
+        storage.add_backend(Arc::new(MemoryStorage::new()));
+        storage
+            .store(
+                "127.0.0.1",
+                &Authentication::BasicHTTP {
+                    username: "lab".to_string(),
+                    password: "secret".to_string(),
+                },
+            )
+            .unwrap();
+        let client =
+            authenticated_client(Some(AuthenticationMiddleware::from_auth_storage(storage)));
+        let file_name = "private-tool-0.3.1-0.tar.bz2";
+        let record = RepoDataRecord {
+            package_record: package_record_for_archive(&archive).unwrap(),
+            url: format!("http://127.0.0.1:{port}/noarch/{file_name}")
+                .parse()
+                .unwrap(),
+            identifier: file_name.parse().unwrap(),
+            channel: Some(format!("http://127.0.0.1:{port}/")),
+        };
+        let destination = tempdir.path().join("cache").join(file_name);
+        fs::create_dir_all(destination.parent().unwrap()).unwrap();
+
+        fetch_package_archive(&client, &record, &destination)
+            .await
+            .unwrap();
+
+        // "lab:secret" in base64.
+        assert_eq!(
+            server.join().unwrap().as_deref(),
+            Some("Basic bGFiOnNlY3JldA==")
+        );
+        assert_eq!(fs::read(&destination).unwrap(), fs::read(&archive).unwrap());
+    }
+
+    #[test]
+    fn reindexing_empties_the_repodata_of_subdirs_that_lost_their_packages() {
+        let tempdir = tempdir().unwrap();
+        let channel = tempdir.path().join("lab-channel");
+        fs::create_dir_all(channel.join("linux-aarch64")).unwrap();
+        let archive = channel.join("linux-aarch64/glue-scripts-0.3.1-0.tar.bz2");
+        write_package(&archive, "glue-scripts", "linux-aarch64");
+        let index = index_local_channel(&channel, true, false).unwrap();
+        assert!(index.subdirs.contains(&"linux-aarch64".to_string()));
+
+        fs::remove_file(&archive).unwrap();
+        let index = index_local_channel(&channel, false, false).unwrap();
+
+        assert_eq!(index.packages, 0);
+        assert!(index.subdirs.contains(&"linux-aarch64".to_string()));
+        let repodata = RepoData::from_path(channel.join("linux-aarch64/repodata.json")).unwrap();
+        assert!(repodata.packages.is_empty());
+        assert!(repodata.conda_packages.is_empty());
+        assert!(!channel.join("linux-aarch64/repodata.json.zst").exists());
+    }
+}
@@ -0,0 +1,363 @@
+use crate::error::{EnvError, Result};
+use rattler_conda_types::{Channel, MatchSpec, ParseStrictness, Platform, RepoDataRecord};
+use rattler_digest::{compute_file_digest, Sha256};
+use rattler_lock::{
+    CondaPackageData, LockFile, PlatformData, PlatformName, DEFAULT_ENVIRONMENT_NAME,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Suffix of the file next to a lockfile that keeps the specs its YAML requested; the
+/// rattler lockfile format has no field for them, so the lockfile itself stays as rattler
+/// wrote it.
+const REQUESTED_SPECS_SUFFIX: &str = ".enva-specs.json";
+
+/// Requested specs of the lockfile whose sha256 is `lockfile_sha256`.
+#[derive(Debug, Serialize, Deserialize)]
+struct LockedSpecs {
+    lockfile_sha256: String,
+    requested_specs: Vec<String>,
+}
+
+/// Pinned package records for one platform of a lockfile environment.
+#[derive(Debug, Clone)]
+pub struct LockedEnvironment {
+    pub channels: Vec<String>,
+    /// The specs the environment was solved for, which environments created from the
+    /// lockfile record as requested.
+    pub requested_specs: Vec<String>,
+    pub records: Vec<RepoDataRecord>,
+}
+
+pub fn write_lock_file(
+    path: &Path,
+    channels: &[Channel],
+    platform: Platform,
+    virtual_packages: Vec<String>,
+    requested_specs: &[String],
+    records: Vec<RepoDataRecord>,
+) -> Result<()> {
+    let platform_name = PlatformName::from(&platform);
+    let mut builder = LockFile::builder()
+        .with_platforms(vec![PlatformData {
+            name: platform_name.clone(),
+            subdir: platform,
+            virtual_packages,
+        }])
+        .map_err(|error| {
+            EnvError::Internal(format!(
+                "Failed to initialize lockfile platforms: {}",
+                error
+            ))
+        })?
+        .with_channels(
+            DEFAULT_ENVIRONMENT_NAME,
+            channels
+                .iter()
+                .map(|channel| channel.base_url.as_str().to_string()),
+        );
+
+    let mut records = records;
+    records.sort_by(|left, right| {
+        left.package_record
+            .name
+            .as_normalized()
+            .cmp(right.package_record.name.as_normalized())
+    });
+    for record in records {
+        builder
+            .add_conda_package(
+                DEFAULT_ENVIRONMENT_NAME,
+                platform_name.as_str(),
+                CondaPackageData::from(record),
+            )
+            .map_err(|error| {
+                EnvError::Internal(format!("Failed to add package to lockfile: {}", error))
+            })?;
+    }
+
+    builder.finish().to_path(path).map_err(|error| {
+        EnvError::FileOperation(format!(
+            "Failed to write lockfile {}: {}",
+            path.display(),
+            error
+        ))
+    })?;
+
+    let specs = LockedSpecs {
+        lockfile_sha256: lock_file_sha256(path)?,
+        requested_specs: requested_specs.to_vec(),
+    };
+    let specs_path = requested_specs_path(path);
+    let content = serde_json::to_string_pretty(&specs)?;
+    fs::write(&specs_path, content).map_err(|error| {
+        EnvError::FileOperation(format!(
+            "Failed to write requested specs {}: {}",
+            specs_path.display(),
+            error
+        ))
+    })
+}
+
+/// File next to a lockfile that keeps the specs `enva lock` solved for.
+fn requested_specs_path(lock_file: &Path) -> PathBuf {
+    let mut file_name = lock_file.file_name().unwrap_or_default().to_os_string();
+    file_name.push(REQUESTED_SPECS_SUFFIX);
+    lock_file.with_file_name(file_name)
+}
+
+fn lock_file_sha256(path: &Path) -> Result<String> {
+    let sha256 = compute_file_digest::<Sha256>(path).map_err(|error| {
+        EnvError::FileOperation(format!(
+            "Failed to hash lockfile {}: {}",
+            path.display(),
+            error
+        ))
+    })?;
+    Ok(hex::encode(sha256))
+}
+
+/// Reads the specs stored next to the lockfile, unless the lockfile has changed since
+/// they were written.
+fn stored_requested_specs(path: &Path) -> Result<Option<Vec<String>>> {
+    let specs_path = requested_specs_path(path);
+    if !specs_path.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(&specs_path).map_err(|error| {
+        EnvError::FileOperation(format!(
+            "Failed to read requested specs {}: {}",
+            specs_path.display(),
+            error
+        ))
+    })?;
+    let specs = serde_json::from_str::<LockedSpecs>(&content).map_err(|error| {
+        EnvError::Validation(format!(
+            "Failed to parse requested specs {}: {}",
+            specs_path.display(),
+            error
+        ))
+    })?;
+    if specs.lockfile_sha256 != lock_file_sha256(path)? {
+        return Ok(None);
+    }
+    Ok(Some(specs.requested_specs))
+}
+
+/// Names of the locked packages no other locked package depends on, standing in for the
+/// requested specs of lockfiles written by other tools.
+fn top_level_package_names(records: &[RepoDataRecord]) -> Vec<String> {
+    let dependencies = records
+        .iter()
+        .flat_map(|record| &record.package_record.depends)
+        .filter_map(|dependency| MatchSpec::from_str(dependency, ParseStrictness::Lenient).ok())
+        .filter_map(|spec| spec.name.as_exact().cloned())
+        .collect::<HashSet<_>>();
+    records
+        .iter()
+        .map(|record| &record.package_record.name)
+        .filter(|name| !dependencies.contains(*name))
+        .map(|name| name.as_normalized().to_string())
+        .collect()
+}
+
+pub fn read_locked_environment(path: &Path, platform: Platform) -> Result<LockedEnvironment> {
+    if !path.exists() {
+        return Err(EnvError::FileNotFound(path.to_path_buf()));
+    }
+
+    let lock_file = LockFile::from_path(path).map_err(|error| {
+        EnvError::Validation(format!(
+            "Failed to parse lockfile {}: {}",
+            path.display(),
+            error
+        ))
+    })?;
+    let environment = lock_file
+        .default_environment()
+        .or_else(|| {
+            let mut environments = lock_file.environments();
+            match environments.len() {
+                1 => environments.next().map(|(_, environment)| environment),
+                _ => None,
+            }
+        })
+        .ok_or_else(|| {
+            EnvError::Validation(format!(
+                "Lockfile {} does not contain a '{}' environment",
+                path.display(),
+                DEFAULT_ENVIRONMENT_NAME
+            ))
+        })?;
+
+    let locked_platform = environment
+        .platforms()
+        .find(|candidate| candidate.subdir() == platform)
+        .ok_or_else(|| {
+            EnvError::Validation(format!(
+                "Lockfile {} has no packages for platform {}",
+                path.display(),
+                platform
+            ))
+        })?;
+
+    if environment.has_pypi_packages(locked_platform) {
+        return Err(EnvError::Validation(format!(
+            "Lockfile {} contains pypi packages, which are not supported yet by rattler backend",
+            path.display()
+        )));
+    }
+
+    let records = environment
+        .conda_repodata_records(locked_platform)
+        .map_err(|error| {
+            EnvError::Validation(format!(
+                "Failed to read locked packages from {}: {}",
+                path.display(),
+                error
+            ))
+        })?
+        .unwrap_or_default();
+    let requested_specs = match stored_requested_specs(path)? {
+        Some(specs) => specs,
+        None => top_level_package_names(&records),
+    };
+
+    Ok(LockedEnvironment {
+        channels: environment
+            .channels()
+            .iter()
+            .map(|channel| channel.url.clone())
+            .collect(),
+        requested_specs,
+        records,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_locked_environment, requested_specs_path, write_lock_file};
+    use crate::test_fixtures::repodata_record;
+    use rattler_conda_types::{Channel, ChannelConfig, Platform, RepoDataRecord};
+    use rattler_lock::LockFile;
+    use std::fs;
+    use std::path::PathBuf;
+    use tempfile::tempdir;
+
+    fn record(name: &str, version: &str, subdir: &str) -> RepoDataRecord {
+        let mut record = repodata_record(name, version, "h0_0", "conda-forge", subdir);
+        record.package_record.sha256 = Some(
+            rattler_digest::parse_digest_from_hex::<rattler_digest::Sha256>(
+                "5b04be5e2a1ad6e4ad8fd2c7a0d0d3bbff7c1b2c6a7b6e0dfb9a0d3ac1a9c3e1",
+            )
+            .unwrap(),
+        );
+        record
+    }
+
+    #[test]
+    fn lockfile_round_trips_records_with_urls_and_hashes() {
+        let tempdir = tempdir().unwrap();
+        let path = tempdir.path().join("otter-core.lock");
+        let channel_config = ChannelConfig::default_with_root_dir(PathBuf::from("/"));
+        let channels = vec![Channel::from_str("conda-forge", &channel_config).unwrap()];
+        let platform = Platform::Linux64;
+
+        write_lock_file(
+            &path,
+            &channels,
+            platform,
+            vec!["__glibc=2.31=0".to_string()],
+            &["zlib >=1.3".to_string()],
+            vec![
+                record("zlib", "1.3.1", "linux-64"),
+                record("tzdata", "2024a", "noarch"),
+            ],
+        )
+        .unwrap();
+
+        let locked = read_locked_environment(&path, platform).unwrap();
+        let mut names = locked
+            .records
+            .iter()
+            .map(|record| record.package_record.name.as_normalized().to_string())
+            .collect::<Vec<String>>();
+        names.sort();
+
+        assert_eq!(names, vec!["tzdata", "zlib"]);
+        assert_eq!(locked.requested_specs, vec!["zlib >=1.3"]);
+        assert_eq!(
+            locked.channels,
+            vec!["https://conda.anaconda.org/conda-forge/"]
+        );
+        assert!(locked
+            .records
+            .iter()
+            .all(|record| record.package_record.sha256.is_some()));
+        assert!(locked.records.iter().any(|record| record
+            .url
+            .as_str()
+            .ends_with("linux-64/zlib-1.3.1-h0_0.conda")));
+        // The lockfile is exactly what rattler renders; the specs live next to it.
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            LockFile::from_path(&path)
+                .unwrap()
+                .render_to_string()
+                .unwrap()
+        );
+        assert!(requested_specs_path(&path).exists());
+    }
+
+    #[test]
+    fn lockfile_without_current_platform_is_rejected() {
+        let tempdir = tempdir().unwrap();
+        let path = tempdir.path().join("otter-core.lock");
+
+        write_lock_file(
+            &path,
+            &[],
+            Platform::Linux64,
+            Vec::new(),
+            &[],
+            vec![record("zlib", "1.3.1", "linux-64")],
+        )
+        .unwrap();
+
+        let error = read_locked_environment(&path, Platform::OsxArm64).unwrap_err();
+        assert!(error.to_string().contains("no packages for platform"));
+    }
+
+    #[test]
+    fn lockfiles_without_requested_specs_request_their_top_level_packages() {
+        let tempdir = tempdir().unwrap();
+        let path = tempdir.path().join("foreign.lock");
+        let mut samtools = record("samtools", "1.20", "linux-64");
+        samtools.package_record.depends = vec!["htslib >=1.20".to_string()];
+        write_lock_file(
+            &path,
+            &[],
+            Platform::Linux64,
+            Vec::new(),
+            &["samtools 1.20.*".to_string()],
+            vec![samtools, record("htslib", "1.20", "linux-64")],
+        )
+        .unwrap();
+        let locked = read_locked_environment(&path, Platform::Linux64).unwrap();
+        assert_eq!(locked.requested_specs, vec!["samtools 1.20.*"]);
+
+        // Specs stored for an earlier version of the lockfile are ignored.
+        let mut edited = fs::read_to_string(&path).unwrap();
+        edited.push_str("# edited by hand\n");
+        fs::write(&path, edited).unwrap();
+        let locked = read_locked_environment(&path, Platform::Linux64).unwrap();
+        assert_eq!(locked.records.len(), 2);
+        assert_eq!(locked.requested_specs, vec!["samtools"]);
+
+        fs::remove_file(requested_specs_path(&path)).unwrap();
+        let locked = read_locked_environment(&path, Platform::Linux64).unwrap();
+        assert_eq!(locked.requested_specs, vec!["samtools"]);
+    }
+}
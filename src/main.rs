@@ -2,6 +2,8 @@
 
 use clap::Parser;
 use enva::env::{execute_env_command, EnvCommand};
+use enva::env_diff::DIFF_EXIT_ERROR;
+use enva::error::EnvError;
 use std::io::{self, IsTerminal};
 use std::path::PathBuf;
 
@@ -31,6 +33,10 @@ struct Cli {
     #[arg(long)]
     json: bool,
 
+    /// Solve only from cached repodata and install only from the package cache
+    #[arg(long, global = true)]
+    offline: bool,
+
     /// Environment subcommands
     #[command(subcommand)]
     command: EnvCommand,
@@ -43,9 +49,27 @@ fn command_supports_startup_banner(command: &EnvCommand) -> bool {
             | EnvCommand::Activate(_)
             | EnvCommand::Deactivate(_)
             | EnvCommand::Shell(_)
+            | EnvCommand::Export(_)
+    )
+}
+
+/// Commands that print their outcome and then report it through the status carried by
+/// `EnvError::ProcessExit`.
+fn command_reports_exit_status(command: &EnvCommand) -> bool {
+    matches!(
+        command,
+        EnvCommand::Diff(_) | EnvCommand::Verify(_) | EnvCommand::Shell(_)
     )
 }
 
+/// Status of a failed command; `diff` keeps 1 for "the sides differ".
+fn command_error_exit_code(command: &EnvCommand) -> i32 {
+    match command {
+        EnvCommand::Diff(_) => DIFF_EXIT_ERROR,
+        _ => 1,
+    }
+}
+
 fn should_display_startup_banner(cli: &Cli, standard_error_is_terminal: bool) -> bool {
     !cli.quiet
         && !cli.json
@@ -65,14 +89,46 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         let _ = tracing_subscriber::fmt::try_init();
     }
 
-    execute_env_command(cli.command, cli.verbose, cli.log, cli.dry_run, cli.json).await?;
+    let json = cli.json;
+    let reports_exit_status = command_reports_exit_status(&cli.command);
+    let error_exit_code = command_error_exit_code(&cli.command);
+    let result = execute_env_command(
+        cli.command,
+        cli.verbose,
+        cli.log,
+        cli.dry_run,
+        json,
+        cli.offline,
+    )
+    .await;
+    match result {
+        Err(EnvError::ProcessExit { code }) if reports_exit_status => {
+            std::process::exit(code.unwrap_or(1))
+        }
+        // The conflict tree is unreadable through the `Debug` output of a returned error.
+        Err(EnvError::SolveConflict(conflict)) => {
+            if json {
+                println!("{}", serde_json::to_string_pretty(&conflict)?);
+            } else {
+                eprintln!("{}", conflict);
+            }
+            std::process::exit(error_exit_code);
+        }
+        Err(error) if error_exit_code != 1 => {
+            eprintln!("Error: {:?}", error);
+            std::process::exit(error_exit_code);
+        }
+        result => result?,
+    }
 
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{should_display_startup_banner, Cli};
+    use super::{
+        command_error_exit_code, command_reports_exit_status, should_display_startup_banner, Cli,
+    };
     use clap::Parser;
 
     fn parse_cli(arguments: &[&str]) -> Cli {
@@ -105,4 +161,22 @@ mod tests {
         let quiet_cli = parse_cli(&["enva", "--quiet", "list"]);
         assert!(!should_display_startup_banner(&quiet_cli, true));
     }
+
+    #[test]
+    fn only_status_reporting_commands_exit_with_their_process_status() {
+        let diff = parse_cli(&["enva", "diff", "otter-core", "otter-extra"]);
+        let run = parse_cli(&["enva", "run", "example", "--", "false"]);
+
+        assert!(command_reports_exit_status(&diff.command));
+        assert!(!command_reports_exit_status(&run.command));
+        assert_eq!(command_error_exit_code(&diff.command), 2);
+        assert_eq!(command_error_exit_code(&run.command), 1);
+    }
+
+    #[test]
+    fn offline_flag_is_accepted_before_and_after_the_subcommand() {
+        assert!(parse_cli(&["enva", "--offline", "list"]).offline);
+        assert!(parse_cli(&["enva", "create", "--name", "otter-core", "--offline"]).offline);
+        assert!(!parse_cli(&["enva", "list"]).offline);
+    }
 }
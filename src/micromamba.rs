@@ -5,17 +5,19 @@
 //! adoption, and explicit fallback flows.
 
 use crate::backend::{
-    append_environment_run_command, append_environment_shell_arguments, EnvironmentName,
-    OutputMode, RunCommand,
+    append_environment_run_command, append_environment_shell_arguments, ChannelPriorityMode,
+    EnvironmentName, OutputMode, RunCommand, TransactionApproval,
 };
 use crate::error::{EnvError, Result};
 use crate::ownership::ownership_record_path;
 use crate::package_manager::{PackageManager, PackageManagerDetector};
+use crate::solve_conflict::SolveConflict;
 use crate::{BUILT_IN_ENV_NAMES, CORE_ENV_NAME, EXTRA_ENV_NAME, SNAKEMAKE_ENV_NAME};
 use indicatif::{ProgressBar, ProgressStyle};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
+use std::io::IsTerminal;
 #[cfg(unix)]
 use std::os::unix::fs::PermissionsExt;
 use std::path::{Path, PathBuf};
@@ -72,6 +74,47 @@ fn captured_command_failure_detail(output: &Output) -> String {
     String::new()
 }
 
+#[derive(Debug, Clone, Copy)]
+enum PrefixPackageChange {
+    Install,
+    Uninstall,
+    Update,
+}
+
+impl PrefixPackageChange {
+    fn subcommand(self) -> &'static str {
+        match self {
+            Self::Install => "install",
+            Self::Uninstall => "remove",
+            Self::Update => "update",
+        }
+    }
+
+    fn progressive(self) -> &'static str {
+        match self {
+            Self::Install => "Installing",
+            Self::Uninstall => "Removing",
+            Self::Update => "Updating",
+        }
+    }
+
+    fn completed(self) -> &'static str {
+        match self {
+            Self::Install => "Installed",
+            Self::Uninstall => "Removed",
+            Self::Update => "Updated",
+        }
+    }
+
+    fn preposition(self) -> &'static str {
+        match self {
+            Self::Install => "into",
+            Self::Uninstall => "from",
+            Self::Update => "in",
+        }
+    }
+}
+
 fn validate_micromamba_executable(path: &Path) -> Result<PathBuf> {
     let canonical_path = normalize_and_validate_path(path)?;
     let output = Command::new(&canonical_path)
@@ -225,6 +268,16 @@ pub struct ValidationResult {
     pub estimated_packages: usize,
     pub estimated_size_mb: u64,
     pub channels_accessible: Vec<String>,
+    #[serde(default)]
+    pub solved_packages: Option<usize>,
+    #[serde(default)]
+    pub download_size_bytes: Option<u64>,
+    #[serde(default)]
+    pub install_size_bytes: Option<u64>,
+    #[serde(default)]
+    pub solver_message: Option<String>,
+    #[serde(default)]
+    pub solve_conflict: Option<SolveConflict>,
 }
 
 /// Version configuration for environments
@@ -261,6 +314,12 @@ pub struct MicromambaManager {
     creation_lock: Arc<Mutex<()>>,
     /// Cached environment prefixes for this package manager instance
     env_list_cache: Arc<StdMutex<Option<Vec<PathBuf>>>>,
+    /// How package installs and updates are confirmed
+    transaction_approval: TransactionApproval,
+    /// Channels installs and updates use instead of conda-forge and bioconda
+    channels: Vec<String>,
+    /// Channel priority passed to installs and updates, if one was asked for
+    channel_priority: Option<ChannelPriorityMode>,
 }
 
 impl Clone for MicromambaManager {
@@ -274,6 +333,9 @@ impl Clone for MicromambaManager {
             // Reuse the same lock across clones to maintain synchronization
             creation_lock: Arc::clone(&self.creation_lock),
             env_list_cache: Arc::clone(&self.env_list_cache),
+            transaction_approval: self.transaction_approval,
+            channels: self.channels.clone(),
+            channel_priority: self.channel_priority,
         }
     }
 }
@@ -355,6 +417,9 @@ impl MicromambaManager {
             version_config: VersionConfig::default(),
             creation_lock: Arc::new(Mutex::new(())),
             env_list_cache: Arc::new(StdMutex::new(None)),
+            transaction_approval: TransactionApproval::default(),
+            channels: Vec::new(),
+            channel_priority: None,
         };
 
         if initialize_envs {
@@ -395,6 +460,28 @@ impl MicromambaManager {
         }
     }
 
+    /// Sets how package installs and updates are confirmed: a dry run passes `--dry-run`,
+    /// an interactive prompt leaves the confirmation to the package manager, and otherwise
+    /// `-y` is passed.
+    pub fn with_transaction_approval(mut self, approval: TransactionApproval) -> Self {
+        self.transaction_approval = approval;
+        self
+    }
+
+    /// Sets the channels installs and updates solve against, in priority order; without
+    /// any they use conda-forge and bioconda.
+    pub fn with_channels(mut self, channels: Vec<String>) -> Self {
+        self.channels = channels;
+        self
+    }
+
+    /// Sets the channel priority of installs and updates; without one the package
+    /// manager's own configuration applies.
+    pub fn with_channel_priority(mut self, channel_priority: Option<ChannelPriorityMode>) -> Self {
+        self.channel_priority = channel_priority;
+        self
+    }
+
     /// Get detected package manager
     pub fn get_package_manager(&self) -> PackageManager {
         self.pm_type
@@ -450,6 +537,9 @@ impl MicromambaManager {
             version_config: VersionConfig::default(),
             creation_lock: Arc::new(Mutex::new(())),
             env_list_cache: Arc::new(StdMutex::new(None)),
+            transaction_approval: TransactionApproval::default(),
+            channels: Vec::new(),
+            channel_priority: None,
         };
 
         manager.initialize_environments(true).await?;
@@ -472,6 +562,9 @@ impl MicromambaManager {
             version_config,
             creation_lock: Arc::new(Mutex::new(())),
             env_list_cache: Arc::new(StdMutex::new(None)),
+            transaction_approval: TransactionApproval::default(),
+            channels: Vec::new(),
+            channel_priority: None,
         };
 
         manager.initialize_environments(true).await?;
@@ -1346,6 +1439,11 @@ impl MicromambaManager {
             estimated_packages,
             estimated_size_mb,
             channels_accessible: vec![],
+            solved_packages: None,
+            download_size_bytes: None,
+            install_size_bytes: None,
+            solver_message: None,
+            solve_conflict: None,
         })
     }
 
@@ -1402,6 +1500,36 @@ impl MicromambaManager {
         packages: &[String],
         output_mode: OutputMode,
     ) -> Result<()> {
+        let prefix = self.existing_environment_prefix(env_name).await?;
+        self.install_packages_by_prefix(&prefix, packages, output_mode)
+            .await
+    }
+
+    /// Remove packages from environment
+    pub async fn uninstall_packages(
+        &self,
+        env_name: &str,
+        packages: &[String],
+        output_mode: OutputMode,
+    ) -> Result<()> {
+        let prefix = self.existing_environment_prefix(env_name).await?;
+        self.uninstall_packages_by_prefix(&prefix, packages, output_mode)
+            .await
+    }
+
+    /// Update packages in environment; an empty package list updates everything
+    pub async fn update_packages(
+        &self,
+        env_name: &str,
+        packages: &[String],
+        output_mode: OutputMode,
+    ) -> Result<()> {
+        let prefix = self.existing_environment_prefix(env_name).await?;
+        self.update_packages_by_prefix(&prefix, packages, output_mode)
+            .await
+    }
+
+    async fn existing_environment_prefix(&self, env_name: &str) -> Result<PathBuf> {
         if !self.environment_exists(env_name).await? {
             return Err(EnvError::Execution(format!(
                 "Environment '{}' does not exist. Please create it first using 'xdxtools env create --name {}'",
@@ -1410,8 +1538,7 @@ impl MicromambaManager {
             )));
         }
 
-        let prefix = self
-            .find_environment_prefixes(env_name)
+        self.find_environment_prefixes(env_name)
             .await?
             .into_iter()
             .next()
@@ -1420,23 +1547,56 @@ impl MicromambaManager {
                     "Environment '{}' does not have a resolvable prefix",
                     env_name
                 ))
-            })?;
+            })
+    }
 
-        self.install_packages_by_prefix(&prefix, packages, output_mode)
+    pub async fn install_packages_by_prefix(
+        &self,
+        prefix: &Path,
+        packages: &[String],
+        output_mode: OutputMode,
+    ) -> Result<()> {
+        self.change_packages_by_prefix(PrefixPackageChange::Install, prefix, packages, output_mode)
             .await
     }
 
-    pub async fn install_packages_by_prefix(
+    pub async fn uninstall_packages_by_prefix(
         &self,
         prefix: &Path,
         packages: &[String],
         output_mode: OutputMode,
+    ) -> Result<()> {
+        self.change_packages_by_prefix(
+            PrefixPackageChange::Uninstall,
+            prefix,
+            packages,
+            output_mode,
+        )
+        .await
+    }
+
+    pub async fn update_packages_by_prefix(
+        &self,
+        prefix: &Path,
+        packages: &[String],
+        output_mode: OutputMode,
+    ) -> Result<()> {
+        self.change_packages_by_prefix(PrefixPackageChange::Update, prefix, packages, output_mode)
+            .await
+    }
+
+    async fn change_packages_by_prefix(
+        &self,
+        change: PrefixPackageChange,
+        prefix: &Path,
+        packages: &[String],
+        output_mode: OutputMode,
     ) -> Result<()> {
         use tokio::process::Command as AsyncCommand;
 
         let _lock = self.creation_lock.lock().await;
 
-        if packages.is_empty() {
+        if packages.is_empty() && !matches!(change, PrefixPackageChange::Update) {
             return Ok(());
         }
 
@@ -1448,15 +1608,18 @@ impl MicromambaManager {
         }
 
         info!(
-            "Installing packages in environment prefix '{}': {:?}",
+            "{} packages in environment prefix '{}': {:?}",
+            change.progressive(),
             prefix.display(),
             packages
         );
         debug!("package manager path: {:?}", self.pm_path);
 
-        let progress = if matches!(output_mode, OutputMode::Summary) {
+        let mut progress = if matches!(output_mode, OutputMode::Summary) {
             Some(Self::summary_spinner(format!(
-                "Installing packages into {} via {}...",
+                "{} packages {} {} via {}...",
+                change.progressive(),
+                change.preposition(),
                 prefix.display(),
                 self.pm_type
             ))?)
@@ -1465,56 +1628,95 @@ impl MicromambaManager {
         };
 
         let mut cmd = AsyncCommand::new(&self.pm_path);
-        cmd.arg("install")
-            .arg("-p")
-            .arg(prefix)
-            .arg("--override-channels")
-            .arg("-c")
-            .arg("conda-forge")
-            .arg("-c")
-            .arg("bioconda")
-            .arg("-y");
+        cmd.arg(change.subcommand()).arg("-p").arg(prefix);
+        if !matches!(change, PrefixPackageChange::Uninstall) {
+            cmd.arg("--override-channels");
+            if self.channels.is_empty() {
+                cmd.arg("-c").arg("conda-forge").arg("-c").arg("bioconda");
+            }
+            for channel in &self.channels {
+                cmd.arg("-c").arg(channel);
+            }
+            match self.channel_priority {
+                Some(ChannelPriorityMode::Strict) => {
+                    cmd.arg("--strict-channel-priority");
+                }
+                Some(ChannelPriorityMode::Disabled) => {
+                    cmd.arg("--no-channel-priority");
+                }
+                Some(ChannelPriorityMode::Flexible) | None => {}
+            }
+        }
+        if packages.is_empty() {
+            cmd.arg("--all");
+        }
+        // Removals are not confirmed, matching the native backend.
+        let approval = match change {
+            PrefixPackageChange::Uninstall => TransactionApproval::AssumeYes,
+            PrefixPackageChange::Install | PrefixPackageChange::Update => self.transaction_approval,
+        };
+        let dry_run = approval == TransactionApproval::DryRun;
+        let prompt = approval == TransactionApproval::Prompt
+            && !matches!(output_mode, OutputMode::Quiet)
+            && std::io::stdin().is_terminal()
+            && std::io::stderr().is_terminal();
+        if dry_run {
+            cmd.arg("--dry-run");
+        } else if !prompt {
+            cmd.arg("-y");
+        }
 
         for package in packages {
             cmd.arg(package);
         }
 
-        let stashed_ownership_marker = Self::stash_ownership_marker(prefix)?;
-        let output_result = match output_mode {
-            OutputMode::Stream => {
-                println!(
-                    "Installing packages into {} via {}...",
-                    prefix.display(),
-                    self.pm_type
-                );
-                cmd.stdout(std::process::Stdio::inherit())
-                    .stderr(std::process::Stdio::inherit());
-                self.apply_env_to_command(&mut cmd);
-                cmd.status()
-                    .await
-                    .map(|status| std::process::Output {
-                        status,
-                        stdout: Vec::new(),
-                        stderr: Vec::new(),
-                    })
-                    .map_err(|e| {
-                        EnvError::Execution(format!(
-                            "Failed to execute {} install: {}",
-                            self.pm_type, e
-                        ))
-                    })
+        // The plan of a dry run and the package manager's own prompt must stay visible.
+        let inherit_output = matches!(output_mode, OutputMode::Stream) || dry_run || prompt;
+        if inherit_output {
+            if let Some(pb) = progress.take() {
+                pb.finish_and_clear();
             }
-            OutputMode::Summary | OutputMode::Quiet => {
-                cmd.stdout(std::process::Stdio::piped())
-                    .stderr(std::process::Stdio::piped());
-                self.apply_env_to_command(&mut cmd);
-                cmd.output().await.map_err(|e| {
+        }
+
+        let stashed_ownership_marker = Self::stash_ownership_marker(prefix)?;
+        let output_result = if inherit_output {
+            println!(
+                "{} packages {} {} via {}...",
+                change.progressive(),
+                change.preposition(),
+                prefix.display(),
+                self.pm_type
+            );
+            cmd.stdout(std::process::Stdio::inherit())
+                .stderr(std::process::Stdio::inherit());
+            self.apply_env_to_command(&mut cmd);
+            cmd.status()
+                .await
+                .map(|status| std::process::Output {
+                    status,
+                    stdout: Vec::new(),
+                    stderr: Vec::new(),
+                })
+                .map_err(|e| {
                     EnvError::Execution(format!(
-                        "Failed to execute {} install: {}",
-                        self.pm_type, e
+                        "Failed to execute {} {}: {}",
+                        self.pm_type,
+                        change.subcommand(),
+                        e
                     ))
                 })
-            }
+        } else {
+            cmd.stdout(std::process::Stdio::piped())
+                .stderr(std::process::Stdio::piped());
+            self.apply_env_to_command(&mut cmd);
+            cmd.output().await.map_err(|e| {
+                EnvError::Execution(format!(
+                    "Failed to execute {} {}: {}",
+                    self.pm_type,
+                    change.subcommand(),
+                    e
+                ))
+            })
         };
 
         let restore_result = Self::restore_ownership_marker(prefix, stashed_ownership_marker);
@@ -1522,7 +1724,9 @@ impl MicromambaManager {
         let result = match (output_result, restore_result) {
             (Ok(output), Ok(())) if output.status.success() => Ok(output),
             (Ok(output), Ok(())) => Err(EnvError::Execution(format!(
-                "Failed to install packages into {} using {}: exit code {:?}{}",
+                "Failed to {} packages {} {} using {}: exit code {:?}{}",
+                change.subcommand(),
+                change.preposition(),
                 prefix.display(),
                 self.pm_type,
                 output.status.code(),
@@ -1531,7 +1735,9 @@ impl MicromambaManager {
             (Err(error), Ok(())) => Err(error),
             (Ok(output), Err(error)) if output.status.success() => Err(error),
             (Ok(output), Err(error)) => Err(EnvError::Execution(format!(
-                "Failed to install packages into {} using {}: exit code {:?}{}; additionally failed to restore enva ownership marker: {}",
+                "Failed to {} packages {} {} using {}: exit code {:?}{}; additionally failed to restore enva ownership marker: {}",
+                change.subcommand(),
+                change.preposition(),
                 prefix.display(),
                 self.pm_type,
                 output.status.code(),
@@ -1549,7 +1755,8 @@ impl MicromambaManager {
         match (&progress, &result) {
             (Some(pb), Ok(_)) => pb.finish_and_clear(),
             (Some(pb), Err(error)) => pb.abandon_with_message(format!(
-                "✗ Failed package install for {}: {}",
+                "✗ Failed package {} for {}: {}",
+                change.subcommand(),
                 prefix.display(),
                 error
             )),
@@ -1557,9 +1764,18 @@ impl MicromambaManager {
         }
 
         match result {
+            Ok(_) if dry_run => {
+                println!("Dry run: no changes were made to {}", prefix.display());
+                Ok(())
+            }
             Ok(output) => {
                 if matches!(output_mode, OutputMode::Summary) {
-                    println!("✓ Installed packages into {}", prefix.display());
+                    println!(
+                        "✓ {} packages {} {}",
+                        change.completed(),
+                        change.preposition(),
+                        prefix.display()
+                    );
                 }
                 if !output.stdout.is_empty() && matches!(output_mode, OutputMode::Stream) {
                     print!("{}", String::from_utf8_lossy(&output.stdout));
@@ -1568,7 +1784,8 @@ impl MicromambaManager {
                     eprint!("{}", String::from_utf8_lossy(&output.stderr));
                 }
                 info!(
-                    "Successfully installed packages in environment prefix '{}'",
+                    "Successfully {} packages in environment prefix '{}'",
+                    change.completed().to_lowercase(),
                     prefix.display()
                 );
                 Ok(())
@@ -1796,6 +2013,9 @@ mod tests {
             version_config: VersionConfig::default(),
             creation_lock: Arc::new(Mutex::new(())),
             env_list_cache: Arc::new(StdMutex::new(None)),
+            transaction_approval: TransactionApproval::default(),
+            channels: Vec::new(),
+            channel_priority: None,
         }
     }
 
@@ -1942,6 +2162,119 @@ dependencies:
         fs::set_permissions(binary_path, permissions).unwrap();
     }
 
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn prefix_package_changes_pass_the_transaction_approval_to_the_package_manager() {
+        let temporary_directory = tempdir().unwrap();
+        let prefix = temporary_directory.path().join("envs").join("demo");
+        create_fake_environment(&prefix);
+        let arguments = temporary_directory.path().join("arguments");
+        let binary = temporary_directory.path().join("micromamba");
+        fs::write(
+            &binary,
+            format!("#!/bin/sh\necho \"$*\" >> '{}'\n", arguments.display()),
+        )
+        .unwrap();
+        let mut permissions = fs::metadata(&binary).unwrap().permissions();
+        permissions.set_mode(0o755);
+        fs::set_permissions(&binary, permissions).unwrap();
+        let mut manager = build_test_manager(temporary_directory.path());
+        manager.pm_path = binary;
+        let packages = vec!["samtools".to_string()];
+
+        for approval in [
+            TransactionApproval::DryRun,
+            TransactionApproval::AssumeYes,
+            TransactionApproval::Prompt,
+        ] {
+            manager
+                .clone()
+                .with_transaction_approval(approval)
+                .install_packages_by_prefix(&prefix, &packages, OutputMode::Quiet)
+                .await
+                .unwrap();
+        }
+        manager
+            .clone()
+            .with_transaction_approval(TransactionApproval::DryRun)
+            .uninstall_packages_by_prefix(&prefix, &packages, OutputMode::Quiet)
+            .await
+            .unwrap();
+
+        let calls = fs::read_to_string(&arguments).unwrap();
+        let calls = calls.lines().collect::<Vec<&str>>();
+        assert_eq!(calls.len(), 4);
+        assert!(calls[0].starts_with("install -p"));
+        assert!(calls[0].ends_with("--dry-run samtools"), "{}", calls[0]);
+        // A prompt cannot be answered without a terminal, so it is assumed like `--yes`.
+        for call in &calls[1..] {
+            assert!(call.ends_with("-y samtools"), "{call}");
+            assert!(!call.contains("--dry-run"), "{call}");
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn prefix_package_changes_pass_the_channels_and_priority_to_the_package_manager() {
+        let temporary_directory = tempdir().unwrap();
+        let prefix = temporary_directory.path().join("envs").join("demo");
+        create_fake_environment(&prefix);
+        let arguments = temporary_directory.path().join("arguments");
+        let binary = temporary_directory.path().join("micromamba");
+        fs::write(
+            &binary,
+            format!("#!/bin/sh\necho \"$*\" >> '{}'\n", arguments.display()),
+        )
+        .unwrap();
+        let mut permissions = fs::metadata(&binary).unwrap().permissions();
+        permissions.set_mode(0o755);
+        fs::set_permissions(&binary, permissions).unwrap();
+        let mut manager = build_test_manager(temporary_directory.path());
+        manager.pm_path = binary;
+        let manager = manager.with_transaction_approval(TransactionApproval::AssumeYes);
+        let packages = vec!["samtools".to_string()];
+
+        manager
+            .install_packages_by_prefix(&prefix, &packages, OutputMode::Quiet)
+            .await
+            .unwrap();
+        manager
+            .clone()
+            .with_channels(vec![
+                "file:///data/lab-channel".to_string(),
+                "conda-forge".to_string(),
+            ])
+            .with_channel_priority(Some(ChannelPriorityMode::Strict))
+            .install_packages_by_prefix(&prefix, &packages, OutputMode::Quiet)
+            .await
+            .unwrap();
+        manager
+            .clone()
+            .with_channel_priority(Some(ChannelPriorityMode::Disabled))
+            .update_packages_by_prefix(&prefix, &[], OutputMode::Quiet)
+            .await
+            .unwrap();
+
+        let calls = fs::read_to_string(&arguments).unwrap();
+        let calls = calls.lines().collect::<Vec<&str>>();
+        assert_eq!(calls.len(), 3);
+        assert!(
+            calls[0].contains("--override-channels -c conda-forge -c bioconda -y"),
+            "{}",
+            calls[0]
+        );
+        assert!(
+            calls[1].contains(
+                "--override-channels -c file:///data/lab-channel -c conda-forge --strict-channel-priority -y"
+            ),
+            "{}",
+            calls[1]
+        );
+        assert!(!calls[1].contains("bioconda"), "{}", calls[1]);
+        assert!(calls[2].starts_with("update -p"), "{}", calls[2]);
+        assert!(calls[2].contains("--no-channel-priority"), "{}", calls[2]);
+    }
+
     #[cfg(unix)]
     #[test]
     fn explicitly_configured_micromamba_path_takes_precedence() {
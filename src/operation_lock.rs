@@ -13,9 +13,21 @@ use std::path::{Path, PathBuf};
 pub enum LockOperation {
     Create,
     Install,
+    Uninstall,
+    Update,
+    Sync,
     Remove,
     Adopt,
     Run,
+    Export,
+    History,
+    Rollback,
+    Diff,
+    Verify,
+    Repair,
+    Clone,
+    Relocate,
+    Pack,
     CacheUse,
     CacheClean,
 }
@@ -86,7 +86,7 @@ pub fn write_rattler_ownership_record(
     Ok(record)
 }
 
-fn write_file_atomically(path: &Path, content: &[u8], label: &str) -> Result<()> {
+pub(crate) fn write_file_atomically(path: &Path, content: &[u8], label: &str) -> Result<()> {
     let parent = path.parent().ok_or_else(|| {
         EnvError::FileOperation(format!("{} path has no parent: {}", label, path.display()))
     })?;
@@ -0,0 +1,545 @@
+use crate::error::{EnvError, Result};
+use chrono::Utc;
+use rattler_conda_types::package::FileMode;
+use rattler_conda_types::prefix_record::PathType;
+use rattler_conda_types::{Platform, PrefixRecord};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+
+const PACK_FORMAT_VERSION: u32 = 1;
+const PACK_MANIFEST_NAME: &str = "enva-pack.json";
+const PACK_PREFIX_DIRECTORY: &str = "prefix";
+
+/// File of a packed prefix that embeds the prefix it was installed into.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PackedPlaceholder {
+    pub package: String,
+    pub path: PathBuf,
+    pub file_mode: FileMode,
+}
+
+/// First entry of an `enva pack` archive, describing where the packed prefix came from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackManifest {
+    pub version: u32,
+    pub original_prefix: PathBuf,
+    pub platform: String,
+    pub created_at: String,
+    pub packages: Vec<String>,
+    /// Files that are rewritten for the new prefix; unpacking checks the archive has them all.
+    pub placeholders: Vec<PackedPlaceholder>,
+}
+
+impl PackManifest {
+    pub fn from_records(original_prefix: &Path, records: &[PrefixRecord]) -> Self {
+        let mut packages = Vec::new();
+        let mut placeholders = Vec::new();
+        for record in records {
+            let package = &record.repodata_record.package_record;
+            packages.push(format!(
+                "{}={}={}",
+                package.name.as_normalized(),
+                package.version,
+                package.build
+            ));
+            for entry in &record.paths_data.paths {
+                let file_mode = match (&entry.prefix_placeholder, entry.path_type) {
+                    (Some(_), _) => entry.file_mode.unwrap_or(FileMode::Text),
+                    (None, PathType::UnixPythonEntryPoint) => FileMode::Text,
+                    _ => continue,
+                };
+                placeholders.push(PackedPlaceholder {
+                    package: package.name.as_normalized().to_string(),
+                    path: entry.relative_path.clone(),
+                    file_mode,
+                });
+            }
+        }
+        packages.sort();
+
+        Self {
+            version: PACK_FORMAT_VERSION,
+            original_prefix: original_prefix.to_path_buf(),
+            platform: Platform::current().to_string(),
+            created_at: Utc::now().to_rfc3339(),
+            packages,
+            placeholders,
+        }
+    }
+}
+
+fn archive_error(action: &str, path: &Path, error: io::Error) -> EnvError {
+    EnvError::FileOperation(format!("{} {}: {}", action, path.display(), error))
+}
+
+fn append_prefix_entries<W: io::Write>(
+    builder: &mut tar::Builder<W>,
+    prefix: &Path,
+    directory: &Path,
+    excluded: &[PathBuf],
+    files: &mut usize,
+) -> Result<()> {
+    let mut entries = fs::read_dir(directory)
+        .map_err(|error| archive_error("Failed to read", directory, error))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<PathBuf>>>()
+        .map_err(|error| archive_error("Failed to read", directory, error))?;
+    entries.sort();
+
+    for path in entries {
+        if excluded.contains(&path) {
+            continue;
+        }
+        let relative = path.strip_prefix(prefix).unwrap_or(&path);
+        let name = Path::new(PACK_PREFIX_DIRECTORY).join(relative);
+        builder
+            .append_path_with_name(&path, &name)
+            .map_err(|error| archive_error("Failed to pack", &path, error))?;
+        let metadata = fs::symlink_metadata(&path)
+            .map_err(|error| archive_error("Failed to inspect", &path, error))?;
+        if metadata.is_dir() {
+            append_prefix_entries(builder, prefix, &path, excluded, files)?;
+        } else {
+            *files += 1;
+        }
+    }
+    Ok(())
+}
+
+/// Writes `prefix` and `manifest` to a zstd-compressed tar archive at `output`, leaving
+/// out `excluded` paths. Returns the number of files and symlinks packed.
+pub fn write_pack_archive(
+    prefix: &Path,
+    manifest: &PackManifest,
+    excluded: &[PathBuf],
+    output: &Path,
+) -> Result<usize> {
+    let file_name = output
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .ok_or_else(|| {
+            EnvError::Validation(format!(
+                "Archive path has no file name: {}",
+                output.display()
+            ))
+        })?;
+    let partial = output.with_file_name(format!(".{}.part", file_name));
+    let result = (|| -> Result<usize> {
+        let file = File::create(&partial)
+            .map_err(|error| archive_error("Failed to create archive", &partial, error))?;
+        let encoder = zstd::Encoder::new(file, 0)
+            .map_err(|error| archive_error("Failed to start compressing", &partial, error))?;
+        let mut builder = tar::Builder::new(encoder);
+        builder.follow_symlinks(false);
+
+        let manifest_bytes = serde_json::to_vec_pretty(manifest)?;
+        let mut header = tar::Header::new_gnu();
+        header.set_size(manifest_bytes.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(Utc::now().timestamp().max(0) as u64);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, PACK_MANIFEST_NAME, manifest_bytes.as_slice())
+            .map_err(|error| archive_error("Failed to write manifest to", &partial, error))?;
+
+        let mut files = 0;
+        append_prefix_entries(&mut builder, prefix, prefix, excluded, &mut files)?;
+        builder
+            .into_inner()
+            .and_then(|encoder| encoder.finish())
+            .and_then(|file| file.sync_all())
+            .map_err(|error| archive_error("Failed to finish archive", &partial, error))?;
+        Ok(files)
+    })();
+
+    match result {
+        Ok(files) => {
+            fs::rename(&partial, output)
+                .map_err(|error| archive_error("Failed to publish archive", output, error))?;
+            Ok(files)
+        }
+        Err(error) => {
+            let _ = fs::remove_file(&partial);
+            Err(error)
+        }
+    }
+}
+
+fn open_archive(
+    archive: &Path,
+) -> Result<tar::Archive<zstd::Decoder<'static, io::BufReader<File>>>> {
+    let file = File::open(archive).map_err(|error| match error.kind() {
+        io::ErrorKind::NotFound => EnvError::FileNotFound(archive.to_path_buf()),
+        _ => archive_error("Failed to open archive", archive, error),
+    })?;
+    let decoder = zstd::Decoder::new(file)
+        .map_err(|error| archive_error("Failed to decompress archive", archive, error))?;
+    Ok(tar::Archive::new(decoder))
+}
+
+fn read_manifest(entry: &mut impl Read, archive: &Path) -> Result<PackManifest> {
+    let mut content = Vec::new();
+    entry
+        .read_to_end(&mut content)
+        .map_err(|error| archive_error("Failed to read manifest of", archive, error))?;
+    let manifest: PackManifest = serde_json::from_slice(&content).map_err(|error| {
+        EnvError::Validation(format!(
+            "Archive {} has an invalid {}: {}",
+            archive.display(),
+            PACK_MANIFEST_NAME,
+            error
+        ))
+    })?;
+    if manifest.version != PACK_FORMAT_VERSION {
+        return Err(EnvError::Validation(format!(
+            "Archive {} uses pack format {}, but this enva reads format {}",
+            archive.display(),
+            manifest.version,
+            PACK_FORMAT_VERSION
+        )));
+    }
+    Ok(manifest)
+}
+
+/// Path of an archive entry inside the packed prefix, or `None` for entries outside it.
+fn prefix_relative_path(entry_path: &Path) -> Result<Option<PathBuf>> {
+    let Ok(relative) = entry_path.strip_prefix(PACK_PREFIX_DIRECTORY) else {
+        return Ok(None);
+    };
+    if !relative
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        return Err(EnvError::PermissionDenied(format!(
+            "Refusing to unpack archive entry outside the prefix: {}",
+            entry_path.display()
+        )));
+    }
+    Ok(Some(relative.to_path_buf()))
+}
+
+/// Refuses to write through a symlink created by an earlier archive entry.
+pub(crate) fn ensure_no_symlink_ancestor(root: &Path, relative: &Path) -> Result<()> {
+    let mut current = root.to_path_buf();
+    let Some(parent) = relative.parent() else {
+        return Ok(());
+    };
+    for component in parent.components() {
+        current.push(component);
+        if fs::symlink_metadata(&current).is_ok_and(|metadata| metadata.file_type().is_symlink()) {
+            return Err(EnvError::PermissionDenied(format!(
+                "Refusing to write {} through the symlink {}",
+                relative.display(),
+                current.display()
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Target a packed symlink should have once its prefix is published at `published_prefix`.
+/// Absolute targets inside the original prefix move with it; anything else that leaves the
+/// prefix is refused.
+fn unpacked_symlink_target(
+    manifest: &PackManifest,
+    published_prefix: &Path,
+    relative: &Path,
+    target: &Path,
+) -> Result<PathBuf> {
+    if target.is_absolute() {
+        return match target.strip_prefix(&manifest.original_prefix) {
+            Ok(inside) => Ok(published_prefix.join(inside)),
+            Err(_) => Err(EnvError::PermissionDenied(format!(
+                "Refusing to unpack symlink escaping the packed prefix: {} -> {}",
+                relative.display(),
+                target.display()
+            ))),
+        };
+    }
+
+    let mut depth = relative.components().count().saturating_sub(1);
+    for component in target.components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir if depth > 0 => depth -= 1,
+            _ => {
+                return Err(EnvError::PermissionDenied(format!(
+                    "Refusing to unpack symlink escaping the packed prefix: {} -> {}",
+                    relative.display(),
+                    target.display()
+                )))
+            }
+        }
+    }
+    Ok(target.to_path_buf())
+}
+
+/// Extracts the prefix stored in `archive` into the empty directory `destination`, which
+/// will be published at `published_prefix`, and returns the archive's manifest.
+pub fn extract_pack_archive(
+    archive: &Path,
+    destination: &Path,
+    published_prefix: &Path,
+) -> Result<PackManifest> {
+    let mut tar = open_archive(archive)?;
+    let mut entries = tar
+        .entries()
+        .map_err(|error| archive_error("Failed to read archive", archive, error))?;
+    let manifest = match entries.next() {
+        Some(Ok(mut entry))
+            if entry
+                .path()
+                .is_ok_and(|path| path.as_ref() == Path::new(PACK_MANIFEST_NAME)) =>
+        {
+            read_manifest(&mut entry, archive)?
+        }
+        Some(Err(error)) => return Err(archive_error("Failed to read archive", archive, error)),
+        _ => {
+            return Err(EnvError::Validation(format!(
+                "{} is not an enva pack archive: it does not start with {}",
+                archive.display(),
+                PACK_MANIFEST_NAME
+            )))
+        }
+    };
+    // Relocation replaces this prefix in the unpacked files, so it has to be a real one.
+    if !manifest.original_prefix.is_absolute() || manifest.original_prefix.parent().is_none() {
+        return Err(EnvError::PermissionDenied(format!(
+            "Archive {} names an invalid original prefix: {}",
+            archive.display(),
+            manifest.original_prefix.display()
+        )));
+    }
+    if manifest.platform != Platform::current().as_str() {
+        return Err(EnvError::Validation(format!(
+            "Archive {} was packed for {}, but this machine is {}",
+            archive.display(),
+            manifest.platform,
+            Platform::current()
+        )));
+    }
+
+    for entry in entries {
+        let mut entry =
+            entry.map_err(|error| archive_error("Failed to read archive", archive, error))?;
+        let entry_path = entry
+            .path()
+            .map_err(|error| archive_error("Failed to read entry path in", archive, error))?
+            .into_owned();
+        let Some(relative) = prefix_relative_path(&entry_path)? else {
+            continue;
+        };
+        let entry_type = entry.header().entry_type();
+        if !(entry_type.is_file() || entry_type.is_dir() || entry_type.is_symlink()) {
+            return Err(EnvError::Validation(format!(
+                "Unsupported entry type in archive {}: {}",
+                archive.display(),
+                entry_path.display()
+            )));
+        }
+
+        ensure_no_symlink_ancestor(destination, &relative)?;
+        let target = destination.join(&relative);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|error| archive_error("Failed to create directory", parent, error))?;
+        }
+        if entry_type.is_symlink() {
+            let link_name = entry
+                .link_name()
+                .map_err(|error| archive_error("Failed to read symlink in", archive, error))?
+                .ok_or_else(|| {
+                    EnvError::Validation(format!(
+                        "Symlink without a target in archive {}: {}",
+                        archive.display(),
+                        entry_path.display()
+                    ))
+                })?;
+            let link_target =
+                unpacked_symlink_target(&manifest, published_prefix, &relative, &link_name)?;
+            if cfg!(windows) {
+                return Err(EnvError::Validation(format!(
+                    "Unpacking symlinks is unsupported on Windows: {} -> {}",
+                    relative.display(),
+                    link_target.display()
+                )));
+            }
+            #[cfg(unix)]
+            std::os::unix::fs::symlink(&link_target, &target)
+                .map_err(|error| archive_error("Failed to unpack symlink", &target, error))?;
+            continue;
+        }
+        entry
+            .unpack(&target)
+            .map_err(|error| archive_error("Failed to unpack", &target, error))?;
+    }
+
+    // Relocation skips files it cannot find, so an archive that lost some of them would
+    // otherwise unpack into an environment that still points at the packed prefix.
+    let missing = manifest
+        .placeholders
+        .iter()
+        .filter(|placeholder| fs::symlink_metadata(destination.join(&placeholder.path)).is_err())
+        .map(|placeholder| format!("{} ({})", placeholder.path.display(), placeholder.package))
+        .collect::<Vec<String>>();
+    if !missing.is_empty() {
+        return Err(EnvError::Validation(format!(
+            "Archive {} is missing files that embed the packed prefix: {}",
+            archive.display(),
+            missing.join(", ")
+        )));
+    }
+
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        extract_pack_archive, unpacked_symlink_target, write_pack_archive, PackManifest,
+        PackedPlaceholder,
+    };
+    use rattler_conda_types::package::FileMode;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use tempfile::tempdir;
+
+    #[test]
+    fn archives_round_trip_prefix_files_and_symlinks_without_excluded_entries() {
+        let tempdir = tempdir().unwrap();
+        let prefix = tempdir.path().join("envs/demo");
+        fs::create_dir_all(prefix.join("conda-meta")).unwrap();
+        fs::create_dir_all(prefix.join("bin")).unwrap();
+        fs::write(prefix.join("bin/tool"), "#!/bin/sh\n").unwrap();
+        fs::write(prefix.join("conda-meta/enva-ownership.json"), "{}").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("tool", prefix.join("bin/tool-alias")).unwrap();
+
+        let archive = tempdir.path().join("demo.tar.zst");
+        let manifest = PackManifest::from_records(&prefix, &[]);
+        write_pack_archive(
+            &prefix,
+            &manifest,
+            &[prefix.join("conda-meta/enva-ownership.json")],
+            &archive,
+        )
+        .unwrap();
+
+        let destination = tempdir.path().join("unpacked");
+        fs::create_dir(&destination).unwrap();
+        let unpacked =
+            extract_pack_archive(&archive, &destination, Path::new("/envs/unpacked")).unwrap();
+
+        assert_eq!(unpacked.original_prefix, prefix);
+        assert_eq!(
+            fs::read_to_string(destination.join("bin/tool")).unwrap(),
+            "#!/bin/sh\n"
+        );
+        assert!(!destination.join("conda-meta/enva-ownership.json").exists());
+        #[cfg(unix)]
+        assert_eq!(
+            fs::read_link(destination.join("bin/tool-alias")).unwrap(),
+            PathBuf::from("tool")
+        );
+    }
+
+    #[test]
+    fn unpacking_requires_every_file_the_manifest_lists_for_relocation() {
+        let tempdir = tempdir().unwrap();
+        let prefix = tempdir.path().join("envs/demo");
+        fs::create_dir_all(prefix.join("conda-meta")).unwrap();
+        fs::create_dir_all(prefix.join("bin")).unwrap();
+        fs::write(
+            prefix.join("bin/tool"),
+            format!("#!{}/bin/python\n", prefix.display()),
+        )
+        .unwrap();
+        let mut manifest = PackManifest::from_records(&prefix, &[]);
+        manifest.placeholders.push(PackedPlaceholder {
+            package: "tool".to_string(),
+            path: PathBuf::from("bin/tool"),
+            file_mode: FileMode::Text,
+        });
+
+        let complete = tempdir.path().join("complete.tar.zst");
+        write_pack_archive(&prefix, &manifest, &[], &complete).unwrap();
+        let destination = tempdir.path().join("complete");
+        fs::create_dir(&destination).unwrap();
+        let unpacked =
+            extract_pack_archive(&complete, &destination, Path::new("/envs/complete")).unwrap();
+        assert_eq!(unpacked.placeholders, manifest.placeholders);
+
+        let truncated = tempdir.path().join("truncated.tar.zst");
+        write_pack_archive(&prefix, &manifest, &[prefix.join("bin/tool")], &truncated).unwrap();
+        let destination = tempdir.path().join("truncated");
+        fs::create_dir(&destination).unwrap();
+        let error = extract_pack_archive(&truncated, &destination, Path::new("/envs/truncated"))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("bin/tool (tool)"), "{}", error);
+    }
+
+    #[test]
+    fn archives_with_a_relative_or_root_original_prefix_are_refused() {
+        let tempdir = tempdir().unwrap();
+        let prefix = tempdir.path().join("envs/demo");
+        fs::create_dir_all(prefix.join("conda-meta")).unwrap();
+        for original_prefix in ["/", "envs/demo"] {
+            let mut manifest = PackManifest::from_records(&prefix, &[]);
+            manifest.original_prefix = PathBuf::from(original_prefix);
+            let archive = tempdir.path().join("crafted.tar.zst");
+            write_pack_archive(&prefix, &manifest, &[], &archive).unwrap();
+            let destination = tempdir.path().join("unpacked");
+            fs::create_dir_all(&destination).unwrap();
+
+            let error = extract_pack_archive(&archive, &destination, Path::new("/envs/copy"))
+                .unwrap_err()
+                .to_string();
+            assert!(error.contains("invalid original prefix"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn symlink_targets_follow_the_prefix_and_may_not_escape_it() {
+        let manifest = PackManifest::from_records(Path::new("/envs/source"), &[]);
+        let published = Path::new("/project/envs/copy");
+
+        assert_eq!(
+            unpacked_symlink_target(
+                &manifest,
+                published,
+                Path::new("bin/python"),
+                Path::new("/envs/source/bin/python3.12")
+            )
+            .unwrap(),
+            PathBuf::from("/project/envs/copy/bin/python3.12")
+        );
+        assert_eq!(
+            unpacked_symlink_target(
+                &manifest,
+                published,
+                Path::new("lib/libz.so"),
+                Path::new("../lib/libz.so.1")
+            )
+            .unwrap(),
+            PathBuf::from("../lib/libz.so.1")
+        );
+        assert!(unpacked_symlink_target(
+            &manifest,
+            published,
+            Path::new("bin/python"),
+            Path::new("/usr/bin/python3")
+        )
+        .is_err());
+        assert!(unpacked_symlink_target(
+            &manifest,
+            published,
+            Path::new("lib/libz.so"),
+            Path::new("../../outside")
+        )
+        .is_err());
+    }
+}
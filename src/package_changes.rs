@@ -0,0 +1,385 @@
+use rattler_conda_types::RepoDataRecord;
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+
+const DEFAULT_CHANNEL_BASE_URL: &str = "https://conda.anaconda.org/";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PackageChangeKind {
+    Install,
+    Remove,
+    Upgrade,
+    Downgrade,
+    Rebuild,
+    /// Same version and build, fetched from a different channel.
+    ChannelSwitch,
+}
+
+impl PackageChangeKind {
+    fn marker(self) -> char {
+        match self {
+            Self::Install => '+',
+            Self::Remove => '-',
+            Self::Upgrade => '↑',
+            Self::Downgrade => '↓',
+            Self::Rebuild => '~',
+            Self::ChannelSwitch => '*',
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::Install => "install",
+            Self::Remove => "removal",
+            Self::Upgrade => "upgrade",
+            Self::Downgrade => "downgrade",
+            Self::Rebuild => "rebuild",
+            Self::ChannelSwitch => "channel switch",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PackageVersion {
+    pub version: String,
+    pub build: String,
+    pub channel: Option<String>,
+}
+
+impl PackageVersion {
+    fn from_record(record: &RepoDataRecord) -> Self {
+        Self {
+            version: record.package_record.version.to_string(),
+            build: record.package_record.build.clone(),
+            channel: record.channel.as_deref().map(channel_display_name),
+        }
+    }
+}
+
+impl fmt::Display for PackageVersion {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(formatter, "{} ({})", self.version, self.build)
+    }
+}
+
+/// One package-level difference between a `before` and an `after` record set.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PackageChange {
+    pub name: String,
+    pub kind: PackageChangeKind,
+    pub from: Option<PackageVersion>,
+    pub to: Option<PackageVersion>,
+}
+
+impl fmt::Display for PackageChange {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(formatter, "{} {}", self.kind.marker(), self.name)?;
+        match (&self.from, &self.to) {
+            (Some(from), Some(to)) if self.kind == PackageChangeKind::ChannelSwitch => write!(
+                formatter,
+                " {} {} -> {}",
+                to,
+                from.channel.as_deref().unwrap_or("<unknown>"),
+                to.channel.as_deref().unwrap_or("<unknown>")
+            ),
+            (Some(from), Some(to)) => write!(formatter, " {} -> {}", from, to),
+            (Some(version), None) | (None, Some(version)) => write!(formatter, " {}", version),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+/// The package changes a solve would apply to a prefix, shown before they are applied.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TransactionPlan {
+    pub prefix: PathBuf,
+    pub changes: Vec<PackageChange>,
+    /// Archive size of every new record that is not yet in the package cache, by name.
+    pub download_sizes: BTreeMap<String, u64>,
+    pub download_size_bytes: u64,
+}
+
+impl TransactionPlan {
+    /// Plans the move from `before` to `after`; `is_cached` tells which new records are
+    /// already in the package cache and need no download.
+    pub fn new<'a>(
+        prefix: PathBuf,
+        before: impl IntoIterator<Item = &'a RepoDataRecord>,
+        after: &'a [RepoDataRecord],
+        is_cached: impl Fn(&RepoDataRecord) -> bool,
+    ) -> Self {
+        let changes = diff_package_records(before, after);
+        let after = records_by_name(after);
+        let download_sizes = changes
+            .iter()
+            .filter(|change| change.to.is_some())
+            .filter_map(|change| after.get(&change.name).map(|record| (&change.name, record)))
+            .filter(|(_, record)| !is_cached(record))
+            .map(|(name, record)| (name.clone(), record.package_record.size.unwrap_or(0)))
+            .collect::<BTreeMap<String, u64>>();
+        let download_size_bytes = download_sizes.values().sum();
+        Self {
+            prefix,
+            changes,
+            download_sizes,
+            download_size_bytes,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+impl fmt::Display for TransactionPlan {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            formatter,
+            "Transaction plan for {} ({}):",
+            self.prefix.display(),
+            summarize_package_changes(&self.changes)
+        )?;
+        for change in &self.changes {
+            write!(formatter, "  {}", change)?;
+            let channel = change
+                .to
+                .as_ref()
+                .or(change.from.as_ref())
+                .and_then(|version| version.channel.as_deref());
+            if let Some(channel) =
+                channel.filter(|_| change.kind != PackageChangeKind::ChannelSwitch)
+            {
+                write!(formatter, "  [{}]", channel)?;
+            }
+            if let Some(size) = self.download_sizes.get(&change.name) {
+                write!(formatter, "  {}", format_size_bytes(*size))?;
+            }
+            writeln!(formatter)?;
+        }
+        write!(
+            formatter,
+            "  Download: {}",
+            format_size_bytes(self.download_size_bytes)
+        )
+    }
+}
+
+pub(crate) fn format_size_bytes(bytes: u64) -> String {
+    const MIB: f64 = 1024.0 * 1024.0;
+    if bytes >= 1024 * 1024 {
+        format!("{:.1} MiB", bytes as f64 / MIB)
+    } else {
+        format!("{:.1} KiB", bytes as f64 / 1024.0)
+    }
+}
+
+/// Shortens channel URLs on the default server to their names, e.g. `bioconda`.
+pub fn channel_display_name(channel: &str) -> String {
+    channel
+        .strip_prefix(DEFAULT_CHANNEL_BASE_URL)
+        .unwrap_or(channel)
+        .trim_end_matches('/')
+        .to_string()
+}
+
+fn records_by_name<'a>(
+    records: impl IntoIterator<Item = &'a RepoDataRecord>,
+) -> BTreeMap<String, &'a RepoDataRecord> {
+    records
+        .into_iter()
+        .map(|record| {
+            (
+                record.package_record.name.as_normalized().to_string(),
+                record,
+            )
+        })
+        .collect()
+}
+
+/// Compares two record sets by package name; unchanged packages are omitted and the
+/// result is sorted by name.
+pub fn diff_package_records<'a>(
+    before: impl IntoIterator<Item = &'a RepoDataRecord>,
+    after: impl IntoIterator<Item = &'a RepoDataRecord>,
+) -> Vec<PackageChange> {
+    let before = records_by_name(before);
+    let mut after = records_by_name(after);
+
+    let mut changes = Vec::new();
+    for (name, old) in before {
+        let Some(new) = after.remove(&name) else {
+            changes.push(PackageChange {
+                name,
+                kind: PackageChangeKind::Remove,
+                from: Some(PackageVersion::from_record(old)),
+                to: None,
+            });
+            continue;
+        };
+
+        let (from, to) = (
+            PackageVersion::from_record(old),
+            PackageVersion::from_record(new),
+        );
+        let kind = match new.package_record.version.cmp(&old.package_record.version) {
+            Ordering::Greater => PackageChangeKind::Upgrade,
+            Ordering::Less => PackageChangeKind::Downgrade,
+            Ordering::Equal if to.build != from.build => PackageChangeKind::Rebuild,
+            Ordering::Equal if to.channel != from.channel => PackageChangeKind::ChannelSwitch,
+            Ordering::Equal => continue,
+        };
+        changes.push(PackageChange {
+            name,
+            kind,
+            from: Some(from),
+            to: Some(to),
+        });
+    }
+
+    changes.extend(after.into_iter().map(|(name, new)| PackageChange {
+        name,
+        kind: PackageChangeKind::Install,
+        from: None,
+        to: Some(PackageVersion::from_record(new)),
+    }));
+    changes.sort_by(|left, right| left.name.cmp(&right.name));
+    changes
+}
+
+/// Renders counts such as `2 upgrades, 1 install`.
+pub fn summarize_package_changes(changes: &[PackageChange]) -> String {
+    if changes.is_empty() {
+        return "no changes".to_string();
+    }
+
+    let mut parts = Vec::new();
+    for kind in [
+        PackageChangeKind::Install,
+        PackageChangeKind::Upgrade,
+        PackageChangeKind::Downgrade,
+        PackageChangeKind::Rebuild,
+        PackageChangeKind::ChannelSwitch,
+        PackageChangeKind::Remove,
+    ] {
+        let count = changes.iter().filter(|change| change.kind == kind).count();
+        if count > 0 {
+            let plural = if count == 1 { "" } else { "s" };
+            parts.push(format!("{} {}{}", count, kind.label(), plural));
+        }
+    }
+    parts.join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        diff_package_records, summarize_package_changes, PackageChangeKind, TransactionPlan,
+    };
+    use crate::test_fixtures::repodata_record;
+    use rattler_conda_types::RepoDataRecord;
+
+    fn record_from(name: &str, version: &str, build: &str, channel: &str) -> RepoDataRecord {
+        repodata_record(name, version, build, channel, "noarch")
+    }
+
+    fn record(name: &str, version: &str, build: &str) -> RepoDataRecord {
+        record_from(name, version, build, "bioconda")
+    }
+
+    #[test]
+    fn diff_classifies_every_kind_of_change() {
+        let before = vec![
+            record("samtools", "1.19", "h0_0"),
+            record("htslib", "1.20", "h0_0"),
+            record("zlib", "1.3", "h0_0"),
+            record("seqtk", "1.4", "h0_0"),
+            record("python", "3.12", "h0_0"),
+            record("pigz", "2.8", "h0_0"),
+        ];
+        let after = vec![
+            record("samtools", "1.20", "h0_0"),
+            record("htslib", "1.19", "h0_0"),
+            record("zlib", "1.3", "h1_0"),
+            record("python", "3.12", "h0_0"),
+            record("bwa", "0.7.18", "h0_0"),
+            record_from("pigz", "2.8", "h0_0", "conda-forge"),
+        ];
+
+        let changes = diff_package_records(&before, &after);
+        let kinds = changes
+            .iter()
+            .map(|change| (change.name.as_str(), change.kind))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            kinds,
+            vec![
+                ("bwa", PackageChangeKind::Install),
+                ("htslib", PackageChangeKind::Downgrade),
+                ("pigz", PackageChangeKind::ChannelSwitch),
+                ("samtools", PackageChangeKind::Upgrade),
+                ("seqtk", PackageChangeKind::Remove),
+                ("zlib", PackageChangeKind::Rebuild),
+            ]
+        );
+        assert_eq!(
+            changes[3].to_string(),
+            "↑ samtools 1.19 (h0_0) -> 1.20 (h0_0)"
+        );
+        assert_eq!(
+            changes[2].to_string(),
+            "* pigz 2.8 (h0_0) bioconda -> conda-forge"
+        );
+        assert_eq!(
+            changes[3].to.as_ref().unwrap().channel.as_deref(),
+            Some("bioconda")
+        );
+        assert_eq!(
+            summarize_package_changes(&changes),
+            "1 install, 1 upgrade, 1 downgrade, 1 rebuild, 1 channel switch, 1 removal"
+        );
+    }
+
+    #[test]
+    fn transaction_plan_lists_channels_and_sizes_of_uncached_downloads() {
+        let before = vec![
+            record("samtools", "1.19", "h0_0"),
+            record("seqtk", "1.4", "h0_0"),
+        ];
+        let mut samtools = record("samtools", "1.20", "h0_0");
+        samtools.package_record.size = Some(3 * 1024 * 1024);
+        let mut bwa = record("bwa", "0.7.18", "h0_0");
+        bwa.package_record.size = Some(512 * 1024);
+        let after = vec![samtools, bwa];
+
+        let plan = TransactionPlan::new("/envs/qc".into(), &before, &after, |record| {
+            record.package_record.name.as_normalized() == "bwa"
+        });
+
+        assert_eq!(plan.changes.len(), 3);
+        assert_eq!(plan.download_size_bytes, 3 * 1024 * 1024);
+        assert_eq!(
+            plan.to_string(),
+            "Transaction plan for /envs/qc (1 install, 1 upgrade, 1 removal):\n  \
+             + bwa 0.7.18 (h0_0)  [bioconda]\n  \
+             ↑ samtools 1.19 (h0_0) -> 1.20 (h0_0)  [bioconda]  3.0 MiB\n  \
+             - seqtk 1.4 (h0_0)  [bioconda]\n  \
+             Download: 3.0 MiB"
+        );
+        assert!(TransactionPlan::new("/envs/qc".into(), &before, &before, |_| true).is_empty());
+    }
+
+    #[test]
+    fn identical_record_sets_have_no_changes() {
+        let records = vec![record("samtools", "1.20", "h0_0")];
+
+        let changes = diff_package_records(&records, &records);
+
+        assert!(changes.is_empty());
+        assert_eq!(summarize_package_changes(&changes), "no changes");
+    }
+}
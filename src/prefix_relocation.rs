@@ -0,0 +1,413 @@
+use crate::error::{EnvError, Result};
+use crate::pack::ensure_no_symlink_ancestor;
+use rattler::install::link::copy_and_replace_textual_placeholder;
+use rattler_conda_types::package::FileMode;
+use rattler_conda_types::prefix_record::{PathType, PathsEntry};
+use rattler_conda_types::{Platform, PrefixRecord};
+use rattler_digest::{compute_bytes_digest, Sha256};
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// Counts of what `relocate_prefix_records` changed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PrefixRelocation {
+    pub files_rewritten: usize,
+    pub records_updated: usize,
+}
+
+fn io_error(action: &str, path: &Path, error: io::Error) -> EnvError {
+    EnvError::FileOperation(format!("{} {}: {}", action, path.display(), error))
+}
+
+fn prefix_str<'a>(prefix: &'a Path, role: &str) -> Result<&'a str> {
+    prefix.to_str().ok_or_else(|| {
+        EnvError::Validation(format!(
+            "Cannot relocate a prefix whose {} path is not valid UTF-8: {}",
+            role,
+            prefix.display()
+        ))
+    })
+}
+
+fn find_bytes(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+pub(crate) fn replace_all_bytes(input: &[u8], from: &[u8], to: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(input.len());
+    let mut cursor = 0;
+    while let Some(relative) = find_bytes(&input[cursor..], from) {
+        let match_start = cursor + relative;
+        output.extend_from_slice(&input[cursor..match_start]);
+        output.extend_from_slice(to);
+        cursor = match_start + from.len();
+    }
+    output.extend_from_slice(&input[cursor..]);
+    output
+}
+
+/// Replaces `from` with `to` inside every NUL-terminated string that contains it, keeping
+/// the file length. Installing a binary file pads each string with one NUL per byte the
+/// prefix is shorter than the package's placeholder, so a longer `to` may use that padding
+/// but never more than `placeholder_len` allowed for each occurrence.
+fn replace_cstring_prefix(
+    content: &[u8],
+    from: &[u8],
+    to: &[u8],
+    placeholder_len: usize,
+) -> std::result::Result<Vec<u8>, String> {
+    let mut output = Vec::with_capacity(content.len());
+    let mut cursor = 0;
+    while let Some(relative) = find_bytes(&content[cursor..], from) {
+        let start = cursor + relative;
+        let end = content[start..]
+            .iter()
+            .position(|byte| *byte == 0)
+            .map(|offset| start + offset)
+            .unwrap_or(content.len());
+        let padding = content[end..].iter().take_while(|byte| **byte == 0).count();
+        let segment = &content[start..end];
+        let occurrences = segment
+            .windows(from.len())
+            .filter(|window| *window == from)
+            .count();
+        let replaced = replace_all_bytes(segment, from, to);
+        let reserved = occurrences * placeholder_len.saturating_sub(from.len());
+        let growth = replaced.len().saturating_sub(segment.len());
+        if growth > reserved || replaced.len() >= segment.len() + padding {
+            return Err(format!(
+                "the string at byte {} has room for {} more bytes but the new prefix needs {}",
+                start,
+                reserved.min(padding.saturating_sub(1)),
+                growth
+            ));
+        }
+
+        output.extend_from_slice(&content[cursor..start]);
+        output.extend_from_slice(&replaced);
+        output.resize(output.len() + segment.len() + padding - replaced.len(), 0);
+        cursor = end + padding;
+    }
+    output.extend_from_slice(&content[cursor..]);
+    Ok(output)
+}
+
+fn replace_file_contents(path: &Path, content: &[u8]) -> Result<()> {
+    let metadata =
+        fs::metadata(path).map_err(|error| io_error("Failed to inspect", path, error))?;
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let temporary_path = path.with_file_name(format!(".{}.enva-relocate", file_name));
+    fs::write(&temporary_path, content)
+        .map_err(|error| io_error("Failed to write relocated file", &temporary_path, error))?;
+    fs::set_permissions(&temporary_path, metadata.permissions()).map_err(|error| {
+        io_error(
+            "Failed to copy permissions to relocated file",
+            &temporary_path,
+            error,
+        )
+    })?;
+    fs::rename(&temporary_path, path).map_err(|error| {
+        let _ = fs::remove_file(&temporary_path);
+        io_error("Failed to replace relocated file", path, error)
+    })
+}
+
+/// How the file of `entry` has to be rewritten, or `None` when it does not embed the prefix.
+fn relocated_file_mode(entry: &PathsEntry) -> Option<FileMode> {
+    match (&entry.prefix_placeholder, entry.path_type) {
+        (Some(_), _) => Some(entry.file_mode.unwrap_or(FileMode::Text)),
+        (None, PathType::UnixPythonEntryPoint) => Some(FileMode::Text),
+        _ => None,
+    }
+}
+
+/// Refuses record paths that leave `prefix`. The records of an unpacked archive come from
+/// the archive, so they must not be able to point the relocation at other files.
+fn ensure_inside_prefix(prefix: &Path, relative: &Path) -> Result<()> {
+    if relative.as_os_str().is_empty()
+        || !relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+    {
+        return Err(EnvError::PermissionDenied(format!(
+            "Refusing to relocate a file outside the prefix: {}",
+            relative.display()
+        )));
+    }
+    ensure_no_symlink_ancestor(prefix, relative)
+}
+
+/// Rewrites every file of `records` that embeds the prefix it was installed into from
+/// `from` to `to`, and refreshes the sha256 and size recorded for it. `prefix` is where the
+/// files currently are, usually a staging prefix that will be published at `to`.
+pub fn relocate_prefix_records(
+    prefix: &Path,
+    records: Vec<PrefixRecord>,
+    from: &Path,
+    to: &Path,
+) -> Result<PrefixRelocation> {
+    let from_str = prefix_str(from, "source")?;
+    let to_str = prefix_str(to, "target")?;
+    let platform = Platform::current();
+    let mut relocation = PrefixRelocation::default();
+    if from == to {
+        return Ok(relocation);
+    }
+
+    // Every path is checked before the first file is touched.
+    for entry in records
+        .iter()
+        .flat_map(|record| &record.paths_data.paths)
+        .filter(|entry| relocated_file_mode(entry).is_some())
+    {
+        ensure_inside_prefix(prefix, &entry.relative_path)?;
+    }
+
+    for mut record in records {
+        let mut record_changed = false;
+        for entry in &mut record.paths_data.paths {
+            let Some(file_mode) = relocated_file_mode(entry) else {
+                continue;
+            };
+            if file_mode == FileMode::Binary && platform.is_windows() {
+                continue;
+            }
+
+            let path = prefix.join(&entry.relative_path);
+            match fs::symlink_metadata(&path) {
+                // A link is not a file of the package; rewriting it would write its target.
+                Ok(metadata) if metadata.file_type().is_symlink() => continue,
+                Ok(_) => {}
+                Err(error) if error.kind() == io::ErrorKind::NotFound => continue,
+                Err(error) => return Err(io_error("Failed to inspect", &path, error)),
+            }
+            let content =
+                fs::read(&path).map_err(|error| io_error("Failed to read", &path, error))?;
+            if find_bytes(&content, from_str.as_bytes()).is_none() {
+                continue;
+            }
+
+            let relocated = match file_mode {
+                FileMode::Text => {
+                    let mut relocated = Vec::with_capacity(content.len());
+                    copy_and_replace_textual_placeholder(
+                        &content,
+                        &mut relocated,
+                        from_str,
+                        to_str,
+                        &platform,
+                    )
+                    .map_err(|error| io_error("Failed to relocate", &path, error))?;
+                    relocated
+                }
+                FileMode::Binary => {
+                    let placeholder_len = entry
+                        .prefix_placeholder
+                        .as_ref()
+                        .map(String::len)
+                        .unwrap_or(from_str.len());
+                    replace_cstring_prefix(
+                        &content,
+                        from_str.as_bytes(),
+                        to_str.as_bytes(),
+                        placeholder_len,
+                    )
+                    .map_err(|reason| {
+                        EnvError::Validation(format!(
+                            "Cannot relocate binary file {} to {}: {}; choose a shorter target prefix",
+                            entry.relative_path.display(),
+                            to.display(),
+                            reason
+                        ))
+                    })?
+                }
+            };
+
+            replace_file_contents(&path, &relocated)?;
+            let sha256 = compute_bytes_digest::<Sha256>(&relocated);
+            entry.sha256_in_prefix = (entry.sha256 != Some(sha256)).then_some(sha256);
+            entry.size_in_bytes = Some(relocated.len() as u64);
+            relocation.files_rewritten += 1;
+            record_changed = true;
+        }
+
+        if record_changed {
+            let record_path: PathBuf = prefix.join("conda-meta").join(record.file_name());
+            record
+                .write_to_path(&record_path, true)
+                .map_err(|error| io_error("Failed to write prefix record", &record_path, error))?;
+            relocation.records_updated += 1;
+        }
+    }
+
+    Ok(relocation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{relocate_prefix_records, replace_cstring_prefix};
+    use crate::test_fixtures::repodata_record;
+    use crate::verify::verify_prefix;
+    use rattler_conda_types::package::FileMode;
+    use rattler_conda_types::prefix_record::{PathType, PathsEntry};
+    use rattler_conda_types::PrefixRecord;
+    use rattler_digest::{compute_bytes_digest, Sha256};
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use tempfile::tempdir;
+
+    const PLACEHOLDER: &str =
+        "/opt/anaconda1anaconda2anaconda3/placeholder_placeholder_placeholder";
+
+    fn entry(path: &str, contents: &[u8], mode: Option<FileMode>) -> PathsEntry {
+        PathsEntry {
+            relative_path: PathBuf::from(path),
+            original_path: None,
+            path_type: PathType::HardLink,
+            no_link: false,
+            sha256: Some(compute_bytes_digest::<Sha256>(contents)),
+            sha256_in_prefix: None,
+            size_in_bytes: Some(contents.len() as u64),
+            file_mode: mode,
+            prefix_placeholder: mode.map(|_| PLACEHOLDER.to_string()),
+        }
+    }
+
+    fn write_record(prefix: &Path, paths: Vec<PathsEntry>) -> PrefixRecord {
+        let record = PrefixRecord::from_repodata_record(
+            repodata_record("samtools", "1.20", "h0_0", "bioconda", "noarch"),
+            paths,
+        );
+        let record_path = prefix.join("conda-meta").join(record.file_name());
+        record.write_to_path(&record_path, true).unwrap();
+        record
+    }
+
+    #[test]
+    fn cstring_replacement_keeps_length_and_uses_installation_padding() {
+        let mut content = b"\x7fELF".to_vec();
+        content.extend_from_slice(b"/envs/a/lib:/envs/a/bin");
+        content.resize(
+            content.len() + 2 * (PLACEHOLDER.len() - "/envs/a".len()) + 1,
+            0,
+        );
+        content.extend_from_slice(b"tail");
+
+        let relocated =
+            replace_cstring_prefix(&content, b"/envs/a", b"/envs/abc", PLACEHOLDER.len()).unwrap();
+        assert_eq!(relocated.len(), content.len());
+        assert!(relocated.starts_with(b"\x7fELF/envs/abc/lib:/envs/abc/bin\0"));
+        assert!(relocated.ends_with(b"\0tail"));
+
+        let too_long = format!("/{}", "x".repeat(PLACEHOLDER.len()));
+        assert!(replace_cstring_prefix(
+            &content,
+            b"/envs/a",
+            too_long.as_bytes(),
+            PLACEHOLDER.len()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn relocation_rewrites_placeholder_files_and_their_records() {
+        let tempdir = tempdir().unwrap();
+        let staging = tempdir.path().join("staging");
+        let source = tempdir.path().join("envs/source");
+        let target = tempdir.path().join("envs/copy-of-source");
+        fs::create_dir_all(staging.join("conda-meta")).unwrap();
+        fs::create_dir_all(staging.join("bin")).unwrap();
+
+        let script = format!(
+            "#!{}/bin/sh\necho {}/share\n",
+            source.display(),
+            source.display()
+        );
+        let plain = b"no prefix here\n".to_vec();
+        fs::write(staging.join("bin/samtools-wrapper"), &script).unwrap();
+        fs::write(staging.join("bin/readme"), &plain).unwrap();
+        let record = write_record(
+            &staging,
+            vec![
+                entry(
+                    "bin/samtools-wrapper",
+                    script.as_bytes(),
+                    Some(FileMode::Text),
+                ),
+                entry("bin/readme", &plain, None),
+            ],
+        );
+
+        let relocation = relocate_prefix_records(&staging, vec![record], &source, &target).unwrap();
+
+        assert_eq!(relocation.files_rewritten, 1);
+        assert_eq!(relocation.records_updated, 1);
+        assert_eq!(
+            fs::read_to_string(staging.join("bin/samtools-wrapper")).unwrap(),
+            format!(
+                "#!{}/bin/sh\necho {}/share\n",
+                target.display(),
+                target.display()
+            )
+        );
+        let records =
+            vec![
+                PrefixRecord::from_path(staging.join("conda-meta/samtools-1.20-h0_0.json"))
+                    .unwrap(),
+            ];
+        assert!(verify_prefix(&staging, &records)
+            .unwrap()
+            .damaged_packages
+            .is_empty());
+    }
+
+    #[test]
+    fn relocation_refuses_records_that_point_outside_the_prefix() {
+        let tempdir = tempdir().unwrap();
+        let staging = tempdir.path().join("staging");
+        let source = Path::new("/envs/source");
+        fs::create_dir_all(staging.join("conda-meta")).unwrap();
+        let outside = tempdir.path().join("home/.bashrc");
+        fs::create_dir_all(outside.parent().unwrap()).unwrap();
+        let bashrc = b"export PATH=/envs/source/bin:$PATH\n";
+        fs::write(&outside, bashrc).unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(outside.parent().unwrap(), staging.join("home")).unwrap();
+
+        let mut paths = vec!["../home/.bashrc".to_string(), outside.display().to_string()];
+        #[cfg(unix)]
+        paths.push("home/.bashrc".to_string());
+        for path in paths {
+            // A harmless entry first, so a check made only while rewriting would be too late.
+            fs::write(staging.join("tool"), b"#!/envs/source/bin/sh\n").unwrap();
+            let record = write_record(
+                &staging,
+                vec![
+                    entry("tool", b"#!/envs/source/bin/sh\n", Some(FileMode::Text)),
+                    entry(&path, bashrc, Some(FileMode::Text)),
+                ],
+            );
+
+            let error = relocate_prefix_records(
+                &staging,
+                vec![record],
+                source,
+                Path::new("/envs/elsewhere"),
+            )
+            .unwrap_err()
+            .to_string();
+            assert!(error.contains("Refusing"), "{path}: {error}");
+            assert_eq!(fs::read(&outside).unwrap(), bashrc);
+            assert_eq!(
+                fs::read(staging.join("tool")).unwrap(),
+                b"#!/envs/source/bin/sh\n"
+            );
+        }
+    }
+}
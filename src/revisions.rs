@@ -0,0 +1,268 @@
+use crate::error::{EnvError, Result};
+use crate::package_changes::{diff_package_records, summarize_package_changes, PackageChange};
+use chrono::Utc;
+use rattler_conda_types::RepoDataRecord;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const REVISIONS_DIRECTORY: &str = "revisions";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RevisionOperation {
+    /// State found in a prefix that had no revisions before its first enva change.
+    Baseline,
+    Create,
+    Install,
+    Uninstall,
+    Update,
+    Sync,
+    Rollback,
+}
+
+impl RevisionOperation {
+    fn label(self) -> &'static str {
+        match self {
+            Self::Baseline => "baseline",
+            Self::Create => "create",
+            Self::Install => "install",
+            Self::Uninstall => "uninstall",
+            Self::Update => "update",
+            Self::Sync => "sync",
+            Self::Rollback => "rollback",
+        }
+    }
+}
+
+/// Requested specs and the complete record set of a prefix after one enva operation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnvironmentRevision {
+    pub revision: u32,
+    pub timestamp: String,
+    pub operation: RevisionOperation,
+    pub requested_specs: Vec<String>,
+    pub records: Vec<RepoDataRecord>,
+}
+
+pub fn revisions_dir(prefix: &Path) -> PathBuf {
+    prefix
+        .join("conda-meta")
+        .join("enva")
+        .join(REVISIONS_DIRECTORY)
+}
+
+fn revision_path(prefix: &Path, revision: u32) -> PathBuf {
+    revisions_dir(prefix).join(format!("{}.json", revision))
+}
+
+fn revision_numbers(prefix: &Path) -> Result<Vec<u32>> {
+    let directory = revisions_dir(prefix);
+    if !directory.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut numbers = fs::read_dir(&directory)
+        .map_err(|error| {
+            EnvError::FileOperation(format!(
+                "Failed to read revision directory {}: {}",
+                directory.display(),
+                error
+            ))
+        })?
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            if path.extension().and_then(|extension| extension.to_str()) != Some("json") {
+                return None;
+            }
+            path.file_stem()?.to_str()?.parse::<u32>().ok()
+        })
+        .collect::<Vec<u32>>();
+    numbers.sort_unstable();
+    Ok(numbers)
+}
+
+pub fn has_revisions(prefix: &Path) -> Result<bool> {
+    Ok(!revision_numbers(prefix)?.is_empty())
+}
+
+/// Appends the next revision to `prefix`; called on the staging prefix so the new
+/// revision is published together with the packages it describes.
+pub fn record_revision(
+    prefix: &Path,
+    operation: RevisionOperation,
+    requested_specs: Vec<String>,
+    records: Vec<RepoDataRecord>,
+) -> Result<EnvironmentRevision> {
+    let revision = revision_numbers(prefix)?
+        .last()
+        .map(|latest| latest + 1)
+        .unwrap_or(0);
+    let mut records = records;
+    records.sort_by(|left, right| {
+        left.package_record
+            .name
+            .as_normalized()
+            .cmp(right.package_record.name.as_normalized())
+    });
+    let entry = EnvironmentRevision {
+        revision,
+        timestamp: Utc::now().to_rfc3339(),
+        operation,
+        requested_specs,
+        records,
+    };
+
+    let path = revision_path(prefix, revision);
+    let directory = revisions_dir(prefix);
+    fs::create_dir_all(&directory).map_err(|error| {
+        EnvError::FileOperation(format!(
+            "Failed to create revision directory {}: {}",
+            directory.display(),
+            error
+        ))
+    })?;
+    let content = serde_json::to_string_pretty(&entry)?;
+    fs::write(&path, content).map_err(|error| {
+        EnvError::FileOperation(format!(
+            "Failed to write revision {}: {}",
+            path.display(),
+            error
+        ))
+    })?;
+
+    Ok(entry)
+}
+
+pub fn read_revision(prefix: &Path, revision: u32) -> Result<EnvironmentRevision> {
+    let path = revision_path(prefix, revision);
+    if !path.exists() {
+        let available = revision_numbers(prefix)?;
+        return Err(EnvError::Validation(match available.last() {
+            Some(latest) => format!(
+                "Revision {} does not exist for {}; available revisions are 0..={}",
+                revision,
+                prefix.display(),
+                latest
+            ),
+            None => format!("No revisions are recorded for {}", prefix.display()),
+        }));
+    }
+
+    let content = fs::read_to_string(&path).map_err(|error| {
+        EnvError::FileOperation(format!(
+            "Failed to read revision {}: {}",
+            path.display(),
+            error
+        ))
+    })?;
+    serde_json::from_str(&content).map_err(|error| {
+        EnvError::Validation(format!(
+            "Failed to parse revision {}: {}",
+            path.display(),
+            error
+        ))
+    })
+}
+
+pub fn list_revisions(prefix: &Path) -> Result<Vec<EnvironmentRevision>> {
+    revision_numbers(prefix)?
+        .into_iter()
+        .map(|revision| read_revision(prefix, revision))
+        .collect()
+}
+
+/// Changes introduced by each revision relative to the one before it.
+pub fn revision_changes(revisions: &[EnvironmentRevision]) -> Vec<Vec<PackageChange>> {
+    revisions
+        .iter()
+        .enumerate()
+        .map(|(index, revision)| match index.checked_sub(1) {
+            Some(previous) => diff_package_records(&revisions[previous].records, &revision.records),
+            None => diff_package_records(std::iter::empty(), &revision.records),
+        })
+        .collect()
+}
+
+pub fn render_revision_history(revisions: &[EnvironmentRevision]) -> String {
+    let mut lines = Vec::new();
+    for (revision, changes) in revisions.iter().zip(revision_changes(revisions)) {
+        let starts_history = matches!(
+            revision.operation,
+            RevisionOperation::Baseline | RevisionOperation::Create
+        ) || revision.revision == 0;
+        let summary = if starts_history {
+            format!("{} packages", revision.records.len())
+        } else {
+            summarize_package_changes(&changes)
+        };
+        lines.push(format!(
+            "Revision {}  {}  {}  ({})",
+            revision.revision,
+            revision.timestamp,
+            revision.operation.label(),
+            summary
+        ));
+        if !starts_history {
+            lines.extend(changes.iter().map(|change| format!("  {}", change)));
+        }
+    }
+
+    let mut rendered = lines.join("\n");
+    rendered.push('\n');
+    rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        has_revisions, list_revisions, read_revision, record_revision, render_revision_history,
+        RevisionOperation,
+    };
+    use crate::test_fixtures::repodata_record;
+    use rattler_conda_types::RepoDataRecord;
+    use tempfile::tempdir;
+
+    fn record(name: &str, version: &str) -> RepoDataRecord {
+        repodata_record(name, version, "h0_0", "bioconda", "noarch")
+    }
+
+    #[test]
+    fn revisions_are_numbered_sequentially_and_round_trip() {
+        let tempdir = tempdir().unwrap();
+        assert!(!has_revisions(tempdir.path()).unwrap());
+
+        record_revision(
+            tempdir.path(),
+            RevisionOperation::Create,
+            vec!["samtools".to_string()],
+            vec![record("samtools", "1.19"), record("htslib", "1.19")],
+        )
+        .unwrap();
+        let second = record_revision(
+            tempdir.path(),
+            RevisionOperation::Update,
+            vec!["samtools".to_string()],
+            vec![record("samtools", "1.20"), record("htslib", "1.19")],
+        )
+        .unwrap();
+
+        assert_eq!(second.revision, 1);
+        let first = read_revision(tempdir.path(), 0).unwrap();
+        assert_eq!(first.operation, RevisionOperation::Create);
+        assert_eq!(
+            first.records[0].package_record.name.as_normalized(),
+            "htslib"
+        );
+        assert!(read_revision(tempdir.path(), 7)
+            .unwrap_err()
+            .to_string()
+            .contains("available revisions are 0..=1"));
+
+        let rendered = render_revision_history(&list_revisions(tempdir.path()).unwrap());
+        assert!(rendered.contains("Revision 0"));
+        assert!(rendered.contains("create  (2 packages)"));
+        assert!(rendered.contains("update  (1 upgrade)"));
+        assert!(rendered.contains("  ↑ samtools 1.19 (h0_0) -> 1.20 (h0_0)"));
+    }
+}
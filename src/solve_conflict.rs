@@ -0,0 +1,295 @@
+//! Structured reports for environments the solver cannot satisfy.
+//!
+//! The resolvo solver explains an unsolvable request as an indented tree of requirements
+//! and candidates. That text is parsed back into [`ConflictNode`]s so enva can print it
+//! as a tree, serialize it for `--json`, and point at the requested pin to relax.
+
+use rattler_conda_types::MatchSpec;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+const SOLVER_MESSAGE_PREFIX: &str = "Cannot solve the request because of: ";
+const TREE_UNITS: [&str; 4] = ["├─ ", "└─ ", "│  ", "   "];
+
+/// One line of the solver explanation and the lines nested below it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConflictNode {
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<ConflictNode>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SolveConflict {
+    /// Requested specs whose packages take part in the conflict, in request order.
+    pub conflicting_specs: Vec<String>,
+    /// Version-constrained requested specs that, once relaxed, may make the request solvable.
+    pub suggested_relaxations: Vec<String>,
+    pub tree: Vec<ConflictNode>,
+}
+
+impl SolveConflict {
+    /// Builds a report from the messages of `SolveError::Unsolvable` for `requested` specs.
+    pub fn from_solver_messages(messages: &[String], requested: &[MatchSpec]) -> Self {
+        let tree = messages
+            .iter()
+            .flat_map(|message| parse_conflict_tree(message))
+            .collect::<Vec<ConflictNode>>();
+
+        let requests = tree
+            .iter()
+            .flat_map(|node| {
+                if node.message == "The following packages are incompatible" {
+                    node.children.iter().collect::<Vec<_>>()
+                } else {
+                    vec![node]
+                }
+            })
+            .collect::<Vec<&ConflictNode>>();
+        let mut top_level = Vec::new();
+        let mut nested = Vec::new();
+        for request in requests {
+            let name = package_name(&request.message);
+            top_level.extend(name);
+            // Candidates of the request itself are not dependencies of it.
+            let mut dependencies = Vec::new();
+            collect_package_names(&request.children, &mut dependencies);
+            nested.extend(
+                dependencies
+                    .into_iter()
+                    .filter(|other| Some(*other) != name),
+            );
+        }
+
+        let conflicting = requested
+            .iter()
+            .filter(|spec| {
+                spec_name(spec)
+                    .is_some_and(|name| top_level.iter().chain(&nested).any(|other| *other == name))
+            })
+            .collect::<Vec<&MatchSpec>>();
+
+        // A pin that other requested packages depend on is the usual culprit; pins that
+        // only appear at the top of the tree are suggested when there is nothing else.
+        let constrained = conflicting
+            .iter()
+            .filter(|spec| spec.version.is_some())
+            .collect::<Vec<_>>();
+        let mut relax = constrained
+            .iter()
+            .filter(|spec| spec_name(spec).is_some_and(|name| nested.contains(&name)))
+            .map(ToString::to_string)
+            .collect::<Vec<String>>();
+        if relax.is_empty() {
+            relax = constrained.iter().map(ToString::to_string).collect();
+        }
+
+        Self {
+            conflicting_specs: conflicting.iter().map(ToString::to_string).collect(),
+            suggested_relaxations: relax,
+            tree,
+        }
+    }
+
+    /// Renders the conflict tree with the same box-drawing layout the solver uses.
+    pub fn render_tree(&self) -> String {
+        let mut rendered = String::new();
+        for node in &self.tree {
+            rendered.push_str(&node.message);
+            rendered.push('\n');
+            render_children(&node.children, "", &mut rendered);
+        }
+        rendered
+    }
+}
+
+impl fmt::Display for SolveConflict {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.conflicting_specs.is_empty() {
+            writeln!(formatter, "Cannot solve the requested specs")?;
+        } else {
+            writeln!(
+                formatter,
+                "Cannot solve the requested specs: {}",
+                self.conflicting_specs.join(", ")
+            )?;
+        }
+        write!(formatter, "{}", self.render_tree())?;
+        if !self.suggested_relaxations.is_empty() {
+            write!(
+                formatter,
+                "Hint: relax {} in the environment YAML, for example by dropping the version \
+                 constraint",
+                self.suggested_relaxations
+                    .iter()
+                    .map(|spec| format!("`{}`", spec))
+                    .collect::<Vec<String>>()
+                    .join(" or ")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn spec_name(spec: &MatchSpec) -> Option<&str> {
+    spec.name.as_exact().map(|name| name.as_normalized())
+}
+
+/// Returns the package a line of the explanation is about, if any.
+fn package_name(message: &str) -> Option<&str> {
+    let message = message
+        .strip_prefix("No candidates were found for ")
+        .unwrap_or(message);
+    let name = message.split([' ', ',']).next()?;
+    (!name.is_empty() && name != "the" && name != "The").then_some(name)
+}
+
+fn collect_package_names<'a>(nodes: &'a [ConflictNode], names: &mut Vec<&'a str>) {
+    for node in nodes {
+        names.extend(package_name(&node.message));
+        collect_package_names(&node.children, names);
+    }
+}
+
+fn parse_conflict_tree(message: &str) -> Vec<ConflictNode> {
+    let message = message
+        .strip_prefix(SOLVER_MESSAGE_PREFIX)
+        .unwrap_or(message);
+    let mut lines = Vec::new();
+    for line in message.lines().filter(|line| !line.trim().is_empty()) {
+        let mut rest = line;
+        let mut depth = 0;
+        while let Some(unit) = TREE_UNITS.iter().find(|unit| rest.starts_with(**unit)) {
+            rest = &rest[unit.len()..];
+            depth += 1;
+        }
+        lines.push((depth, rest.trim_end().to_string()));
+    }
+
+    let mut roots = Vec::new();
+    let mut iter = lines.into_iter().peekable();
+    while let Some((depth, message)) = iter.next() {
+        roots.push(ConflictNode {
+            message,
+            children: nest_children(depth, &mut iter),
+        });
+    }
+    roots
+}
+
+fn nest_children(
+    parent_depth: usize,
+    lines: &mut std::iter::Peekable<impl Iterator<Item = (usize, String)>>,
+) -> Vec<ConflictNode> {
+    let mut children = Vec::new();
+    while let Some((depth, _)) = lines.peek() {
+        if *depth <= parent_depth {
+            break;
+        }
+        let (depth, message) = lines.next().expect("peeked line exists");
+        children.push(ConflictNode {
+            message,
+            children: nest_children(depth, lines),
+        });
+    }
+    children
+}
+
+fn render_children(nodes: &[ConflictNode], indent: &str, rendered: &mut String) {
+    for (index, node) in nodes.iter().enumerate() {
+        let last = index + 1 == nodes.len();
+        rendered.push_str(indent);
+        rendered.push_str(if last { "└─ " } else { "├─ " });
+        rendered.push_str(&node.message);
+        rendered.push('\n');
+        let child_indent = format!("{}{}", indent, if last { "   " } else { "│  " });
+        render_children(&node.children, &child_indent, rendered);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SolveConflict;
+    use rattler_conda_types::{MatchSpec, ParseStrictness};
+
+    const PINNED_PYTHON: &str = "Cannot solve the request because of: multiqc >=1.19 cannot be \
+installed because there are no viable options:
+├─ multiqc 1.20 would require
+│  └─ python >=3.9, for which no candidates were found.
+└─ multiqc 1.19 would require
+   └─ python >=3.10, for which no candidates were found.
+";
+
+    fn specs(specs: &[&str]) -> Vec<MatchSpec> {
+        specs
+            .iter()
+            .map(|spec| MatchSpec::from_str(spec, ParseStrictness::Lenient).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn pinned_dependency_of_another_request_is_suggested_for_relaxing() {
+        let conflict = SolveConflict::from_solver_messages(
+            &[PINNED_PYTHON.to_string()],
+            &specs(&["python=3.8.18", "multiqc>=1.19", "fastqc"]),
+        );
+
+        assert_eq!(
+            conflict.conflicting_specs,
+            vec!["python 3.8.18.*", "multiqc >=1.19"]
+        );
+        assert_eq!(conflict.suggested_relaxations, vec!["python 3.8.18.*"]);
+        assert_eq!(conflict.tree.len(), 1);
+        assert_eq!(conflict.tree[0].children.len(), 2);
+        assert_eq!(
+            conflict.tree[0].children[1].children[0].message,
+            "python >=3.10, for which no candidates were found."
+        );
+        assert_eq!(
+            conflict.render_tree(),
+            PINNED_PYTHON.trim_start_matches("Cannot solve the request because of: ")
+        );
+
+        let rendered = conflict.to_string();
+        assert!(rendered.starts_with(
+            "Cannot solve the requested specs: python 3.8.18.*, multiqc >=1.19\nmultiqc >=1.19"
+        ));
+        assert!(rendered.ends_with("Hint: relax `python 3.8.18.*` in the environment YAML, for example by dropping the version constraint"));
+
+        let json = serde_json::to_value(&conflict).unwrap();
+        assert_eq!(json["suggested_relaxations"][0], "python 3.8.18.*");
+        assert_eq!(
+            json["tree"][0]["children"][0]["message"],
+            "multiqc 1.20 would require"
+        );
+    }
+
+    #[test]
+    fn incompatible_top_level_requests_and_missing_packages_are_reported() {
+        let message = "The following packages are incompatible
+├─ samtools ==1.20 can be installed with any of the following options:
+│  └─ samtools 1.20 would require
+│     └─ htslib >=1.20
+└─ htslib ==1.19 cannot be installed because there are no viable options:
+   └─ htslib 1.19, which conflicts with the versions reported above.
+";
+        let conflict = SolveConflict::from_solver_messages(
+            &[message.to_string()],
+            &specs(&["samtools==1.20", "htslib==1.19"]),
+        );
+        assert_eq!(
+            conflict.conflicting_specs,
+            vec!["samtools ==1.20", "htslib ==1.19"]
+        );
+        assert_eq!(conflict.suggested_relaxations, vec!["htslib ==1.19"]);
+        assert_eq!(conflict.render_tree(), message);
+
+        let missing = SolveConflict::from_solver_messages(
+            &["No candidates were found for bwa *.\n".to_string()],
+            &specs(&["bwa", "samtools"]),
+        );
+        assert_eq!(missing.conflicting_specs, vec!["bwa"]);
+        assert!(missing.suggested_relaxations.is_empty());
+        assert!(!missing.to_string().contains("Hint"));
+    }
+}
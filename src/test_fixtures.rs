@@ -0,0 +1,30 @@
+//! Package records shared by the unit tests.
+
+use rattler_conda_types::{PackageName, PackageRecord, RepoDataRecord, Version};
+use std::str::FromStr;
+
+/// A record of `name` `version` with build `build`, as `channel` on conda.anaconda.org
+/// serves it from `subdir`.
+pub(crate) fn repodata_record(
+    name: &str,
+    version: &str,
+    build: &str,
+    channel: &str,
+    subdir: &str,
+) -> RepoDataRecord {
+    let mut package_record = PackageRecord::new(
+        PackageName::new_unchecked(name),
+        Version::from_str(version).unwrap(),
+        build.to_string(),
+    );
+    package_record.subdir = subdir.to_string();
+    let file_name = format!("{name}-{version}-{build}.conda");
+    RepoDataRecord {
+        package_record,
+        url: format!("https://conda.anaconda.org/{channel}/{subdir}/{file_name}")
+            .parse()
+            .unwrap(),
+        identifier: file_name.parse().unwrap(),
+        channel: Some(format!("https://conda.anaconda.org/{channel}/")),
+    }
+}
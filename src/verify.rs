@@ -0,0 +1,380 @@
+use crate::error::{EnvError, Result};
+use rattler_conda_types::prefix_record::{PathType, PathsEntry};
+use rattler_conda_types::PrefixRecord;
+use rattler_digest::{compute_file_digest, Sha256};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A file recorded in a package's `paths_data` that no longer matches the prefix.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ModifiedFile {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PackageVerification {
+    pub name: String,
+    pub version: String,
+    pub build: String,
+    pub missing: Vec<PathBuf>,
+    pub modified: Vec<ModifiedFile>,
+}
+
+impl PackageVerification {
+    pub fn is_damaged(&self) -> bool {
+        !self.missing.is_empty() || !self.modified.is_empty()
+    }
+}
+
+/// Result of checking every installed file of a prefix against its `PrefixRecord`s.
+#[derive(Debug, Clone, Serialize)]
+pub struct PrefixVerification {
+    pub prefix: PathBuf,
+    pub packages_checked: usize,
+    pub files_checked: usize,
+    /// Only packages with at least one missing or modified file.
+    pub damaged_packages: Vec<PackageVerification>,
+    /// Files that no installed package owns, e.g. left behind by `pip install`.
+    pub extra_files: Vec<PathBuf>,
+}
+
+impl PrefixVerification {
+    pub fn has_drift(&self) -> bool {
+        !self.damaged_packages.is_empty() || !self.extra_files.is_empty()
+    }
+}
+
+fn io_error(action: &str, path: &Path, error: io::Error) -> EnvError {
+    EnvError::FileOperation(format!("{} {}: {}", action, path.display(), error))
+}
+
+/// Top-level entries written by the installer itself rather than by a package;
+/// `CACHEDIR.TAG` excludes the prefix from backups.
+const PREFIX_METADATA_ENTRIES: &[&str] = &["conda-meta", "CACHEDIR.TAG"];
+
+enum FileState {
+    Intact,
+    Missing,
+    Modified(String),
+}
+
+/// Never reads through a symlinked directory whose target leaves the prefix.
+fn parent_stays_inside(canonical_prefix: &Path, path: &Path) -> bool {
+    path.parent()
+        .and_then(|parent| fs::canonicalize(parent).ok())
+        .is_some_and(|parent| parent.starts_with(canonical_prefix))
+}
+
+fn verify_entry(canonical_prefix: &Path, prefix: &Path, entry: &PathsEntry) -> Result<FileState> {
+    let path = prefix.join(&entry.relative_path);
+    let metadata = match fs::symlink_metadata(&path) {
+        Ok(metadata) => metadata,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(FileState::Missing),
+        Err(error) => return Err(io_error("Failed to inspect", &path, error)),
+    };
+    if !parent_stays_inside(canonical_prefix, &path) {
+        return Ok(FileState::Modified(
+            "resolves outside the prefix through a symlinked directory".to_string(),
+        ));
+    }
+
+    match entry.path_type {
+        PathType::SoftLink if metadata.file_type().is_symlink() => Ok(FileState::Intact),
+        PathType::SoftLink => Ok(FileState::Modified("expected a symlink".to_string())),
+        PathType::Directory if metadata.is_dir() => Ok(FileState::Intact),
+        PathType::Directory => Ok(FileState::Modified("expected a directory".to_string())),
+        _ if metadata.file_type().is_symlink() => {
+            Ok(FileState::Modified("replaced by a symlink".to_string()))
+        }
+        _ if !metadata.is_file() => Ok(FileState::Modified("expected a file".to_string())),
+        _ => {
+            if let Some(expected) = entry.size_in_bytes {
+                if metadata.len() != expected {
+                    return Ok(FileState::Modified(format!(
+                        "size {} differs from recorded {}",
+                        metadata.len(),
+                        expected
+                    )));
+                }
+            }
+            if let Some(expected) = entry.sha256_in_prefix.or(entry.sha256) {
+                let actual = compute_file_digest::<Sha256>(&path)
+                    .map_err(|error| io_error("Failed to hash", &path, error))?;
+                if actual != expected {
+                    return Ok(FileState::Modified("sha256 mismatch".to_string()));
+                }
+            }
+            Ok(FileState::Intact)
+        }
+    }
+}
+
+/// Bytecode that Python writes next to sources at import time is never owned by a
+/// package, so it is not reported as drift.
+fn is_runtime_bytecode(relative_path: &Path) -> bool {
+    relative_path
+        .extension()
+        .is_some_and(|extension| extension == "pyc")
+        && relative_path
+            .parent()
+            .and_then(Path::file_name)
+            .is_some_and(|directory| directory == "__pycache__")
+}
+
+fn collect_unowned_files(
+    prefix: &Path,
+    directory: &Path,
+    owned: &HashSet<PathBuf>,
+    extra_files: &mut Vec<PathBuf>,
+) -> Result<()> {
+    let entries =
+        fs::read_dir(directory).map_err(|error| io_error("Failed to read", directory, error))?;
+    for entry in entries {
+        let entry = entry.map_err(|error| io_error("Failed to read", directory, error))?;
+        let path = entry.path();
+        let relative_path = path.strip_prefix(prefix).unwrap_or(&path).to_path_buf();
+        if PREFIX_METADATA_ENTRIES
+            .iter()
+            .any(|entry| relative_path == Path::new(entry))
+        {
+            continue;
+        }
+
+        // `file_type` does not follow symlinks, so linked directories are reported
+        // as entries instead of being walked.
+        let file_type = entry
+            .file_type()
+            .map_err(|error| io_error("Failed to inspect", &path, error))?;
+        if file_type.is_dir() {
+            collect_unowned_files(prefix, &path, owned, extra_files)?;
+        } else if !owned.contains(&relative_path) && !is_runtime_bytecode(&relative_path) {
+            extra_files.push(relative_path);
+        }
+    }
+    Ok(())
+}
+
+pub fn verify_prefix(prefix: &Path, installed: &[PrefixRecord]) -> Result<PrefixVerification> {
+    let canonical_prefix =
+        fs::canonicalize(prefix).map_err(|error| io_error("Failed to resolve", prefix, error))?;
+    let mut owned = HashSet::new();
+    let mut files_checked = 0;
+    let mut damaged_packages = Vec::new();
+
+    for record in installed {
+        let package = &record.repodata_record.package_record;
+        let mut verification = PackageVerification {
+            name: package.name.as_normalized().to_string(),
+            version: package.version.to_string(),
+            build: package.build.clone(),
+            missing: Vec::new(),
+            modified: Vec::new(),
+        };
+        for entry in &record.paths_data.paths {
+            owned.insert(entry.relative_path.clone());
+            files_checked += 1;
+            match verify_entry(&canonical_prefix, prefix, entry)? {
+                FileState::Intact => {}
+                FileState::Missing => verification.missing.push(entry.relative_path.clone()),
+                FileState::Modified(reason) => verification.modified.push(ModifiedFile {
+                    path: entry.relative_path.clone(),
+                    reason,
+                }),
+            }
+        }
+        if verification.is_damaged() {
+            damaged_packages.push(verification);
+        }
+    }
+    damaged_packages.sort_by(|left, right| left.name.cmp(&right.name));
+
+    let mut extra_files = Vec::new();
+    collect_unowned_files(prefix, prefix, &owned, &mut extra_files)?;
+    extra_files.sort();
+
+    Ok(PrefixVerification {
+        prefix: prefix.to_path_buf(),
+        packages_checked: installed.len(),
+        files_checked,
+        damaged_packages,
+        extra_files,
+    })
+}
+
+pub fn render_verification(verification: &PrefixVerification) -> String {
+    let prefix = verification.prefix.display();
+    if !verification.has_drift() {
+        return format!(
+            "✓ {}: {} packages, {} files verified\n",
+            prefix, verification.packages_checked, verification.files_checked
+        );
+    }
+
+    let mut lines = vec![format!(
+        "✗ {}: {} damaged packages, {} extra files",
+        prefix,
+        verification.damaged_packages.len(),
+        verification.extra_files.len()
+    )];
+    for package in &verification.damaged_packages {
+        lines.push(format!(
+            "  {} {} ({})",
+            package.name, package.version, package.build
+        ));
+        lines.extend(
+            package
+                .missing
+                .iter()
+                .map(|path| format!("    missing   {}", path.display())),
+        );
+        lines.extend(
+            package
+                .modified
+                .iter()
+                .map(|file| format!("    modified  {} ({})", file.path.display(), file.reason)),
+        );
+    }
+    if !verification.extra_files.is_empty() {
+        lines.push("  unowned files:".to_string());
+        lines.extend(
+            verification
+                .extra_files
+                .iter()
+                .map(|path| format!("    extra     {}", path.display())),
+        );
+    }
+
+    let mut rendered = lines.join("\n");
+    rendered.push('\n');
+    rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render_verification, verify_prefix};
+    use crate::test_fixtures::repodata_record;
+    use rattler_conda_types::prefix_record::{PathType, PathsEntry};
+    use rattler_conda_types::PrefixRecord;
+    use rattler_digest::{compute_bytes_digest, Sha256};
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use tempfile::tempdir;
+
+    fn file_entry(relative_path: &str, contents: &[u8]) -> PathsEntry {
+        PathsEntry {
+            relative_path: PathBuf::from(relative_path),
+            original_path: None,
+            path_type: PathType::HardLink,
+            no_link: false,
+            sha256: Some(compute_bytes_digest::<Sha256>(contents)),
+            sha256_in_prefix: None,
+            size_in_bytes: Some(contents.len() as u64),
+            file_mode: None,
+            prefix_placeholder: None,
+        }
+    }
+
+    fn installed_package(prefix: &Path, name: &str, files: &[(&str, &[u8])]) -> PrefixRecord {
+        let repodata = repodata_record(name, "1.0", "h0_0", "bioconda", "noarch");
+        for (relative_path, contents) in files {
+            let path = prefix.join(relative_path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        PrefixRecord::from_repodata_record(
+            repodata,
+            files
+                .iter()
+                .map(|(path, contents)| file_entry(path, contents))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn intact_prefix_has_no_drift() {
+        let tempdir = tempdir().unwrap();
+        let prefix = tempdir.path();
+        fs::create_dir_all(prefix.join("conda-meta")).unwrap();
+        fs::write(prefix.join("conda-meta/history"), "").unwrap();
+        let installed = vec![installed_package(
+            prefix,
+            "samtools",
+            &[("bin/samtools", b"#!/bin/sh\n")],
+        )];
+        fs::create_dir_all(prefix.join("lib/python3.12/__pycache__")).unwrap();
+        fs::write(
+            prefix.join("lib/python3.12/__pycache__/site.cpython-312.pyc"),
+            "",
+        )
+        .unwrap();
+
+        let verification = verify_prefix(prefix, &installed).unwrap();
+
+        assert!(!verification.has_drift());
+        assert_eq!(verification.files_checked, 1);
+        assert!(render_verification(&verification).starts_with("✓ "));
+    }
+
+    #[test]
+    fn reports_missing_modified_and_unowned_files() {
+        let tempdir = tempdir().unwrap();
+        let prefix = tempdir.path().join("env");
+        let outside = tempdir.path().join("outside");
+        fs::create_dir_all(prefix.join("conda-meta")).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        let installed = vec![
+            installed_package(
+                &prefix,
+                "samtools",
+                &[
+                    ("bin/samtools", b"samtools binary"),
+                    ("share/samtools/README", b"readme"),
+                    ("bin/samtools-same-size", b"abc"),
+                ],
+            ),
+            installed_package(&prefix, "seqtk", &[("bin/seqtk", b"seqtk binary")]),
+        ];
+        fs::remove_file(prefix.join("share/samtools/README")).unwrap();
+        fs::write(prefix.join("bin/samtools"), "edited").unwrap();
+        fs::write(prefix.join("bin/samtools-same-size"), "xyz").unwrap();
+        fs::write(prefix.join("bin/pip"), "stray pip install").unwrap();
+        #[cfg(unix)]
+        {
+            fs::write(outside.join("secret"), "not part of the prefix").unwrap();
+            std::os::unix::fs::symlink(&outside, prefix.join("linked")).unwrap();
+        }
+
+        let verification = verify_prefix(&prefix, &installed).unwrap();
+
+        assert!(verification.has_drift());
+        assert_eq!(verification.damaged_packages.len(), 1);
+        let samtools = &verification.damaged_packages[0];
+        assert_eq!(
+            samtools.missing,
+            vec![PathBuf::from("share/samtools/README")]
+        );
+        assert_eq!(
+            samtools
+                .modified
+                .iter()
+                .map(|file| (file.path.to_str().unwrap(), file.reason.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("bin/samtools", "size 6 differs from recorded 15"),
+                ("bin/samtools-same-size", "sha256 mismatch"),
+            ]
+        );
+        let mut expected_extra = vec![PathBuf::from("bin/pip")];
+        if cfg!(unix) {
+            expected_extra.push(PathBuf::from("linked"));
+        }
+        assert_eq!(verification.extra_files, expected_extra);
+        let rendered = render_verification(&verification);
+        assert!(rendered.contains("    missing   share/samtools/README"));
+        assert!(rendered.contains("    extra     bin/pip"));
+    }
+}